@@ -4,17 +4,181 @@
 //! project in run mode. Exports a single public function called check_last_run
 //! that accepts a directory where client, participant, and coordinator log files
 //! are found, and the number of clients, participants. Loads and analyses 
-//! log files to check a handful of correctness invariants. 
+//! log files to check a handful of correctness invariants, returning a 
+//! CheckReport, or a CheckError listing every Violation found. 
 //! 
 extern crate log;
 extern crate stderrlog;
 extern crate clap;
 extern crate ctrlc;
-use std::collections::HashMap;
-use oplog::OpLog;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use oplog::{CorruptRecord, OpLog, OpLogFormat, ReadError};
 use message::ProtocolMessage;
 use message::MessageType;
 use message;
+use golden;
+use kvstore;
+use golden::Outcome;
+use tpcoptions::TPCOptions;
+use vclock;
+use participant::ParticipantState;
+use summary;
+use summary::TransactionOutcome;
+
+///
+/// Violation
+/// one way the logs of a run break 2PC's invariants (see check_last_run())
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    DuplicateYesVote { participant: String, txid: i32, yes_votes: usize },
+    NotAtomic { participant: String, txid: i32, logged: Vec<MessageType> },
+    UnilateralCommit { participant: String, txid: i32 },
+    TooManyAborts { participant: String, aborted: usize, coordinator_aborted: usize },
+    AbandonedWithoutVote { participant: String, txid: i32 },
+    CommitBeforeCommitPoint { participant: String, txid: i32 },
+    OutOfCausalOrder { participant: String, txid: i32, mtype: MessageType },
+    IllegalTransition { participant: String, txid: i32, transition: Option<(ParticipantState, ParticipantState)> },
+    SavepointMismatch { participant: String, txid: i32, segment: i32 },
+    SplitTransaction { log: String, txid: i32, mtype: MessageType, ops: Vec<i32>, requested: Vec<i32> },
+    LostInFailover { log: String, txid: i32, mtype: MessageType },
+    StoreDiverged(String),
+    NeverTerminated { participant: String, txid: i32 },
+    CommitUnacked { participant: String, txid: i32 },
+    Corrupt { log: String, record: CorruptRecord },
+    OutOfSequence { txid: i32, seqno: i32, expected: i32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::DuplicateYesVote { participant, txid, yes_votes } =>
+                write!(f, "coordinator committed txid {} but {} logged {} yes votes for it, not 1", txid, participant, yes_votes),
+            Violation::NotAtomic { participant, txid, logged } =>
+                write!(f, "coordinator committed txid {} but {} didn't commit it too (it logged {:?})", txid, participant, logged),
+            Violation::UnilateralCommit { participant, txid } =>
+                write!(f, "{} committed txid {}, which the coordinator didn't commit", participant, txid),
+            Violation::TooManyAborts { participant, aborted, coordinator_aborted } =>
+                write!(f, "{} aborted {} txids but the coordinator only aborted {}", participant, aborted, coordinator_aborted),
+            Violation::AbandonedWithoutVote { participant, txid } =>
+                write!(f, "{} abandoned txid {} without having voted on it", participant, txid),
+            Violation::CommitBeforeCommitPoint { participant, txid } =>
+                write!(f, "{} committed txid {} before the coordinator's commit point", participant, txid),
+            Violation::OutOfCausalOrder { participant, txid, mtype } =>
+                write!(f, "{} {:?} for txid {} is out of happens-before order with the coordinator's decision", participant, mtype, txid),
+            Violation::IllegalTransition { participant, txid, transition } =>
+                write!(f, "{} made an illegal state transition {:?} for txid {}", participant, transition, txid),
+            Violation::SavepointMismatch { participant, txid, segment } =>
+                write!(f, "{} has a commit for txid {} through savepoint {}, which doesn't match the agreed savepoint", participant, txid, segment),
+            Violation::SplitTransaction { log, txid, mtype, ops, requested } =>
+                write!(f, "{} {:?} for txid {} covers operations {:?}, not the {:?} the client requested", log, mtype, txid, ops, requested),
+            Violation::LostInFailover { log, txid, mtype } =>
+                write!(f, "{} {:?} for txid {} was lost or changed across failover", log, mtype, txid),
+            Violation::StoreDiverged(problem) => write!(f, "{}", problem),
+            Violation::NeverTerminated { participant, txid } =>
+                write!(f, "{} never terminated txid {}", participant, txid),
+            Violation::CommitUnacked { participant, txid } =>
+                write!(f, "coordinator committed txid {} but never got {}'s ack of the commit", txid, participant),
+            Violation::Corrupt { log, record } => write!(f, "{}: {}", log, record),
+            Violation::OutOfSequence { txid, seqno, expected } =>
+                write!(f, "coordinator committed txid {} as seqno {}, not {}: the commit order has a gap or goes backwards", txid, seqno, expected),
+        }
+    }
+}
+
+///
+/// ParticipantCounts
+/// how a participant's log says its transactions ended
+///
+#[derive(Clone, Debug, Default)]
+pub struct ParticipantCounts {
+    pub committed: usize,
+    pub aborted: usize,         // including presumed aborts
+    pub abandoned: usize,
+    pub presumed: usize,
+}
+
+///
+/// CheckReport
+/// what check_last_run() found: how every transaction ended according
+/// to the coordinator, and what each participant's log adds up to
+///
+#[derive(Clone, Debug)]
+pub struct CheckReport {
+    pub committed: usize,       // coordinator's totals
+    pub aborted: usize,
+    pub participants: BTreeMap<String, ParticipantCounts>,
+    pub outcomes: Vec<TransactionOutcome>, // in txid order
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (p, counts) in self.participants.iter() {
+            writeln!(f, "{}: C:{} of {}(C-global), A:{} of {}(A-global), abandoned:{}{}",
+                     p, counts.committed, self.committed, counts.aborted, self.aborted, counts.abandoned,
+                     if counts.presumed > 0 { format!(", presumed:{}", counts.presumed) } else { String::new() })?;
+        }
+        for t in self.outcomes.iter() {
+            writeln!(f, "txid {}: {}{}{} ({} op {})", t.txid, t.outcome,
+                     if t.segment > 0 { format!(" through savepoint {}", t.segment) } else { String::new() },
+                     t.reason.map_or(String::new(), |reason| format!(": {}", reason)),
+                     t.client, t.opid)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// CheckError
+/// why check_last_run() failed: a log it needed wasn't there or 
+/// couldn't be read (or held a record that doesn't decode, or segments
+/// in different formats), or the logs break 2PC's invariants (along 
+/// with the report on what they do say). compare_golden() also fails 
+/// with Unsupported for a run the golden model can't replay.
+///
+#[derive(Debug)]
+pub enum CheckError {
+    MissingLog(String),
+    Io(String, io::Error),
+    BadRecord { path: String, offset: u64, what: String },
+    MixedFormats { path: String, format: OpLogFormat, log: OpLogFormat },
+    Unsupported(String),
+    Violations(CheckReport, Vec<Violation>),
+}
+
+impl From<ReadError> for CheckError {
+    fn from(err: ReadError) -> CheckError {
+        match err {
+            ReadError::BadRecord { path, offset, what } => CheckError::BadRecord { path, offset, what },
+            ReadError::MixedFormats { path, format, log } => CheckError::MixedFormats { path, format, log },
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckError::MissingLog(path) => write!(f, "check: FAIL, no log at {}", path),
+            CheckError::Io(path, err) => write!(f, "check: FAIL, can't read log {}: {}", path, err),
+            CheckError::BadRecord { path, offset, what } => 
+                write!(f, "check: FAIL, bad record in {} at offset {}: {}", path, offset, what),
+            CheckError::MixedFormats { path, format, log } => 
+                write!(f, "check: FAIL, {} is {:?} but earlier segments of its log are {:?}: a log can't mix formats", path, format, log),
+            CheckError::Unsupported(why) => write!(f, "check: FAIL, {}", why),
+            CheckError::Violations(report, violations) => {
+                write!(f, "{}", report)?;
+                for violation in violations.iter() {
+                    writeln!(f, "violation: {}", violation)?;
+                }
+                write!(f, "check: FAIL, {} violation(s)", violations.len())
+            },
+        }
+    }
+}
 
 ///
 /// check_participant()
@@ -25,20 +189,19 @@ use message;
 /// 
 /// <params>
 ///     participant: name of participant (label)
-///     ncommit: number of committed transactions from coordinator
 ///     nabort: number of aborted transactions from coordinator
 ///     ccommitted: map of committed transactions from coordinator
 ///     plog: map of participant operations
+///     violations: where anything the two disagree on is added
 ///
 fn check_participant(
     participant: &String,
-    ncommit: usize,
     nabort: usize,
     ccommitted: &HashMap<i32, ProtocolMessage>,
-    plog: &HashMap<i32, ProtocolMessage>
-    ) -> bool {
+    plog: &HashMap<i32, ProtocolMessage>,
+    violations: &mut Vec<Violation>
+    ) -> ParticipantCounts {
     
-    let mut result = true;
     let pcommitted = plog.iter()
         .filter(|e| (*e.1).mtype == MessageType::CoordinatorCommit)
         .map(|(k,v)| (k.clone(), v.clone()));
@@ -48,28 +211,23 @@ fn check_participant(
     let paborted = plog.iter()
         .filter(|e| (*e.1).mtype == MessageType::CoordinatorAbort)
         .map(|(k,v)| (k.clone(), v.clone()));
+    let pabandoned = plog.iter()
+        .filter(|e| e.1.mtype == MessageType::ParticipantAbandon)
+        .map(|(k,v)| (*k, v.clone()));
+    let npresumed = plog.values().filter(|e| e.mtype == MessageType::PresumedAbort).count();
 
     let mcommit: HashMap<i32, message::ProtocolMessage> = pcommitted.collect();
     let mlcommit: HashMap<i32, message::ProtocolMessage> = plcommitted.collect();
     let mabort: HashMap<i32, message::ProtocolMessage> = paborted.collect();
-    let npcommit = mcommit.len();
-    let nlcommit = mlcommit.len();
+    let mabandon: HashMap<i32, message::ProtocolMessage> = pabandoned.collect();
     let npabort = mabort.len();
-    result &= (npcommit <= ncommit) && (nlcommit >= ncommit);
-    result &= npabort <= nabort;
-    assert!(ncommit <= nlcommit);
-    assert!(npcommit <= ncommit);
-    assert!(nabort >= npabort);
+    if npabort + npresumed > nabort {
+        violations.push(Violation::TooManyAborts { participant: participant.clone(), aborted: npabort + npresumed, coordinator_aborted: nabort });
+    }
 
     for (_k, v) in ccommitted.iter() {
         let txid = v.txid;
-        let mut _foundtxid = 0;
         let mut foundlocaltxid = 0;
-        for (_k2, v2) in mcommit.iter() {
-            if v2.txid == txid {
-                _foundtxid += 1;
-            }
-        }
         for (_k3, v3) in mlcommit.iter() {
 			// handle the case where the participant simply doesn't get 
 			// the global commit message from the coordinator. If the 
@@ -79,16 +237,37 @@ fn check_participant(
                 foundlocaltxid += 1;
             }
         }		
-        result &= foundlocaltxid == 1;
-        assert!(foundlocaltxid == 1); // exactly one commit of txid per participant
+        // at most one yes vote for txid per participant (a missing one 
+        // is check_atomicity()'s to report)
+        if foundlocaltxid > 1 {
+            violations.push(Violation::DuplicateYesVote { participant: participant.clone(), txid, yes_votes: foundlocaltxid });
+        }
+    }
+    for (_k, v) in mcommit.iter() {
+        if !ccommitted.values().any(|c| c.txid == v.txid) {
+            violations.push(Violation::UnilateralCommit { participant: participant.clone(), txid: v.txid });
+        }
+    }
+
+    // an abandoned txid is one the participant stopped waiting on at 
+    // shut down. it never learned the decision, so it is allowed to be
+    // missing a commit/abort record, but it must have voted on it.
+    for (_k, v) in mabandon.iter() {
+        let txid = v.txid;
+        let voted = plog.values()
+            .filter(|e| e.txid == txid)
+            .any(|e| e.mtype == MessageType::ParticipantVoteCommit || 
+                     e.mtype == MessageType::ParticipantVoteAbort);
+        if !voted {
+            violations.push(Violation::AbandonedWithoutVote { participant: participant.clone(), txid });
+        }
+    }
+    ParticipantCounts {
+        committed: mcommit.len(),
+        aborted: npabort + npresumed,
+        abandoned: mabandon.len(),
+        presumed: npresumed,
     }
-    println!("{} OK: C:{} == {}(C-global), A:{} <= {}(A-global)",
-             participant.clone(),
-             npcommit,
-             ncommit,
-             npabort,
-             nabort);
-    result
 }
 
 ///
@@ -96,19 +275,30 @@ fn check_participant(
 /// 
 /// accepts a directory where client, participant, and coordinator log files
 /// are found, and the number of clients, participants. Loads and analyses 
-/// log files to check a handful of correctness invariants. 
+/// log files to check a handful of correctness invariants. returns a
+/// report of what the logs say if they hold, and every violation (with
+/// the report) if they don't.
 ///
 /// <params>
 ///     n_clients: number of clients
 ///     n_requests: number of requests per client
 ///     n_participants: number of participants
 ///     logpathbase: directory for client, participant, and coordinator logs 
+///     liveness: also check that every proposed transaction terminated,
+///         and that every commit was acked by the participants
+///     replicated: the run had a backup coordinator (--replicate): check
+///         against its log, and that failover lost or changed no decision
+///     kv_store: participants kept key-value stores (--kv-store): check
+///         they hold exactly what the coordinator's commits wrote
 ///
 pub fn check_last_run(
     n_clients: i32,
     n_requests: i32, 
     n_participants: i32, 
-    logpathbase: &String) {
+    logpathbase: &String,
+    liveness: bool,
+    replicated: bool,
+    kv_store: bool) -> Result<CheckReport, CheckError> {
 
         info!("Checking 2PC run:  {} requests * {} clients, {} participants", 
               n_requests, 
@@ -119,29 +309,1029 @@ pub fn check_last_run(
         for pid in 0..n_participants {
              let pid_str = format!("participant_{}", pid);
              let plogpath = format!("{}{}.log", logpathbase, pid_str);
-             let plog = OpLog::from_file(plogpath);
+             readable(&plogpath)?;
+             let plog = OpLog::try_from_file(plogpath)?;
              logs.insert(pid_str, plog);
         }
-        let clogpath = format!("{}{}", logpathbase, "coordinator.log");
-        let clog = OpLog::from_file(clogpath);        
+        let clogpath = format!("{}{}", logpathbase, coordinator_log(replicated));
+        readable(&clogpath)?;
+        let clog = OpLog::try_from_file(clogpath.clone())?;
 
         let lck = clog.arc();
         let cmap = lck.lock().unwrap();
+
+        let mut violations = vec![];
+        // every record is what was written (the rest skip any that aren't)
+        violations.extend(check_integrity(n_participants, logpathbase, replicated)?.into_iter()
+            .map(|(log, record)| Violation::Corrupt { log, record }));
         let committed: HashMap<i32, message::ProtocolMessage> = 
-            cmap.iter().filter(|e| (*e.1).mtype == MessageType::CoordinatorCommit)
-                       .map(|(k,v)| (k.clone(), v.clone()))
+            coordinator_decisions(&cmap).into_iter().filter(|e| e.1.mtype == MessageType::CoordinatorCommit)
                        .collect();
         let aborted: HashMap<i32, message::ProtocolMessage> = 
             cmap.iter().filter(|e| (*e.1).mtype == MessageType::CoordinatorAbort)
                        .map(|(k,v)| (k.clone(), v.clone()))
                        .collect();
+        // under --presumed-abort an abort is only logged as the client's result
+        let presumed = cmap.values()
+            .filter(|e| e.mtype == MessageType::ClientResultAbort)
+            .filter(|e| !aborted.values().any(|a| a.txid == e.txid))
+            .count();
         let ncommit = committed.len();
-        let nabort = aborted.len();
+        let nabort = aborted.len() + presumed;
 
+        let mut participants = BTreeMap::new();
         for(p, v) in logs.iter() {
             let plck = v.arc();
             let plog = plck.lock().unwrap();
-            check_participant(p, ncommit, nabort, &committed, &plog);
+            participants.insert(p.clone(), check_participant(p, nabort, &committed, &plog, &mut violations));
+            // every participant commits what the coordinator committed
+            let cdecisions = coordinator_decisions(&cmap);
+            let ptxids = records_by_txid(&format!("{}{}.log", logpathbase, p), &cdecisions, &plog)?;
+            for pm in check_atomicity(&cdecisions, &ptxids) {
+                let mut logged: Vec<&ProtocolMessage> = ptxids.get(&pm.txid).into_iter().flatten()
+                    .filter(|r| r.mtype != MessageType::ParticipantTransition)
+                    .collect();
+                logged.sort_by_key(|r| r.uid);
+                violations.push(Violation::NotAtomic {
+                    participant: p.clone(),
+                    txid: pm.txid,
+                    logged: logged.iter().map(|r| r.mtype).collect(),
+                });
+            }
+            // no participant commits ahead of the commit point
+            violations.extend(check_commit_point(&cmap, &plog).into_iter()
+                .map(|pm| Violation::CommitBeforeCommitPoint { participant: p.clone(), txid: pm.txid }));
+            // votes happen before commit decisions, decisions before participants log them
+            violations.extend(check_causality(&coordinator_decisions(&cmap), &plog).into_iter()
+                .map(|pm| Violation::OutOfCausalOrder { participant: p.clone(), txid: pm.txid, mtype: pm.mtype }));
+            // every participant moves through its state machine one legal step at a time
+            violations.extend(check_transitions(&plog).into_iter()
+                .map(|pm| Violation::IllegalTransition { participant: p.clone(), txid: pm.txid, transition: pm.transition }));
+        }
+
+        if presumed > 0 {
+            println!("coordinator presumed {} abort(s), logging only the client's result", presumed);
         }
+        let undelivered = cmap.values().filter(|e| e.mtype == MessageType::CoordinatorAbandon).count();
+        if undelivered > 0 {
+            println!("coordinator abandoned {} decision(s) undelivered when its shutdown drain timed out", undelivered);
+        }
+        let scavenged = cmap.values().filter(|e| e.mtype == MessageType::CoordinatorScavenge).count();
+        if scavenged > 0 {
+            println!("coordinator scavenged {} txid(s) that outlived --max-txn-lifetime-ms", scavenged);
+        }
+        let under_replicated = cmap.values().filter(|e| e.mtype == MessageType::InsufficientReplicas).count();
+        if under_replicated > 0 {
+            println!("coordinator aborted {} txid(s) for having fewer than --min-live-participants live", under_replicated);
+        }
+        let stragglers: Vec<&str> = cmap.values()
+            .filter(|e| e.mtype == MessageType::ExitAckTimeout)
+            .map(|e| e.senderid.as_str())
+            .collect();
+        if !stragglers.is_empty() {
+            println!("coordinator timed out waiting for exit acks from {}", stragglers.join(", "));
+        }
+
+        let plogs: HashMap<String, HashMap<i32, ProtocolMessage>> = logs.iter()
+            .map(|(p, v)| (p.clone(), v.arc().lock().unwrap().clone()))
+            .collect();
+        // commits go exactly as far as every participant agreed
+        violations.extend(check_savepoints(&coordinator_decisions(&cmap), &plogs).into_iter()
+            .map(|(p, pm)| Violation::SavepointMismatch { participant: p, txid: pm.txid, segment: pm.segment }));
+        // every transaction is proposed, voted on and decided as a whole
+        let requested = requested_ops(&cmap);
+        violations.extend(check_units(&cmap, &plogs).into_iter()
+            .map(|(log, pm)| Violation::SplitTransaction {
+                log,
+                txid: pm.txid,
+                mtype: pm.mtype,
+                requested: requested.get(&pm.txid).cloned().unwrap_or_default(),
+                ops: pm.ops,
+            }));
+
+        // commits are numbered 1, 2, 3, ... in the order they were made
+        let out_of_sequence = check_sequence(cmap.values().cloned());
+        let sequenced = cmap.values().filter(|e| e.mtype == MessageType::CoordinatorCommit && e.seqno != 0).count();
+        if sequenced > 0 && out_of_sequence.is_empty() {
+            println!("commit sequence: seqno 1..{}, gap-free", sequenced);
+        }
+        violations.extend(out_of_sequence.into_iter()
+            .map(|(pm, expected)| Violation::OutOfSequence { txid: pm.txid, seqno: pm.seqno, expected }));
+
+        if replicated {
+            // the backup has every decision the primary made, unchanged
+            violations.extend(check_failover(logpathbase, &None)?.into_iter()
+                .map(|(log, pm)| Violation::LostInFailover { log, txid: pm.txid, mtype: pm.mtype }));
+        }
+
+        if kv_store {
+            // every participant holds exactly the committed writes
+            violations.extend(check_state(n_participants, logpathbase, &cmap).into_iter().map(Violation::StoreDiverged));
+        }
+
+        if liveness {
+            // every proposed transaction terminates
+            let stuck = check_liveness(&cmap, &plogs);
+            println!("liveness: {} stuck", stuck.len());
+            violations.extend(stuck.into_iter()
+                .map(|(p, pm)| Violation::NeverTerminated { participant: p, txid: pm.txid }));
+            // and phase 2 reached every participant
+            violations.extend(check_decision_acks(&cmap, &plogs).into_iter()
+                .map(|(p, pm)| Violation::CommitUnacked { participant: p, txid: pm.txid }));
+        }
+
+        let report = CheckReport {
+            committed: ncommit,
+            aborted: nabort,
+            participants,
+            outcomes: summary::transactions(&clogpath),
+        };
+        if violations.is_empty() {
+            Ok(report)
+        } else {
+            Err(CheckError::Violations(report, violations))
+        }
+}
+
+
+
+///
+/// check_integrity()
+///
+/// every record that fails its checksum (--log-checksums) in the logs 
+/// a check reads, with the log file it's in. the readers skip them, so
+/// the other checks never see them: they're reported here instead.
+///
+fn check_integrity(n_participants: i32, logpathbase: &str, replicated: bool) -> Result<Vec<(String, CorruptRecord)>, CheckError> {
+    let mut paths: Vec<String> = (0..n_participants)
+        .map(|pid| format!("{}participant_{}.log", logpathbase, pid))
+        .collect();
+    paths.push(format!("{}{}", logpathbase, coordinator_log(replicated)));
+    let mut corrupt = vec![];
+    for path in paths.iter() {
+        corrupt.extend(OpLog::verify(path)?);
+    }
+    Ok(corrupt)
+}
+
+///
+/// readable()
+/// 
+/// whether the log at path can be opened: if it can't, the CheckError
+/// that says why (MissingLog if it isn't there at all)
+///
+fn readable(path: &str) -> Result<(), CheckError> {
+    match fs::File::open(path) {
+        Ok(_file) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(CheckError::MissingLog(path.to_string())),
+        Err(err) => Err(CheckError::Io(path.to_string(), err)),
+    }
 }
 
+///
+/// load_log()
+/// 
+/// the records of the log at path, keyed by uid: all of them, or only
+/// those for txids in the given window, streamed so nothing else is
+/// held in memory. a window of a log with a sidecar index (--log-index)
+/// is read by seeking to its txids' records, not by scanning the log.
+///
+fn load_log(path: &str, txids: &Option<Range<i32>>) -> Result<HashMap<i32, ProtocolMessage>, CheckError> {
+    readable(path)?;
+    let mut records = HashMap::new();
+    if let (Some(window), true) = (txids, OpLog::has_index(path)) {
+        let index = OpLog::load_index(path)?;
+        for txid in index.keys().filter(|txid| window.contains(txid)) {
+            records.extend(OpLog::read_txid(path, &index, *txid)?.into_iter().map(|pm| (pm.uid, pm)));
+        }
+        return Ok(records);
+    }
+    for pm in OpLog::records(path) {
+        let pm = pm?;
+        if txids.as_ref().is_none_or(|window| window.contains(&pm.txid)) {
+            records.insert(pm.uid, pm);
+        }
+    }
+    Ok(records)
+}
+
+/// the coordinator's records, and each participant's by name
+type RunLogs = (HashMap<i32, ProtocolMessage>, HashMap<String, HashMap<i32, ProtocolMessage>>);
+
+///
+/// load_logs()
+/// 
+/// read the coordinator log and every participant log under logpathbase
+/// (only the records for txids in the window, if given), returning the
+/// coordinator's records and a map from participant name to that 
+/// participant's records.
+///
+fn load_logs(
+    n_participants: i32, 
+    logpathbase: &str,
+    replicated: bool,
+    txids: &Option<Range<i32>>
+    ) -> Result<RunLogs, CheckError> {
+
+    let mut plogs = HashMap::new();
+    for pid in 0..n_participants {
+        let pid_str = format!("participant_{}", pid);
+        let plogpath = format!("{}{}.log", logpathbase, pid_str);
+        plogs.insert(pid_str, load_log(&plogpath, txids)?);
+    }
+    let clogpath = format!("{}{}", logpathbase, coordinator_log(replicated));
+    let cmap = load_log(&clogpath, txids)?;
+    Ok((cmap, plogs))
+}
+
+///
+/// log_paths()
+/// 
+/// every log a check of the run under logpathbase reads: each 
+/// participant's, and the coordinator's (both of them, with a backup)
+///
+fn log_paths(n_participants: i32, logpathbase: &str, replicated: bool) -> Vec<String> {
+    let mut paths: Vec<String> = (0..n_participants)
+        .map(|pid| format!("{}participant_{}.log", logpathbase, pid))
+        .collect();
+    paths.push(format!("{}{}", logpathbase, coordinator_log(replicated)));
+    if replicated {
+        paths.push(format!("{}{}", logpathbase, coordinator_log(false)));
+    }
+    paths
+}
+
+///
+/// txid_windows()
+/// 
+/// split the txids logged under logpathbase into consecutive windows of
+/// size txids each, for checking one window at a time. streams every 
+/// log once to find the lowest and highest txid (or, for a log with a
+/// sidecar index, reads them off the index).
+///
+fn txid_windows(
+    n_participants: i32, 
+    logpathbase: &str,
+    replicated: bool,
+    size: i32
+    ) -> Result<Vec<Range<i32>>, CheckError> {
+
+    let (mut lowest, mut highest) = (i32::MAX, i32::MIN);
+    for path in log_paths(n_participants, logpathbase, replicated).iter() {
+        let txids: Box<dyn Iterator<Item = Result<i32, ReadError>>> = if OpLog::has_index(path) {
+            Box::new(OpLog::load_index(path)?.into_keys().map(Ok))
+        } else {
+            Box::new(OpLog::records(path).map(|pm| pm.map(|pm| pm.txid)))
+        };
+        for txid in txids {
+            let txid = txid?;
+            lowest = lowest.min(txid);
+            highest = highest.max(txid);
+        }
+    }
+    if lowest > highest {
+        return Ok(vec![]);
+    }
+    Ok((lowest as i64..=highest as i64).step_by(size as usize)
+        .map(|start| start as i32..(start + size as i64).min(i32::MAX as i64) as i32)
+        .collect())
+}
+
+///
+/// coordinator_log()
+/// 
+/// the log with the coordinator's whole history: with a backup 
+/// coordinator (--replicate), the backup's, which has everything the 
+/// primary logged plus whatever it did after taking over
+///
+pub fn coordinator_log(replicated: bool) -> &'static str {
+    if replicated {
+        "coordinator_backup.log"
+    } else {
+        "coordinator.log"
+    }
+}
+
+///
+/// check_failover()
+/// 
+/// EXPERIMENTAL (--replicate): every decision in the primary's log must
+/// be in the backup's, unchanged, and the backup (which carried on after
+/// a failover) must never have decided a txid twice. returns (log, 
+/// record) for decisions that were lost, changed, or reversed. with a 
+/// window, only its txids are checked.
+///
+fn check_failover(logpathbase: &str, txids: &Option<Range<i32>>) -> Result<Vec<(String, ProtocolMessage)>, CheckError> {
+    let primary = load_log(&format!("{}{}", logpathbase, coordinator_log(false)), txids)?;
+    let backup = load_log(&format!("{}{}", logpathbase, coordinator_log(true)), txids)?;
+    let mut bad = vec![];
+    let mut backup_decisions: HashMap<i32, Vec<&ProtocolMessage>> = HashMap::new();
+    for pm in backup.values() {
+        if pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort {
+            backup_decisions.entry(pm.txid).or_default().push(pm);
+        }
+    }
+    for (txid, pm) in decisions(&primary) {
+        let kept = backup_decisions.get(&txid).into_iter().flatten().any(|b| (b.mtype, b.segment) == (pm.mtype, pm.segment));
+        if !kept {
+            bad.push((String::from(coordinator_log(false)), pm));
+        }
+    }
+    for decided in backup_decisions.values().filter(|d| d.len() > 1) {
+        bad.extend(decided.iter().map(|pm| (String::from(coordinator_log(true)), (*pm).clone())));
+    }
+    Ok(bad)
+}
+
+///
+/// check_state()
+/// 
+/// --kv-store: every participant's saved store must hold exactly what
+/// replaying the coordinator's commits gives: no write from an aborted
+/// transaction, none missing from a committed one, and none past the 
+/// agreed savepoint. returns a description of every key that differs
+/// (and of every store that couldn't be read).
+///
+fn check_state(n_participants: i32, logpathbase: &str, cmap: &HashMap<i32, ProtocolMessage>) -> Vec<String> {
+    let mut bad = vec![];
+    for pid in 0..n_participants {
+        let p = format!("participant_{}", pid);
+        let path = kvstore::state_path(logpathbase, &p);
+        let state = match kvstore::load(&path) {
+            Ok(state) => state,
+            Err(err) => {
+                bad.push(format!("{} has no readable store at {}: {}", p, path, err));
+                continue;
+            },
+        };
+        let expected = kvstore::replay(cmap, state.keys, state.segments);
+        for key in expected.keys().chain(state.data.keys()).collect::<std::collections::BTreeSet<_>>() {
+            let (want, have) = (expected.get(key), state.data.get(key));
+            if want != have {
+                bad.push(format!("{} holds {}={:?}, the coordinator's commits say {:?}", p, key, have, want));
+            }
+        }
+    }
+    bad
+}
+
+///
+/// decisions()
+/// 
+/// the CoordinatorCommit/CoordinatorAbort records in a log, keyed by txid
+/// (and a participant's PresumedAbort records, under --presumed-abort)
+///
+fn decisions(log: &HashMap<i32, ProtocolMessage>) -> HashMap<i32, ProtocolMessage> {
+    log.values()
+        .filter(|e| e.mtype == MessageType::CoordinatorCommit || e.mtype == MessageType::CoordinatorAbort || 
+                    e.mtype == MessageType::PresumedAbort)
+        .map(|e| (e.txid, e.clone()))
+        .collect()
+}
+
+///
+/// coordinator_decisions()
+/// 
+/// decisions() for the coordinator's log. under --protocol 3pc, a 
+/// txid's commit stands from its CoordinatorPreCommit on: a participant
+/// that pre-committed may commit on its own without hearing the commit,
+/// even before it's logged. so a pre-commit stands in for the commit 
+/// (the checks compare participants' commits with it), whether or not
+/// the coordinator got as far as logging the commit.
+///
+fn coordinator_decisions(cmap: &HashMap<i32, ProtocolMessage>) -> HashMap<i32, ProtocolMessage> {
+    let mut cdecisions = decisions(cmap);
+    for pm in cmap.values().filter(|e| e.mtype == MessageType::CoordinatorPreCommit) {
+        cdecisions.insert(pm.txid, ProtocolMessage { mtype: MessageType::CoordinatorCommit, ..pm.clone() });
+    }
+    cdecisions
+}
+
+///
+/// records_by_txid()
+/// 
+/// a participant's records for each txid the coordinator decided. when
+/// the participant's log has a sidecar index the records are read by 
+/// seeking straight to them, otherwise they are grouped from plog.
+///
+fn records_by_txid(
+    plogpath: &str,
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    plog: &HashMap<i32, ProtocolMessage>
+    ) -> Result<HashMap<i32, Vec<ProtocolMessage>>, CheckError> {
+
+    let mut grouped: HashMap<i32, Vec<ProtocolMessage>> = HashMap::new();
+    if OpLog::has_index(plogpath) {
+        let index = OpLog::load_index(plogpath)?;
+        for txid in cdecisions.keys() {
+            grouped.insert(*txid, OpLog::read_txid(plogpath, &index, *txid)?);
+        }
+    } else {
+        for pm in plog.values() {
+            grouped.entry(pm.txid).or_default().push(pm.clone());
+        }
+    }
+    Ok(grouped)
+}
+
+///
+/// check_agreement()
+/// 
+/// every decision a participant logged must match the coordinator's 
+/// decision for the same txid (including, with savepoints on, how many
+/// segments a commit covers). a presumed abort agrees with anything but
+/// a commit: with no abort logged, the coordinator may not have decided
+/// at all. returns the offending participant records.
+///
+fn check_agreement(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    plog: &HashMap<i32, ProtocolMessage>
+    ) -> Vec<ProtocolMessage> {
+
+    decisions(plog).into_iter()
+        .filter(|(txid, pm)| match pm.mtype {
+            MessageType::PresumedAbort => cdecisions.get(txid).is_some_and(|c| c.mtype == MessageType::CoordinatorCommit),
+            _ => cdecisions.get(txid).map(|c| (c.mtype, c.segment)) != Some((pm.mtype, pm.segment)),
+        })
+        .map(|(_txid, pm)| pm)
+        .collect()
+}
+
+///
+/// check_durability()
+/// 
+/// every txid the coordinator committed must have a commit vote in 
+/// the participant's log. returns the offending coordinator records.
+///
+fn check_durability(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    ptxids: &HashMap<i32, Vec<ProtocolMessage>>
+    ) -> Vec<ProtocolMessage> {
+
+    cdecisions.values()
+        .filter(|c| c.mtype == MessageType::CoordinatorCommit)
+        .filter(|c| !ptxids.get(&c.txid).into_iter().flatten().any(|p| p.mtype == MessageType::ParticipantVoteCommit))
+        .cloned()
+        .collect()
+}
+
+///
+/// check_atomicity()
+/// 
+/// every txid the coordinator committed must have been committed by 
+/// the participant too: its log must hold a commit vote, no abort vote,
+/// and the coordinator's commit (or, if it gave up waiting at shut 
+/// down, an abandon). a read-only commit vote (--read-only-ratio) 
+/// needs neither: there was nothing for the participant to commit.
+/// returns the offending coordinator records.
+///
+fn check_atomicity(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    ptxids: &HashMap<i32, Vec<ProtocolMessage>>
+    ) -> Vec<ProtocolMessage> {
+
+    cdecisions.values()
+        .filter(|c| c.mtype == MessageType::CoordinatorCommit)
+        .filter(|c| {
+            let logged: Vec<MessageType> = ptxids.get(&c.txid).into_iter().flatten().map(|p| p.mtype).collect();
+            let read_only = ptxids.get(&c.txid).into_iter().flatten()
+                .any(|p| p.mtype == MessageType::ParticipantVoteCommit && p.read_only);
+            !(logged.contains(&MessageType::ParticipantVoteCommit) &&
+              !logged.contains(&MessageType::ParticipantVoteAbort) &&
+              (read_only || logged.contains(&MessageType::CoordinatorCommit) || logged.contains(&MessageType::ParticipantAbandon)))
+        })
+        .cloned()
+        .collect()
+}
+
+///
+/// happened_before()
+/// 
+/// whether record a was generated before record b, which may be in 
+/// another log: by timestamp, or for records logged before messages 
+/// carried one, by uid (uids come from one process-wide counter, so 
+/// they order records across files too)
+///
+fn happened_before(a: &ProtocolMessage, b: &ProtocolMessage) -> bool {
+    if a.timestamp > 0 && b.timestamp > 0 {
+        (a.timestamp, a.uid) < (b.timestamp, b.uid)
+    } else {
+        a.uid < b.uid
+    }
+}
+
+///
+/// check_ordering()
+/// 
+/// a participant must log its vote before the coordinator logs the 
+/// decision, and log the decision only after the coordinator did (a 
+/// presumed abort follows no decision, so it isn't checked). see 
+/// happened_before() for how records in different logs are ordered.
+/// returns the offending participant decision records.
+///
+fn check_ordering(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    plog: &HashMap<i32, ProtocolMessage>,
+    ptxids: &HashMap<i32, Vec<ProtocolMessage>>
+    ) -> Vec<ProtocolMessage> {
+
+    decisions(plog).into_iter()
+        .filter(|(_txid, pm)| pm.mtype != MessageType::PresumedAbort)
+        .filter(|(txid, pm)| {
+            let after_coordinator = cdecisions.get(txid).is_some_and(|c| happened_before(c, pm));
+            let after_vote = ptxids.get(txid).into_iter().flatten().any(|v| happened_before(v, pm) && 
+                (v.mtype == MessageType::ParticipantVoteCommit || v.mtype == MessageType::ParticipantVoteAbort));
+            !(after_coordinator && after_vote)
+        })
+        .map(|(_txid, pm)| pm)
+        .collect()
+}
+
+///
+/// check_causality()
+/// 
+/// with vector clocks (--vector-clocks), the happens-before version of
+/// check_ordering: the coordinator's decision must happen before the
+/// participant logs it, and for a commit, the participant's commit vote
+/// must happen before the decision. records without a clock are not 
+/// checked. returns the offending participant records.
+///
+fn check_causality(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    plog: &HashMap<i32, ProtocolMessage>
+    ) -> Vec<ProtocolMessage> {
+
+    plog.values()
+        .filter(|p| !p.clock.is_empty())
+        .filter(|p| {
+            let c = match cdecisions.get(&p.txid) {
+                Some(c) if !c.clock.is_empty() => c,
+                _ => return false,
+            };
+            match p.mtype {
+                MessageType::CoordinatorCommit | MessageType::CoordinatorAbort => 
+                    !vclock::happens_before(&c.clock, &p.clock),
+                MessageType::ParticipantVoteCommit if c.mtype == MessageType::CoordinatorCommit => 
+                    !vclock::happens_before(&p.clock, &c.clock),
+                _ => false,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+///
+/// check_transitions()
+/// 
+/// a participant's logged state transitions for each txid must start 
+/// from Quiescent, each pick up where the last one left off, and each be
+/// a move ParticipantState::allows() (no going from Quiescent straight
+/// to Decided, say). returns the offending transition records.
+///
+fn check_transitions(plog: &HashMap<i32, ProtocolMessage>) -> Vec<ProtocolMessage> {
+
+    let mut by_txid: HashMap<i32, Vec<&ProtocolMessage>> = HashMap::new();
+    for pm in plog.values().filter(|pm| pm.mtype == MessageType::ParticipantTransition) {
+        by_txid.entry(pm.txid).or_default().push(pm);
+    }
+    let mut bad = vec![];
+    for moves in by_txid.values_mut() {
+        moves.sort_by_key(|pm| pm.uid);
+        let mut state = ParticipantState::Quiescent;
+        for pm in moves.iter() {
+            match pm.transition {
+                Some((from, to)) if from == state && from.allows(to) => state = to,
+                _ => {
+                    bad.push((*pm).clone());
+                    break;
+                },
+            }
+        }
+    }
+    bad
+}
+
+///
+/// check_exactly_once()
+/// 
+/// a participant votes at most once and learns at most one decision per
+/// txid. returns the records of any txid seen more than once.
+///
+fn check_exactly_once(plog: &HashMap<i32, ProtocolMessage>) -> Vec<ProtocolMessage> {
+
+    let mut votes: HashMap<i32, Vec<&ProtocolMessage>> = HashMap::new();
+    let mut decided: HashMap<i32, Vec<&ProtocolMessage>> = HashMap::new();
+    for pm in plog.values() {
+        match pm.mtype {
+            MessageType::ParticipantVoteCommit | MessageType::ParticipantVoteAbort => 
+                votes.entry(pm.txid).or_default().push(pm),
+            MessageType::CoordinatorCommit | MessageType::CoordinatorAbort | MessageType::PresumedAbort => 
+                decided.entry(pm.txid).or_default().push(pm),
+            _ => {},
+        }
+    }
+    votes.values().chain(decided.values())
+        .filter(|v| v.len() > 1)
+        .flat_map(|v| v.iter().map(|pm| (*pm).clone()))
+        .collect()
+}
+
+///
+/// check_commit_point()
+/// 
+/// every commit record a participant logged must come after (see 
+/// happened_before()) the coordinator's CommitPoint for that txid, or
+/// under --protocol 3pc, its CoordinatorPreCommit (see 
+/// coordinator_decisions()). returns the offending participant commit
+/// records.
+///
+fn check_commit_point(
+    cmap: &HashMap<i32, ProtocolMessage>,
+    plog: &HashMap<i32, ProtocolMessage>
+    ) -> Vec<ProtocolMessage> {
+
+    let mut commit_points: HashMap<i32, &ProtocolMessage> = cmap.values()
+        .filter(|e| e.mtype == MessageType::CommitPoint)
+        .map(|e| (e.txid, e))
+        .collect();
+    commit_points.extend(cmap.values()
+        .filter(|e| e.mtype == MessageType::CoordinatorPreCommit)
+        .map(|e| (e.txid, e)));
+    plog.values()
+        .filter(|e| e.mtype == MessageType::CoordinatorCommit)
+        .filter(|e| commit_points.get(&e.txid).is_none_or(|point| !happened_before(point, e)))
+        .cloned()
+        .collect()
+}
+
+///
+/// check_savepoints()
+/// 
+/// EXPERIMENTAL (--segments): a commit through savepoint k is only safe
+/// if every participant voted to commit through at least k, and should 
+/// go exactly as far as the last savepoint all of them reached. returns
+/// (log, record) for commit votes that fall short of the coordinator's 
+/// commit, and coordinator commits that stop short of (or go past) the
+/// last agreed savepoint.
+///
+fn check_savepoints(
+    cdecisions: &HashMap<i32, ProtocolMessage>,
+    plogs: &HashMap<String, HashMap<i32, ProtocolMessage>>
+    ) -> Vec<(String, ProtocolMessage)> {
+
+    let mut bad = vec![];
+    for c in cdecisions.values().filter(|c| c.mtype == MessageType::CoordinatorCommit) {
+        let mut agreed = None;
+        for (p, plog) in plogs.iter() {
+            for v in plog.values().filter(|v| v.txid == c.txid && v.mtype == MessageType::ParticipantVoteCommit) {
+                if v.segment < c.segment {
+                    bad.push((p.clone(), v.clone()));
+                }
+                agreed = Some(agreed.map_or(v.segment, |a: i32| a.min(v.segment)));
+            }
+        }
+        if agreed.is_some_and(|a| a != c.segment) {
+            bad.push((String::from("coordinator"), c.clone()));
+        }
+    }
+    bad
+}
+
+///
+/// requested_ops()
+/// 
+/// the operations each txid's client request bundled (--ops-per-txn),
+/// by txid
+///
+fn requested_ops(cmap: &HashMap<i32, ProtocolMessage>) -> HashMap<i32, Vec<i32>> {
+    cmap.values()
+        .filter(|e| e.mtype == MessageType::ClientRequest)
+        .map(|e| (e.txid, e.ops.clone()))
+        .collect()
+}
+
+///
+/// check_units()
+/// 
+/// with --ops-per-txn a transaction's operations stand or fall 
+/// together: every proposal, vote and decision for a txid, in any log,
+/// must cover exactly the operations its client request bundled, so no
+/// one can vote on or commit part of one. returns (log, record) for 
+/// every record that covers some other set.
+///
+fn check_units(
+    cmap: &HashMap<i32, ProtocolMessage>,
+    plogs: &HashMap<String, HashMap<i32, ProtocolMessage>>
+    ) -> Vec<(String, ProtocolMessage)> {
+
+    let requested = requested_ops(cmap);
+    let logs = std::iter::once((String::from("coordinator"), cmap)).chain(plogs.iter().map(|(p, plog)| (p.clone(), plog)));
+    let mut bad = vec![];
+    for (log, records) in logs {
+        bad.extend(records.values()
+            .filter(|e| matches!(e.mtype, MessageType::CoordinatorPropose | MessageType::ParticipantVoteCommit |
+                                          MessageType::ParticipantVoteAbort | MessageType::CoordinatorPreCommit |
+                                          MessageType::CoordinatorCommit | MessageType::CoordinatorAbort))
+            .filter(|e| requested.get(&e.txid).is_some_and(|ops| *ops != e.ops))
+            .map(|e| (log.clone(), e.clone())));
+    }
+    bad
+}
+
+///
+/// check_liveness()
+/// 
+/// every proposed txid must terminate. a participant that logged the
+/// proposal must also have logged a decision for it (or presumed one), 
+/// or abandoned it, or have voted on it read-only (which ends it).
+/// a txid the coordinator never decided (e.g. it halted) has still 
+/// terminated, as unknown, once every participant let go of it. 
+/// returns (log, propose record) for every txid that was left hanging.
+///
+fn check_liveness(
+    cmap: &HashMap<i32, ProtocolMessage>,
+    plogs: &HashMap<String, HashMap<i32, ProtocolMessage>>
+    ) -> Vec<(String, ProtocolMessage)> {
+
+    let mut stuck = vec![];
+    for (p, plog) in plogs.iter() {
+        let finished: HashSet<i32> = plog.values()
+            .filter(|e| e.mtype == MessageType::CoordinatorCommit || 
+                        e.mtype == MessageType::CoordinatorAbort ||
+                        e.mtype == MessageType::PresumedAbort ||
+                        e.mtype == MessageType::ParticipantAbandon ||
+                        (e.mtype == MessageType::ParticipantVoteCommit && e.read_only))
+            .map(|e| e.txid)
+            .collect();
+        stuck.extend(plog.values()
+            .filter(|e| e.mtype == MessageType::CoordinatorPropose && !finished.contains(&e.txid))
+            .map(|e| (p.clone(), e.clone())));
+    }
+    let held: HashSet<i32> = stuck.iter().map(|(_p, pm)| pm.txid).collect();
+    let cdecisions = coordinator_decisions(cmap);
+    stuck.extend(cmap.values()
+        .filter(|e| e.mtype == MessageType::CoordinatorPropose)
+        .filter(|e| !cdecisions.contains_key(&e.txid) && held.contains(&e.txid))
+        .map(|e| (String::from("coordinator"), e.clone())));
+    stuck
+}
+
+///
+/// check_decision_acks()
+/// 
+/// phase 2 must complete: every txid the coordinator committed must 
+/// have been acked by every participant that got its proposal (the 
+/// coordinator logs each ParticipantDecisionAck it gets), except one
+/// that voted read-only, which is sent no decision. returns 
+/// (participant, commit record) for every ack that's missing.
+///
+fn check_decision_acks(
+    cmap: &HashMap<i32, ProtocolMessage>,
+    plogs: &HashMap<String, HashMap<i32, ProtocolMessage>>
+    ) -> Vec<(String, ProtocolMessage)> {
+
+    let acked: HashSet<(&str, i32)> = cmap.values()
+        .filter(|e| e.mtype == MessageType::ParticipantDecisionAck)
+        .map(|e| (e.senderid.as_str(), e.txid))
+        .collect();
+    let mut missing = vec![];
+    for (p, plog) in plogs.iter() {
+        let proposed: HashSet<i32> = plog.values()
+            .filter(|e| e.mtype == MessageType::CoordinatorPropose)
+            .map(|e| e.txid)
+            .collect();
+        let read_only: HashSet<i32> = plog.values()
+            .filter(|e| e.mtype == MessageType::ParticipantVoteCommit && e.read_only)
+            .map(|e| e.txid)
+            .collect();
+        missing.extend(decisions(cmap).into_values()
+            .filter(|c| c.mtype == MessageType::CoordinatorCommit)
+            .filter(|c| proposed.contains(&c.txid) && !read_only.contains(&c.txid) && !acked.contains(&(p.as_str(), c.txid)))
+            .map(|c| (p.clone(), c)));
+    }
+    missing
+}
+
+///
+/// check_sequence()
+/// 
+/// the coordinator's commits, in the order it logged them, should carry
+/// seqnos 1, 2, 3, ... with no gaps (see ProtocolMessage::seqno). 
+/// commits without one (logged before commits carried one) are skipped.
+/// returns (commit, the seqno it should have had) for each one out of
+/// sequence. takes the records as an iterator so quiet mode can stream
+/// them rather than load the log.
+///
+fn check_sequence(records: impl Iterator<Item = ProtocolMessage>) -> Vec<(ProtocolMessage, i32)> {
+    let mut commits: Vec<ProtocolMessage> = records
+        .filter(|e| e.mtype == MessageType::CoordinatorCommit && e.seqno != 0)
+        .collect();
+    commits.sort_by_key(|e| e.uid);
+    let mut expected = 1;
+    let mut bad = vec![];
+    for pm in commits.into_iter() {
+        let next = pm.seqno + 1;
+        if pm.seqno != expected {
+            bad.push((pm, expected));
+        }
+        expected = next;
+    }
+    bad
+}
+
+///
+/// compare_golden()
+/// 
+/// compare the coordinator's logged decisions with what the golden model
+/// (see golden.rs) decides for the same seed and options. txids the model
+/// says had a failure injected, and txids the coordinator never decided,
+/// are skipped. prints a summary line and every mismatch, and returns
+/// true if there were none, or the CheckError if a log couldn't be read
+/// or the options are ones the model can't replay.
+///
+/// <params>
+///     opts: the options the run used, including its --seed
+///
+pub fn compare_golden(opts: &TPCOptions) -> Result<bool, CheckError> {
+
+    let seed = match opts.seed {
+        Some(seed) => seed,
+        None => return Err(CheckError::Unsupported(String::from("comparing against the golden model needs the run's --seed"))),
+    };
+    if opts.correlated_failure_prob > 0.0 || opts.fault_schedule.is_some() {
+        return Err(CheckError::Unsupported(String::from("the golden model doesn't model correlated faults or fault schedules")));
+    }
+
+    let (cmap, plogs) = load_logs(opts.num_participants, &opts.logpath, opts.replicate, &None)?;
+    let mut proposals: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (p, plog) in plogs.iter() {
+        let id = p["participant_".len()..].parse::<i32>().unwrap();
+        let mut proposed: Vec<&ProtocolMessage> = plog.values()
+            .filter(|e| e.mtype == MessageType::CoordinatorPropose)
+            .collect();
+        proposed.sort_by_key(|e| e.uid);
+        proposals.insert(id, proposed.iter().map(|e| e.txid).collect());
+    }
+    let model = golden::outcomes(opts, seed, &proposals);
+
+    let cdecisions = decisions(&cmap);
+    let mut matched = 0;
+    let mut skipped = 0;
+    let mut mismatched = vec![];
+    for (txid, outcome) in model.iter() {
+        let logged = match (outcome, cdecisions.get(txid)) {
+            (Outcome::Failed, _) | (_, None) => {
+                skipped += 1;
+                continue;
+            },
+            (_, Some(c)) if c.mtype == MessageType::CoordinatorCommit => Outcome::Commit(c.segment),
+            (_, Some(_c)) => Outcome::Abort,
+        };
+        if logged == *outcome {
+            matched += 1;
+        } else {
+            mismatched.push((*txid, *outcome, logged));
+        }
+    }
+    mismatched.sort();
+    println!("golden: matched:{} skipped:{} mismatched:{}", matched, skipped, mismatched.len());
+    for (txid, expected, logged) in mismatched.iter() {
+        println!("golden txid {}: model {:?}, coordinator logged {:?}", txid, expected, logged);
+    }
+    Ok(mismatched.is_empty())
+}
+
+///
+/// validate_last_run()
+/// 
+/// quiet variant of check_last_run for scripting: runs the agreement,
+/// durability, ordering, exactly-once, commit-point, causality and savepoint checks
+/// over every participant (and optionally failover, liveness, decision acks and state), prints a one
+/// line pass/fail summary, and prints the offending records only for checks that 
+/// failed. returns true if every check passed, or the CheckError if a
+/// log couldn't be read.
+///
+/// every check only looks at one txid's records at a time, so with a 
+/// window size the logs are checked window (of txids) by window, and 
+/// only one window's records are ever held in memory. the state check
+/// is the exception: it still needs every commit record.
+///
+/// <params>
+///     n_participants: number of participants
+///     logpathbase: directory for client, participant, and coordinator logs 
+///     liveness: also check that every proposed transaction terminated,
+///         and that every commit was acked
+///     replicated: the run had a backup coordinator (see check_last_run)
+///     kv_store: participants kept key-value stores (see check_last_run)
+///     window: check this many txids at a time (0 = load everything)
+///
+pub fn validate_last_run(
+    n_participants: i32, 
+    logpathbase: &str,
+    liveness: bool,
+    replicated: bool,
+    kv_store: bool,
+    window: i32) -> Result<bool, CheckError> {
+
+    let mut names = vec!["agreement", "durability", "atomicity", "ordering", "exactly-once", "commit-point", "causality", "transitions", "savepoints", "units"];
+    if replicated {
+        names.push("failover");
+    }
+    if liveness {
+        names.push("liveness");
+        names.push("acks");
+    }
+    let mut failures: Vec<(&str, Vec<(String, ProtocolMessage)>)> = names.into_iter().map(|name| (name, vec![])).collect();
+    // every log has to be there before it's split into windows: a window
+    // over no logs at all finds nothing wrong
+    for path in log_paths(n_participants, logpathbase, replicated).iter() {
+        readable(path)?;
+    }
+    let windows: Vec<Option<Range<i32>>> = if window > 0 {
+        txid_windows(n_participants, logpathbase, replicated, window)?.into_iter().map(Some).collect()
+    } else {
+        vec![None]
+    };
+
+    let mut peak_records = 0;
+    for txids in windows.iter() {
+        let (cmap, plogs) = load_logs(n_participants, logpathbase, replicated, txids)?;
+        peak_records = peak_records.max(cmap.len() + plogs.values().map(|plog| plog.len()).sum::<usize>());
+        let cdecisions = coordinator_decisions(&cmap);
+        for (p, plog) in plogs.iter() {
+            let ptxids = records_by_txid(&format!("{}{}.log", logpathbase, p), &cdecisions, plog)?;
+            let found = [
+                check_agreement(&cdecisions, plog),
+                check_durability(&cdecisions, &ptxids),
+                check_atomicity(&cdecisions, &ptxids),
+                check_ordering(&cdecisions, plog, &ptxids),
+                check_exactly_once(plog),
+                check_commit_point(&cmap, plog),
+                check_causality(&cdecisions, plog),
+                check_transitions(plog),
+            ];
+            for (i, records) in found.iter().enumerate() {
+                failures[i].1.extend(records.iter().map(|pm| (p.clone(), pm.clone())));
+            }
+        }
+        for (name, bad) in failures.iter_mut().skip(8) {
+            match *name {
+                "savepoints" => bad.extend(check_savepoints(&cdecisions, &plogs)),
+                "units" => bad.extend(check_units(&cmap, &plogs)),
+                "failover" => bad.extend(check_failover(logpathbase, txids)?),
+                "liveness" => bad.extend(check_liveness(&cmap, &plogs)),
+                "acks" => bad.extend(check_decision_acks(&cmap, &plogs)),
+                _ => {},
+            }
+        }
+    }
+
+    // store contents aren't records, so this one reports on its own
+    let clogpath = format!("{}{}", logpathbase, coordinator_log(replicated));
+    let mut commits: HashMap<i32, ProtocolMessage> = HashMap::new();
+    for pm in OpLog::records(&clogpath) {
+        let pm = pm?;
+        if pm.mtype == MessageType::CoordinatorCommit {
+            commits.insert(pm.uid, pm);
+        }
+    }
+    let diverged = if kv_store {
+        check_state(n_participants, logpathbase, &commits)
+    } else {
+        vec![]
+    };
+
+    // the commit order spans every window, so it's checked on its own
+    let out_of_sequence = check_sequence(commits.into_values());
+
+    // corrupt records aren't read back as records either
+    let corrupt = check_integrity(n_participants, logpathbase, replicated)?;
+
+    let mut summary: Vec<String> = failures.iter()
+        .map(|(name, bad)| format!("{}:{}", name, if bad.is_empty() { "PASS" } else { "FAIL" }))
+        .collect();
+    summary.push(format!("sequence:{}", if out_of_sequence.is_empty() { "PASS" } else { "FAIL" }));
+    summary.push(format!("integrity:{}", if corrupt.is_empty() { "PASS" } else { "FAIL" }));
+    if kv_store {
+        summary.push(format!("state:{}", if diverged.is_empty() { "PASS" } else { "FAIL" }));
+    }
+    println!("{}", summary.join(" "));
+    for (name, bad) in failures.iter() {
+        for (p, pm) in bad.iter() {
+            println!("{} {}: {:?}", name, p, pm);
+        }
+    }
+    for problem in diverged.iter() {
+        println!("state {}", problem);
+    }
+    for (pm, expected) in out_of_sequence.iter() {
+        println!("sequence coordinator: expected seqno {}: {:?}", expected, pm);
+    }
+    for (log, record) in corrupt.iter() {
+        println!("integrity {}: {}", log, record);
+    }
+    if window > 0 {
+        println!("checked {} window(s) of {} txids, holding at most {} records at once", windows.len(), window, peak_records);
+    }
+    Ok(failures.iter().all(|(_name, bad)| bad.is_empty()) && diverged.is_empty() && out_of_sequence.is_empty() && corrupt.is_empty())
+}
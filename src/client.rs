@@ -4,18 +4,55 @@
 //! 
 extern crate log;
 extern crate stderrlog;
-use std::sync::mpsc::{Sender, Receiver};
-use std::sync::atomic::{AtomicI32, AtomicBool, Ordering};
-use std::sync::{Arc};
-use std::time::Duration;
+extern crate rand;
+extern crate crossbeam_channel;
+use client::rand::prelude::*;
+use client::crossbeam_channel::{Sender, Receiver, RecvTimeoutError, TryRecvError, TrySendError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::thread;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use message;
 use message::MessageType;
 use message::RequestStatus;
+use tpcoptions;
+use vclock::Clock;
+use coordinator::derive_seed;
+use dist::Distribution;
+use summary;
+use pool::Step;
+use shutdown::{self, ExitSignal};
+use runstate::RunState;
 
-// static counter for getting unique TXID numbers
-static TXID_COUNTER: AtomicI32 = AtomicI32::new(1);
+// the longest a client thinks before checking whether the run is over
+const THINK_SLICE: Duration = Duration::from_millis(10);
+
+///
+/// Request
+/// what a client request says, apart from its txid (handed out as it's
+/// sent, so txids stay unique across clients)
+///
+#[derive(Clone, Copy, Debug)]
+struct Request {
+    opid: i32,
+    deadline_ms: u64,
+    read_only: bool,
+}
+
+///
+/// Pace
+/// --worker-threads: where step() is in protocol()'s closed loop
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pace {
+    Ready,              // the next request goes out on the next step
+    Thinking(Instant),  // ... at this time
+    Awaiting { txid: i32, resends: u32, until: Instant }, // waiting on txid's result until a resend is due
+    Exiting,            // done sending, waiting for the exit signal
+    Done,
+}
 
 // client state and 
 // primitives for communicating with 
@@ -26,10 +63,34 @@ pub struct Client {
     pub id_str: String,
     pub ports: (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>),
     pub running: Arc<AtomicBool>,
+    exit: ExitSignal,                   // raised once running is cleared (see wait_for_exit_signal())
+    run: RunState,                      // shared with the run's coordinator and other clients: txids come from it, latencies go to it
     pub successful: i32,
     pub failed: i32, 
     pub unknown: i32,
     pub opid: i32,
+    arrival_rate: f64,          // open-loop requests/sec (0 = closed loop)
+    outstanding: HashMap<i32, Instant>, // txids sent but not yet answered -> when they were sent
+    requests: HashMap<i32, message::ProtocolMessage>, // ... -> the request sent for them, to resend (see resend_operation())
+    latencies: Vec<Duration>,   // request->result latency of every answered txid
+    rng: StdRng,
+    pub backpressured: HashMap<i32, Duration>, // txid -> how long sending its request waited on a full coordinator queue (--channel-capacity)
+    result_retries: Option<u32>,        // resends before giving up on a result (None = keep resending)
+    result_timeout: Duration,           // wait before each resend
+    pub resent: i32,                    // requests resent for want of a result
+    pub retries_exhausted: i32,         // txids declared unknown after running out of resends
+    deadlines: Vec<(u64, f64)>,         // (deadline ms, fraction of requests) to attach to requests
+    read_only_ratio: f64,               // fraction of requests that only read
+    think: Option<Distribution>,        // closed-loop think time between requests
+    ops_per_txn: i32,                   // operations bundled into each request, with consecutive opids
+    pregenerated: Option<VecDeque<Request>>, // every request, built up front (--pregenerate), still to send
+    think_rng: StdRng,                  // draws think times, apart from rng so they don't shift its stream
+    pub with_deadline: i32,             // requests sent with a deadline
+    pub missed_deadline: i32,           // ... that the coordinator aborted because it ran out
+    pub read_only: i32,                 // requests sent read-only
+    clock: Clock,
+    pace: Pace,                         // --worker-threads: where step() is up to
+    issued: i32,                        // --worker-threads: requests step() has sent
 }
 
 ///
@@ -57,92 +118,359 @@ impl Client {
                is: String,
                tx: Sender<message::ProtocolMessage>,
                rx: Receiver<message::ProtocolMessage>,
-               r: Arc<AtomicBool>) -> Client {
-        Client {
+               r: Arc<AtomicBool>,
+               exit: ExitSignal,
+               run: RunState,
+               opts: &tpcoptions::TPCOptions,
+               seed: u64) -> Client {
+        let mut client = Client {
             id: i,
             id_str: is,
             ports: (tx, rx),
             running: r,
+            exit,
+            run,
             successful: 0,
             failed: 0,
             unknown: 0,
             opid: 0,
-        }   
+            arrival_rate: opts.arrival_rate,
+            outstanding: HashMap::new(),
+            requests: HashMap::new(),
+            latencies: vec![],
+            rng: StdRng::seed_from_u64(seed),
+            backpressured: HashMap::new(),
+            result_retries: opts.client_result_retries,
+            result_timeout: Duration::from_millis(opts.client_result_timeout_ms),
+            resent: 0,
+            retries_exhausted: 0,
+            deadlines: opts.deadlines.clone(),
+            read_only_ratio: opts.read_only_ratio,
+            think: opts.think_dist,
+            ops_per_txn: opts.ops_per_txn,
+            think_rng: StdRng::seed_from_u64(derive_seed(seed, "think")),
+            with_deadline: 0,
+            missed_deadline: 0,
+            read_only: 0,
+            clock: Clock::new(&format!("Client_{}", i), opts.vector_clocks),
+            pregenerated: None,
+            pace: Pace::Ready,
+            issued: 0,
+        };
+        if opts.pregenerate {
+            client.pregenerate(opts.num_requests);
+        }
+        client
+    }
+
+    ///
+    /// pregenerate()
+    /// build all n_requests requests now, before any arrival times are
+    /// drawn, so what is asked for doesn't depend on when it's asked
+    /// 
+    fn pregenerate(&mut self, n_requests: i32) {
+        let mut queue = VecDeque::new();
+        for n in 0..n_requests {
+            let request = Request {
+                opid: n * self.ops_per_txn,
+                deadline_ms: self.next_deadline(),
+                read_only: self.next_read_only(),
+            };
+            info!("Client {} pregenerated request {:?}", self.id, request);
+            queue.push_back(request);
+        }
+        self.pregenerated = Some(queue);
+    }
+
+    ///
+    /// next_request()
+    /// the next request to send: the next pregenerated one, or one made
+    /// up now
+    /// 
+    fn next_request(&mut self) -> Request {
+        if let Some(request) = self.pregenerated.as_mut().and_then(|queue| queue.pop_front()) {
+            self.opid = request.opid + self.ops_per_txn;
+            return request;
+        }
+        let opid = self.opid;
+        self.opid = self.opid + self.ops_per_txn;
+        Request {
+            opid,
+            deadline_ms: self.next_deadline(),
+            read_only: self.next_read_only(),
+        }
     }
 
     ///
     /// wait_for_exit_signal(&mut self)
-    /// wait until the running flag is set by the CTRL-C handler
+    /// wait until the running flag is cleared by the CTRL-C handler (or
+    /// the coordinator shutting down), blocking on the exit signal 
+    /// rather than spinning on the flag
     /// 
     pub fn wait_for_exit_signal(&mut self) {
 
         trace!("Client_{} waiting for exit signal", self.id);
 
-        let mut val = self.running.load(Ordering::SeqCst);
-        while val {
-            val = self.running.load(Ordering::SeqCst);
-        }
+        shutdown::wait(&self.running, &self.exit);
 
         trace!("Client_{} exiting", self.id);
     }
 
     /// 
     /// send_next_operation(&mut self)
-    /// send the next operation to the coordinator
+    /// send the next operation (or, with --ops-per-txn, the next batch of
+    /// them, as one transaction) to the coordinator, returning its txid
     /// 
-    pub fn send_next_operation(&mut self) {
+    pub fn send_next_operation(&mut self) -> i32 {
 
         trace!("Client_{}::send_next_operation", self.id);
 
         // create a new request with a unique TXID.         
-        let request_no: i32 = self.opid; 
-        self.opid = self.opid + 1;
-        let txid = TXID_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let request = self.next_request();
+        let request_no: i32 = request.opid; 
+        let txid = self.run.next_txid();
 
         info!("Client {} request({})->txid:{} called", self.id, request_no, txid);
+        if request.deadline_ms > 0 {
+            self.with_deadline += 1;
+        }
+        if request.read_only {
+            self.read_only += 1;
+        }
         let pm = message::ProtocolMessage::generate(message::MessageType::ClientRequest, 
                                                     txid, 
                                                     format!("Client_{}", self.id), 
-                                                    request_no);
+                                                    request_no)
+            .with_deadline(request.deadline_ms)
+            .with_read_only(request.read_only);
+        let pm = if self.ops_per_txn > 1 {
+            pm.with_ops((request_no..request_no + self.ops_per_txn).collect())
+        } else {
+            pm
+        };
 
         info!("client {} calling send...", self.id);
 
-        let res = self.ports.0.send(pm);
-        match res {
-            // NEED TO LOG
-            Ok(_val) => info!("client {} succesfully sent request {}", self.id, request_no),
-            Err(err) => panic!("client {} send returned an error {:?}", self.id, err),
+        let pm = self.clock.stamp(pm);
+        self.run.stepper.step(&format!("Client_{}", self.id), "sends", &pm);
+        let started = Instant::now();
+        self.outstanding.insert(txid, started);
+        self.requests.insert(txid, pm.clone());
+        if self.send_request(pm) {
+            info!("client {} succesfully sent request {}", self.id, request_no);
+        } else {
+            panic!("client {} send failed: the coordinator is gone", self.id);
         }
 
         trace!("Client_{}::exit send_next_operation", self.id);
+        txid
+    }
+
+    ///
+    /// next_deadline()
+    /// the deadline for the next request: each configured deadline with 
+    /// its fraction's probability, otherwise none (0)
+    /// 
+    fn next_deadline(&mut self) -> u64 {
+        if self.deadlines.is_empty() {
+            return 0;
+        }
+        let mut u: f64 = self.rng.gen();
+        for (ms, fraction) in self.deadlines.iter() {
+            if u < *fraction {
+                return *ms;
+            }
+            u -= fraction;
+        }
+        0
+    }
+
+    ///
+    /// next_read_only()
+    /// whether the next request only reads, with probability 
+    /// read_only_ratio. draws nothing when no requests do, so the rest 
+    /// of rng's stream is as it was.
+    /// 
+    fn next_read_only(&mut self) -> bool {
+        self.read_only_ratio > 0.0 && self.rng.gen::<f64>() < self.read_only_ratio
     }
 
     ///
     /// recv_result()
     /// Wait for the coordinator to respond with the result for the 
-    /// last issued request. Note that we assume the coordinator does 
-    /// not fail in this simulation
+    /// last issued request (txid). The request or its result may have 
+    /// been lost, so every result_timeout without one the request is 
+    /// resent (see resend_operation()). With result_retries set, once the
+    /// resends run out the txid is declared unknown and the client moves
+    /// on; otherwise it keeps resending until the coordinator exits.
     /// 
-    pub fn recv_result(&mut self) {
+    pub fn recv_result(&mut self, txid: i32) {
 
         trace!("Client_{}::recv_result", self.id);
 
-        let res = self.ports.1.recv();
+        let mut resends = 0;
+        loop {
+            let res = self.ports.1.recv_timeout(self.result_timeout);
+            if self.on_result(txid, &mut resends, res) {
+                break;
+            }
+        }
+
+        trace!("Client_{}::exit recv_result", self.id);
+    }
+
+    ///
+    /// on_result()
+    /// one turn of recv_result(): act on what was received (or the wait
+    /// timing out) while waiting on txid's result, having resent its 
+    /// request resends times so far. true once we're done waiting.
+    ///
+    fn on_result(&mut self, txid: i32, resends: &mut u32, res: Result<message::ProtocolMessage, RecvTimeoutError>) -> bool {
         match res {
             Ok(result) => {
-                info!("client {} parsing result", self.id);
-                match result.mtype {
-                    // NEED TO LOG
-                    message::MessageType::ClientResultCommit => self.successful = self.successful + 1,
-                    message::MessageType::ClientResultAbort => self.failed = self.failed + 1,
-                    message::MessageType::CoordinatorExit => self.running.store(false, Ordering::SeqCst),
-                    _ => self.unknown = self.unknown + 1,
+                let done = result.txid == txid || result.mtype == MessageType::CoordinatorExit;
+                self.handle_result(result);
+                done
+            },
+            Err(RecvTimeoutError::Timeout) => {
+                if self.result_retries.is_some_and(|retries| *resends >= retries) {
+                    info!("client {} gave up on txid {} after {} resends", self.id, txid, resends);
+                    self.outstanding.remove(&txid);
+                    self.requests.remove(&txid);
+                    self.unknown += 1;
+                    self.retries_exhausted += 1;
+                    return true;
                 }
+                *resends += 1;
+                self.resend_operation(txid);
+                false
             },
-            Err(_err) => {},
+            Err(RecvTimeoutError::Disconnected) => true,
         }
+    }
 
-        trace!("Client_{}::exit recv_result", self.id);
+    ///
+    /// send_request()
+    /// send pm to the coordinator. with --channel-capacity its queue can
+    /// be full: then wait for room, and note how long pm's txid waited.
+    /// an unbounded queue is never full, so never pushes back. false if
+    /// the coordinator is gone.
+    ///
+    fn send_request(&mut self, pm: message::ProtocolMessage) -> bool {
+        match self.ports.0.try_send(pm) {
+            Ok(()) => true,
+            Err(TrySendError::Full(pm)) => {
+                let (txid, started) = (pm.txid, Instant::now());
+                let sent = self.ports.0.send(pm).is_ok();
+                *self.backpressured.entry(txid).or_insert(Duration::from_secs(0)) += started.elapsed();
+                sent
+            },
+            Err(TrySendError::Disconnected(_pm)) => false,
+        }
+    }
+
+    ///
+    /// resend_operation()
+    /// send the request for txid to the coordinator again, txid and all.
+    /// the coordinator only runs a txid once: a request it has already 
+    /// taken is answered from its outcome cache if it has been decided, 
+    /// and otherwise left for the decision to answer.
+    /// 
+    fn resend_operation(&mut self, txid: i32) {
+        let pm = match self.requests.get(&txid) {
+            Some(pm) => pm.copy(),
+            None => return,
+        };
+        info!("client {} resending its request for txid {}", self.id, txid);
+        let pm = self.clock.stamp(pm);
+        self.run.stepper.step(&format!("Client_{}", self.id), "sends", &pm);
+        if !self.send_request(pm) {
+            info!("client {} could not resend its request for txid {}: the coordinator is gone", self.id, txid);
+        }
+        self.resent += 1;
+    }
+
+    ///
+    /// handle_result()
+    /// tally a message received from the coordinator
+    /// 
+    fn handle_result(&mut self, result: message::ProtocolMessage) {
+
+        self.run.stepper.step(&format!("Client_{}", self.id), "receives", &result);
+        self.clock.observe(&result);
+        info!("client {} parsing result", self.id);
+        if result.mtype == MessageType::CoordinatorExit {
+            shutdown::stop(&self.running, &self.exit);
+            return;
+        }
+        // a result for a txid we aren't waiting on is a duplicate (answer 
+        // to a resent request) or arrived after we gave up on it: already
+        // counted
+        let sent = match self.outstanding.remove(&result.txid) {
+            Some(sent) => sent,
+            None => return,
+        };
+        self.requests.remove(&result.txid);
+        self.latencies.push(sent.elapsed());
+        match result.mtype {
+            // NEED TO LOG
+            message::MessageType::ClientResultCommit => self.successful = self.successful + 1,
+            message::MessageType::ClientResultAbort => {
+                self.failed = self.failed + 1;
+                if result.deadline_ms > 0 {
+                    self.missed_deadline += 1;
+                }
+            },
+            _ => self.unknown = self.unknown + 1,
+        }
+    }
+
+    ///
+    /// next_interarrival()
+    /// time until the next open-loop request: exponentially distributed
+    /// with mean 1/arrival_rate, giving a Poisson arrival process
+    /// 
+    fn next_interarrival(&mut self) -> Duration {
+        let u: f64 = self.rng.gen();
+        Duration::from_secs_f64(-(1.0 - u).ln() / self.arrival_rate)
+    }
+
+    ///
+    /// protocol_open_loop()
+    /// issue n_requests as a Poisson process at self.arrival_rate requests
+    /// per second, without waiting for earlier results first. results 
+    /// that arrive between sends are matched back up by txid, and the 
+    /// client waits for anything still outstanding once all are sent.
+    /// 
+    fn protocol_open_loop(&mut self, n_requests: i32) {
+
+        let mut next_arrival = Instant::now();
+        for _i in 0..n_requests {
+            next_arrival += self.next_interarrival();
+            while self.running.load(Ordering::SeqCst) {
+                let now = Instant::now();
+                if now >= next_arrival {
+                    break;
+                }
+                match self.ports.1.recv_timeout(next_arrival - now) {
+                    Ok(result) => self.handle_result(result),
+                    Err(RecvTimeoutError::Timeout) => {},
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            self.send_next_operation();
+        }
+
+        while !self.outstanding.is_empty() && self.running.load(Ordering::SeqCst) {
+            match self.ports.1.recv_timeout(Duration::from_millis(100)) {
+                Ok(result) => self.handle_result(result),
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
     }
 
     ///
@@ -152,13 +480,50 @@ impl Client {
     /// 
     pub fn report_status(&mut self) {
 
-        // TODO: collect real stats!
-        let _successful_ops: i32 = self.successful;
-        let _failed_ops: i32 = self.failed;
-        let _unknown_ops: i32 = self.unknown;
-        // println!("Client_{}:\tC:{}\tA:{}\tU:{}", self.id, successful_ops, failed_ops, unknown_ops);
+        let successful_ops: i32 = self.successful;
+        let failed_ops: i32 = self.failed;
+        let unknown_ops: i32 = self.unknown;
+        println!("Client_{}:\tC:{}\tA:{}\tU:{}", self.id, successful_ops, failed_ops, unknown_ops);
+        self.latencies.sort();
+        println!("Client_{}:\tlatency {}", self.id, summary::latency_report(&self.latencies));
+        if self.resent > 0 {
+            println!("Client_{}:\tresent requests:{}", self.id, self.resent);
+        }
+        if self.retries_exhausted > 0 {
+            println!("Client_{}:\tunknown after retries:{}", self.id, self.retries_exhausted);
+        }
+        if let Some(ref queue) = self.pregenerated {
+            println!("Client_{}:\tpregenerated:{} requests\tunsent:{}", self.id, self.opid as usize + queue.len(), queue.len());
+        }
+        if self.with_deadline > 0 {
+            println!("Client_{}:\twith deadline:{}\tmissed:{} ({:.0}%)", self.id, self.with_deadline, self.missed_deadline,
+                     100.0 * self.missed_deadline as f64 / self.with_deadline as f64);
+        }
+        if self.read_only > 0 {
+            println!("Client_{}:\tread-only requests:{}", self.id, self.read_only);
+        }
+        if !self.backpressured.is_empty() {
+            println!("Client_{}:\tbackpressured requests:{}\twaited:{:?}", self.id, self.backpressured.len(), 
+                     self.backpressured.values().sum::<Duration>());
+        }
     }    
 
+    ///
+    /// think_for()
+    /// sit idle for pause between requests (--think-dist), in slices
+    /// short enough that a CTRL-C doesn't wait out a long think time
+    ///
+    fn think_for(&self, pause: Duration) {
+        let until = Instant::now() + pause;
+        while self.running.load(Ordering::SeqCst) {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            thread::sleep(left.min(THINK_SLICE));
+        }
+    }
+
     ///
     /// protocol()
     /// Implements the client side of the 2PC protocol
@@ -171,20 +536,98 @@ impl Client {
         // run the 2PC protocol for each of n_requests
 
         let mut running;
-        for _i in 0..n_requests {
-            running = self.running.load(Ordering::SeqCst);
-            if running {
-                self.send_next_operation();
-                self.recv_result();
-            } else {
-                break;
+        if self.arrival_rate > 0.0 {
+            self.protocol_open_loop(n_requests);
+        } else {
+            for i in 0..n_requests {
+                running = self.running.load(Ordering::SeqCst);
+                if running {
+                    let txid = self.send_next_operation();
+                    self.recv_result(txid);
+                    if let Some(think) = self.think {
+                        if i + 1 < n_requests {
+                            let pause = think.sample(&mut self.think_rng);
+                            self.think_for(pause);
+                        }
+                    }
+                } else {
+                    break;
+                }
             }
         }
-        drop(&self.ports.0);
+        self.leave();
 
         // wait for signal to exit
         // and then report status
         self.wait_for_exit_signal();
         self.report_status();
     }
+
+    ///
+    /// leave()
+    /// done sending requests: write off what was never answered, and 
+    /// hand in our latencies
+    ///
+    fn leave(&mut self) {
+        // anything never answered has an unknown outcome
+        self.unknown += self.outstanding.len() as i32;
+        self.outstanding.clear();
+        self.requests.clear();
+        drop(&self.ports.0);
+        self.run.latencies.lock().unwrap().extend_from_slice(&self.latencies);
+    }
+
+    ///
+    /// step()
+    /// --worker-threads: protocol()'s closed loop a step at a time, for
+    /// a worker that drives many clients. a step sends a request, or 
+    /// handles one message (or a wait on a result timing out), and never
+    /// blocks waiting on the coordinator or sleeps out a think time: 
+    /// it's Idle if nothing is due yet, and Done once we've reported.
+    ///
+    pub fn step(&mut self, n_requests: i32) -> Step {
+        match self.pace {
+            Pace::Ready if !self.running.load(Ordering::SeqCst) || self.issued >= n_requests => {
+                self.leave();
+                self.pace = Pace::Exiting;
+                Step::Busy
+            },
+            Pace::Ready => {
+                let txid = self.send_next_operation();
+                self.issued += 1;
+                self.pace = Pace::Awaiting { txid, resends: 0, until: Instant::now() + self.result_timeout };
+                Step::Busy
+            },
+            Pace::Thinking(until) if self.running.load(Ordering::SeqCst) && Instant::now() < until => Step::Idle,
+            Pace::Thinking(_until) => {
+                self.pace = Pace::Ready;
+                Step::Busy
+            },
+            Pace::Awaiting { txid, mut resends, until } => {
+                let res = match self.ports.1.try_recv() {
+                    Ok(result) => Ok(result),
+                    // an operator paused at a step shouldn't make us resend
+                    Err(TryRecvError::Empty) if self.run.stepper.enabled() || Instant::now() < until => return Step::Idle,
+                    Err(TryRecvError::Empty) => Err(RecvTimeoutError::Timeout),
+                    Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+                };
+                self.pace = if !self.on_result(txid, &mut resends, res) {
+                    Pace::Awaiting { txid, resends, until: Instant::now() + self.result_timeout }
+                } else {
+                    match self.think {
+                        Some(think) if self.issued < n_requests => Pace::Thinking(Instant::now() + think.sample(&mut self.think_rng)),
+                        _ => Pace::Ready,
+                    }
+                };
+                Step::Busy
+            },
+            Pace::Exiting if self.running.load(Ordering::SeqCst) => Step::Idle,
+            Pace::Exiting => {
+                self.report_status();
+                self.pace = Pace::Done;
+                Step::Done
+            },
+            Pace::Done => Step::Done,
+        }
+    }
 }
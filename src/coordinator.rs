@@ -5,32 +5,74 @@
 extern crate log;
 extern crate stderrlog;
 extern crate rand;
+extern crate crossbeam_channel;
 use coordinator::rand::prelude::*;use std::thread;
 use std::sync::{Arc};
-use std::sync::Mutex;
-use std::sync::mpsc;
-use std::sync::mpsc::channel;
-use std::sync::mpsc::{Sender, Receiver};
-use std::time::Duration;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicI32};
+use coordinator::crossbeam_channel::{bounded, unbounded, Sender, Receiver, RecvTimeoutError, TryRecvError, Select};
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::cell::{Cell, RefCell};
 use std::sync::atomic::{AtomicBool, Ordering};
 use message::ProtocolMessage;
-use message::MessageType;
+use message::{AbortReason, MessageType};
 use message::RequestStatus;
 use message;
 use oplog;
 use client;
 use participant;
+use tpcoptions;
+use faults::{FaultAction, FaultSchedule};
+use vclock::Clock;
+use policy::{self, Decision, DecisionPolicy, Vote};
+use failure::{self, FailureModel};
+use partition::Partition;
+use replica::ReplicaLink;
+use netdelay::Network;
+use summary;
+use shutdown::{self, ExitSignal};
+use runstate::RunState;
+use stepper::Stepper;
+
+// times a decision is resent to a participant that hasn't acked it
+// before giving up on the ack (see chase_acks())
+const MAX_DECISION_RESENDS: u32 = 3;
 
 /// CoordinatorState
-/// States for 2PC state machine
+/// States for 2PC state machine. Quiescent while clients and 
+/// participants join, Active while waiting for a request with nothing
+/// in flight, CollectingVotes once a txid has been proposed, 
+/// PreCommitting while a 3pc pre-commit goes out and is acked, Committing
+/// or Aborting while a decision goes out, and Completed once protocol()
+/// is done handling requests. with --max-inflight, CollectingVotes means
+/// at least one txid is in flight.
 /// 
-/// TODO: add and/or delete!
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CoordinatorState {    
     Quiescent,          
     Active,
+    CollectingVotes,
+    PreCommitting,
+    Committing,
+    Aborting,
+    Completed,
+}
+
+///
+/// derive_seed()
+/// derive a per-component RNG seed from the global seed and the 
+/// component's name (e.g. "coordinator", "participant_3"), so each 
+/// component gets a distinct but reproducible random stream. 
+/// 
+pub fn derive_seed(base: u64, component: &str) -> u64 {
+    // FNV-1a over the component name, folded into the base seed
+    let mut hash: u64 = 0xcbf29ce484222325 ^ base;
+    for b in component.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
 }
 
 /// Coordinator
@@ -39,21 +81,173 @@ pub enum CoordinatorState {
 pub struct Coordinator {
     state: CoordinatorState,
     log: oplog::OpLog,
-    msg_success_prob: f64,
-    ops_success_prob: f64,
+    failures: Box<dyn FailureModel>, // which of our sends are lost (--failure-model)
     running: Arc<AtomicBool>,
+    exit: ExitSignal,           // raised with running cleared, for the clients and participants waiting on it
+    run: RunState,              // shared with the run's clients and participants
+    opts: tpcoptions::TPCOptions,
+    early_client_reply: bool,   // reply to the client before broadcasting the decision
+    correlated_failure_prob: f64, // chance a txid suffers a fault shared by several participants
+    pub correlated_aborts: i32, // aborted txids that had a correlated fault
+    seed: u64,
+    rng: RefCell<StdRng>,       // draws correlated faults
+    faults: RefCell<FaultSchedule>,
+    clock: RefCell<Clock>,
     pub client_ports: (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>),
     pub part_ports: (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>),
     pub client_data: HashMap<String, (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>)>,
-    pub participant_data: HashMap<String, (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>)>,
+    pub participant_data: HashMap<String, (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>, Heartbeats)>,
     num_clients: i32,
     num_participants: i32,
-    all_voted: bool,
+    policy: Box<dyn DecisionPolicy>, // turns each transaction's votes into a decision
     num_req_handled: i32,
     total_req: i32,
     pub successful: i32,
     pub failed: i32, 
     pub unknown: i32,
+    pub served: BTreeMap<String, i32>, // decided transactions per client, for fairness (see summary::fairness())
+    next_seqno: i32,            // the next commit's place in the commit order (see ProtocolMessage::seqno)
+    halted: bool,               // a log write failed; stop handling requests
+    outcomes: HashMap<i32, Decision>, // txid -> logged decision, to answer inquiries (see outcome())
+    outcome_order: VecDeque<i32>, // txids in outcomes, oldest first, for eviction
+    outcome_cache: usize,       // most outcomes kept in memory (0 = all of them)
+    segments: i32,              // EXPERIMENTAL: savepoint-separated segments per transaction (1 = off)
+    pub partial_commits: i32,   // commits through a savepoint short of the last segment
+    pub deadline_aborts: i32,   // txids aborted because their client's deadline ran out first
+    max_txn_lifetime: Option<Duration>, // undecided txids older than this are scavenged (aborted)
+    pub scavenged: i32,         // txids aborted for outliving max_txn_lifetime
+    min_live: usize,            // fewest participants that must answer a proposal for it to commit
+    pub under_replicated: i32,  // txids the policy would have committed, aborted for too few live participants
+    abort_reasons: BTreeMap<AbortReason, i32>, // why the txids we aborted were aborted
+    log_budget: Option<u64>,    // bytes all logs may append this run (--max-total-log-bytes)
+    largest_txn_bytes: u64,     // most log bytes one transaction has taken so far
+    pub out_of_log_budget: bool, // stopped early so the logs stay within log_budget
+    pub redriven: i32,          // txids earlier runs left in doubt, settled on --resume (see redrive())
+    decision_requests: Cell<i32>, // recovering participants' requests for a decision, answered
+    partition: Option<Partition>, // scripted network partition, if any
+    during_partition: (i32, i32), // (commits, aborts) of txids proposed while partitioned
+    outside_partition: (i32, i32), // ... and of the rest, with a partition scripted
+    lost_proposals: BTreeMap<String, i32>, // participant -> proposals it never got for being cut off
+    drain_timeout: Duration,    // after CTRL-C, how long to keep trying to finish the in-flight txid
+    drain_started: Cell<Option<Instant>>, // when we noticed CTRL-C mid-transaction
+    exit_ack_timeout: Duration, // how long to wait at shutdown for participants to ack the exit
+    vote_timeout: Duration,     // how long to wait for each participant's vote
+    network: Network,           // simulated latency and reordering on what we and the participants send (see netdelay.rs)
+    request_poll: Duration,     // how long recv_request() waits for any client's request before giving up
+    last_serviced_index: usize, // the client we last took a request from, by position in name order (see clients_in_turn())
+    requests_taken: HashMap<String, i32>, // client -> requests taken from it, to know how many a client that leaves still owed
+    requested: HashSet<i32>,    // txids we've taken a request for, so a resent one isn't run again (see already_taken())
+    pub resent_requests: i32,   // requests a client resent for a txid we'd already taken
+    gone_clients: BTreeSet<String>, // clients whose channel disconnected (see client_gone())
+    gone_participants: BTreeSet<String>, // participants whose channel disconnected, their votes missing from then on (see participant_gone())
+    send_attempts: u32,         // tries at a dropped send before giving up (see send_with_retry())
+    send_backoff: Duration,     // pause between them
+    unreachable: RefCell<BTreeSet<String>>, // participants we gave up sending to
+    heartbeat: Option<Duration>, // --heartbeat-ms: how often participants send heartbeats (None = they don't)
+    max_missed: u32,            // heartbeats in a row a participant may miss before we count it failed
+    failed_participants: RefCell<BTreeSet<String>>, // participants currently counted failed (see participant_failed())
+    votes_given_up: Cell<i32>,  // votes we stopped waiting for because their participant had failed
+    max_inflight: usize,        // txids proposed at once (1 = one at a time, see pipeline())
+    parallel_prepare: bool,     // send proposals to every participant at once (see broadcast() and select_votes())
+    presumed_abort: bool,       // aborts are neither logged nor broadcast (see conclude())
+    three_phase: bool,          // --protocol 3pc: pre-commit every commit first (see pre_commit())
+    durable: bool,              // --durable: force decisions to stable storage before sending them (see force_or_halt())
+    pub acks_missing: i32,      // 3pc: pre-commits a participant didn't ack in time
+    awaiting_acks: RefCell<BTreeMap<i32, AwaitingAcks>>, // txid -> its decision, while some participants haven't acked it
+    pub decisions_resent: i32,  // decisions resent to a participant that hadn't acked them
+    pub decision_acks_missing: i32, // decision acks we gave up on after MAX_DECISION_RESENDS
+    pub read_only_votes: i32,   // read-only votes on txids we committed: those participants got no phase 2
+    pub exit_acks_missing: i32, // participants that didn't ack it in time
+    replica: Option<ReplicaLink>, // EXPERIMENTAL: backup every logged record is shipped to
+    crashed: Cell<bool>,        // a scheduled crash fired (with a backup to take over)
+}
+
+///
+/// Endpoints
+/// the coordinator's ends of its client and participant channels: the
+/// network, as far as the other components can tell. a crashed primary
+/// hands them to the backup taking over from it.
+///
+#[derive(Debug)]
+pub struct Endpoints {
+    client_data: HashMap<String, (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>)>,
+    participant_data: HashMap<String, (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>, Heartbeats)>,
+}
+
+///
+/// Heartbeats
+/// our end of a participant's heartbeat channel (--heartbeat-ms), and
+/// when we last heard a heartbeat on it
+///
+#[derive(Debug)]
+pub struct Heartbeats {
+    rx: Receiver<message::ProtocolMessage>,
+    last_seen: Cell<Instant>,
+}
+
+///
+/// TxState
+/// a txid the coordinator has proposed and not yet decided
+///
+#[derive(Debug)]
+struct TxState {
+    request: ProtocolMessage,
+    client: String,             // the client that asked, to reply to
+    start_bytes: u64,           // RunState::bytes_appended() when it started
+    deadline: Option<Instant>,  // the client's deadline, if it gave one
+    end_of_life: Option<Instant>, // when --max-txn-lifetime-ms scavenges it
+    votes_due: Instant,         // --max-inflight: votes not in by now count as missing, once it's the oldest in flight
+    partitioned: bool,          // proposed while a partition was active
+    unreached: HashSet<String>, // participants the proposal was lost to
+    given_up: HashSet<String>,  // participants we stopped waiting on for having failed
+    votes: HashMap<String, Vote>, // participant -> its vote, once in (or timed out)
+    read_only: HashSet<String>, // participants that voted read-only: done with the txid, so they get no phase 2
+}
+
+///
+/// AwaitingAcks
+/// a decision sent to participants that haven't all acked it yet
+///
+#[derive(Debug)]
+struct AwaitingAcks {
+    decision: ProtocolMessage,
+    pending: BTreeSet<String>,  // participants yet to ack it
+    due: Instant,               // resend it to them if they still haven't by now
+    resends: u32,
+}
+
+///
+/// SendFailure
+/// why send_with_retry() gave up on a message
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendFailure {
+    Disconnected,       // the other end has exited
+    Dropped(u32),       // every one of this many attempts was dropped
+}
+
+///
+/// Attempt
+/// what happens to one try at sending a message, drawn by draw_attempt()
+/// and carried out by carry_out()
+///
+#[derive(Debug)]
+enum Attempt {
+    Dropped(Option<Duration>),                  // lost (after a fault's delay, if any)
+    Delivered(Option<Duration>, ProtocolMessage), // goes out, stamped, after a fault's delay if any
+    Swallowed,                                  // the coordinator has crashed: nothing goes out, and nobody should retry
+}
+
+///
+/// vote_of()
+/// the vote a participant's phase 1 reply carries
+///
+fn vote_of(pm: &ProtocolMessage) -> Vote {
+    if pm.mtype == MessageType::ParticipantVoteAbort {
+        Vote::Abort
+    } else {
+        Vote::Commit(pm.segment)
+    }
 }
 
 ///
@@ -75,39 +269,189 @@ impl Coordinator {
     /// <params>
     ///     logpath: directory for log files --> create a new log there. 
     ///     r: atomic bool --> still running?
-    ///     msg_success_prob --> probability sends succeed
+    ///     exit: raised when r is cleared (see shutdown.rs)
+    ///     run: the run's shared state (see runstate.rs), handed on to
+    ///         the clients and participants that join
+    ///     opts: command line options (success probabilities, request 
+    ///         counts, log limits, ...)
+    ///     seed --> global seed; coordinator and participants derive their own from it
     ///
     pub fn new(
         logpath: String, 
         r: Arc<AtomicBool>, 
-        msg_success_prob: f64,
-        ops_success_prob: f64,
-        total_requests: i32) -> Coordinator {
+        exit: ExitSignal,
+        run: RunState,
+        opts: &tpcoptions::TPCOptions,
+        seed: u64) -> Coordinator {
 
-        Coordinator {
+        let mut log = if opts.in_memory_log {
+            oplog::OpLog::new_in_memory()
+        } else if opts.resume {
+            oplog::OpLog::reopen(logpath, opts.log_max_bytes, opts.async_log, opts.log_index, oplog::OpLogFormat::from_string(&opts.log_format))
+        } else {
+            oplog::OpLog::with_options(logpath, opts.log_max_bytes, opts.async_log, opts.log_index, oplog::OpLogFormat::from_string(&opts.log_format))
+        };
+        if let Some(n) = opts.disk_full_after {
+            log.fail_after(n);
+        }
+        if opts.log_checksums {
+            log.checksum_records();
+        }
+        log.count_appended(run.log_bytes.clone());
+        let mut coordinator = Coordinator {
             state: CoordinatorState::Quiescent,
-            log: oplog::OpLog::new(logpath),
-            msg_success_prob: msg_success_prob,
-            ops_success_prob: ops_success_prob,
+            log,
+            failures: failure::from_string(&opts.failure_model, 1.0, opts.success_probability_msg, derive_seed(seed, "drops")),
             running: r,
+            exit,
+            run,
+            opts: opts.clone(),
+            early_client_reply: opts.early_client_reply,
+            correlated_failure_prob: opts.correlated_failure_prob,
+            correlated_aborts: 0,
+            seed,
+            rng: RefCell::new(StdRng::seed_from_u64(derive_seed(seed, "coordinator"))),
+            faults: RefCell::new(FaultSchedule::for_component(opts.fault_schedule.as_deref(), "coordinator")),
+            clock: RefCell::new(Clock::new("coordinator", opts.vector_clocks)),
             client_data: HashMap::new(),
             participant_data: HashMap::new(),
             num_clients: 0,
             num_participants: 0,
-            client_ports: (channel()),
-            part_ports: (channel()),
-            all_voted: true,
+            client_ports: (unbounded()),
+            part_ports: (unbounded()),
+            policy: policy::from_string(&opts.decision_policy),
             num_req_handled: 0,
-            total_req: total_requests,
+            total_req: opts.num_requests * opts.num_clients,
             successful: 0,
             failed: 0,
             unknown: 0,
+            halted: false,
+            outcomes: HashMap::new(),
+            outcome_order: VecDeque::new(),
+            outcome_cache: opts.outcome_cache,
+            segments: opts.segments,
+            partial_commits: 0,
+            served: BTreeMap::new(),
+            next_seqno: 1,
+            deadline_aborts: 0,
+            partition: Partition::from_options(opts),
+            during_partition: (0, 0),
+            lost_proposals: BTreeMap::new(),
+            outside_partition: (0, 0),
+            drain_timeout: Duration::from_millis(opts.drain_timeout_ms),
+            exit_ack_timeout: Duration::from_millis(opts.exit_ack_timeout_ms),
+            vote_timeout: Duration::from_millis(opts.vote_timeout_ms),
+            network: Network::from_options(opts),
+            request_poll: Duration::from_millis(opts.request_poll_ms),
+            last_serviced_index: 0,
+            requests_taken: HashMap::new(),
+            requested: HashSet::new(),
+            resent_requests: 0,
+            gone_clients: BTreeSet::new(),
+            gone_participants: BTreeSet::new(),
+            send_attempts: opts.send_attempts.max(1),
+            send_backoff: Duration::from_millis(opts.send_backoff_ms),
+            unreachable: RefCell::new(BTreeSet::new()),
+            heartbeat: if opts.heartbeat_ms > 0 { Some(Duration::from_millis(opts.heartbeat_ms)) } else { None },
+            max_missed: opts.max_missed,
+            failed_participants: RefCell::new(BTreeSet::new()),
+            votes_given_up: Cell::new(0),
+            max_inflight: opts.max_inflight.max(1) as usize,
+            parallel_prepare: opts.parallel_prepare,
+            presumed_abort: opts.presumed_abort,
+            three_phase: opts.protocol == "3pc",
+            durable: opts.durable,
+            acks_missing: 0,
+            awaiting_acks: RefCell::new(BTreeMap::new()),
+            decisions_resent: 0,
+            decision_acks_missing: 0,
+            read_only_votes: 0,
+            max_txn_lifetime: opts.max_txn_lifetime_ms.map(Duration::from_millis),
+            scavenged: 0,
+            min_live: opts.min_live_participants.max(0) as usize,
+            under_replicated: 0,
+            abort_reasons: BTreeMap::new(),
+            log_budget: opts.max_total_log_bytes,
+            largest_txn_bytes: 0,
+            out_of_log_budget: false,
+            redriven: 0,
+            decision_requests: Cell::new(0),
+            exit_acks_missing: 0,
+            drain_started: Cell::new(None),
+            replica: None,
+            crashed: Cell::new(false),
+        };
+        if opts.resume {
+            coordinator.resume();
+        }
+        coordinator
+    }
+
+    ///
+    /// resume()
+    /// --resume: pick up the committed state earlier runs left in the 
+    /// log (the outcome of every txid they decided, and the commit/abort
+    /// counts) and carry txids and record uids on past the highest ones
+    /// logged. what they left in doubt is settled by redrive(), once the
+    /// participants have joined.
+    ///
+    fn resume(&mut self) {
+        let records: Vec<ProtocolMessage> = self.log.arc().lock().unwrap().values().cloned().collect();
+        let requesters: HashMap<i32, String> = records.iter()
+            .filter(|pm| pm.mtype == MessageType::ClientRequest)
+            .map(|pm| (pm.txid, pm.senderid.clone()))
+            .collect();
+        self.requested.extend(requesters.keys());
+        let decided: HashSet<i32> = records.iter()
+            .filter(|pm| pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort)
+            .map(|pm| pm.txid)
+            .collect();
+        for pm in records.iter() {
+            // under presumed abort, an abort only shows up as the client's result
+            let presumed = pm.mtype == MessageType::ClientResultAbort && !decided.contains(&pm.txid);
+            if pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort || presumed {
+                if let Some(client) = requesters.get(&pm.txid) {
+                    *self.served.entry(client.clone()).or_insert(0) += 1;
+                }
+            }
+            // our clock carries on from the last one we logged
+            self.clock.borrow_mut().observe(pm);
+            match pm.mtype {
+                MessageType::CoordinatorCommit => {
+                    self.successful += 1;
+                    if pm.segment != 0 && pm.segment < self.segments {
+                        self.partial_commits += 1;
+                    }
+                    self.remember(pm.txid, Decision::Commit(pm.segment));
+                },
+                MessageType::CoordinatorAbort => {
+                    self.failed += 1;
+                    self.remember(pm.txid, Decision::Abort);
+                },
+                MessageType::ClientResultAbort if presumed => {
+                    self.failed += 1;
+                    self.remember(pm.txid, Decision::Abort);
+                },
+                _ => {},
+            }
         }
+        if let Some(max_uid) = records.iter().map(|pm| pm.uid).max() {
+            message::resume_uids(max_uid);
+        }
+        if let Some(max_txid) = records.iter().map(|pm| pm.txid).max() {
+            self.run.resume_txids(max_txid);
+        }
+        // commit numbering carries on past the earlier run's
+        if let Some(max_seqno) = records.iter().map(|pm| pm.seqno).max() {
+            self.next_seqno = self.next_seqno.max(max_seqno + 1);
+        }
+        info!("coordinator resumed {} records: C:{} A:{}", records.len(), self.successful, self.failed);
     }
 
     /// 
     /// participant_join()
-    /// handle the addition of a new participant
+    /// handle the addition of a new participant, going by name: the key
+    /// we reach it by and its id_str both
     /// HINT: keep track of any channels involved!
     /// HINT: you'll probably need to change this routine's 
     ///       signature to return something!
@@ -117,20 +461,32 @@ impl Coordinator {
 
         assert!(self.state == CoordinatorState::Quiescent);
 
-        let (p_tx, coord_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = channel();
-        let (coord_tx, p_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = channel();
-        let part = participant::Participant::new(self.num_participants, self.num_participants.to_string(), p_tx, 
-                    p_rx, format!("{}/participant_{}.log", logpathbase, self.num_participants), self.running.clone(), self.ops_success_prob, self.msg_success_prob);
+        let (p_tx, coord_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = unbounded();
+        let (coord_tx, p_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = unbounded();
+        let (hb_tx, hb_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = unbounded();
+        let pseed = derive_seed(self.seed, &format!("participant_{}", self.num_participants));
+        let p_tx = self.network.link(p_tx, derive_seed(pseed, "network"));
+        let coord_tx = self.network.link(coord_tx, derive_seed(self.seed, &format!("network_participant_{}", self.num_participants)));
+        let mut part = participant::Participant::new(self.num_participants, name.clone(), p_tx, 
+                    p_rx, format!("{}/participant_{}.log", logpathbase, self.num_participants), self.running.clone(), self.exit.clone(), self.run.clone(), &self.opts, pseed);
+        
+        if let Some(interval) = self.heartbeat {
+            part.heartbeat_to(hb_tx, interval);
+        }
         
         self.num_participants = self.num_participants + 1;
-        self.participant_data.insert(name, (coord_tx, coord_rx));
+        let heartbeats = Heartbeats { rx: hb_rx, last_seen: Cell::new(Instant::now()) };
+        // the key we reach the participant by is the name it goes by
+        assert_eq!(part.id_str, name);
+        self.participant_data.insert(name, (coord_tx, coord_rx, heartbeats));
 
         part
     }
 
     /// 
     /// client_join()
-    /// handle the addition of a new client
+    /// handle the addition of a new client, going by name (as in 
+    /// participant_join())
     /// HINTS: keep track of any channels involved!
     /// HINT: you'll probably need to change this routine's 
     ///       signature to return something!
@@ -141,11 +497,22 @@ impl Coordinator {
         assert!(self.state == CoordinatorState::Quiescent);
         
 
-        let (client_tx, coord_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = channel();
-        let (coord_tx, client_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = channel();
-        let client = client::Client::new(self.num_clients, (self.num_clients).to_string(), client_tx, client_rx, self.running.clone());
+        // --channel-capacity: a client that gets that far ahead of us 
+        // blocks sending, rather than queueing up requests without limit
+        let (client_tx, coord_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = if self.opts.channel_capacity > 0 {
+            bounded(self.opts.channel_capacity)
+        } else {
+            unbounded()
+        };
+        let (coord_tx, client_rx): (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>) = unbounded();
+        let cseed = derive_seed(self.seed, &format!("client_{}", self.num_clients));
+        let coord_tx = self.network.link(coord_tx, derive_seed(self.seed, &format!("network_client_{}", self.num_clients)));
+        let client = client::Client::new(self.num_clients, name.clone(), client_tx, client_rx, self.running.clone(), self.exit.clone(), self.run.clone(), &self.opts, cseed);
 
+        // a client that never gets served still counts against fairness
+        self.served.insert(format!("Client_{}", self.num_clients), 0);
         self.num_clients = self.num_clients + 1;
+        assert_eq!(client.id_str, name);
         self.client_data.insert(name, (coord_tx, coord_rx));
 
         client
@@ -159,203 +526,1894 @@ impl Coordinator {
     /// 
     pub fn send(&self, sender: &Sender<ProtocolMessage>, pm: ProtocolMessage, panic: &mut bool) -> bool {
 
-        let x: f64 = random();
-        let mut result: bool = false;
-        if x < self.msg_success_prob {
-            let res = sender.send(pm.clone());
-            match res {
-                Ok(_val) => result = true,
-                Err(_err) => {
-                    *panic = true;
-                },
-            }
+        let attempt = self.draw_attempt(pm);
+        Coordinator::carry_out(sender, attempt, &self.run.stepper, panic)
+    }     
+
+    ///
+    /// draw_attempt()
+    /// decide what happens to one try at sending pm: a scheduled fault,
+    /// or else the message success probability. nothing is sent yet, so
+    /// the draws can be made in order on this thread and the sends made
+    /// on others (see broadcast()).
+    ///
+    fn draw_attempt(&self, pm: ProtocolMessage) -> Attempt {
+        if self.crashed.get() {
+            // nothing leaves a crashed coordinator; don't make callers retry
+            return Attempt::Swallowed;
+        }
+        let mut delay = None;
+        match self.faults.borrow_mut().take(pm.txid, pm.mtype) {
+            Some(FaultAction::Drop) => return Attempt::Dropped(None),
+            Some(FaultAction::Delay(d)) => delay = Some(d),
+            // without a backup to take over, a coordinator crash would just
+            // end the run, so it's only modeled with --replicate
+            Some(FaultAction::Crash) if self.replica.is_some() => {
+                warn!("coordinator crashing at {:?} for txid {}", pm.mtype, pm.txid);
+                self.crashed.set(true);
+                self.replica.as_ref().unwrap().fail();
+                return Attempt::Swallowed;
+            },
+            Some(FaultAction::Crash) | None => {},
+        }
+        if self.failures.should_deliver(&pm) {
+            Attempt::Delivered(delay, self.clock.borrow_mut().stamp(pm))
         } else {
             // don't send anything!
             // (simulates failure)
-            result = false;
+            Attempt::Dropped(delay)
         }
-        result
-    }     
+    }
 
-    /// 
-    /// recv_request()
-    /// receive a message from a client
-    /// to start off the protocol.
-    /// 
-    pub fn recv_request(&mut self, found: &mut bool) -> (Option<ProtocolMessage>, String) {
+    ///
+    /// carry_out()
+    /// make a try drawn by draw_attempt(), showing it to stepper. returns
+    /// true if it counts as sent; sets panic if the other end has exited.
+    ///
+    fn carry_out(sender: &Sender<ProtocolMessage>, attempt: Attempt, stepper: &Stepper, panic: &mut bool) -> bool {
+        match attempt {
+            Attempt::Swallowed => true,
+            Attempt::Dropped(delay) => {
+                if let Some(delay) = delay {
+                    thread::sleep(delay);
+                }
+                false
+            },
+            Attempt::Delivered(delay, pm) => {
+                if let Some(delay) = delay {
+                    thread::sleep(delay);
+                }
+                stepper.show("coordinator", "sends", &pm);
+                if sender.send(pm).is_err() {
+                    *panic = true;
+                    return false;
+                }
+                true
+            },
+        }
+    }
 
-        let mut result = Option::None;
-        assert!(self.state == CoordinatorState::Quiescent);        
-        trace!("coordinator::recv_request...");
+    ///
+    /// send_with_retry()
+    /// send pm, retrying a dropped send up to send_attempts times in all,
+    /// send_backoff apart. gives up early if the other end has exited.
+    ///
+    pub fn send_with_retry(&self, sender: &Sender<ProtocolMessage>, pm: ProtocolMessage) -> Result<(), SendFailure> {
+        let mut panic = false;
+        for attempt in 0..self.send_attempts {
+            if attempt > 0 {
+                thread::sleep(self.send_backoff);
+            }
+            if self.send(sender, pm.clone(), &mut panic) {
+                return Ok(());
+            }
+            if panic {
+                return Err(SendFailure::Disconnected);
+            }
+        }
+        Err(SendFailure::Dropped(self.send_attempts))
+    }
 
-        for _i in 0..10 {
-            for (key, val) in self.client_data.iter() {
-                // let rx = rec as Receiver<message::ProtocolMessage>;
-                let pm = val.1.recv_timeout(Duration::from_millis(10));
-                match pm {
-                    Ok(val) => {
-                        result = Some(val);
-                        *found = true;
-                        // break;
-                        trace!("leaving coordinator::recv_request");
-                        return (result, key.clone());
-                    }
-                    Err(err) => {
-                        match err {
-                            mpsc::RecvTimeoutError::Timeout => {
-                                continue;
-                            }
-                            mpsc::RecvTimeoutError::Disconnected => {
-                                // let map = &mut self.client_data;
-                                // map.remove(key);
-                                // // self.client_data.remove(key);
-                                continue;
-                            }
-                        }
-                    }
-                }
+    ///
+    /// plan_send()
+    /// draw every try send_with_retry() would make at sending pm, up to
+    /// the first one that isn't dropped
+    ///
+    fn plan_send(&self, pm: &ProtocolMessage) -> Vec<Attempt> {
+        let mut attempts = vec![];
+        for _ in 0..self.send_attempts {
+            let attempt = self.draw_attempt(pm.clone());
+            let last = !matches!(attempt, Attempt::Dropped(_));
+            attempts.push(attempt);
+            if last {
+                break;
             }
         }
+        attempts
+    }
 
-        *found = false;
-        trace!("leaving coordinator::recv_request");
-        (result, String::from(""))
-    }        
+    ///
+    /// retry_planned()
+    /// send_with_retry(), making the tries plan_send() drew
+    ///
+    fn retry_planned(sender: &Sender<ProtocolMessage>, attempts: Vec<Attempt>, backoff: Duration, stepper: &Stepper) -> Result<(), SendFailure> {
+        let tries = attempts.len() as u32;
+        let mut panic = false;
+        for (i, attempt) in attempts.into_iter().enumerate() {
+            if i > 0 {
+                thread::sleep(backoff);
+            }
+            if Coordinator::carry_out(sender, attempt, stepper, &mut panic) {
+                return Ok(());
+            }
+            if panic {
+                return Err(SendFailure::Disconnected);
+            }
+        }
+        Err(SendFailure::Dropped(tries))
+    }
 
     ///
-    /// report_status()
-    /// report the abort/commit/unknown status (aggregate) of all 
-    /// transaction requests made by this coordinator before exiting. 
-    /// 
-    pub fn report_status(&mut self) {
-        let successful_ops: i32 = self.successful; // TODO!
-        let failed_ops: i32 = self.failed; // TODO!
-        let unknown_ops: i32 = self.unknown; // TODO! 
-        println!("coordinator:\tC:{}\tA:{}\tU:{}", successful_ops, failed_ops, unknown_ops);
-    }    
+    /// broadcast()
+    /// --parallel-prepare: send_to_participant() pm to each of keys, each
+    /// on a thread of its own, so one participant's retries and delays 
+    /// don't hold up the rest. what happens to every try is drawn first,
+    /// here and in participant order, so a seeded run loses the same 
+    /// messages it would sending them one at a time.
+    ///
+    fn broadcast(&self, keys: &[String], pm: &ProtocolMessage) {
+        let planned: Vec<(&String, &Sender<ProtocolMessage>, Vec<Attempt>)> = keys.iter()
+            .filter(|key| !self.unreachable.borrow().contains(*key))
+            .map(|key| (key, &self.participant_data[key].0, self.plan_send(pm)))
+            .collect();
+        let (backoff, stepper) = (self.send_backoff, &self.run.stepper);
+        let results: Vec<(&String, Result<(), SendFailure>)> = thread::scope(|scope| {
+            let sends: Vec<_> = planned.into_iter()
+                .map(|(key, sender, attempts)| scope.spawn(move || (key, Coordinator::retry_planned(sender, attempts, backoff, stepper))))
+                .collect();
+            sends.into_iter().map(|send| send.join().unwrap()).collect()
+        });
+        for (key, result) in results {
+            if let Err(failure) = result {
+                warn!("coordinator giving up on participant_{} sending {:?} for txid {}: {:?}", key, pm.mtype, pm.txid, failure);
+                self.unreachable.borrow_mut().insert(key.clone());
+            }
+        }
+    }
 
     ///
-    /// protocol()
-    /// Implements the coordinator side of the 2PC protocol
-    /// HINT: if the simulation ends early, don't keep handling requests!
-    /// HINT: wait for some kind of exit signal before returning from the protocol!
-    /// 
-    pub fn protocol(&mut self) {
+    /// send_to_participant()
+    /// send_with_retry() to participant key. one we can't get through to
+    /// is recorded as unreachable and not tried again, rather than holding
+    /// up every txid after it. returns true if pm was sent.
+    ///
+    fn send_to_participant(&self, key: &str, sender: &Sender<ProtocolMessage>, pm: ProtocolMessage) -> bool {
+        if self.unreachable.borrow().contains(key) {
+            return false;
+        }
+        match self.send_with_retry(sender, pm.clone()) {
+            Ok(()) => true,
+            Err(failure) => {
+                warn!("coordinator giving up on participant_{} sending {:?} for txid {}: {:?}", key, pm.mtype, pm.txid, failure);
+                self.unreachable.borrow_mut().insert(key.to_string());
+                false
+            }
+        }
+    }
 
-        let mut active;
-        while self.num_req_handled != self.total_req {
-            active = self.running.load(Ordering::SeqCst);
-            if active {
-                let mut found = false;
-                let res = self.recv_request(&mut found);
-                if found {
-                    let pm = res.0.unwrap();
-                    self.log.append(pm.mtype, pm.txid, pm.senderid, pm.opid);
-                    assert_eq!(pm.mtype, MessageType::ClientRequest);
-                    let prepare = ProtocolMessage::generate(MessageType::CoordinatorPropose, pm.txid, format!("coordinator"), pm.opid);
-                    self.log.append(prepare.mtype, prepare.txid, prepare.senderid.clone(), prepare.opid);
-                    for (key, val) in self.participant_data.iter() {
-                        let mut panic = false;
-                        let mut res = self.send(&val.0, prepare.clone(), &mut panic);
-                        if !res && !panic {
-                            while !res {
-                                res = self.send(&val.0, prepare.clone(), &mut panic);
-                                if panic {break; }
-                            }
-                        }
-                    }
+    ///
+    /// cut_off()
+    /// true if a scripted partition is dropping messages to and from
+    /// participant key right now
+    ///
+    fn cut_off(&self, key: &str) -> bool {
+        self.partition.as_ref().is_some_and(|p| p.cuts(key))
+    }
 
-                    // wait for ready from all p
-                    for (_key, val) in self.participant_data.iter() {
-                        let res = val.1.recv_timeout(Duration::from_millis(500));
-                        match res {
-                            Ok(value) => {
-                                if value.mtype == MessageType::ParticipantVoteAbort {
-                                    self.all_voted = false;
-                                } else {
-                                    continue;
-                                }
-                            }
-                            Err(_err) => {
-                                self.all_voted = false;
-                            }
-                        }
-                    }
+    ///
+    /// participant_failed()
+    /// --heartbeat-ms: true if participant key has missed max_missed 
+    /// heartbeats in a row, i.e. it's dead rather than just slow. it 
+    /// stays counted failed until a heartbeat from it turns up again.
+    ///
+    fn participant_failed(&self, key: &str, heartbeats: &Heartbeats) -> bool {
+        let interval = match self.heartbeat {
+            Some(interval) => interval,
+            None => return false,
+        };
+        while heartbeats.rx.try_recv().is_ok() {
+            heartbeats.last_seen.set(Instant::now());
+        }
+        let silent = heartbeats.last_seen.get().elapsed();
+        let failed = silent > interval * self.max_missed;
+        let mut marked = self.failed_participants.borrow_mut();
+        if failed && marked.insert(key.to_string()) {
+            warn!("coordinator counting participant_{} failed: no heartbeat for {:?}", key, silent);
+        } else if !failed && marked.remove(key) {
+            info!("coordinator hearing participant_{}'s heartbeats again", key);
+        }
+        failed
+    }
 
-                    // send global to all part
-                    let mes;
-                    if self.all_voted {
-                        mes = message::ProtocolMessage::generate(MessageType::CoordinatorCommit, pm.txid, format!("coordinator"), pm.opid);
-                        self.successful = self.successful + 1;
-                        // obviously need to log
-                    } else {
-                        mes = message::ProtocolMessage::generate(MessageType::CoordinatorAbort, pm.txid, format!("coordinator"), pm.opid);
-                        self.failed = self.failed + 1;
-                    }
+    ///
+    /// replicate_to()
+    /// EXPERIMENTAL (--replicate): ship every record we log to a backup
+    /// coordinator before acting on it
+    ///
+    pub fn replicate_to(&mut self, replica: ReplicaLink) {
+        self.replica = Some(replica);
+    }
 
-                    self.log.append(mes.mtype, mes.txid, mes.senderid.clone(), mes.opid);
-                    for (key, val) in self.participant_data.iter() {
-                        let mut panic = false;
-                        let mut res = self.send(&val.0, mes.clone(), &mut panic);
-                        if !res && !panic {
-                            while !res {
-                                res = self.send(&val.0, mes.clone(), &mut panic);
-                                if panic {break; }
-                            }
-                        }
+    /// true if a scheduled crash took this coordinator down
+    pub fn crashed(&self) -> bool {
+        self.crashed.get()
+    }
+
+    ///
+    /// hand_off()
+    /// give up our client and participant endpoints (to the backup
+    /// taking over from us)
+    ///
+    pub fn hand_off(&mut self) -> Endpoints {
+        Endpoints {
+            client_data: std::mem::take(&mut self.client_data),
+            participant_data: std::mem::take(&mut self.participant_data),
+        }
+    }
+
+    ///
+    /// adopt()
+    /// take over the client and participant endpoints of a crashed primary
+    ///
+    pub fn adopt(&mut self, endpoints: Endpoints) {
+        self.num_clients = endpoints.client_data.len() as i32;
+        self.num_participants = endpoints.participant_data.len() as i32;
+        for name in endpoints.client_data.keys() {
+            self.served.entry(format!("Client_{}", name)).or_insert(0);
+        }
+        self.client_data = endpoints.client_data;
+        self.participant_data = endpoints.participant_data;
+    }
+
+    ///
+    /// recover()
+    /// EXPERIMENTAL (--replicate): after taking over from a crashed 
+    /// primary (and loading its replicated log, see resume()), settle the
+    /// one transaction it may have been in the middle of. a decision it
+    /// logged may not have reached everyone, so send it again; without
+    /// one, the primary can't have told anyone anything, so abort. the
+    /// client gets the outcome either way. duplicates are harmless: a 
+    /// participant that has the decision already just acks it again, and
+    /// clients ignore results for txids they aren't waiting on.
+    ///
+    pub fn recover(&mut self) {
+        let mut records: Vec<ProtocolMessage> = self.log.arc().lock().unwrap().values().cloned().collect();
+        records.sort_by_key(|pm| pm.uid);
+        self.num_req_handled = records.iter().filter(|pm| pm.mtype == MessageType::ClientRequest).count() as i32;
+        let request = match records.iter().rev().find(|pm| pm.mtype == MessageType::ClientRequest) {
+            Some(request) => request.clone(),
+            None => return,
+        };
+        let client = request.senderid.trim_start_matches("Client_").to_string();
+        let decided = records.iter().find(|pm| pm.txid == request.txid &&
+            (pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort));
+        let replied = records.iter().find(|pm| pm.txid == request.txid &&
+            (pm.mtype == MessageType::ClientResultCommit || pm.mtype == MessageType::ClientResultAbort));
+
+        let mes = match decided {
+            Some(decision) => {
+                info!("backup coordinator resending {:?} for in-flight txid {}", decision.mtype, decision.txid);
+                ProtocolMessage::generate(decision.mtype, decision.txid, String::from("coordinator"), decision.opid)
+                    .with_segment(decision.segment)
+                    .with_ops(decision.ops.clone())
+                    .with_abort_reason(decision.abort_reason)
+            },
+            None => {
+                info!("backup coordinator aborting in-flight txid {}", request.txid);
+                let abort = ProtocolMessage::generate(MessageType::CoordinatorAbort, request.txid, String::from("coordinator"), request.opid)
+                    .with_ops(request.ops.clone())
+                    .with_abort_reason(Some(AbortReason::Recovery));
+                if !self.force_or_halt(&abort) {
+                    return;
+                }
+                self.failed += 1;
+                *self.abort_reasons.entry(AbortReason::Recovery).or_insert(0) += 1;
+                *self.served.entry(request.senderid.clone()).or_insert(0) += 1;
+                self.remember(request.txid, Decision::Abort);
+                abort
+            },
+        };
+        let sent = self.participant_data.iter()
+            .filter(|(key, val)| self.send_to_participant(key, &val.0, mes.clone()))
+            .map(|(key, _val)| key.clone())
+            .collect();
+        self.await_acks(&mes, sent);
+        match replied {
+            Some(cl_res) => {
+                if let Some(cl_send) = self.client_data.get(&client) {
+                    if let Err(failure) = self.send_with_retry(&cl_send.0, cl_res.copy()) {
+                        warn!("coordinator couldn't resend client {} its {:?} for txid {}: {:?}", client, cl_res.mtype, cl_res.txid, failure);
                     }
+                }
+            },
+            None => {
+                let mtype = if mes.mtype == MessageType::CoordinatorCommit {
+                    MessageType::ClientResultCommit
+                } else {
+                    MessageType::ClientResultAbort
+                };
+                let cl_res = ProtocolMessage::generate(mtype, request.txid, String::from("coordinator"), request.opid)
+                    .with_segment(mes.segment)
+                    .with_abort_reason(mes.abort_reason);
+                self.reply_to_client(&client, &cl_res);
+            },
+        }
+    }
+
+    ///
+    /// redrive()
+    /// --resume: settle what earlier runs left in doubt, once the 
+    /// participants have joined (and before anything new is proposed, so
+    /// the decisions reach them first). a txid proposed but never decided
+    /// may have yes votes waiting on it, so abort it. a decision may not
+    /// have reached every participant if it was abandoned when a drain
+    /// timed out, or if it was the last one logged (one txid is in flight
+    /// at a time, so only that one can have been cut off mid-broadcast;
+    /// with --max-inflight, participants that resume ask about the rest
+    /// themselves), so send those again. participants that already have a decision
+    /// just ack it again. the clients that asked are gone, so they aren't told,
+    /// and their requests that were never proposed count as unknown.
+    ///
+    pub fn redrive(&mut self) {
+        let mut records: Vec<ProtocolMessage> = self.log.arc().lock().unwrap().values().cloned().collect();
+        records.sort_by_key(|pm| pm.uid);
+        let mut txids: BTreeMap<i32, Vec<ProtocolMessage>> = BTreeMap::new();
+        for pm in records.iter().filter(|pm| pm.txid >= 0) {
+            txids.entry(pm.txid).or_default().push(pm.clone());
+        }
+        let is_decision = |pm: &ProtocolMessage| pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort;
+        let last_decided = records.iter().rev().find(|pm| is_decision(pm)).map(|pm| pm.txid);
 
-                    let cl_res;
-                    if self.all_voted {
-                        cl_res = message::ProtocolMessage::generate(MessageType::ClientResultCommit, pm.txid, format!("coordinator"), pm.opid);
-                    } else {
-                        cl_res = message::ProtocolMessage::generate(MessageType::ClientResultAbort, pm.txid, format!("coordinator"), pm.opid);
+        for (txid, logged) in txids.iter() {
+            let find = |mtype: MessageType| logged.iter().find(|pm| pm.mtype == mtype);
+            let request = match find(MessageType::ClientRequest) {
+                Some(request) => request,
+                None => continue,
+            };
+            let mes = match logged.iter().find(|pm| is_decision(pm)) {
+                None if find(MessageType::CoordinatorPropose).is_none() => {
+                    self.unknown += 1;
+                    continue;
+                },
+                // presumed abort: nothing to send, a participant that
+                // asks is told abort (see answer_decision_request())
+                None if find(MessageType::ClientResultAbort).is_some() => continue,
+                None => {
+                    info!("coordinator aborting txid {}, left undecided by an earlier run", txid);
+                    let abort = ProtocolMessage::generate(MessageType::CoordinatorAbort, *txid, String::from("coordinator"), request.opid)
+                        .with_ops(request.ops.clone())
+                        .with_abort_reason(Some(AbortReason::Recovery));
+                    if !self.force_or_halt(&abort) {
+                        return;
+                    }
+                    self.failed += 1;
+                    *self.abort_reasons.entry(AbortReason::Recovery).or_insert(0) += 1;
+                    *self.served.entry(request.senderid.clone()).or_insert(0) += 1;
+                    self.remember(*txid, Decision::Abort);
+                    abort
+                },
+                Some(decision) => {
+                    let abandoned = find(MessageType::CoordinatorAbandon).is_some();
+                    if !abandoned && last_decided != Some(*txid) {
+                        continue;
                     }
-                    self.log.append(cl_res.mtype, cl_res.txid, cl_res.senderid.clone(), cl_res.opid);
-                    let cl_send = self.client_data.get(&res.1).unwrap();
-                    let mut panic = false;
-                    let mut res = self.send(&cl_send.0, cl_res.clone(), &mut panic);
-                    if !res && !panic {
-                        while !res {
-                            res = self.send(&cl_send.0, cl_res.clone(), &mut panic);
-                            if panic {break; }
+                    if decision.mtype == MessageType::CoordinatorCommit && find(MessageType::CommitPoint).is_none() {
+                        let commit_point = ProtocolMessage::generate(MessageType::CommitPoint, *txid, String::from("coordinator"), decision.opid);
+                        if !self.log_or_halt(&commit_point) {
+                            return;
                         }
                     }
+                    info!("coordinator resending {:?} for txid {}, which an earlier run may not have delivered", decision.mtype, txid);
+                    ProtocolMessage::generate(decision.mtype, *txid, String::from("coordinator"), decision.opid)
+                        .with_segment(decision.segment)
+                        .with_ops(decision.ops.clone())
+                        .with_abort_reason(decision.abort_reason)
+                },
+            };
+            if request.read_only {
+                // its participants voted read-only: none is in doubt
+                continue;
+            }
+            self.redriven += 1;
+            let sent = self.participant_data.iter()
+                .filter(|(key, val)| self.send_to_participant(key, &val.0, mes.clone()))
+                .map(|(key, _val)| key.clone())
+                .collect();
+            self.await_acks(&mes, sent);
+        }
+    }
+
+    ///
+    /// drain_expired()
+    /// after CTRL-C we keep going until the in-flight txid is finished,
+    /// but no longer than the drain timeout. true once it has run out.
+    ///
+    fn drain_expired(&self) -> bool {
+        if self.running.load(Ordering::SeqCst) {
+            return false;
+        }
+        let started = self.drain_started.get().unwrap_or_else(Instant::now);
+        self.drain_started.set(Some(started));
+        started.elapsed() >= self.drain_timeout
+    }
+
+    ///
+    /// clients_in_turn()
+    /// the clients' names in the order they get a turn to have a request
+    /// taken: name order, starting with the one after the client we last
+    /// took one from, so no client is always looked at first
+    ///
+    fn clients_in_turn(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.client_data.keys().cloned().collect();
+        keys.sort();
+        if !keys.is_empty() {
+            let start = (self.last_serviced_index + 1) % keys.len();
+            keys.rotate_left(start);
+        }
+        keys
+    }
+
+    ///
+    /// took_request()
+    /// we're taking pm from client key: move the turn on past it
+    ///
+    fn took_request(&mut self, key: &str, pm: &ProtocolMessage) {
+        self.run.stepper.show("coordinator", "receives", pm);
+        self.clock.borrow_mut().observe(pm);
+        if pm.mtype == MessageType::ClientRequest && self.requested.contains(&pm.txid) {
+            self.resent_requests += 1;
+        } else if pm.mtype == MessageType::ClientRequest {
+            *self.requests_taken.entry(key.to_string()).or_insert(0) += 1;
+        }
+        let mut keys: Vec<&String> = self.client_data.keys().collect();
+        keys.sort();
+        self.last_serviced_index = keys.iter().position(|k| *k == key).unwrap_or(0);
+    }
+
+    /// 
+    /// recv_request()
+    /// receive a message from a client
+    /// to start off the protocol. clients with a request already waiting
+    /// take turns (see clients_in_turn()); with none waiting, wait on 
+    /// every client's channel at once, for up to request_poll, and take
+    /// whichever request arrives first.
+    /// 
+    pub fn recv_request(&mut self, found: &mut bool) -> (Option<ProtocolMessage>, String) {
+
+        // only ever waits for a request with nothing in flight
+        assert!(self.state == CoordinatorState::Active, "coordinator waiting for a request in state {:?}", self.state);
+        trace!("coordinator::recv_request...");
 
-                    self.all_voted = true;
-                    self.num_req_handled = self.num_req_handled + 1;
+        let mut gone = vec![];
+        let mut received = None;
+        for key in self.clients_in_turn() {
+            match self.client_data[&key].1.try_recv() {
+                Ok(pm) => {
+                    received = Some((key, pm));
+                    break;
+                },
+                Err(TryRecvError::Disconnected) => gone.push(key),
+                Err(TryRecvError::Empty) => {},
+            }
+        }
+        if received.is_none() {
+            let waiting: Vec<String> = self.clients_in_turn().into_iter().filter(|key| !gone.contains(key)).collect();
+            let rxs: Vec<&Receiver<ProtocolMessage>> = waiting.iter().map(|key| &self.client_data[key].1).collect();
+            let mut sel = Select::new();
+            for rx in rxs.iter() {
+                sel.recv(rx);
+            }
+            let deadline = Instant::now() + self.request_poll;
+            while let Ok(oper) = sel.select_deadline(deadline) {
+                let i = oper.index();
+                match oper.recv(rxs[i]) {
+                    Ok(pm) => {
+                        received = Some((waiting[i].clone(), pm));
+                        break;
+                    },
+                    // a client that has exited would otherwise be ready forever
+                    Err(_err) => {
+                        sel.remove(i);
+                        gone.push(waiting[i].clone());
+                    },
                 }
-            } else {
-                break;
             }
         }
+        for key in gone {
+            self.client_gone(&key);
+        }
 
-        active = self.running.load(Ordering::SeqCst);
-        if active {
-            let exit = message::ProtocolMessage::generate(MessageType::CoordinatorExit, -1, format!("coordinator"), -1);
-            for (key, val) in self.participant_data.iter() {
-                let mut panic = false;
-                let mut res = self.send(&val.0, exit.clone(), &mut panic);
-                if !res && !panic {
-                    while !res {
-                        res = self.send(&val.0, exit.clone(), &mut panic);
-                        if panic {break; }
-                    }
-                }
+        trace!("leaving coordinator::recv_request");
+        match received {
+            Some((key, pm)) => {
+                self.took_request(&key, &pm);
+                *found = true;
+                (Some(pm), key)
+            },
+            None => {
+                *found = false;
+                (None, String::from(""))
+            },
+        }
+    }        
+
+    ///
+    /// try_recv_request()
+    /// --max-inflight: like recv_request(), but only take a request that's
+    /// already waiting, so votes for the txids in flight aren't held up
+    ///
+    fn try_recv_request(&mut self, found: &mut bool) -> (Option<ProtocolMessage>, String) {
+        assert!(self.state == CoordinatorState::CollectingVotes, "coordinator admitting a request in state {:?}", self.state);
+        for key in self.clients_in_turn() {
+            match self.client_data[&key].1.try_recv() {
+                Ok(pm) => {
+                    self.took_request(&key, &pm);
+                    *found = true;
+                    return (Some(pm), key);
+                },
+                Err(TryRecvError::Disconnected) => self.client_gone(&key),
+                Err(TryRecvError::Empty) => {},
             }
-            for (key, val) in self.client_data.iter() {
-                let mut panic = false;
-                let mut res = self.send(&val.0, exit.clone(), &mut panic);
-                if !res && !panic {
-                    while !res {
-                        res = self.send(&val.0, exit.clone(), &mut panic);
-                        if panic {break; }
-                    }
+        }
+        *found = false;
+        (None, String::from(""))
+    }
+
+    ///
+    /// client_gone()
+    /// client key's channel disconnected: stop listening and sending to
+    /// it, and stop expecting the requests it still owed us
+    ///
+    fn client_gone(&mut self, key: &str) {
+        if self.client_data.remove(key).is_none() {
+            return;
+        }
+        let owed = (self.opts.num_requests - self.requests_taken.get(key).copied().unwrap_or(0)).max(0);
+        warn!("coordinator lost client {}: its channel disconnected with {} request(s) unsent", key, owed);
+        self.total_req -= owed;
+        self.gone_clients.insert(key.to_string());
+    }
+
+    ///
+    /// participant_gone()
+    /// participant key's channel disconnected: stop listening and sending
+    /// to it. its votes count as missing from here on, so nothing waits
+    /// for them.
+    ///
+    fn participant_gone(&mut self, key: &str) {
+        if self.participant_data.remove(key).is_none() {
+            return;
+        }
+        warn!("coordinator lost participant_{}: its channel disconnected, counting its votes missing from here on", key);
+        self.gone_participants.insert(key.to_string());
+    }
+
+    ///
+    /// has_all_votes()
+    /// whether tx has heard from every participant still connected
+    ///
+    fn has_all_votes(&self, tx: &TxState) -> bool {
+        self.participant_data.keys().all(|key| tx.votes.contains_key(key))
+    }
+
+    ///
+    /// log_or_halt()
+    /// append pm to the log. if the write fails (e.g. the disk is full) 
+    /// nothing that depends on pm may be sent, so the coordinator halts:
+    /// it stops handling requests and tells everyone to exit. returns 
+    /// true if pm was logged.
+    /// 
+    fn log_or_halt(&mut self, pm: &ProtocolMessage) -> bool {
+        if self.crashed.get() {
+            return false;
+        }
+        let record = self.clock.borrow_mut().stamp(pm.copy());
+        match self.log.append_record(record.clone()) {
+            Ok(()) => {
+                if let Some(ref replica) = self.replica {
+                    replica.ship(&record);
                 }
+                true
+            },
+            Err(err) => {
+                error!("coordinator failed to log {:?} for txid {}: {}; halting", pm.mtype, pm.txid, err);
+                self.halted = true;
+                false
             }
         }
+    }
 
-        self.running.store(false, Ordering::SeqCst);
+    ///
+    /// force_or_halt()
+    /// log_or_halt() a record that anyone may act on as soon as they hear
+    /// of it, i.e. a decision: it's written out (not just queued, with
+    /// --async-log) before this returns true, and with --durable forced
+    /// to stable storage (fsync) too
+    ///
+    fn force_or_halt(&mut self, pm: &ProtocolMessage) -> bool {
+        if !self.log_or_halt(pm) {
+            return false;
+        }
+        let written = if self.durable {
+            self.log.sync()
+        } else {
+            self.log.flush()
+        };
+        match written {
+            Ok(()) => true,
+            Err(err) => {
+                error!("coordinator failed to write {:?} for txid {} out to disk: {}; halting", pm.mtype, pm.txid, err);
+                self.halted = true;
+                false
+            }
+        }
+    }
+
+    ///
+    /// reply_to_client()
+    /// log the ClientResult* message for a transaction and send it to 
+    /// the client that requested it, retrying dropped sends
+    /// 
+    fn reply_to_client(&mut self, client: &str, cl_res: &ProtocolMessage) {
+
+        if !self.log_or_halt(cl_res) {
+            return;
+        }
+        let cl_send = match self.client_data.get(client) {
+            Some(cl_send) => cl_send,
+            None => {
+                debug!("coordinator not sending gone client {} its {:?} for txid {}", client, cl_res.mtype, cl_res.txid);
+                return;
+            }
+        };
+        if let Err(failure) = self.send_with_retry(&cl_send.0, cl_res.clone()) {
+            warn!("coordinator couldn't send {} its {:?} for txid {}: {:?}", client, cl_res.mtype, cl_res.txid, failure);
+        }
+    }
+
+    ///
+    /// already_taken()
+    /// whether pm is about a txid we've already taken the request for: an
+    /// inquiry, or the request again, resent by a client whose request or
+    /// result may have been lost. either way it's answered (see 
+    /// answer_inquiry()), not run again.
+    ///
+    fn already_taken(&self, pm: &ProtocolMessage) -> bool {
+        pm.mtype == MessageType::ClientInquire || self.requested.contains(&pm.txid)
+    }
+
+    ///
+    /// answer_inquiry()
+    /// a client that got no result for a txid is asking again. resend the
+    /// outcome if the txid has been decided; if it hasn't, stay quiet and
+    /// let the client ask again (or give up). an outcome evicted from 
+    /// the cache (--outcome-cache) is still in the log, as the result we
+    /// logged for the client.
+    /// 
+    fn answer_inquiry(&mut self, client: &str, inquiry: &ProtocolMessage) {
+
+        let cl_res = match self.outcome(inquiry.txid) {
+            Some(Decision::Commit(savepoint)) =>
+                message::ProtocolMessage::generate(MessageType::ClientResultCommit, inquiry.txid, String::from("coordinator"), inquiry.opid)
+                    .with_segment(savepoint),
+            Some(Decision::Abort) =>
+                message::ProtocolMessage::generate(MessageType::ClientResultAbort, inquiry.txid, String::from("coordinator"), inquiry.opid),
+            None => match self.logged_result(inquiry.txid) {
+                Some(cl_res) => cl_res,
+                None => {
+                    info!("coordinator has no outcome for {}'s inquiry on txid {}", client, inquiry.txid);
+                    return;
+                },
+            },
+        };
+        let cl_send = match self.client_data.get(client) {
+            Some(cl_send) => cl_send,
+            None => return,
+        };
+        if let Err(failure) = self.send_with_retry(&cl_send.0, cl_res.clone()) {
+            warn!("coordinator couldn't send {} its {:?} for txid {}: {:?}", client, cl_res.mtype, cl_res.txid, failure);
+        }
+    }
+
+    ///
+    /// answer_decision_request()
+    /// a participant recovering from a restart (see Participant::recover()),
+    /// or one that has waited too long for a decision, wants to know how
+    /// a txid it voted on ended. answer from the log if
+    /// it was decided (under presumed abort, a client told abort with no
+    /// decision logged means abort); if not, stay quiet: redrive() or the
+    /// transaction in flight will get the decision to it.
+    /// 
+    fn answer_decision_request(&self, key: &str, to: &Sender<ProtocolMessage>, request: &ProtocolMessage) {
+        let decision = {
+            let lck = self.log.arc();
+            let log = lck.lock().unwrap();
+            let find = |mtypes: &[MessageType]| log.values().find(|pm| pm.txid == request.txid && mtypes.contains(&pm.mtype)).cloned();
+            find(&[MessageType::CoordinatorCommit, MessageType::CoordinatorAbort])
+                .or_else(|| find(&[MessageType::ClientResultAbort]).map(|pm| ProtocolMessage { mtype: MessageType::CoordinatorAbort, ..pm }))
+        };
+        let decision = match decision {
+            Some(decision) => decision,
+            None => {
+                info!("coordinator has no decision for {}'s request on txid {}", request.senderid, request.txid);
+                return;
+            }
+        };
+        info!("coordinator telling {} txid {} ended in {:?}", request.senderid, request.txid, decision.mtype);
+        let answer = ProtocolMessage::generate(decision.mtype, decision.txid, String::from("coordinator"), decision.opid)
+            .with_segment(decision.segment)
+            .with_ops(decision.ops.clone());
+        if self.send_to_participant(key, to, answer.clone()) {
+            self.await_acks(&answer, BTreeSet::from([key.to_string()]));
+        }
+        self.decision_requests.set(self.decision_requests.get() + 1);
+    }
+
+    ///
+    /// outcome()
+    /// the decision logged for txid, if it has been decided and is still
+    /// in memory. None for txids that are undecided, unknown, or were 
+    /// evicted to keep the cache within --outcome-cache; under presumed
+    /// abort, treat those as aborted unless the log says otherwise.
+    ///
+    pub fn outcome(&self, txid: i32) -> Option<Decision> {
+        self.outcomes.get(&txid).copied()
+    }
+
+    ///
+    /// logged_result()
+    /// a copy of the ClientResult* we logged for txid, if there is one.
+    /// slow (it looks through the whole log), so only for outcomes that
+    /// outcome() no longer has.
+    ///
+    fn logged_result(&self, txid: i32) -> Option<ProtocolMessage> {
+        let lck = self.log.arc();
+        let log = lck.lock().unwrap();
+        log.values()
+            .find(|pm| pm.txid == txid && (pm.mtype == MessageType::ClientResultCommit || pm.mtype == MessageType::ClientResultAbort))
+            .map(|pm| pm.copy())
+    }
+
+    ///
+    /// remember()
+    /// keep txid's decision for outcome(), evicting the oldest outcome 
+    /// once there are more than outcome_cache of them
+    ///
+    fn remember(&mut self, txid: i32, decision: Decision) {
+        if self.outcomes.insert(txid, decision).is_none() {
+            self.outcome_order.push_back(txid);
+        }
+        if self.outcome_cache > 0 && self.outcomes.len() > self.outcome_cache {
+            if let Some(oldest) = self.outcome_order.pop_front() {
+                self.outcomes.remove(&oldest);
+            }
+        }
+    }
+
+    ///
+    /// await_exit_acks()
+    /// after broadcasting CoordinatorExit, give the participants up to 
+    /// exit_ack_timeout (all together) to ack it. one that doesn't is 
+    /// logged as an ExitAckTimeout and left behind: a stuck participant
+    /// mustn't keep the coordinator from shutting down. neither should 
+    /// one that's already gone without acking.
+    ///
+    fn await_exit_acks(&mut self) {
+        let deadline = Instant::now() + self.exit_ack_timeout;
+        let mut missing = vec![];
+        for (key, val) in self.participant_data.iter() {
+            loop {
+                match val.1.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(pm) => {
+                        self.run.stepper.show("coordinator", "receives", &pm);
+                        self.clock.borrow_mut().observe(&pm);
+                        if pm.mtype == MessageType::ParticipantExitAck {
+                            break;
+                        }
+                        trace!("coordinator skipping {:?} for txid {} while waiting for exit acks", pm.mtype, pm.txid);
+                    },
+                    Err(RecvTimeoutError::Timeout) => {
+                        missing.push((key.clone(), format!("coordinator gave up on participant_{}'s exit ack after {:?}", key, self.exit_ack_timeout)));
+                        break;
+                    },
+                    Err(RecvTimeoutError::Disconnected) => {
+                        missing.push((key.clone(), format!("participant_{} left without acking the coordinator's exit", key)));
+                        break;
+                    },
+                }
+            }
+        }
+        for (key, why) in missing {
+            warn!("{}", why);
+            self.exit_acks_missing += 1;
+            let timeout = ProtocolMessage::generate(MessageType::ExitAckTimeout, -1, format!("participant_{}", key), -1);
+            self.log_or_halt(&timeout);
+        }
+    }
+
+    ///
+    /// await_acks()
+    /// we sent decision mes to the participants in sent: expect each of
+    /// them to ack it (see chase_acks() for what happens if one doesn't)
+    ///
+    fn await_acks(&self, mes: &ProtocolMessage, sent: BTreeSet<String>) {
+        if sent.is_empty() {
+            return;
+        }
+        let mut awaiting = self.awaiting_acks.borrow_mut();
+        let waiting = awaiting.entry(mes.txid).or_insert_with(|| AwaitingAcks {
+            decision: mes.clone(),
+            pending: BTreeSet::new(),
+            due: Instant::now() + self.vote_timeout(),
+            resends: 0,
+        });
+        waiting.pending.extend(sent);
+    }
+
+    ///
+    /// note_decision_ack()
+    /// participant key acked the decision for ack's txid: log the ack, so
+    /// the checker can tell phase 2 reached it, and stop resending it the
+    /// decision. an ack we weren't waiting for (a repeat, or one we had
+    /// given up on) is skipped.
+    ///
+    fn note_decision_ack(&mut self, key: &str, ack: &ProtocolMessage) {
+        {
+            let mut awaiting = self.awaiting_acks.borrow_mut();
+            let fresh = match awaiting.get_mut(&ack.txid) {
+                Some(waiting) => waiting.pending.remove(key),
+                None => false,
+            };
+            if !fresh {
+                trace!("coordinator skipping participant_{}'s repeat ack for txid {}", key, ack.txid);
+                return;
+            }
+            if awaiting[&ack.txid].pending.is_empty() {
+                awaiting.remove(&ack.txid);
+            }
+        }
+        self.log_or_halt(ack);
+    }
+
+    ///
+    /// take_acks()
+    /// with nothing in flight: take whatever decision acks (and decision
+    /// requests) have arrived, without waiting. anything else waiting is
+    /// a stale vote or pre-commit ack, which nobody needs any more.
+    /// returns true if anything arrived.
+    ///
+    fn take_acks(&mut self) -> bool {
+        let mut heard = false;
+        let mut acks = vec![];
+        let mut gone = vec![];
+        for (key, val) in self.participant_data.iter() {
+            loop {
+                let value = match val.1.try_recv() {
+                    Ok(value) => value,
+                    Err(TryRecvError::Disconnected) => {
+                        gone.push(key.clone());
+                        break;
+                    },
+                    Err(TryRecvError::Empty) => break,
+                };
+                heard = true;
+                self.run.stepper.show("coordinator", "receives", &value);
+                self.clock.borrow_mut().observe(&value);
+                match value.mtype {
+                    MessageType::ParticipantDecisionAck => acks.push((key.clone(), value)),
+                    MessageType::ParticipantDecisionRequest => self.answer_decision_request(key, &val.0, &value),
+                    _ => trace!("coordinator skipping stale {:?} for txid {}", value.mtype, value.txid),
+                }
+            }
+        }
+        for (key, ack) in acks {
+            self.note_decision_ack(&key, &ack);
+        }
+        for key in gone {
+            self.participant_gone(&key);
+        }
+        heard
+    }
+
+    ///
+    /// chase_acks()
+    /// resend every decision whose acks are overdue to the participants
+    /// that haven't acked it: the decision or the ack may have been lost.
+    /// after MAX_DECISION_RESENDS resends, give up on them. a participant
+    /// that has gone, or that we gave up sending to, isn't waited on.
+    ///
+    fn chase_acks(&mut self) {
+        let now = Instant::now();
+        let overdue: Vec<i32> = self.awaiting_acks.borrow().iter()
+            .filter(|(_txid, waiting)| waiting.due <= now)
+            .map(|(txid, _waiting)| *txid)
+            .collect();
+        let mut resent = 0;
+        for txid in overdue {
+            let mut waiting = self.awaiting_acks.borrow_mut().remove(&txid).unwrap();
+            waiting.pending.retain(|key| self.participant_data.contains_key(key) && !self.unreachable.borrow().contains(key));
+            if waiting.resends >= MAX_DECISION_RESENDS {
+                for key in waiting.pending.iter() {
+                    warn!("coordinator giving up on participant_{}'s ack of {:?} for txid {}", key, waiting.decision.mtype, txid);
+                }
+                self.decision_acks_missing += waiting.pending.len() as i32;
+                continue;
+            }
+            for key in waiting.pending.iter() {
+                if self.cut_off(key) {
+                    continue;
+                }
+                info!("coordinator resending {:?} for txid {} to participant_{}, which hasn't acked it", waiting.decision.mtype, txid, key);
+                self.send_to_participant(key, &self.participant_data[key].0, waiting.decision.clone());
+                resent += 1;
+            }
+            waiting.resends += 1;
+            waiting.due = now + self.vote_timeout();
+            if !waiting.pending.is_empty() {
+                self.awaiting_acks.borrow_mut().insert(txid, waiting);
+            }
+        }
+        self.decisions_resent += resent;
+    }
+
+    ///
+    /// settle_acks()
+    /// at shutdown, before telling the participants to exit: wait for 
+    /// every decision still unacked to be acked or given up on (see 
+    /// chase_acks()), so the last txids get the same chances the rest did
+    ///
+    fn settle_acks(&mut self) {
+        while !self.awaiting_acks.borrow().is_empty() {
+            if !self.take_acks() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            self.chase_acks();
+        }
+    }
+
+    ///
+    /// report_status()
+    /// report the abort/commit/unknown status (aggregate) of all 
+    /// transaction requests made by this coordinator before exiting. 
+    /// 
+    pub fn report_status(&mut self) {
+        let successful_ops: i32 = self.successful; // TODO!
+        let failed_ops: i32 = self.failed; // TODO!
+        let unknown_ops: i32 = self.unknown; // TODO! 
+        println!("coordinator:\tC:{}\tA:{}\tU:{}\tseed:{}", successful_ops, failed_ops, unknown_ops, derive_seed(self.seed, "coordinator"));
+        if self.correlated_failure_prob > 0.0 {
+            println!("coordinator:\tcorrelated aborts:{}\tindependent aborts:{}", self.correlated_aborts, failed_ops - self.correlated_aborts);
+        }
+        if self.segments > 1 {
+            println!("coordinator:\tpartial commits:{}", self.partial_commits);
+        }
+        if self.deadline_aborts > 0 {
+            println!("coordinator:\tdeadline aborts:{}", self.deadline_aborts);
+        }
+        if !self.abort_reasons.is_empty() {
+            let counts: Vec<String> = self.abort_reasons.iter().map(|(reason, n)| format!("{}:{}", reason, n)).collect();
+            println!("coordinator:\taborts by reason: {}", counts.join(" "));
+        }
+        if !self.unreachable.borrow().is_empty() {
+            let names: Vec<String> = self.unreachable.borrow().iter().map(|key| format!("participant_{}", key)).collect();
+            println!("coordinator:\tgave up sending to unreachable {}", names.join(" "));
+        }
+        if self.votes_given_up.get() > 0 {
+            let names: Vec<String> = self.failed_participants.borrow().iter().map(|key| format!("participant_{}", key)).collect();
+            println!("coordinator:\tstopped waiting for {} vote(s) from participants that missed {} heartbeats{}", 
+                     self.votes_given_up.get(), self.max_missed,
+                     if names.is_empty() { String::new() } else { format!(" (still failed: {})", names.join(" ")) });
+        }
+        if self.decision_requests.get() > 0 {
+            println!("coordinator:\tanswered {} decision request(s) from recovering or waiting participants", self.decision_requests.get());
+        }
+        if self.resent_requests > 0 {
+            println!("coordinator:\tresent requests answered without re-running them:{}", self.resent_requests);
+        }
+        if self.redriven > 0 {
+            println!("coordinator:\tre-drove {} txid(s) earlier runs left in doubt", self.redriven);
+        }
+        if self.out_of_log_budget {
+            println!("coordinator:\tstopped at the log budget ({} bytes appended of {}) after {} of {} transactions", 
+                     self.run.bytes_appended(), self.log_budget.unwrap(), self.num_req_handled, self.total_req);
+        }
+        if self.under_replicated > 0 {
+            println!("coordinator:\tinsufficient replicas aborts:{}", self.under_replicated);
+        }
+        if self.scavenged > 0 {
+            println!("coordinator:\tscavenged (outlived max lifetime):{}", self.scavenged);
+        }
+        if self.exit_acks_missing > 0 {
+            println!("coordinator:\texit acks missing:{}", self.exit_acks_missing);
+        }
+        if !self.gone_participants.is_empty() || !self.gone_clients.is_empty() {
+            let names: Vec<String> = self.gone_participants.iter().map(|key| format!("participant_{}", key))
+                .chain(self.gone_clients.iter().map(|key| format!("client {}", key)))
+                .collect();
+            println!("coordinator:\tchannels disconnected mid-run: {}", names.join(", "));
+        }
+        if self.acks_missing > 0 {
+            println!("coordinator:\tpre-commit acks missing:{}", self.acks_missing);
+        }
+        if self.read_only_votes > 0 {
+            println!("coordinator:\tread-only votes on commits (no phase 2 sent):{}", self.read_only_votes);
+        }
+        if self.decisions_resent > 0 || self.decision_acks_missing > 0 {
+            println!("coordinator:\tdecisions resent for want of an ack:{}\tdecision acks missing:{}", self.decisions_resent, self.decision_acks_missing);
+        }
+        if self.crashed.get() {
+            println!("coordinator:\tcrashed, handing over to the backup");
+        }
+        if self.partition.is_some() {
+            let rate = |(c, a): (i32, i32)| if c + a > 0 { 100.0 * a as f64 / (c + a) as f64 } else { 0.0 };
+            println!("coordinator:\tduring partition C:{} A:{} ({:.0}% aborted)\toutside C:{} A:{} ({:.0}% aborted)",
+                     self.during_partition.0, self.during_partition.1, rate(self.during_partition),
+                     self.outside_partition.0, self.outside_partition.1, rate(self.outside_partition));
+            for (key, lost) in self.lost_proposals.iter() {
+                println!("coordinator:\tparticipant_{} missed {} proposal(s) while cut off", key, lost);
+            }
+        }
+        if let Some(index) = summary::fairness(&self.served) {
+            let counts: Vec<String> = self.served.iter().map(|(client, n)| format!("{}:{}", client, n)).collect();
+            println!("coordinator:\tserved per client: {}\tfairness (Jain):{:.3}", counts.join(" "), index);
+        }
+        if self.policy.name() != "unanimous" {
+            println!("coordinator:\tdecision policy:{}", self.policy.name());
+        }
+        if self.opts.measure_contention {
+            let (acquisitions, waited) = self.log.contention();
+            println!("coordinator:\toplog lock acquisitions:{}\twaited:{:?}", acquisitions, waited);
+        }
+    }    
+
+    ///
+    /// log_budget_exhausted()
+    /// --max-total-log-bytes: would one more transaction, as big as the
+    /// biggest so far, take the logs past their budget? if so, note it: 
+    /// the run stops here, the same way it does when the log fails
+    ///
+    fn log_budget_exhausted(&mut self) -> bool {
+        let budget = match self.log_budget {
+            Some(budget) => budget,
+            None => return false,
+        };
+        let appended = self.run.bytes_appended();
+        if appended + self.largest_txn_bytes <= budget {
+            return false;
+        }
+        warn!("coordinator stopping: logs have appended {} of their {} byte budget, and a transaction can take {}", 
+              appended, budget, self.largest_txn_bytes);
+        self.out_of_log_budget = true;
+        true
+    }
+
+    ///
+    /// set_state()
+    /// move the coordinator's state machine to `to`
+    ///
+    fn set_state(&mut self, to: CoordinatorState) {
+        if self.state != to {
+            debug!("coordinator {:?} -> {:?}", self.state, to);
+            self.state = to;
+        }
+    }
+
+    ///
+    /// vote_timeout()
+    /// how long to wait for a participant's vote. an operator paused at 
+    /// a step shouldn't make votes time out.
+    ///
+    fn vote_timeout(&self) -> Duration {
+        if self.run.stepper.enabled() {
+            Duration::from_secs(3600)
+        } else {
+            self.vote_timeout
+        }
+    }
+
+    ///
+    /// propose()
+    /// start on a client's request: log it, log the proposal and send it
+    /// to every participant. returns None if the coordinator has to stop
+    /// (the log failed or a scheduled crash fired).
+    ///
+    fn propose(&mut self, pm: ProtocolMessage, client: String) -> Option<TxState> {
+        let start_bytes = self.run.bytes_appended();
+        if !self.log_or_halt(&pm) {
+            self.unknown += 1;
+            return None;
+        }
+        assert_eq!(pm.mtype, MessageType::ClientRequest);
+        self.requested.insert(pm.txid);
+        let deadline = if pm.deadline_ms > 0 {
+            Some(Instant::now() + Duration::from_millis(pm.deadline_ms))
+        } else {
+            None
+        };
+        // each participant's vote gets its own timeout, so a 
+        // txid waiting on several dead ones could take a long
+        // time to settle: the lifetime caps the whole thing
+        let end_of_life = self.max_txn_lifetime.map(|lifetime| Instant::now() + lifetime);
+        if self.correlated_failure_prob > 0.0 && self.rng.borrow_mut().gen::<f64>() < self.correlated_failure_prob {
+            // one underlying fault, every participant in the blast radius votes abort
+            info!("coordinator injecting correlated fault for txid {}", pm.txid);
+            self.run.fault_oracle.lock().unwrap().insert(pm.txid);
+        }
+        let prepare = ProtocolMessage::generate(MessageType::CoordinatorPropose, pm.txid, String::from("coordinator"), pm.opid)
+            .with_ops(pm.ops.clone())
+            .with_read_only(pm.read_only);
+        if !self.log_or_halt(&prepare) {
+            self.unknown += 1;
+            return None;
+        }
+        // a partitioned participant never hears about the txid;
+        // retrying the proposal would only stall until it heals
+        let partitioned = self.partition.as_ref().is_some_and(|p| p.active());
+        let mut unreached = HashSet::new();
+        let mut reachable = vec![];
+        for (key, val) in self.participant_data.iter() {
+            if self.cut_off(key) {
+                info!("coordinator partitioned from participant_{}, proposal for txid {} lost", key, pm.txid);
+                *self.lost_proposals.entry(key.clone()).or_insert(0) += 1;
+                unreached.insert(key.clone());
+                continue;
+            }
+            if self.parallel_prepare {
+                reachable.push(key.clone());
+            } else {
+                self.send_to_participant(key, &val.0, prepare.clone());
+            }
+        }
+        if self.parallel_prepare {
+            self.broadcast(&reachable, &prepare);
+        }
+        if self.crashed.get() {
+            return None;
+        }
+        let mut tx = TxState {
+            request: pm,
+            client,
+            start_bytes,
+            deadline,
+            end_of_life,
+            votes_due: Instant::now(),
+            partitioned,
+            unreached,
+            given_up: HashSet::new(),
+            votes: HashMap::new(),
+            read_only: HashSet::new(),
+        };
+        tx.votes_due = self.votes_due(&tx);
+        Some(tx)
+    }
+
+    ///
+    /// votes_due()
+    /// --max-inflight: when votes for tx should be in if its vote timeout
+    /// starts now, capped by its deadline and lifetime
+    ///
+    fn votes_due(&self, tx: &TxState) -> Instant {
+        [tx.deadline, tx.end_of_life].iter().flatten()
+            .fold(Instant::now() + self.vote_timeout(), |due, d| due.min(*d))
+    }
+
+    ///
+    /// collect_votes()
+    /// wait for ready from all p, one at a time. a participant that
+    /// wasn't up yet (or was slow) can still be answering an older 
+    /// proposal, so skip votes for other txids until this one's vote
+    /// shows up or the timeout runs out. with savepoints on, each yes 
+    /// vote says which savepoint the participant got to. nobody waits 
+    /// past the client's deadline, if it gave one, or past the txid's 
+    /// lifetime. with heartbeats on, nobody waits on a participant that
+    /// has missed too many of them either, or on one that has gone. nor
+    /// on one a partition kept the proposal from: no vote is coming.
+    ///
+    fn collect_votes(&mut self, tx: &mut TxState) {
+        let vote_timeout = self.vote_timeout();
+        let mut acks = vec![];
+        let mut gone = vec![];
+        for (key, val) in self.participant_data.iter() {
+            if tx.unreached.contains(key) {
+                tx.votes.insert(key.clone(), Vote::Missing);
+                continue;
+            }
+            let mut deadline = Instant::now() + vote_timeout;
+            if let Some(tx_deadline) = tx.deadline {
+                deadline = deadline.min(tx_deadline);
+            }
+            if let Some(tx_end_of_life) = tx.end_of_life {
+                deadline = deadline.min(tx_end_of_life);
+            }
+            let vote = loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break Vote::Missing;
+                }
+                if self.participant_failed(key, &val.2) {
+                    debug!("coordinator not waiting on failed participant_{}'s vote for txid {}", key, tx.request.txid);
+                    self.votes_given_up.set(self.votes_given_up.get() + 1);
+                    tx.given_up.insert(key.clone());
+                    break Vote::Missing;
+                }
+                // wake up every heartbeat to see whether it's still alive
+                let wait = self.heartbeat.map_or(deadline - now, |interval| interval.min(deadline - now));
+                match val.1.recv_timeout(wait) {
+                    Ok(value) => {
+                        self.run.stepper.show("coordinator", "receives", &value);
+                        self.clock.borrow_mut().observe(&value);
+                        if value.mtype == MessageType::ParticipantDecisionRequest {
+                            self.answer_decision_request(key, &val.0, &value);
+                            continue;
+                        }
+                        if value.mtype == MessageType::ParticipantDecisionAck {
+                            acks.push((key.clone(), value));
+                            continue;
+                        }
+                        if value.txid != tx.request.txid {
+                            trace!("coordinator skipping stale vote for txid {}", value.txid);
+                            continue;
+                        }
+                        if self.cut_off(key) {
+                            info!("coordinator partitioned from participant_{}, vote for txid {} lost", key, value.txid);
+                            continue;
+                        }
+                        if value.read_only {
+                            tx.read_only.insert(key.clone());
+                        }
+                        break vote_of(&value);
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        gone.push(key.clone());
+                        break Vote::Missing;
+                    },
+                }
+            };
+            tx.votes.insert(key.clone(), vote);
+        }
+        for (key, ack) in acks {
+            self.note_decision_ack(&key, &ack);
+        }
+        for key in gone {
+            self.participant_gone(&key);
+        }
+    }
+
+    ///
+    /// select_votes()
+    /// --parallel-prepare: collect_votes(), but taking votes from all the
+    /// participants at once, in whatever order they arrive, rather than
+    /// waiting on each in turn. so the vote timeout runs for the round 
+    /// as a whole, not for each participant's vote.
+    ///
+    fn select_votes(&mut self, tx: &mut TxState) {
+        let deadline = self.votes_due(tx);
+        let mut acks = vec![];
+        let mut gone = vec![];
+        for key in tx.unreached.iter() {
+            tx.votes.insert(key.clone(), Vote::Missing);
+        }
+        loop {
+            for (key, val) in self.participant_data.iter() {
+                if !tx.votes.contains_key(key) && self.participant_failed(key, &val.2) {
+                    debug!("coordinator not waiting on failed participant_{}'s vote for txid {}", key, tx.request.txid);
+                    self.votes_given_up.set(self.votes_given_up.get() + 1);
+                    tx.given_up.insert(key.clone());
+                    tx.votes.insert(key.clone(), Vote::Missing);
+                }
+            }
+            let waiting: Vec<&String> = self.participant_data.keys().filter(|key| !tx.votes.contains_key(*key)).collect();
+            let now = Instant::now();
+            if waiting.is_empty() || now >= deadline {
+                break;
+            }
+            let mut sel = Select::new();
+            for key in waiting.iter() {
+                sel.recv(&self.participant_data[*key].1);
+            }
+            // wake up every heartbeat to see whether they're still alive
+            let wait = self.heartbeat.map_or(deadline - now, |interval| interval.min(deadline - now));
+            let oper = match sel.select_timeout(wait) {
+                Ok(oper) => oper,
+                Err(_timeout) => continue,
+            };
+            let key = waiting[oper.index()];
+            let val = &self.participant_data[key];
+            let value = match oper.recv(&val.1) {
+                Ok(value) => value,
+                Err(_disconnected) => {
+                    gone.push(key.clone());
+                    tx.votes.insert(key.clone(), Vote::Missing);
+                    continue;
+                },
+            };
+            self.run.stepper.show("coordinator", "receives", &value);
+            self.clock.borrow_mut().observe(&value);
+            if value.mtype == MessageType::ParticipantDecisionRequest {
+                self.answer_decision_request(key, &val.0, &value);
+                continue;
+            }
+            if value.mtype == MessageType::ParticipantDecisionAck {
+                acks.push((key.clone(), value));
+                continue;
+            }
+            if value.txid != tx.request.txid {
+                trace!("coordinator skipping stale vote for txid {}", value.txid);
+                continue;
+            }
+            if self.cut_off(key) {
+                info!("coordinator partitioned from participant_{}, vote for txid {} lost", key, value.txid);
+                continue;
+            }
+            if value.read_only {
+                tx.read_only.insert(key.clone());
+            }
+            tx.votes.insert(key.clone(), vote_of(&value));
+        }
+        for key in self.participant_data.keys() {
+            tx.votes.entry(key.clone()).or_insert(Vote::Missing);
+        }
+        for (key, ack) in acks {
+            self.note_decision_ack(&key, &ack);
+        }
+        for key in gone {
+            self.participant_gone(&key);
+        }
+    }
+
+    ///
+    /// gather_votes()
+    /// --max-inflight: take whatever votes have arrived for any txid in
+    /// flight (oldest first), until at least one txid has heard from
+    /// every participant. participants vote on proposals in the order 
+    /// they get them, so only the oldest txid can time out waiting for
+    /// votes (at votes_due); the rest are still queued up behind it. any
+    /// txid can run out its deadline or lifetime, though, and with 
+    /// heartbeats on, no txid waits on a participant that has failed.
+    /// none waits on one a partition kept its proposal from, either.
+    ///
+    fn gather_votes(&mut self, inflight: &mut VecDeque<TxState>) {
+        loop {
+            let mut heard = false;
+            let mut acks = vec![];
+            let mut gone = vec![];
+            for (key, val) in self.participant_data.iter() {
+                loop {
+                    let value = match val.1.try_recv() {
+                        Ok(value) => value,
+                        Err(TryRecvError::Disconnected) => {
+                            gone.push(key.clone());
+                            break;
+                        },
+                        Err(TryRecvError::Empty) => break,
+                    };
+                    heard = true;
+                    self.run.stepper.show("coordinator", "receives", &value);
+                    self.clock.borrow_mut().observe(&value);
+                    if value.mtype == MessageType::ParticipantDecisionRequest {
+                        self.answer_decision_request(key, &val.0, &value);
+                        continue;
+                    }
+                    if value.mtype == MessageType::ParticipantDecisionAck {
+                        acks.push((key.clone(), value));
+                        continue;
+                    }
+                    let tx = match inflight.iter_mut().find(|tx| tx.request.txid == value.txid) {
+                        Some(tx) if !tx.votes.contains_key(key) => tx,
+                        _ => {
+                            trace!("coordinator skipping stale vote for txid {}", value.txid);
+                            continue;
+                        }
+                    };
+                    if self.cut_off(key) {
+                        info!("coordinator partitioned from participant_{}, vote for txid {} lost", key, value.txid);
+                        continue;
+                    }
+                    if value.read_only {
+                        tx.read_only.insert(key.clone());
+                    }
+                    tx.votes.insert(key.clone(), vote_of(&value));
+                }
+            }
+            for (key, ack) in acks {
+                self.note_decision_ack(&key, &ack);
+            }
+            for key in gone {
+                self.participant_gone(&key);
+            }
+            let now = Instant::now();
+            let mut ready = false;
+            for (i, tx) in inflight.iter_mut().enumerate() {
+                let expired = [tx.deadline, tx.end_of_life].iter().flatten().any(|d| now >= *d);
+                if expired || (i == 0 && now >= tx.votes_due) {
+                    for key in self.participant_data.keys() {
+                        tx.votes.entry(key.clone()).or_insert(Vote::Missing);
+                    }
+                }
+                for key in tx.unreached.iter() {
+                    tx.votes.entry(key.clone()).or_insert(Vote::Missing);
+                }
+                for (key, val) in self.participant_data.iter() {
+                    if !tx.votes.contains_key(key) && self.participant_failed(key, &val.2) {
+                        debug!("coordinator not waiting on failed participant_{}'s vote for txid {}", key, tx.request.txid);
+                        self.votes_given_up.set(self.votes_given_up.get() + 1);
+                        tx.given_up.insert(key.clone());
+                        tx.votes.insert(key.clone(), Vote::Missing);
+                    }
+                }
+                ready |= self.has_all_votes(tx);
+            }
+            if ready {
+                return;
+            }
+            if !heard {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    ///
+    /// pre_commit()
+    /// --protocol 3pc: the round between voting and commit. log a 
+    /// pre-commit for tx through savepoint and send it to every 
+    /// participant (they all voted yes), then wait for their acks, each 
+    /// for up to the vote timeout. a missing ack doesn't hold the commit
+    /// up: a pre-committed participant that doesn't hear it commits on 
+    /// its own. returns false if the coordinator has to stop (the log 
+    /// failed or a scheduled crash fired).
+    ///
+    fn pre_commit(&mut self, tx: &TxState, savepoint: i32) -> bool {
+        let pm = &tx.request;
+        self.set_state(CoordinatorState::PreCommitting);
+        let precommit = message::ProtocolMessage::generate(MessageType::CoordinatorPreCommit, pm.txid, String::from("coordinator"), pm.opid)
+            .with_segment(savepoint)
+            .with_ops(pm.ops.clone());
+        if !self.force_or_halt(&precommit) {
+            return false;
+        }
+        let mut sent = vec![];
+        for (key, val) in self.participant_data.iter() {
+            if tx.unreached.contains(key) || self.cut_off(key) {
+                continue;
+            }
+            if self.send_to_participant(key, &val.0, precommit.clone()) {
+                sent.push(key.clone());
+            }
+        }
+        if self.crashed.get() {
+            return false;
+        }
+        let vote_timeout = self.vote_timeout();
+        let mut acks = vec![];
+        let mut gone = vec![];
+        for key in sent {
+            let val = &self.participant_data[&key];
+            let deadline = Instant::now() + vote_timeout;
+            let acked = loop {
+                let now = Instant::now();
+                if now >= deadline || self.participant_failed(&key, &val.2) {
+                    break false;
+                }
+                let wait = self.heartbeat.map_or(deadline - now, |interval| interval.min(deadline - now));
+                match val.1.recv_timeout(wait) {
+                    Ok(value) => {
+                        self.run.stepper.show("coordinator", "receives", &value);
+                        self.clock.borrow_mut().observe(&value);
+                        if value.mtype == MessageType::ParticipantDecisionRequest {
+                            self.answer_decision_request(&key, &val.0, &value);
+                            continue;
+                        }
+                        if value.mtype == MessageType::ParticipantDecisionAck {
+                            acks.push((key.clone(), value));
+                            continue;
+                        }
+                        if value.mtype == MessageType::ParticipantAck && value.txid == pm.txid {
+                            break true;
+                        }
+                        trace!("coordinator skipping stale {:?} for txid {}", value.mtype, value.txid);
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        gone.push(key.clone());
+                        break false;
+                    },
+                }
+            };
+            if !acked {
+                info!("coordinator committing txid {} without participant_{}'s pre-commit ack", pm.txid, key);
+                self.acks_missing += 1;
+            }
+        }
+        for (key, ack) in acks {
+            self.note_decision_ack(&key, &ack);
+        }
+        for key in gone {
+            self.participant_gone(&key);
+        }
+        true
+    }
+
+    ///
+    /// why_abort()
+    /// why tx's votes came to an abort: a participant's abort vote if 
+    /// there was one, otherwise what kept a vote from arriving. a vote
+    /// that never came from a participant the proposal reached and that
+    /// was still alive timed out (it was slow, or lost on its way back).
+    ///
+    fn why_abort(&self, tx: &TxState) -> AbortReason {
+        if tx.votes.values().any(|v| *v == Vote::Abort) {
+            return AbortReason::ParticipantVote;
+        }
+        let missing: Vec<&String> = self.participant_data.keys().chain(self.gone_participants.iter())
+            .filter(|key| tx.votes.get(*key).is_none_or(|v| *v == Vote::Missing))
+            .collect();
+        if missing.iter().any(|key| tx.unreached.contains(*key)) {
+            AbortReason::Unreachable
+        } else if missing.iter().any(|key| tx.given_up.contains(*key) || self.gone_participants.contains(*key)) {
+            AbortReason::ParticipantFailed
+        } else {
+            AbortReason::VoteTimeout
+        }
+    }
+
+    ///
+    /// conclude()
+    /// decide a txid whose votes are in, log the decision and send it to
+    /// the participants and the client. returns false if the coordinator
+    /// has to stop (the log failed or a scheduled crash fired).
+    ///
+    fn conclude(&mut self, tx: TxState) -> bool {
+        let pm = &tx.request;
+        // a participant that has gone still counts, its vote missing if
+        // it didn't get one in first
+        let votes: Vec<Vote> = self.participant_data.keys().chain(self.gone_participants.iter())
+            .map(|key| tx.votes.get(key).copied().unwrap_or(Vote::Missing))
+            .collect();
+
+        // send global to all part
+        let expired = tx.deadline.is_some_and(|d| Instant::now() >= d);
+        let outlived = tx.end_of_life.is_some_and(|d| Instant::now() >= d);
+        let (decision, reason) = if expired {
+            info!("coordinator aborting txid {}: its {}ms deadline ran out", pm.txid, pm.deadline_ms);
+            self.deadline_aborts += 1;
+            (Decision::Abort, Some(AbortReason::Deadline))
+        } else if outlived {
+            info!("coordinator scavenging txid {}: still undecided after {:?}", pm.txid, self.max_txn_lifetime.unwrap());
+            let scavenge = message::ProtocolMessage::generate(MessageType::CoordinatorScavenge, pm.txid, String::from("coordinator"), pm.opid);
+            if !self.log_or_halt(&scavenge) {
+                self.unknown += 1;
+                return false;
+            }
+            self.scavenged += 1;
+            (Decision::Abort, Some(AbortReason::Scavenged))
+        } else {
+            match self.policy.decide(&votes) {
+                Decision::Abort => {
+                    let reason = self.why_abort(&tx);
+                    info!("coordinator aborting txid {}: {}", pm.txid, reason);
+                    (Decision::Abort, Some(reason))
+                },
+                decision => (decision, None),
+            }
+        };
+        // a participant is live if it answered the proposal at
+        // all; committing with too few of them puts the data
+        // on too few replicas, however the policy counts yeses
+        let live = votes.iter().filter(|v| **v != Vote::Missing).count();
+        let (decision, reason) = if decision != Decision::Abort && live < self.min_live {
+            info!("coordinator aborting txid {}: insufficient replicas ({} live, need {})", pm.txid, live, self.min_live);
+            let insufficient = message::ProtocolMessage::generate(MessageType::InsufficientReplicas, pm.txid, String::from("coordinator"), pm.opid);
+            if !self.log_or_halt(&insufficient) {
+                self.unknown += 1;
+                return false;
+            }
+            self.under_replicated += 1;
+            (Decision::Abort, Some(AbortReason::InsufficientReplicas))
+        } else {
+            (decision, reason)
+        };
+        let commit = decision != Decision::Abort;
+        if let Decision::Commit(savepoint) = decision {
+            if self.three_phase && !self.pre_commit(&tx, savepoint) {
+                self.unknown += 1;
+                return false;
+            }
+        }
+        self.set_state(if commit { CoordinatorState::Committing } else { CoordinatorState::Aborting });
+        let mes = match decision {
+            Decision::Commit(savepoint) =>
+                message::ProtocolMessage::generate(MessageType::CoordinatorCommit, pm.txid, String::from("coordinator"), pm.opid)
+                    .with_segment(savepoint)
+                    .with_seqno(self.next_seqno),
+            Decision::Abort =>
+                message::ProtocolMessage::generate(MessageType::CoordinatorAbort, pm.txid, String::from("coordinator"), pm.opid)
+                    .with_abort_reason(reason),
+        }.with_ops(pm.ops.clone()).with_read_only(pm.read_only);
+
+        // under presumed abort an abort is neither logged nor 
+        // broadcast: the client's result is its only record, and a 
+        // participant that hears nothing presumes it
+        let presumed = !commit && self.presumed_abort;
+
+        // a decision that didn't make it to the log must never 
+        // reach a participant or client: leave the txid undecided.
+        // a commit and its CommitPoint are forced out together, so a
+        // commit that's durable always has its commit point logged 
+        // (nothing a participant logs about committing may come 
+        // before it), and one that isn't was never decided
+        let logged = if presumed {
+            true
+        } else if commit {
+            let commit_point = message::ProtocolMessage::generate(MessageType::CommitPoint, mes.txid, mes.senderid.clone(), mes.opid);
+            self.log_or_halt(&mes) && self.force_or_halt(&commit_point)
+        } else {
+            self.force_or_halt(&mes)
+        };
+        if !logged {
+            self.unknown += 1;
+            return false;
+        }
+        *self.served.entry(pm.senderid.clone()).or_insert(0) += 1;
+        if commit {
+            self.next_seqno += 1;
+            self.successful += 1;
+            if mes.segment != 0 && mes.segment < self.segments {
+                self.partial_commits += 1;
+            }
+            self.read_only_votes += tx.read_only.len() as i32;
+        } else {
+            self.failed += 1;
+            if let Some(reason) = reason {
+                *self.abort_reasons.entry(reason).or_insert(0) += 1;
+            }
+            if self.run.fault_oracle.lock().unwrap().contains(&pm.txid) {
+                self.correlated_aborts += 1;
+            }
+        }
+        if self.partition.is_some() {
+            let tally = if tx.partitioned { &mut self.during_partition } else { &mut self.outside_partition };
+            if commit {
+                tally.0 += 1;
+            } else {
+                tally.1 += 1;
+            }
+        }
+        let cl_res;
+        if commit {
+            cl_res = message::ProtocolMessage::generate(MessageType::ClientResultCommit, pm.txid, String::from("coordinator"), pm.opid)
+                .with_segment(mes.segment);
+        } else {
+            cl_res = message::ProtocolMessage::generate(MessageType::ClientResultAbort, pm.txid, String::from("coordinator"), pm.opid)
+                .with_deadline(if expired { pm.deadline_ms } else { 0 })
+                .with_abort_reason(reason);
+        }
+        self.remember(pm.txid, decision);
+        if self.early_client_reply {
+            // the client's answer only depends on the decision, which 
+            // is durable once logged (force_or_halt() above), so don't
+            // make it wait for the participant broadcast below. the 
+            // broadcast still happens right after, on this thread (not
+            // alongside the reply): each participant's channel has to 
+            // carry the decision ahead of our next proposal. sends don't
+            // block, so this only saves the client anything when the 
+            // broadcast does: retrying a dropped decision, or waiting on
+            // a cut off participant.
+            self.reply_to_client(&tx.client, &cl_res);
+        }
+
+        let mut undelivered = vec![];
+        let mut sent = BTreeSet::new();
+        for (key, val) in self.participant_data.iter() {
+            // a read-only transaction has nobody waiting on phase 2: 
+            // whoever didn't vote read-only never saw the proposal, or 
+            // did and its vote was lost
+            if presumed || tx.unreached.contains(key) || tx.read_only.contains(key) || pm.read_only {
+                continue;
+            }
+            // a participant cut off mid-transaction is waiting on
+            // the decision: keep resending it until the network heals,
+            // or CTRL-C's drain runs out of time
+            while self.cut_off(key) && !self.drain_expired() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if self.cut_off(key) {
+                undelivered.push(key.clone());
+                continue;
+            }
+            if self.send_to_participant(key, &val.0, mes.clone()) {
+                sent.insert(key.clone());
+            }
+        }
+        self.await_acks(&mes, sent);
+
+        if !self.early_client_reply {
+            self.reply_to_client(&tx.client, &cl_res);
+        }
+        if !undelivered.is_empty() {
+            warn!("coordinator drain timed out, abandoning txid {} undelivered to participant(s) {}", pm.txid, undelivered.join(","));
+            let abandon = ProtocolMessage::generate(MessageType::CoordinatorAbandon, pm.txid, String::from("coordinator"), pm.opid);
+            self.log_or_halt(&abandon);
+        }
+
+        self.num_req_handled = self.num_req_handled + 1;
+        self.largest_txn_bytes = self.largest_txn_bytes.max(self.run.bytes_appended() - tx.start_bytes);
+        !(self.halted || self.crashed.get())
+    }
+
+    ///
+    /// pipeline()
+    /// --max-inflight: the protocol loop with up to max_inflight txids 
+    /// proposed at once. requests keep being admitted while there's room,
+    /// votes are sorted out by txid, and each txid is decided as soon as
+    /// every participant has voted on it (see gather_votes() for when 
+    /// they time out). after CTRL-C or once the log budget runs out 
+    /// nothing new is admitted, but what's in flight is still seen 
+    /// through.
+    ///
+    fn pipeline(&mut self) {
+        // in the order they were proposed
+        let mut inflight: VecDeque<TxState> = VecDeque::new();
+        let mut admitting = true;
+        'run: loop {
+            if admitting && (!self.running.load(Ordering::SeqCst) || self.log_budget_exhausted()) {
+                admitting = false;
+            }
+            if admitting && self.num_req_handled + (inflight.len() as i32) >= self.total_req {
+                admitting = false;
+            }
+            if admitting && inflight.len() < self.max_inflight {
+                // with nothing in flight there's nothing else to do, so
+                // wait for a request the usual way
+                let mut found = false;
+                let res = if inflight.is_empty() {
+                    self.recv_request(&mut found)
+                } else {
+                    self.try_recv_request(&mut found)
+                };
+                if found {
+                    let pm = res.0.unwrap();
+                    if self.already_taken(&pm) {
+                        self.answer_inquiry(&res.1, &pm);
+                        continue;
+                    }
+                    match self.propose(pm, res.1) {
+                        Some(tx) => {
+                            inflight.push_back(tx);
+                            self.set_state(CoordinatorState::CollectingVotes);
+                            continue;
+                        },
+                        None => break,
+                    }
+                }
+            }
+            if inflight.is_empty() {
+                if admitting {
+                    continue;
+                }
+                break;
+            }
+            self.gather_votes(&mut inflight);
+            self.chase_acks();
+            let oldest = inflight.front().map(|tx| tx.request.txid);
+            let (ready, waiting): (VecDeque<TxState>, VecDeque<TxState>) = inflight.drain(..)
+                .partition(|tx| self.has_all_votes(tx));
+            inflight = waiting;
+            for tx in ready {
+                if !self.conclude(tx) {
+                    break 'run;
+                }
+            }
+            self.set_state(if inflight.is_empty() { CoordinatorState::Active } else { CoordinatorState::CollectingVotes });
+            // the participants may only now get to the new oldest txid, 
+            // so its vote timeout starts over
+            if inflight.front().map(|tx| tx.request.txid) != oldest {
+                if let Some(tx) = inflight.front_mut() {
+                    tx.votes_due = self.votes_due(tx);
+                }
+            }
+        }
+        if !inflight.is_empty() {
+            info!("coordinator stopped with {} txid(s) still in flight", inflight.len());
+        }
+    }
+
+    ///
+    /// protocol()
+    /// Implements the coordinator side of the 2PC protocol
+    /// HINT: if the simulation ends early, don't keep handling requests!
+    /// HINT: wait for some kind of exit signal before returning from the protocol!
+    /// 
+    pub fn protocol(&mut self) {
+
+        let mut active;
+        if self.total_req <= 0 {
+            info!("coordinator: no transactions were requested, going straight to shutdown");
+        }
+        self.set_state(CoordinatorState::Active);
+        if self.max_inflight > 1 {
+            self.pipeline();
+        } else {
+            while self.num_req_handled < self.total_req {
+                // nothing is in flight between transactions
+                self.take_acks();
+                self.chase_acks();
+                active = self.running.load(Ordering::SeqCst);
+                if active {
+                    if self.log_budget_exhausted() {
+                        break;
+                    }
+                    let mut found = false;
+                    let res = self.recv_request(&mut found);
+                    if found {
+                        let pm = res.0.unwrap();
+                        if self.already_taken(&pm) {
+                            self.answer_inquiry(&res.1, &pm);
+                            continue;
+                        }
+                        let mut tx = match self.propose(pm, res.1) {
+                            Some(tx) => tx,
+                            None => break,
+                        };
+                        self.set_state(CoordinatorState::CollectingVotes);
+                        if self.parallel_prepare {
+                            self.select_votes(&mut tx);
+                        } else {
+                            self.collect_votes(&mut tx);
+                        }
+                        if !self.conclude(tx) {
+                            break;
+                        }
+                        self.set_state(CoordinatorState::Active);
+                    }
+                } else {
+                    break;
+                }
+            }
+        }
+
+        self.set_state(CoordinatorState::Completed);
+        if self.crashed.get() {
+            // the backup takes it from here: no exit broadcast, and the
+            // run isn't over
+            self.report_status();
+            return;
+        }
+        if let Some(ref replica) = self.replica {
+            replica.shutdown();
+        }
+
+        active = self.running.load(Ordering::SeqCst);
+        if active {
+            if !self.halted {
+                self.settle_acks();
+            }
+            let exit = message::ProtocolMessage::generate(MessageType::CoordinatorExit, -1, format!("coordinator"), -1);
+            for (key, val) in self.participant_data.iter() {
+                self.send_to_participant(key, &val.0, exit.clone());
+            }
+            for (key, val) in self.client_data.iter() {
+                if let Err(failure) = self.send_with_retry(&val.0, exit.clone()) {
+                    warn!("coordinator couldn't tell client {} to exit: {:?}", key, failure);
+                }
+            }
+            self.await_exit_acks();
+        }
+
+        if let Err(err) = self.log.flush() {
+            error!("coordinator failed to write out its log: {}", err);
+        }
+        shutdown::stop(&self.running, &self.exit);
         self.report_status();
 
                                 
@@ -0,0 +1,97 @@
+//!
+//! dist.rs
+//! Delay distributions for queueing experiments: client think time
+//! (--think-dist) and participant service time (--service-dist). A spec
+//! names the distribution and its parameters, in milliseconds:
+//!
+//!     const:5             always 5ms
+//!     exp:10              exponential, mean 10ms
+//!     uniform:2,8         uniform between 2ms and 8ms
+//!     lognormal:10,0.5    lognormal with median 10ms, shape (sigma) 0.5
+//!
+extern crate rand;
+use self::rand::Rng;
+use std::fmt;
+use std::time::Duration;
+
+///
+/// Distribution
+/// a delay distribution, parameters in ms
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Constant(f64),
+    Exponential { mean: f64 },
+    Uniform { low: f64, high: f64 },
+    LogNormal { median: f64, sigma: f64 },
+}
+
+impl Distribution {
+
+    ///
+    /// from_string()
+    /// parse a spec like "exp:10" (see the top of this file)
+    ///
+    pub fn from_string(spec: &str) -> Distribution {
+        let (name, params) = spec.split_once(':').unwrap_or_else(|| panic!("distribution {} needs parameters, e.g. exp:10", spec));
+        let params: Vec<f64> = params.split(',').map(|p| p.parse::<f64>().unwrap()).collect();
+        let dist = match (name, params.as_slice()) {
+            ("const", [ms]) => Distribution::Constant(*ms),
+            ("exp", [mean]) => Distribution::Exponential { mean: *mean },
+            ("uniform", [low, high]) if low <= high => Distribution::Uniform { low: *low, high: *high },
+            ("lognormal", [median, sigma]) => Distribution::LogNormal { median: *median, sigma: *sigma },
+            _ => panic!("unknown distribution {} (const:<ms>, exp:<mean>, uniform:<low>,<high> or lognormal:<median>,<sigma>)", spec),
+        };
+        if params.iter().any(|p| *p < 0.0) {
+            panic!("distribution {} has a negative parameter", spec);
+        }
+        dist
+    }
+
+    ///
+    /// mean()
+    /// the distribution's mean, e.g. to compare with queueing models
+    ///
+    pub fn mean(&self) -> Duration {
+        let ms = match *self {
+            Distribution::Constant(ms) => ms,
+            Distribution::Exponential { mean } => mean,
+            Distribution::Uniform { low, high } => (low + high) / 2.0,
+            Distribution::LogNormal { median, sigma } => median * (sigma * sigma / 2.0).exp(),
+        };
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    ///
+    /// sample()
+    /// draw a delay using rng
+    ///
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        let ms = match *self {
+            Distribution::Constant(ms) => ms,
+            Distribution::Exponential { mean } => {
+                let u: f64 = rng.gen();
+                -(1.0 - u).ln() * mean
+            },
+            Distribution::Uniform { low, high } => low + rng.gen::<f64>() * (high - low),
+            Distribution::LogNormal { median, sigma } => {
+                // Box-Muller for a standard normal
+                let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+                let z = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                median * (sigma * z).exp()
+            },
+        };
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Distribution::Constant(ms) => write!(f, "const({}ms)", ms),
+            Distribution::Exponential { mean } => write!(f, "exp(mean {}ms)", mean),
+            Distribution::Uniform { low, high } => write!(f, "uniform({}ms..{}ms, mean {:?})", low, high, self.mean()),
+            Distribution::LogNormal { median, sigma } => write!(f, "lognormal(median {}ms, sigma {}, mean {:?})", median, sigma, self.mean()),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//!
+//! estimate.rs
+//! Dry-run cost estimate for a configuration (--estimate): log volume,
+//! disk space, threads and runtime, worked out from the options plus a
+//! short calibration of the log write path, without running anything.
+//!
+extern crate serde_json;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+use message::{MessageType, ProtocolMessage};
+use oplog::{OpLog, OpLogFormat};
+use tpcoptions::TPCOptions;
+use vclock::VectorClock;
+
+// records each component logs for one transaction (see protocol()):
+// the coordinator logs the request, proposal, decision, the commit
+// point (for a commit) and the client's result; a participant logs the
+// proposal, its vote and the decision, and the coordinator its ack of 
+// the decision (counted here, with the participant's records)
+const COORDINATOR_RECORDS: u64 = 5;
+const PARTICIPANT_RECORDS: u64 = 4;
+
+// how long the coordinator waits for a vote that was lost
+const VOTE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// calibration transactions to average the log write cost over
+const CALIBRATION_ROUNDS: u32 = 20;
+
+///
+/// sample_record()
+/// a record as large as any of its type will get in this run: widest
+/// ids, highest savepoint, and (with vector clocks) an entry for every
+/// component
+///
+fn sample_record(opts: &TPCOptions, mtype: MessageType, senderid: &str, last_uid: u64) -> ProtocolMessage {
+    let transactions = opts.num_clients * opts.num_requests;
+    let mut pm = ProtocolMessage::generate(mtype, transactions, senderid.to_string(), opts.num_requests);
+    pm.uid = last_uid.min(i32::MAX as u64) as i32;
+    if opts.segments > 1 {
+        pm = pm.with_segment(opts.segments);
+    }
+    if mtype == MessageType::ClientRequest {
+        pm = pm.with_deadline(opts.deadlines.iter().map(|(ms, _fraction)| *ms).max().unwrap_or(0));
+    }
+    if opts.vector_clocks {
+        let mut clock = VectorClock::new();
+        clock.insert(String::from("coordinator"), last_uid);
+        for n in 0..opts.num_participants {
+            clock.insert(format!("participant_{}", n), last_uid);
+        }
+        for n in 0..opts.num_clients {
+            clock.insert(format!("client_{}", n), last_uid);
+        }
+        pm.clock = clock;
+    }
+    if !opts.log_checksums {
+        pm.checksum = None;
+    }
+    pm
+}
+
+fn record_bytes(pm: &ProtocolMessage) -> u64 {
+    serde_json::to_vec(pm).unwrap().len() as u64 + 1
+}
+
+///
+/// calibrate()
+/// time the log appends for one transaction (the coordinator's and every
+/// participant's records) against a scratch log, averaged over a few rounds
+///
+fn calibrate(opts: &TPCOptions, records: &[ProtocolMessage]) -> Duration {
+    let path = std::env::temp_dir().join(format!("tpc-estimate-{}.log", process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let start = Instant::now();
+    {
+        let mut log = OpLog::with_options(path.clone(), 0, opts.async_log, opts.log_index, OpLogFormat::from_string(&opts.log_format));
+        if opts.log_checksums {
+            log.checksum_records();
+        }
+        for _ in 0..CALIBRATION_ROUNDS {
+            for pm in records.iter() {
+                log.append_record(pm.clone()).unwrap();
+            }
+        }
+        log.flush().unwrap();
+    }
+    let elapsed = start.elapsed();
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(format!("{}.idx", path));
+    elapsed / CALIBRATION_ROUNDS
+}
+
+fn human_bytes(n: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = n as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+///
+/// report()
+/// print the estimate for opts
+///
+pub fn report(opts: &TPCOptions) {
+    let transactions = (opts.num_clients * opts.num_requests) as u64;
+    let participants = opts.num_participants as u64;
+    let records_per_tx = COORDINATOR_RECORDS + PARTICIPANT_RECORDS * participants;
+    let total_records = transactions * records_per_tx;
+
+    let coordinator = [
+        sample_record(opts, MessageType::ClientRequest, &format!("Client_{}", opts.num_clients - 1), total_records),
+        sample_record(opts, MessageType::CoordinatorPropose, "coordinator", total_records),
+        sample_record(opts, MessageType::CoordinatorCommit, "coordinator", total_records),
+        sample_record(opts, MessageType::CommitPoint, "coordinator", total_records),
+        sample_record(opts, MessageType::ClientResultCommit, "coordinator", total_records),
+    ];
+    let participant = [
+        sample_record(opts, MessageType::CoordinatorPropose, "coordinator", total_records),
+        sample_record(opts, MessageType::ParticipantVoteCommit, &format!("participant_{}", participants.saturating_sub(1)), total_records),
+        sample_record(opts, MessageType::CoordinatorCommit, "coordinator", total_records),
+        sample_record(opts, MessageType::ParticipantDecisionAck, &format!("participant_{}", participants.saturating_sub(1)), total_records),
+    ];
+    let coordinator_bytes: u64 = coordinator.iter().map(record_bytes).sum();
+    let participant_bytes: u64 = participant.iter().map(record_bytes).sum();
+    let log_bytes = transactions * (coordinator_bytes + participant_bytes * participants);
+    // "<txid> <segment> <offset>\n", offsets as wide as the largest log
+    let index_bytes = if opts.log_index {
+        let width = |n: u64| n.to_string().len() as u64;
+        total_records * (width(transactions) + 1 + 1 + 1 + width(log_bytes) + 1)
+    } else {
+        0
+    };
+
+    // main, coordinator, one per client and participant, plus a
+    // background writer per log with --async-log
+    let mut threads = 2 + opts.num_clients as u64 + participants;
+    if opts.async_log {
+        threads += 1 + participants;
+    }
+
+    let mut calibration_records: Vec<ProtocolMessage> = coordinator.to_vec();
+    for _ in 0..participants {
+        calibration_records.extend(participant.iter().cloned());
+    }
+    let per_tx = calibrate(opts, &calibration_records);
+    let lost_votes = transactions as f64 * participants as f64 * (1.0 - opts.success_probability_msg);
+    let mut runtime = per_tx * transactions as u32 + VOTE_TIMEOUT.mul_f64(lost_votes);
+    if let Some(service) = opts.service_dist {
+        // participants work on a proposal in parallel
+        runtime += service.mean() * transactions as u32;
+    }
+    if opts.arrival_rate > 0.0 {
+        runtime = runtime.max(Duration::from_secs_f64(opts.num_requests as f64 / opts.arrival_rate));
+    }
+
+    println!("ESTIMATE (dry run, nothing was executed)");
+    println!("  transactions:\t{} ({} clients x {} requests)", transactions, opts.num_clients, opts.num_requests);
+    println!("  log records:\t{} ({} per transaction)", total_records, records_per_tx);
+    println!("  log bytes:\t{} (coordinator {}, each participant {})",
+             human_bytes(log_bytes), human_bytes(transactions * coordinator_bytes), human_bytes(transactions * participant_bytes));
+    if opts.log_index {
+        println!("  index bytes:\t{}", human_bytes(index_bytes));
+    }
+    println!("  disk needed:\t{} in {}", human_bytes(log_bytes + index_bytes), opts.logpath);
+    println!("  threads:\t{}", threads);
+    println!("  runtime:\t{:?} (calibrated {:?} of log writes per transaction)", runtime, per_tx);
+    println!("assumptions:");
+    println!("  - every transaction commits, so every record is logged and as large as it gets");
+    println!("  - transactions run one at a time; runtime is dominated by log writes");
+    if opts.success_probability_msg < 1.0 {
+        println!("  - each lost vote (expected {:.0}) costs a {:?} vote timeout", lost_votes, VOTE_TIMEOUT);
+    }
+    if let Some(service) = opts.service_dist {
+        println!("  - each transaction waits one mean service time, {:?}; think time overlaps other clients' transactions", service.mean());
+    }
+    if opts.arrival_rate > 0.0 {
+        println!("  - open loop: no shorter than {} requests at {}/s per client", opts.num_requests, opts.arrival_rate);
+    }
+    println!("  - calibration wrote to {}, which may not be as fast as {}", std::env::temp_dir().display(), opts.logpath);
+}
@@ -0,0 +1,148 @@
+//!
+//! failure.rs
+//! Which messages get lost and which operations fail. The coordinator
+//! and every participant hold a FailureModel (chosen with
+//! --failure-model) and ask it before each unreliable send and each
+//! operation. Implement the trait and add it to from_string() to plug
+//! in another.
+//!
+extern crate rand;
+use std::cell::{Cell, RefCell};
+use std::fmt::Debug;
+use failure::rand::prelude::*;
+use failure::rand::rngs::StdRng;
+use coordinator::derive_seed;
+use message::{MessageType, ProtocolMessage};
+
+pub trait FailureModel: Debug + Send {
+    /// the name the model is selected and reported by
+    fn name(&self) -> String;
+    /// whether msg makes it to its destination (false: it's lost)
+    fn should_deliver(&self, msg: &ProtocolMessage) -> bool;
+    /// whether the next operation succeeds
+    fn should_op_succeed(&self) -> bool;
+    /// --warmup-success-ramp: scale the chance an operation succeeds by
+    /// fraction (0..1) from now on. models that don't ramp ignore it.
+    fn ramp(&self, _fraction: f64) {}
+}
+
+///
+/// BernoulliModel
+/// every message gets through with probability -S and every operation
+/// succeeds with probability -s, each independently of the last. draws
+/// come from the component's seeded stream in the order golden.rs
+/// replays them, so a message sent with -S 1.0 draws nothing.
+///
+#[derive(Debug)]
+pub struct BernoulliModel {
+    op_success_prob: f64,
+    msg_success_prob: f64,
+    fraction: Cell<f64>,        // the warmup ramp (1 = warmed up)
+    rng: RefCell<StdRng>,
+    ack_rng: RefCell<StdRng>,   // draws which decision acks (and requests for a decision) are lost, apart from rng so they don't shift its stream
+}
+
+impl BernoulliModel {
+    pub fn new(op_success_prob: f64, msg_success_prob: f64, seed: u64) -> BernoulliModel {
+        BernoulliModel {
+            op_success_prob,
+            msg_success_prob,
+            fraction: Cell::new(1.0),
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+            ack_rng: RefCell::new(StdRng::seed_from_u64(derive_seed(seed, "acks"))),
+        }
+    }
+}
+
+impl FailureModel for BernoulliModel {
+    fn name(&self) -> String {
+        String::from("bernoulli")
+    }
+    fn should_deliver(&self, msg: &ProtocolMessage) -> bool {
+        // how many of these get sent depends on timing, not just on 
+        // the proposals handled
+        if msg.mtype == MessageType::ParticipantDecisionAck || msg.mtype == MessageType::ParticipantDecisionRequest {
+            return self.ack_rng.borrow_mut().gen::<f64>() < self.msg_success_prob;
+        }
+        self.msg_success_prob == 1.0 || self.rng.borrow_mut().gen::<f64>() < self.msg_success_prob
+    }
+    fn should_op_succeed(&self) -> bool {
+        self.rng.borrow_mut().gen::<f64>() <= self.op_success_prob * self.fraction.get()
+    }
+    fn ramp(&self, fraction: f64) {
+        self.fraction.set(fraction);
+    }
+}
+
+///
+/// BurstyModel
+/// failures come in clustered windows rather than one at a time: each
+/// send or operation outside a burst starts one with probability
+/// burst_prob, and it and the burst_len - 1 after it all fail. outside
+/// a burst everything succeeds (-s and -S are ignored).
+///
+#[derive(Debug)]
+pub struct BurstyModel {
+    pub burst_prob: f64,
+    pub burst_len: u32,
+    remaining: Cell<u32>,       // failures left in the current burst
+    rng: RefCell<StdRng>,
+}
+
+impl BurstyModel {
+    pub fn new(burst_prob: f64, burst_len: u32, seed: u64) -> BurstyModel {
+        BurstyModel {
+            burst_prob,
+            burst_len,
+            remaining: Cell::new(0),
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    ///
+    /// next_fails()
+    /// whether the next send or operation falls in a burst, starting
+    /// one if the draw says to
+    ///
+    fn next_fails(&self) -> bool {
+        if self.remaining.get() == 0 && self.rng.borrow_mut().gen::<f64>() < self.burst_prob {
+            self.remaining.set(self.burst_len);
+        }
+        if self.remaining.get() == 0 {
+            return false;
+        }
+        self.remaining.set(self.remaining.get() - 1);
+        true
+    }
+}
+
+impl FailureModel for BurstyModel {
+    fn name(&self) -> String {
+        format!("bursty:{}:{}", self.burst_prob, self.burst_len)
+    }
+    fn should_deliver(&self, _msg: &ProtocolMessage) -> bool {
+        !self.next_fails()
+    }
+    fn should_op_succeed(&self) -> bool {
+        !self.next_fails()
+    }
+}
+
+///
+/// from_string()
+/// the model named by --failure-model: "bernoulli" (with -s and -S) or
+/// "bursty:<burst prob>:<burst len>", drawing from seed
+///
+pub fn from_string(s: &str, op_success_prob: f64, msg_success_prob: f64, seed: u64) -> Box<dyn FailureModel> {
+    match s {
+        "bernoulli" => Box::new(BernoulliModel::new(op_success_prob, msg_success_prob, seed)),
+        _ if s.starts_with("bursty:") => {
+            let params: Vec<&str> = s["bursty:".len()..].split(':').collect();
+            if params.len() != 2 {
+                panic!("--failure-model bursty takes bursty:<burst prob>:<burst len>, not {}", s);
+            }
+            Box::new(BurstyModel::new(params[0].parse::<f64>().unwrap(), params[1].parse::<u32>().unwrap(), seed))
+        },
+        _ => panic!("unknown failure model {}", s),
+    }
+}
@@ -0,0 +1,123 @@
+//!
+//! faults.rs
+//! Deterministic fault injection from a schedule file (--fault-schedule).
+//!
+//! The schedule has one fault per line:
+//!
+//!     <txid> <message type> <component> <action>
+//!
+//! e.g.
+//!
+//!     # lose participant_1's commit vote for txid 3
+//!     3 ParticipantVoteCommit participant_1 drop
+//!     # hold the coordinator's commit for txid 5 back by 200ms
+//!     5 CoordinatorCommit coordinator delay:200
+//!     # participant_2 dies as the proposal for txid 7 arrives
+//!     7 CoordinatorPropose participant_2 crash
+//!
+//! A fault fires the first time <component> handles a <message type>
+//! message for <txid>: the coordinator when it sends one, a participant
+//! when it sends or receives one. Actions are:
+//!
+//!     drop        the message is lost (a sender that retries will resend it)
+//!     delay:<ms>  the message is held up for <ms> milliseconds
+//!     crash       the participant stops handling messages for the rest
+//!                 of the run. the coordinator only crashes with 
+//!                 --replicate, where its backup takes over (replica.rs)
+//!
+//! Each fault fires at most once. Blank lines and lines starting with #
+//! are skipped, and faults that never match anything are ignored.
+//!
+extern crate serde_json;
+use std::fs;
+use std::time::Duration;
+use message::MessageType;
+
+///
+/// FaultAction
+/// what happens to a message a fault matches
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultAction {
+    Drop,
+    Delay(Duration),
+    Crash,
+}
+
+#[derive(Clone, Debug)]
+struct Fault {
+    txid: i32,
+    mtype: MessageType,
+    component: String,
+    action: FaultAction,
+}
+
+///
+/// FaultSchedule
+/// the faults still to fire for one component
+///
+#[derive(Clone, Debug, Default)]
+pub struct FaultSchedule {
+    faults: Vec<Fault>,
+}
+
+impl FaultAction {
+    fn from_string(s: &str) -> FaultAction {
+        match s {
+            "drop" => FaultAction::Drop,
+            "crash" => FaultAction::Crash,
+            _ if s.starts_with("delay:") =>
+                FaultAction::Delay(Duration::from_millis(s["delay:".len()..].parse::<u64>().unwrap())),
+            _ => panic!("unknown fault action {}", s),
+        }
+    }
+}
+
+impl FaultSchedule {
+
+    ///
+    /// for_component()
+    /// the faults in the schedule file at path (if any) that apply to
+    /// component, e.g. "coordinator" or "participant_2"
+    ///
+    pub fn for_component(path: Option<&str>, component: &str) -> FaultSchedule {
+        let path = match path {
+            Some(path) => path,
+            None => return FaultSchedule::default(),
+        };
+        let contents = fs::read_to_string(path).unwrap();
+        let mut faults = vec![];
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                panic!("{}:{}: expected <txid> <message type> <component> <action>", path, n + 1);
+            }
+            let fault = Fault {
+                txid: fields[0].parse::<i32>().unwrap(),
+                mtype: serde_json::from_str(&format!("\"{}\"", fields[1])).unwrap(),
+                component: fields[2].to_string(),
+                action: FaultAction::from_string(fields[3]),
+            };
+            if fault.component == component {
+                faults.push(fault);
+            }
+        }
+        FaultSchedule { faults }
+    }
+
+    ///
+    /// take()
+    /// if a fault is scheduled for a txid/mtype message, remove it from
+    /// the schedule and return its action
+    ///
+    pub fn take(&mut self, txid: i32, mtype: MessageType) -> Option<FaultAction> {
+        let i = self.faults.iter().position(|f| f.txid == txid && f.mtype == mtype)?;
+        let fault = self.faults.remove(i);
+        info!("{} injecting scheduled fault {:?} on {:?} for txid {}", fault.component, fault.action, mtype, txid);
+        Some(fault.action)
+    }
+}
@@ -0,0 +1,85 @@
+//!
+//! golden.rs
+//! In-memory reference model of 2PC for model-based testing. Given the
+//! run's seed and options, and the proposals each participant handled,
+//! it works out synchronously (no threads, no timeouts) what every
+//! participant voted and so what the coordinator should have decided.
+//! The checker compares this against the coordinator's log.
+//!
+extern crate rand;
+use golden::rand::prelude::*;
+use std::collections::HashMap;
+use coordinator::derive_seed;
+use tpcoptions::TPCOptions;
+
+///
+/// Outcome
+/// the model's decision for a txid
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Commit(i32),    // commit through this savepoint (0 = whole transaction)
+    Abort,
+    Failed,         // a failure was injected: a vote was lost or never cast
+}
+
+///
+/// outcomes()
+/// replay every participant's random stream over the proposals it
+/// handled, in the order it handled them, making the same draws in the
+/// same order as Participant::perform_operation: the operation (each of
+/// them, up to the first that fails, with --ops-per-txn), then any 
+/// further savepoint segments, then (with -S < 1) the vote send.
+///
+/// <params>
+///     opts: the options the run used
+///     seed: the run's global seed
+///     proposals: participant id -> txids it received proposals for, in order
+///
+pub fn outcomes(
+    opts: &TPCOptions,
+    seed: u64,
+    proposals: &HashMap<i32, Vec<i32>>
+    ) -> HashMap<i32, Outcome> {
+
+    let mut votes: HashMap<i32, Vec<Outcome>> = HashMap::new();
+    for (id, txids) in proposals.iter() {
+        let mut rng = StdRng::seed_from_u64(derive_seed(seed, &format!("participant_{}", id)));
+        let op_success_prob = opts.op_success_prob(*id);
+        for txid in txids.iter() {
+            let failed = (0..opts.ops_per_txn.max(1)).any(|_| rng.gen::<f64>() > op_success_prob);
+            let mut vote = if failed {
+                Outcome::Abort
+            } else if opts.segments <= 1 {
+                Outcome::Commit(0)
+            } else {
+                let mut done = 1;
+                while done < opts.segments && rng.gen::<f64>() <= op_success_prob {
+                    done += 1;
+                }
+                Outcome::Commit(done)
+            };
+            if opts.success_probability_msg != 1.0 && rng.gen::<f64>() >= opts.success_probability_msg {
+                vote = Outcome::Failed;
+            }
+            votes.entry(*txid).or_default().push(vote);
+        }
+    }
+
+    votes.into_iter()
+        .map(|(txid, v)| {
+            let outcome = if v.len() < opts.num_participants as usize || v.contains(&Outcome::Failed) {
+                Outcome::Failed
+            } else if v.contains(&Outcome::Abort) {
+                Outcome::Abort
+            } else {
+                let savepoint = v.iter()
+                    .filter_map(|o| match o { Outcome::Commit(s) => Some(*s), _ => None })
+                    .min()
+                    .unwrap_or(0);
+                Outcome::Commit(savepoint)
+            };
+            (txid, outcome)
+        })
+        .collect()
+}
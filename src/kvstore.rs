@@ -0,0 +1,162 @@
+//!
+//! kvstore.rs
+//! A tiny key-value store per participant (--kv-store), so transactions
+//! change real data rather than just logging votes. Every participant
+//! holds a full replica of the same --kv-keys keys. A transaction writes
+//! one key per segment, chosen and valued by its txid (so no request
+//! needs to carry data): a yes vote stages the writes, a commit applies
+//! them (only as far as the agreed savepoint), and an abort throws them
+//! away. At exit each participant saves its store as JSON in
+//! participant_<n>.state next to its log, where the checker compares it
+//! with what replaying the coordinator's commits says it should hold.
+//!
+extern crate serde;
+extern crate serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use message::{MessageType, ProtocolMessage};
+
+///
+/// State
+/// what a participant's .state file holds
+///
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    pub keys: i32,
+    pub segments: i32,
+    pub data: BTreeMap<String, String>,
+}
+
+///
+/// KvStore
+/// a participant's committed data, plus the writes of the transactions
+/// it voted yes on and hasn't heard a decision for
+///
+#[derive(Debug)]
+pub struct KvStore {
+    path: String,
+    state: State,
+    staged: HashMap<i32, Vec<(String, String)>>,
+}
+
+///
+/// state_path()
+/// where participant (e.g. "participant_0") keeps its store
+///
+pub fn state_path(logpathbase: &str, participant: &str) -> String {
+    format!("{}{}.state", logpathbase, participant)
+}
+
+///
+/// writes()
+/// the (key, value) pairs txid writes, one per segment, in segment
+/// order. the value says which txid and segment wrote it.
+///
+pub fn writes(txid: i32, keys: i32, segments: i32) -> Vec<(String, String)> {
+    (1..=segments)
+        .map(|segment| {
+            let key = (txid as i64 * segments as i64 + segment as i64 - 1).rem_euclid(keys as i64);
+            (format!("k{}", key), format!("{}.{}", txid, segment))
+        })
+        .collect()
+}
+
+///
+/// apply()
+/// apply txid's writes through savepoint (0 = all of them) to data
+///
+fn apply(data: &mut BTreeMap<String, String>, txid_writes: Vec<(String, String)>, savepoint: i32) {
+    let n = if savepoint == 0 { txid_writes.len() } else { savepoint as usize };
+    for (key, value) in txid_writes.into_iter().take(n) {
+        data.insert(key, value);
+    }
+}
+
+///
+/// load()
+/// read a .state file
+///
+pub fn load(path: &str) -> io::Result<State> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+///
+/// replay()
+/// the data every participant should hold: the writes of every
+/// CoordinatorCommit in coord_log, applied in the order they were logged
+/// (a read-only transaction's commit has no writes to apply)
+///
+pub fn replay(coord_log: &HashMap<i32, ProtocolMessage>, keys: i32, segments: i32) -> BTreeMap<String, String> {
+    let mut commits: Vec<&ProtocolMessage> = coord_log.values()
+        .filter(|pm| pm.mtype == MessageType::CoordinatorCommit && !pm.read_only)
+        .collect();
+    commits.sort_by_key(|pm| pm.uid);
+    let mut data = BTreeMap::new();
+    for pm in commits {
+        apply(&mut data, writes(pm.txid, keys, segments), pm.segment);
+    }
+    data
+}
+
+impl KvStore {
+
+    ///
+    /// new()
+    /// an empty store kept at path, or (resume) whatever an earlier run
+    /// left there
+    ///
+    pub fn new(path: String, keys: i32, segments: i32, resume: bool) -> KvStore {
+        let mut state = State {
+            keys,
+            segments,
+            data: BTreeMap::new(),
+        };
+        if resume {
+            if let Ok(earlier) = load(&path) {
+                state.data = earlier.data;
+            }
+        }
+        KvStore {
+            path,
+            state,
+            staged: HashMap::new(),
+        }
+    }
+
+    ///
+    /// stage()
+    /// we voted yes on txid: hold on to its writes until the decision
+    ///
+    pub fn stage(&mut self, txid: i32) {
+        self.staged.insert(txid, writes(txid, self.state.keys, self.state.segments));
+    }
+
+    ///
+    /// commit()
+    /// apply txid's staged writes through savepoint (0 = all of them)
+    ///
+    pub fn commit(&mut self, txid: i32, savepoint: i32) {
+        if let Some(txid_writes) = self.staged.remove(&txid) {
+            apply(&mut self.state.data, txid_writes, savepoint);
+        }
+    }
+
+    ///
+    /// abort()
+    /// discard txid's staged writes
+    ///
+    pub fn abort(&mut self, txid: i32) {
+        self.staged.remove(&txid);
+    }
+
+    ///
+    /// save()
+    /// write the committed data to the store's .state file. anything
+    /// still staged was never decided, so it isn't saved.
+    ///
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.state).unwrap())
+    }
+}
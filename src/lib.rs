@@ -0,0 +1,41 @@
+//!
+//! lib.rs
+//! The simulator as a library, so a test or another binary can drive a
+//! run (simulation::run()) and look at what came of it. main.rs is the
+//! command line on top of it.
+//!
+#[macro_use]
+extern crate log;
+extern crate stderrlog;
+extern crate clap;
+extern crate ctrlc;
+extern crate rand;
+pub mod message;
+pub mod oplog;
+pub mod coordinator;
+pub mod participant;
+pub mod client;
+pub mod checker;
+pub mod tpcoptions;
+pub mod stepper;
+pub mod faults;
+pub mod golden;
+pub mod vclock;
+pub mod summary;
+pub mod resultsdb;
+pub mod report;
+pub mod policy;
+pub mod failure;
+pub mod estimate;
+pub mod partition;
+pub mod seal;
+pub mod replica;
+pub mod kvstore;
+pub mod dist;
+pub mod verify;
+pub mod merge;
+pub mod shutdown;
+pub mod runstate;
+pub mod netdelay;
+pub mod pool;
+pub mod simulation;
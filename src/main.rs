@@ -1,228 +1,67 @@
 #[macro_use]
 extern crate log;
 extern crate stderrlog;
-extern crate clap;
-extern crate ctrlc;
-use std::thread;
-use std::thread::JoinHandle;
-pub mod message;
-pub mod oplog;
-pub mod coordinator;
-pub mod participant;
-pub mod client;
-pub mod checker;
-pub mod tpcoptions;
-use coordinator::Coordinator;
-use participant::Participant;
-use client::Client;
-use std::sync::{Arc};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration, Instant};
-///
-/// register_clients()
-/// 
-/// The coordinator needs to know about all clients. 
-/// This function should create clients and use some communication 
-/// primitive to ensure the coordinator and clients are aware of 
-/// each other and able to exchange messages. Starting threads to run the
-/// client protocol should be deferred until after all the communication 
-/// structures are created. 
-/// 
-/// HINT: you probably want to look at rust's mpsc::channel or crossbeam 
-///       channels to set up communication. Communication in 2PC 
-///       is duplex!
-/// 
-/// HINT: read the logpathbase documentation carefully.
-/// 
-/// <params>
-///     coordinator: the coordinator!
-///     n_clients: number of clients to create and register
-///     logpathbase: each participant, client, and the coordinator 
-///         needs to maintain its own operation and commit log. 
-///         The project checker assumes a specific directory structure 
-///         for files backing these logs. Concretely, participant log files 
-///         will be expected to be produced in:
-///            logpathbase/client_<num>.log
-///     running: atomic bool indicating whether the simulation is still running
-///
-fn register_clients(
-    coordinator: &mut Coordinator,
-    n_clients: i32) -> Vec<Client> {
+extern crate concurrency_2pc;
+use concurrency_2pc::{checker, dist, estimate, merge, seal, simulation, summary, tpcoptions, verify};
+use std::time::Duration;
 
-    let mut clients = vec![];
-    for i in 0..n_clients {
-        let client = coordinator.client_join(format!("{}", i));
-        clients.push(client);
-    }
-    // register clients with coordinator (set up communication channels and sync objects)
-    // add client to the vector and return the vector.
-    clients
-}
-
-/// 
-/// register_participants()
-/// 
-/// The coordinator needs to know about all participants. 
-/// This function should create participants and use some communication 
-/// primitive to ensure the coordinator and participants are aware of 
-/// each other and able to exchange messages. Starting threads to run the
-/// participant protocol should be deferred until after all the communication 
-/// structures are created. 
-/// 
-/// HINT: you probably want to look at rust's mpsc::channel or crossbeam 
-///       channels to set up communication. Note that communication in 2PC 
-///       is duplex!
-/// 
-/// HINT: read the logpathbase documentation carefully.
+///
+/// report_latencies()
 /// 
-/// <params>
-///     coordinator: the coordinator!
-///     n_participants: number of participants to create an register
-///     logpathbase: each participant, client, and the coordinator 
-///         needs to maintain its own operation and commit log. 
-///         The project checker assumes a specific directory structure 
-///         for files backing these logs. Concretely, participant log files 
-///         will be expected to be produced in:
-///            logpathbase/participant_<num>.log
-///     running: atomic bool indicating whether the simulation is still running
-///     success_prob_op: [0.0..1.0] probability that operations succeed.
-///     success_prob_msg: [0.0..1.0] probability that sends succeed.
+/// print the run-wide request latency distribution merged across
+/// every client's samples, and a histogram of it.
 ///
-fn register_participants(
-    coordinator: &mut Coordinator,
-    n_participants: i32,
-    logpathbase: &String,
-    success_prob_op: f64,
-    success_prob_msg: f64) -> Vec<Participant> {
-
-    let mut participants = vec![];
-    // register participants with coordinator (set up communication channels and sync objects)
-    // add client to the vector and return the vector.
-    for i in 0..n_participants {
-        let part = coordinator.participant_join(format!("{}", i), logpathbase);
-        participants.push(part);
+fn report_latencies(samples: &[Duration]) {
+    println!("Latency: {}", summary::latency_report(samples));
+    let buckets = summary::histogram(samples);
+    let most = buckets.iter().map(|(_bound, n)| *n).max().unwrap_or(0);
+    for (bound, n) in buckets.iter() {
+        println!("  <={:>9}us: {:>6} {}", bound, n, "#".repeat((40 * n).div_ceil(most.max(1))));
     }
-
-    participants
 }
 
 ///
-/// launch_clients()
-/// 
-/// create a thread per client to run the client
-/// part of the 2PC protocol. Somewhere in each of the threads created
-/// here, there should be a call to Client::protocol(...). Telling the client
-/// how many requests to send is probably a good idea. :-)
-/// 
-/// <params>
-/// participants: a vector of Participant structs
-/// handles: (optional depending on design) -- a mutable vector 
-///    to return wait handles to the caller
+/// run()
+/// run the simulation opts describes and print what came of it
 ///
-fn launch_clients(
-    clients: Vec<Client>,
-    n_requests: i32,
-    handles: &mut Vec<JoinHandle<()>>) {
-
-    for mut client in clients {
-        let handle = std::thread::spawn(move || {
-            client.protocol(n_requests);
-        });
-        handles.push(handle);
+fn run(opts: & tpcoptions::TPCOptions) {
+    let stats = simulation::run(opts);
+    println!("Time elapsed is: {:?}", stats.elapsed);
+    if opts.num_clients * opts.num_requests <= 0 {
+        println!("No transactions were requested ({} clients x {} requests), so none were run", opts.num_clients, opts.num_requests);
     }
-
-    // do something to create threads for client 'processes'
-    // the mutable handles parameter allows you to return 
-    // more than one wait handle to the caller to join on. 
+    if opts.think_dist.is_some() || opts.service_dist.is_some() {
+        let describe = |d: Option<dist::Distribution>| d.map_or(String::from("none"), |d| d.to_string());
+        println!("Queueing: think:{}\tservice:{}\tthroughput:{:.1} tx/s",
+                 describe(opts.think_dist), describe(opts.service_dist),
+                 (stats.committed + stats.aborted + stats.unknown) as f64 / stats.elapsed.as_secs_f64());
+    }
+    report_latencies(&stats.latencies);
+    report_totals(opts, &stats);
+    println!("Seed is: {}", stats.seed);
 }
 
 ///
-/// launch_participants()
-/// 
-/// create a thread per participant to run the participant 
-/// part of the 2PC protocol. Somewhere in each of the threads created
-/// here, there should be a call to Participant::participate(...).
+/// report_totals()
 /// 
-/// <params>
-/// participants: a vector of Participant structs
-/// handles: (optional depending on design) -- a mutable vector 
-///    to return wait handles to the caller
+/// one summary of who committed, aborted and lost track of what, once
+/// every thread has joined: the coordinator's totals, and the 
+/// participants' and clients' summed. flags a lossless run in which 
+/// the participants don't add up to the coordinator.
 ///
-fn launch_participants(
-    participants: Vec<Participant>,
-    handles: &mut Vec<JoinHandle<()>>) {
-
-    // do something to create threads for participant 'processes'
-    // the mutable handles parameter allows you to return 
-    // more than one wait handle to the caller to join on.
-    for mut participant in participants {
-        let handle = std::thread::spawn(move || {
-            participant.protocol();
-        });
-        handles.push(handle);
-    } 
-}
-
-/// 
-/// run()
-/// opts: an options structure describing mode and parameters
-/// 
-/// 0. install a signal handler that manages a global atomic boolean flag
-/// 1. creates a new coordinator
-/// 2. creates new clients and registers them with the coordinator
-/// 3. creates new participants and registers them with coordinator
-/// 4. launches participants in their own threads
-/// 5. launches clients in their own threads
-/// 6. creates a thread to run the coordinator protocol
-/// 
-fn run(opts: & tpcoptions::TPCOptions) {
-
-    // vector for wait handles, allowing us to 
-    // wait for client, participant, and coordinator 
-    // threads to join.
-    let start = Instant::now();
-    let mut handles: Vec<JoinHandle<()>> = vec![];  
-    let num_clients = opts.num_clients;
-    let num_participants = opts.num_participants;
-    let num_requests = opts.num_requests; 
-    let logpathbase = &opts.logpath;
-    let message_succ = opts.success_probability_msg;
-    let ops_succ = opts.success_probability_ops;  
-
-    // create an atomic bool object and a signal handler
-    // that sets it. this allows us to inform clients and 
-    // participants that we are exiting the simulation 
-    // by pressing "control-C", which will set the running 
-    // flag to false. 
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-    ctrlc::set_handler(move || {
-        println!("CTRL-C!");
-        r.store(false, Ordering::SeqCst); 
-    }).expect("Error setting signal handler!");
-
-    // create a coordinator, create and register clients and participants
-    // launch threads for all, and wait on handles. 
-    let cpath = format!("{}{}", opts.logpath, "coordinator.log");
-    let mut coordinator: Coordinator = coordinator::Coordinator::new(cpath, running.clone(), message_succ, ops_succ, num_requests * num_clients);
-    let clients: Vec<Client>; 
-    let participants: Vec<Participant>;
-    clients = register_clients(&mut coordinator, num_clients);
-    participants = register_participants(&mut coordinator, num_participants, logpathbase, ops_succ, message_succ);
-    let coord_handle = std::thread::spawn(move || {
-        coordinator.protocol();
-    });
-    handles.push(coord_handle);
-    launch_clients(clients, num_requests, &mut handles);
-    launch_participants(participants, &mut handles);
-
-    for join_handle in handles {
-        join_handle.join().expect("can't join on associated thread");
+fn report_totals(opts: & tpcoptions::TPCOptions, stats: &simulation::SimulationStats) {
+    println!("Totals:");
+    println!("  coordinator:\tC:{}\tA:{}\tU:{}", stats.committed, stats.aborted, stats.unknown);
+    println!("  participants:\tC:{}\tA:{}\tU:{}\t(summed over {})", 
+             stats.participant_committed, stats.participant_aborted, stats.participant_unknown, opts.num_participants);
+    println!("  clients:\tC:{}\tA:{}\tU:{}\t(summed over {})", 
+             stats.client_committed, stats.client_aborted, stats.client_unknown, opts.num_clients);
+    // a CTRL-C leaves txids undecided, and the participants short of them
+    if simulation::lossless(opts) && stats.unknown == 0 {
+        if let Some(disagreement) = stats.disagreement(opts.num_participants) {
+            println!("  MISMATCH: {}", disagreement);
+        }
     }
-    let duration = start.elapsed();
-    println!("Time elapsed is: {:?}", duration);
-
-    // wait for clients, participants, and coordinator here...
 }
 
 ///
@@ -239,13 +78,60 @@ fn main() {
             .init()
             .unwrap();
 
+    if let Err(err) = opts.validate() {
+        error!("{}", err);
+        std::process::exit(2);
+    }
+
+    if opts.mode == "check" && opts.verify_seal && !seal::verify(&opts.logpath) {
+        std::process::exit(1);
+    }
+
     match opts.mode.as_ref() {
 
+        "run" if opts.estimate => estimate::report(&opts),
         "run" => run(&opts),
-        "check" => checker::check_last_run(opts.num_clients, 
+        "check" if opts.golden => match checker::compare_golden(&opts) {
+            Ok(true) => {},
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            },
+        },
+        "check" if opts.quiet => match checker::validate_last_run(opts.num_participants, &opts.logpath, opts.check_liveness, opts.replicate, opts.kv_store, opts.check_window_size) {
+            Ok(true) => {},
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            },
+        },
+        "verify" => {
+            if !verify::verify(&opts.logpath, &opts.decision_policy) {
+                std::process::exit(1);
+            }
+        },
+        "merge-logs" => match merge::merge_logs(&opts.logpath) {
+            Ok((logs, records)) => println!("Merged {} records from {} logs into {}{}", records, logs, opts.logpath, merge::GLOBAL_LOG),
+            Err(err) => {
+                error!("could not merge the logs in {}: {}", opts.logpath, err);
+                std::process::exit(1);
+            },
+        },
+        "check" => match checker::check_last_run(opts.num_clients, 
                                         opts.num_requests, 
                                         opts.num_participants, 
-                                        &opts.logpath.to_string()),
+                                        &opts.logpath.to_string(),
+                                        opts.check_liveness,
+                                        opts.replicate,
+                                        opts.kv_store) {
+            Ok(report) => println!("{}check: PASS", report),
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            },
+        },
         _ => panic!("unknown mode"),
     }
 }
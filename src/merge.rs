@@ -0,0 +1,74 @@
+//!
+//! merge.rs
+//! Merge every component's log in a log directory into one global.log
+//! (-m merge-logs), so a run can be read top to bottom instead of by
+//! jumping between files. Each line is the record as its own log has it,
+//! as JSON whatever --log-format the logs were written in, plus "log": the
+//! component whose log it came from, since senderid names who sent a
+//! message, not who logged it.
+//!
+//! Records are ordered by uid. Uids come from one process-wide counter,
+//! so they are the order the records were appended in across all the
+//! files: a global timestamp, and one consistent with the vector clocks'
+//! happens-before (a message is logged by its sender before anyone can
+//! log receiving it). Uids only repeat across runs that didn't --resume
+//! the same directory, and those ties are broken by the logging
+//! component, then by record type.
+//!
+extern crate serde_json;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use message::ProtocolMessage;
+use oplog::OpLog;
+
+pub const GLOBAL_LOG: &str = "global.log";
+
+///
+/// component_logs()
+/// the names of the component logs under logpath (rotated segments are
+/// read along with their first file), sorted
+///
+fn component_logs(logpath: &str) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in fs::read_dir(logpath)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(component) = name.strip_suffix(".log") {
+            if name != GLOBAL_LOG {
+                names.push(component.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+///
+/// merge_logs()
+/// write every record in the component logs under logpath, in global
+/// order, to logpath's global.log. returns how many logs and records
+/// were merged.
+///
+pub fn merge_logs(logpath: &str) -> io::Result<(usize, usize)> {
+    let components = component_logs(logpath)?;
+    let mut records: Vec<(String, ProtocolMessage)> = vec![];
+    for component in components.iter() {
+        let log = OpLog::from_file(format!("{}{}.log", logpath, component));
+        let lck = log.arc();
+        let map = lck.lock().unwrap();
+        records.extend(map.values().map(|pm| (component.clone(), pm.clone())));
+    }
+    records.sort_by(|(a_log, a), (b_log, b)| {
+        (a.uid, a_log, a.mtype as i32).cmp(&(b.uid, b_log, b.mtype as i32))
+    });
+
+    let mut out = BufWriter::new(fs::File::create(format!("{}{}", logpath, GLOBAL_LOG))?);
+    for (component, pm) in records.iter() {
+        let mut line = serde_json::to_value(pm)?;
+        line["log"] = serde_json::Value::String(component.clone());
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok((components.len(), records.len()))
+}
@@ -7,8 +7,13 @@
 //! 
 extern crate serde;
 extern crate serde_json;
+extern crate crc32fast;
+use std::fmt;
 use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use self::serde_json::Value;
+use vclock::VectorClock;
+use participant::ParticipantState;
 
 ///
 /// MessageType
@@ -27,6 +32,21 @@ pub enum MessageType {
     ClientResultCommit,     // result (success/fail) communicated to client
     ClientResultAbort,      // result (success/fail) communicated to client
     CoordinatorExit,        // Coordinator telling client/participant about shut down
+    ParticipantAbandon,     // Participant gave up waiting on a decision for a txid it voted on
+    CommitPoint,            // Coordinator's commit decision is durable, forced out with it before broadcast
+    ClientInquire,          // Client asking the coordinator for the outcome of a txid it got no result for
+    CoordinatorAbandon,     // Coordinator gave up delivering a logged decision when its shutdown drain timed out
+    ParticipantExitAck,     // Participant acknowledging the coordinator's CoordinatorExit as it leaves
+    ExitAckTimeout,         // Coordinator gave up waiting for a participant's exit ack (senderid names the participant)
+    CoordinatorScavenge,    // Coordinator gave up on a txid that outlived --max-txn-lifetime-ms, logged just before its abort
+    ParticipantDecisionRequest, // Participant recovering from a restart asking the coordinator how an in-doubt txid ended
+    InsufficientReplicas,   // Coordinator would have committed a txid with fewer than --min-live-participants live, logged just before its abort
+    PresumedAbort,          // Participant heard no decision for a txid under --presumed-abort, so aborted it
+    ParticipantTransition,  // Participant moved between states of its state machine (the move is in transition)
+    Heartbeat,              // Participant telling the coordinator it's alive, on a side channel every --heartbeat-ms (never logged)
+    CoordinatorPreCommit,   // Coordinator telling participants a txid everyone voted yes on will commit (--protocol 3pc)
+    ParticipantAck,         // Participant acknowledging a CoordinatorPreCommit (--protocol 3pc)
+    ParticipantDecisionAck, // Participant acknowledging a CoordinatorCommit/CoordinatorAbort it has logged (the coordinator logs it on receipt)
 }
 
 ///
@@ -41,9 +61,51 @@ pub enum RequestStatus {
     Unknown,                // Request status unknown (typically timed out)
 }
 
+///
+/// AbortReason
+/// why the coordinator aborted a txid, carried on its CoordinatorAbort
+/// (and on the client's ClientResultAbort, the only record of an abort
+/// under --presumed-abort)
+///
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AbortReason {
+    ParticipantVote,        // a participant voted to abort
+    VoteTimeout,            // a vote didn't arrive before the vote timeout (slow, or lost in transit)
+    ParticipantFailed,      // a participant went silent on its heartbeats, or exited, before voting
+    Unreachable,            // a partition kept the proposal from a participant
+    Deadline,               // the client's deadline ran out
+    Scavenged,              // the txid outlived --max-txn-lifetime-ms
+    InsufficientReplicas,   // too few participants were live to commit (--min-live-participants)
+    Recovery,               // left undecided by a coordinator that crashed, and aborted on recovery
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            AbortReason::ParticipantVote => "participant vote",
+            AbortReason::VoteTimeout => "vote timeout",
+            AbortReason::ParticipantFailed => "participant failed",
+            AbortReason::Unreachable => "unreachable",
+            AbortReason::Deadline => "deadline",
+            AbortReason::Scavenged => "scavenged",
+            AbortReason::InsufficientReplicas => "insufficient replicas",
+            AbortReason::Recovery => "recovery",
+        })
+    }
+}
+
 /// generator for unique ids of messages
 static COUNTER: AtomicI32 = AtomicI32::new(1);
 
+///
+/// resume_uids()
+/// make sure every uid generated from now on is past max_uid, e.g. the
+/// highest uid in logs an earlier run left behind (see --resume)
+///
+pub fn resume_uids(max_uid: i32) {
+    COUNTER.fetch_max(max_uid + 1, Ordering::SeqCst);
+}
+
 ///
 /// ProtocolMessage
 /// message struct to be send as part of 2PC protocol
@@ -57,6 +119,85 @@ pub struct ProtocolMessage {
     pub txid: i32,
     pub senderid: String, 
     pub opid: i32,
+    // when the message was generated, in ns since the UNIX epoch (0 in
+    // logs written before messages carried one)
+    #[serde(default)]
+    pub timestamp: u128,
+    // EXPERIMENTAL (--segments): the savepoint a vote or commit covers. 
+    // 0 means the whole transaction, and is left out of the log.
+    #[serde(default, skip_serializing_if = "is_whole_transaction")]
+    pub segment: i32,
+    // on a ClientRequest: ms the client allows the coordinator to reach a
+    // decision, from when it receives the request (0 = no deadline). on a
+    // ClientResultAbort: the deadline that ran out, if that's why it aborted.
+    #[serde(default, skip_serializing_if = "is_no_deadline")]
+    pub deadline_ms: u64,
+    // the sender's vector clock (--vector-clocks), empty when off
+    #[serde(default, skip_serializing_if = "VectorClock::is_empty")]
+    pub clock: VectorClock,
+    // on a ParticipantTransition: the (from, to) states of the move
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<(ParticipantState, ParticipantState)>,
+    // --ops-per-txn: the opids of the operations a transaction bundles,
+    // on its request and on everything proposed, voted and decided about
+    // it. empty (and left out of the log) for one operation per txid.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ops: Vec<i32>,
+    // on a CoordinatorAbort or ClientResultAbort: why the coordinator
+    // aborted (None in logs written before aborts carried one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abort_reason: Option<AbortReason>,
+    // on a CoordinatorCommit (and the participants' records of it): its
+    // place in the global commit order, numbered by the coordinator from
+    // 1 with no gaps. 0 (and left out of the log) on anything else.
+    #[serde(default, skip_serializing_if = "is_unsequenced")]
+    pub seqno: i32,
+    // on a ClientRequest, and the proposal and decision for it: the 
+    // transaction only reads (--read-only-ratio). on a 
+    // ParticipantVoteCommit: a read-only vote, after which the 
+    // participant is done with the txid and gets no phase 2. false (and
+    // left out of the log) otherwise.
+    #[serde(default, skip_serializing_if = "is_read_write")]
+    pub read_only: bool,
+    // CRC32 of mtype, txid, senderid and opid (see checksum_of()), so a
+    // corrupted log record is caught rather than misread. only logged 
+    // with --log-checksums; None in logs written without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u32>,
+}
+
+/// the time now, in ns since the UNIX epoch
+fn now_ns() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+}
+
+fn is_whole_transaction(segment: &i32) -> bool {
+    *segment == 0
+}
+
+fn is_no_deadline(deadline_ms: &u64) -> bool {
+    *deadline_ms == 0
+}
+
+fn is_unsequenced(seqno: &i32) -> bool {
+    *seqno == 0
+}
+
+fn is_read_write(read_only: &bool) -> bool {
+    !*read_only
+}
+
+///
+/// checksum_of()
+/// CRC32 over the fields that say what a record is about
+///
+fn checksum_of(mtype: MessageType, txid: i32, senderid: &str, opid: i32) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(format!("{:?}", mtype).as_bytes());
+    hasher.update(&txid.to_le_bytes());
+    hasher.update(senderid.as_bytes());
+    hasher.update(&opid.to_le_bytes());
+    hasher.finalize()
 }
 
 ///
@@ -64,23 +205,106 @@ pub struct ProtocolMessage {
 /// 
 impl ProtocolMessage {
     pub fn generate(t: MessageType, tid: i32, sid: String, oid: i32) -> ProtocolMessage {
+        ProtocolMessage::generate_at(t, tid, sid, oid, now_ns())
+    }
+    ///
+    /// generate_at()
+    /// generate() with the timestamp given rather than read from the
+    /// clock, e.g. to build logs with deterministic timestamps
+    ///
+    pub fn generate_at(t: MessageType, tid: i32, sid: String, oid: i32, ts: u128) -> ProtocolMessage {
+        let checksum = checksum_of(t, tid, &sid, oid);
         ProtocolMessage {
             mtype: t,
             uid: COUNTER.fetch_add(1, Ordering::SeqCst),
             txid: tid,
             senderid: sid,
             opid: oid,
+            timestamp: ts,
+            segment: 0,
+            deadline_ms: 0,
+            clock: VectorClock::new(),
+            transition: None,
+            ops: vec![],
+            abort_reason: None,
+            seqno: 0,
+            read_only: false,
+            checksum: Some(checksum),
+        }
+    }
+    pub fn with_segment(mut self, segment: i32) -> ProtocolMessage {
+        self.segment = segment;
+        self
+    }
+    pub fn with_deadline(mut self, deadline_ms: u64) -> ProtocolMessage {
+        self.deadline_ms = deadline_ms;
+        self
+    }
+    pub fn with_transition(mut self, from: ParticipantState, to: ParticipantState) -> ProtocolMessage {
+        self.transition = Some((from, to));
+        self
+    }
+    pub fn with_ops(mut self, ops: Vec<i32>) -> ProtocolMessage {
+        self.ops = ops;
+        self
+    }
+    pub fn with_abort_reason(mut self, reason: Option<AbortReason>) -> ProtocolMessage {
+        self.abort_reason = reason;
+        self
+    }
+    pub fn with_seqno(mut self, seqno: i32) -> ProtocolMessage {
+        self.seqno = seqno;
+        self
+    }
+    pub fn with_read_only(mut self, read_only: bool) -> ProtocolMessage {
+        self.read_only = read_only;
+        self
+    }
+    ///
+    /// copy()
+    /// a new message (with its own uid) with the same contents as this
+    /// one, e.g. to log a record of a message received
+    ///
+    pub fn copy(&self) -> ProtocolMessage {
+        ProtocolMessage {
+            transition: self.transition,
+            ..ProtocolMessage::generate(self.mtype, self.txid, self.senderid.clone(), self.opid)
+                .with_segment(self.segment)
+                .with_deadline(self.deadline_ms)
+                .with_ops(self.ops.clone())
+                .with_abort_reason(self.abort_reason)
+                .with_seqno(self.seqno)
+                .with_read_only(self.read_only)
         }
     }
     pub fn instantiate(t: MessageType, u: i32, tid: i32, sid: String, oid: i32) -> ProtocolMessage {
+        let checksum = checksum_of(t, tid, &sid, oid);
         ProtocolMessage {
             mtype: t,
             uid: u,
             txid: tid,
             senderid: sid,
             opid: oid,
+            timestamp: now_ns(),
+            segment: 0,
+            deadline_ms: 0,
+            clock: VectorClock::new(),
+            transition: None,
+            ops: vec![],
+            abort_reason: None,
+            seqno: 0,
+            read_only: false,
+            checksum: Some(checksum),
         }
     }
+    ///
+    /// intact()
+    /// false if the message carries a checksum that its contents don't
+    /// match. one without a checksum can't be told apart from intact.
+    ///
+    pub fn intact(&self) -> bool {
+        self.checksum.is_none_or(|sum| sum == checksum_of(self.mtype, self.txid, &self.senderid, self.opid))
+    }
     pub fn from_string(line: &String) -> ProtocolMessage {
         let data: Value = serde_json::from_str(&line.to_string()).unwrap();
         let pm: ProtocolMessage = serde_json::from_value(data).unwrap();
@@ -0,0 +1,185 @@
+//!
+//! netdelay.rs
+//! A simulated network between the coordinator and everyone it talks
+//! to, for what the coordinator and participants send:
+//!
+//!   - latency (--net-latency-ms-min/--net-latency-ms-max): every
+//!     message is delivered a uniformly random delay in that range after
+//!     it was sent, instead of at once. Each link gets a delay line, a
+//!     thread that holds the messages sent on it until they're due and
+//!     then passes them on. A delay line is FIFO: a message is never
+//!     delivered before one sent ahead of it, so a short delay drawn
+//!     right after a long one waits for it. Timeouts run from the send,
+//!     so a round trip slower than the coordinator's --vote-timeout-ms
+//!     loses the vote and aborts the txid.
+//!   - reordering (--reorder-prob): with that probability a message is
+//!     held back and delivered right after the next one sent on its link
+//!     (or after MAX_HOLD, if nothing follows it that soon).
+//!
+extern crate rand;
+extern crate crossbeam_channel;
+use netdelay::rand::prelude::*;
+use netdelay::crossbeam_channel::{unbounded, Sender, RecvTimeoutError};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+use coordinator::derive_seed;
+use message::ProtocolMessage;
+use tpcoptions::TPCOptions;
+
+// longest a message is held back for reordering: the protocol mostly
+// waits on an answer before sending again, so a held message may have
+// nothing to follow it until a timeout fires
+pub const MAX_HOLD: Duration = Duration::from_millis(20);
+
+///
+/// Network
+/// what the network does to the messages on a link
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Network {
+    pub latency: Latency,
+    pub reorder_prob: f64,
+}
+
+///
+/// Latency
+/// the range a link's delays are drawn from, in ms
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Latency {
+    pub min_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Latency {
+
+    ///
+    /// is_zero()
+    /// true if messages go through at once, i.e. there's nothing to delay
+    ///
+    pub fn is_zero(&self) -> bool {
+        self.max_ms == 0
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        Duration::from_millis(rng.gen_range(self.min_ms, self.max_ms + 1))
+    }
+}
+
+impl Network {
+
+    ///
+    /// from_options()
+    /// the network opts asks for
+    ///
+    pub fn from_options(opts: &TPCOptions) -> Network {
+        Network {
+            latency: Latency { min_ms: opts.net_latency_ms_min, max_ms: opts.net_latency_ms_max },
+            reorder_prob: opts.reorder_prob,
+        }
+    }
+
+    ///
+    /// link()
+    /// a sender for downstream that goes through this network: messages
+    /// sent on it are reordered, then delayed, with RNGs seeded from
+    /// seed. on a perfect network it is downstream itself.
+    ///
+    pub fn link(&self, downstream: Sender<ProtocolMessage>, seed: u64) -> Sender<ProtocolMessage> {
+        let delayed = delay_line(downstream, self.latency, seed);
+        reorder_line(delayed, self.reorder_prob, derive_seed(seed, "reorder"))
+    }
+}
+
+///
+/// delay_line()
+/// a sender that delivers what's sent on it to downstream after a delay
+/// drawn (from an RNG seeded with seed) from latency. with zero latency
+/// it is downstream itself. the line's thread exits once every sender
+/// is gone and it has delivered what it holds, or downstream's receiver
+/// is gone, after which sends on it fail as sends on downstream would.
+///
+pub fn delay_line(downstream: Sender<ProtocolMessage>, latency: Latency, seed: u64) -> Sender<ProtocolMessage> {
+    if latency.is_zero() {
+        return downstream;
+    }
+    let (tx, rx) = unbounded::<ProtocolMessage>();
+    thread::spawn(move || {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut held: VecDeque<(Instant, ProtocolMessage)> = VecDeque::new();
+        let mut open = true;
+        while open || !held.is_empty() {
+            let next = match held.front() {
+                Some((due, _pm)) => rx.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match next {
+                Ok(pm) => {
+                    let due = Instant::now() + latency.sample(&mut rng);
+                    let due = held.back().map_or(due, |(last, _pm)| due.max(*last));
+                    held.push_back((due, pm));
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            }
+            while held.front().is_some_and(|(due, _pm)| *due <= Instant::now()) {
+                let (_due, pm) = held.pop_front().unwrap();
+                if downstream.send(pm).is_err() {
+                    return;
+                }
+            }
+            if !open {
+                if let Some((due, _pm)) = held.front() {
+                    thread::sleep(due.saturating_duration_since(Instant::now()));
+                }
+            }
+        }
+    });
+    tx
+}
+
+///
+/// reorder_line()
+/// a sender that passes what's sent on it to downstream, except that
+/// with probability prob (drawn from an RNG seeded with seed) a message
+/// is held back until the next one has gone, or for MAX_HOLD. with
+/// prob 0 it is downstream itself.
+///
+pub fn reorder_line(downstream: Sender<ProtocolMessage>, prob: f64, seed: u64) -> Sender<ProtocolMessage> {
+    if prob == 0.0 {
+        return downstream;
+    }
+    let (tx, rx) = unbounded::<ProtocolMessage>();
+    thread::spawn(move || {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut held: Option<ProtocolMessage> = None;
+        loop {
+            let next = match held {
+                Some(_) => rx.recv_timeout(MAX_HOLD),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let delivered = match next {
+                Ok(pm) => match held.take() {
+                    Some(earlier) => downstream.send(pm).is_ok() && downstream.send(earlier).is_ok(),
+                    None if rng.gen::<f64>() < prob => {
+                        held = Some(pm);
+                        true
+                    },
+                    None => downstream.send(pm).is_ok(),
+                },
+                Err(RecvTimeoutError::Timeout) => downstream.send(held.take().unwrap()).is_ok(),
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Some(earlier) = held.take() {
+                        let _ = downstream.send(earlier);
+                    }
+                    return;
+                },
+            };
+            if !delivered {
+                return;
+            }
+        }
+    });
+    tx
+}
@@ -1,78 +1,1096 @@
-//! 
-//! YOU SHOULD NOT NEED TO CHANGE CODE IN THIS FILE.
-//! 
+//!
+//! oplog.rs
+//! The append-only log every coordinator and participant keeps its 
+//! records in, and the readers that load it back.
+//!
+//!   - writing: append() writes each record inline, or hands it to a 
+//!     background thread (--async-log) that flush() and sync() wait on.
+//!     a log can also live only in memory (--in-memory-log, or
+//!     OpLog::from() over records built by hand). appends can be made 
+//!     to fail for fault injection (fail_after(), fail_randomly()).
+//!   - rotation (--log-max-bytes): once the file at path would grow past
+//!     the limit, records go on in path.1, path.2, ... (segments). a 
+//!     record never straddles two segments.
+//!   - indexing (--log-index): a sidecar path.idx has a "txid segment
+//!     offset" line per record, so read_txid() can seek straight to a 
+//!     txid's records. an index that's missing or stale is rebuilt.
+//!   - formats (--log-format, see OpLogFormat): a line of JSON per 
+//!     record, or length-prefixed bincode after a header. a log can't 
+//!     mix the two.
+//!   - checksums (--log-checksums): each record carries one. a reader
+//!     skips a record that fails it, and verify() reports where it is.
+//!   - reading: records() streams a log, segment by segment; read_all()
+//!     collects it, and try_from_file() loads it to append to. a record
+//!     torn by a crash mid-write ends the log quietly; any other record 
+//!     that can't be read is a ReadError.
+//!
 extern crate serde;
 extern crate serde_json;
 extern crate bincode;
+extern crate rand;
+use oplog::rand::prelude::*;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::thread::JoinHandle;
+use std::mem;
 use std::collections::HashMap;
 use message;
-use std::fs::File;
-use std::io::BufReader;
+use message::{AbortReason, MessageType};
+use participant::ParticipantState;
+use vclock::VectorClock;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, SeekFrom};
 use std::io::prelude::*;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// the first bytes of every segment of a bincode log
+const BINARY_MAGIC: &[u8] = b"2PCLOG\x00\x01";
+
+/// no bincode record comes anywhere near this; a length that does means
+/// the bytes aren't a bincode record at all
+const MAX_BINARY_RECORD: usize = 1 << 20;
+
+///
+/// OpLogFormat
+/// how a log's records are written (--log-format). Json is a line of 
+/// JSON per record. Bincode starts every segment with BINARY_MAGIC, 
+/// then each record is its length (4 bytes, little-endian) followed by
+/// the record, bincode-encoded. readers tell them apart by the header,
+/// and a log can't mix the two.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpLogFormat {
+    Json,
+    Bincode,
+}
+
+impl OpLogFormat {
+    pub fn from_string(name: &str) -> OpLogFormat {
+        match name {
+            "json" => OpLogFormat::Json,
+            "bincode" => OpLogFormat::Bincode,
+            _ => panic!("unknown log format {} (json or bincode)", name),
+        }
+    }
+    /// what every segment in this format starts with
+    fn header(self) -> &'static [u8] {
+        match self {
+            OpLogFormat::Json => b"",
+            OpLogFormat::Bincode => BINARY_MAGIC,
+        }
+    }
+    /// pm as it's written to a log in this format
+    fn encode(self, pm: &message::ProtocolMessage) -> Vec<u8> {
+        match self {
+            OpLogFormat::Json => {
+                let mut record = serde_json::to_vec(pm).unwrap();
+                record.push(b'\n');
+                record
+            },
+            OpLogFormat::Bincode => {
+                let body = bincode::serialize(&BinaryRecord::from(pm.clone())).unwrap();
+                let mut record = (body.len() as u32).to_le_bytes().to_vec();
+                record.extend(body);
+                record
+            },
+        }
+    }
+}
+
+///
+/// BinaryRecord
+/// a ProtocolMessage as a bincode log holds it: every field, always, 
+/// since bincode can't tell which of the fields JSON leaves out when 
+/// they're empty are missing
+///
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BinaryRecord {
+    mtype: MessageType,
+    uid: i32,
+    txid: i32,
+    senderid: String,
+    opid: i32,
+    timestamp: u128,
+    segment: i32,
+    deadline_ms: u64,
+    clock: VectorClock,
+    transition: Option<(ParticipantState, ParticipantState)>,
+    ops: Vec<i32>,
+    abort_reason: Option<AbortReason>,
+    seqno: i32,
+    read_only: bool,
+    checksum: Option<u32>,
+}
+
+impl From<message::ProtocolMessage> for BinaryRecord {
+    fn from(pm: message::ProtocolMessage) -> BinaryRecord {
+        BinaryRecord {
+            mtype: pm.mtype,
+            uid: pm.uid,
+            txid: pm.txid,
+            senderid: pm.senderid,
+            opid: pm.opid,
+            timestamp: pm.timestamp,
+            segment: pm.segment,
+            deadline_ms: pm.deadline_ms,
+            clock: pm.clock,
+            transition: pm.transition,
+            ops: pm.ops,
+            abort_reason: pm.abort_reason,
+            seqno: pm.seqno,
+            read_only: pm.read_only,
+            checksum: pm.checksum,
+        }
+    }
+}
+
+impl From<BinaryRecord> for message::ProtocolMessage {
+    fn from(record: BinaryRecord) -> message::ProtocolMessage {
+        message::ProtocolMessage {
+            mtype: record.mtype,
+            uid: record.uid,
+            txid: record.txid,
+            senderid: record.senderid,
+            opid: record.opid,
+            timestamp: record.timestamp,
+            segment: record.segment,
+            deadline_ms: record.deadline_ms,
+            clock: record.clock,
+            transition: record.transition,
+            ops: record.ops,
+            abort_reason: record.abort_reason,
+            seqno: record.seqno,
+            read_only: record.read_only,
+            checksum: record.checksum,
+        }
+    }
+}
+
+///
+/// CorruptRecord
+/// a record whose contents don't match its checksum (--log-checksums):
+/// where it is (its byte offset in the segment it was read from, or the
+/// key read() was given) and the txid it claims to be about
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CorruptRecord {
+    pub offset: u64,
+    pub txid: i32,
+}
+
+impl fmt::Display for CorruptRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record at offset {} (txid {}) doesn't match its checksum", self.offset, self.txid)
+    }
+}
+
+///
+/// ReadError
+/// why a log couldn't be read back: a complete record in it that 
+/// doesn't decode (the segment file it's in, its byte offset there, and
+/// what's wrong with it), or a segment in a different format from the 
+/// ones before it (a log can't mix formats)
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReadError {
+    BadRecord { path: String, offset: u64, what: String },
+    MixedFormats { path: String, format: OpLogFormat, log: OpLogFormat },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::BadRecord { path, offset, what } => 
+                write!(f, "bad record in {} at offset {}: {}", path, offset, what),
+            ReadError::MixedFormats { path, format, log } => 
+                write!(f, "{} is {:?} but earlier segments of its log are {:?}: a log can't mix formats", path, format, log),
+        }
+    }
+}
+
+impl From<ReadError> for io::Error {
+    fn from(err: ReadError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+///
+/// TxIndex
+/// txid -> (segment, byte offset) of every record for that txid, in 
+/// the order they were appended
+///
+pub type TxIndex = HashMap<i32, Vec<(u32, u64)>>;
 
 #[derive(Debug)]
 pub struct OpLog {
     seqno: i32,
+    format: OpLogFormat,
     log_arc: Arc<Mutex<HashMap<i32, message::ProtocolMessage>>>,
+    writer: LogWriter,
+    lock_acquisitions: u64,
+    lock_wait: Duration, // total time spent waiting to acquire log_arc
+    appends: u64,
+    disk_full_after: Option<u64>, // injected: appends past this many fail
+    write_failures: Option<(f64, StdRng)>, // injected: each append fails with this probability
+    checksums: bool,    // records are written with their checksums (--log-checksums)
+    appended: Option<Arc<AtomicU64>>, // bytes appended, added to a total shared with other logs (see count_appended())
+}
+
+///
+/// SegmentWriter
+/// owns the file(s) backing a log and knows how to rotate them
+///
+#[derive(Debug)]
+struct SegmentWriter {
     path: String,
     lf: File,
+    max_bytes: u64,     // rotate once the active segment would exceed this (0 = never)
+    bytes: u64,         // bytes written to the active segment
+    segment: u32,       // index of the active segment (0 is path itself)
+    index: Option<File>, // sidecar txid index, if maintained
+    format: OpLogFormat,
+}
+
+///
+/// WriterCommand
+/// requests to a background writer thread
+///
+#[derive(Debug)]
+enum WriterCommand {
+    Write(i32, Vec<u8>),            // append one serialized record for a txid
+    Refuse(io::ErrorKind, String),  // a write the disk refuses (injected, see fail_after())
+    Rewrite(Vec<(i32, Vec<u8>)>, Sender<()>), // replace the log contents, ack when done
+    Flush(Sender<io::Result<()>>),  // ack once everything queued before this is written
+    Sync(Sender<io::Result<()>>),   // ... and forced to stable storage
+}
+
+/// the first write a background writer failed, if it has: the records
+/// queued after it are never written, and append(), flush() and sync()
+/// report it from then on
+type WriteFailure = Arc<Mutex<Option<(io::ErrorKind, String)>>>;
+
+///
+/// write_failure()
+/// Err with the write a background writer failed, if it has
+///
+fn write_failure(failure: &WriteFailure) -> io::Result<()> {
+    match *failure.lock().unwrap() {
+        Some((kind, ref why)) => Err(io::Error::new(kind, why.clone())),
+        None => Ok(()),
+    }
+}
+
+///
+/// LogWriter
+/// either write records inline from append(), or hand them to a 
+/// background thread so append() doesn't wait on the disk, or (for a
+/// log built in memory, see From<Vec<ProtocolMessage>>) don't write 
+/// them anywhere
+///
+#[derive(Debug)]
+enum LogWriter {
+    Direct(SegmentWriter),
+    Background(Sender<WriterCommand>, Option<JoinHandle<()>>, WriteFailure),
+    Memory,
+}
+
+///
+/// segment_path()
+/// the file backing segment n of the log at fpath: segment 0 is 
+/// fpath itself, later segments are fpath.1, fpath.2, ...
+/// 
+fn segment_path(fpath: &str, n: u32) -> String {
+    if n == 0 {
+        fpath.to_string()
+    } else {
+        format!("{}.{}", fpath, n)
+    }
+}
+
+///
+/// index_path()
+/// the sidecar txid index for the log at fpath
+/// 
+fn index_path(fpath: &str) -> String {
+    format!("{}.idx", fpath)
+}
+
+fn bad_record(path: &str, offset: u64, what: String) -> ReadError {
+    ReadError::BadRecord { path: path.to_string(), offset, what }
+}
+
+///
+/// SegmentReader
+/// reads the records of one segment file, in whichever format its 
+/// header says it's in
+///
+#[derive(Debug)]
+struct SegmentReader {
+    path: String,
+    reader: BufReader<File>,
+    format: Option<OpLogFormat>, // None for an empty file
+    offset: u64,        // where the next record starts
+    corrupt: Vec<CorruptRecord>, // records skipped for failing their checksum
+}
+
+impl SegmentReader {
+    fn open(path: String) -> io::Result<SegmentReader> {
+        let mut reader = BufReader::new(File::open(&path)?);
+        let start = reader.fill_buf()?;
+        let format = if start.is_empty() {
+            None
+        } else if start.starts_with(BINARY_MAGIC) {
+            Some(OpLogFormat::Bincode)
+        } else {
+            Some(OpLogFormat::Json)
+        };
+        let offset = format.map_or(0, |f| f.header().len() as u64);
+        reader.consume(offset as usize);
+        Ok(SegmentReader { path, reader, format, offset, corrupt: vec![] })
+    }
+    /// go to the record at offset, e.g. one an index points at
+    fn seek(&mut self, offset: u64) {
+        self.reader.seek(SeekFrom::Start(offset)).unwrap();
+        self.offset = offset;
+    }
+    /// the next record, and the offset it starts at. None at the end of
+    /// the segment, or at a record torn by a crash mid-write (an 
+    /// unterminated line, or fewer bytes than its length says). a 
+    /// complete record that doesn't decode is an error. one that decodes
+    /// but fails its checksum is skipped, and noted in corrupt.
+    fn next_record(&mut self) -> Result<Option<(u64, message::ProtocolMessage)>, ReadError> {
+        loop {
+            let (start, pm) = match self.next_decoded()? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            if pm.intact() {
+                return Ok(Some((start, pm)));
+            }
+            warn!("{}: record at offset {} (txid {}) fails its checksum, skipping it", self.path, start, pm.txid);
+            self.corrupt.push(CorruptRecord { offset: start, txid: pm.txid });
+        }
+    }
+    fn next_decoded(&mut self) -> Result<Option<(u64, message::ProtocolMessage)>, ReadError> {
+        let start = self.offset;
+        let format = match self.format {
+            Some(format) => format,
+            None => return Ok(None),
+        };
+        let (len, pm) = match format {
+            OpLogFormat::Json => {
+                let mut line = String::new();
+                let len = self.reader.read_line(&mut line).map_err(|err| bad_record(&self.path, start, err.to_string()))?;
+                if len == 0 || !line.ends_with('\n') {
+                    return Ok(None);
+                }
+                let pm = serde_json::from_str(&line).map_err(|err| bad_record(&self.path, start, format!("{}: {}", err, line.trim_end())))?;
+                (len as u64, pm)
+            },
+            OpLogFormat::Bincode => {
+                let mut len = [0u8; 4];
+                if self.reader.read_exact(&mut len).is_err() {
+                    return Ok(None);
+                }
+                let len = u32::from_le_bytes(len) as usize;
+                if len > MAX_BINARY_RECORD {
+                    return Err(bad_record(&self.path, start, format!("{} byte record", len)));
+                }
+                let mut body = vec![0u8; len];
+                if self.reader.read_exact(&mut body).is_err() {
+                    return Ok(None);
+                }
+                let record: BinaryRecord = bincode::deserialize(&body).map_err(|err| bad_record(&self.path, start, err.to_string()))?;
+                (4 + len as u64, record.into())
+            },
+        };
+        self.offset += len;
+        Ok(Some((start, pm)))
+    }
+}
+
+///
+/// Records
+/// the records of a log and its rotated segments, read one at a time 
+/// rather than all at once (see OpLog::records()). a record torn by a 
+/// crash mid-write ends its segment rather than being an error. a 
+/// record that doesn't decode, or a segment in a different format, is
+/// an Err, and the last thing read.
+///
+#[derive(Debug)]
+pub struct Records {
+    path: String,
+    segment: u32,       // segment being read; once exhausted, how many there were
+    reader: Option<SegmentReader>,
+    format: Option<OpLogFormat>, // the log's format, once a segment has shown it
+    failed: bool,       // hit a ReadError: there's nothing more to read
+}
+
+impl Iterator for Records {
+    type Item = Result<message::ProtocolMessage, ReadError>;
+
+    fn next(&mut self) -> Option<Result<message::ProtocolMessage, ReadError>> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if self.reader.is_none() {
+                let sr = SegmentReader::open(segment_path(&self.path, self.segment)).ok()?;
+                if let (Some(log), Some(format)) = (self.format, sr.format) {
+                    if log != format {
+                        self.failed = true;
+                        return Some(Err(ReadError::MixedFormats { path: sr.path, format, log }));
+                    }
+                }
+                self.format = self.format.or(sr.format);
+                self.reader = Some(sr);
+            }
+            match self.reader.as_mut().unwrap().next_record() {
+                Ok(Some((_offset, pm))) => return Some(Ok(pm)),
+                Ok(None) => {},
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                },
+            }
+            self.reader = None;
+            self.segment += 1;
+        }
+    }
+}
+
+impl SegmentWriter {
+    fn write(&mut self, txid: i32, record: &[u8]) -> io::Result<()> {
+        // rotate before writing so a record never straddles two segments
+        let header = self.format.header();
+        if self.max_bytes > 0 && self.bytes > header.len() as u64 && self.bytes + record.len() as u64 > self.max_bytes {
+            // a segment is forced on its way out, so sync() only ever
+            // has the active one to force
+            self.lf.sync_all()?;
+            self.segment += 1;
+            self.lf = File::create(segment_path(&self.path, self.segment))?;
+            self.lf.write_all(header)?;
+            self.bytes = header.len() as u64;
+        }
+        self.lf.write_all(record)?;
+        self.lf.flush()?;
+        // the record goes in before its index entry, so an entry never
+        // points past the end of the log
+        if let Some(ref mut idx) = self.index {
+            writeln!(idx, "{} {} {}", txid, self.segment, self.bytes)?;
+        }
+        self.bytes += record.len() as u64;
+        Ok(())
+    }
+    fn rewrite(&mut self, records: &[(i32, Vec<u8>)]) {
+        for n in 1..=self.segment {
+            let _ = fs::remove_file(segment_path(&self.path, n));
+        }
+        self.segment = 0;
+        self.lf = File::create(&self.path).unwrap();
+        self.lf.write_all(self.format.header()).unwrap();
+        self.bytes = self.format.header().len() as u64;
+        if self.index.is_some() {
+            self.index = Some(File::create(index_path(&self.path)).unwrap());
+        }
+        for (txid, record) in records {
+            self.lf.write_all(record).unwrap();
+            if let Some(ref mut idx) = self.index {
+                writeln!(idx, "{} {} {}", txid, self.segment, self.bytes).unwrap();
+            }
+            self.bytes += record.len() as u64;
+        }
+        self.lf.flush().unwrap();
+    }
+    fn sync(&mut self) -> io::Result<()> {
+        self.lf.sync_all()?;
+        if let Some(ref idx) = self.index {
+            idx.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// run_writer()
+/// body of a background writer thread: applies commands in the order 
+/// they were queued until every sender is gone. nobody is waiting on a
+/// queued append, so a write that fails is kept in failure for the 
+/// next append, flush or sync to report (and nothing after it is 
+/// written, leaving no hole in the log).
+///
+fn run_writer(mut sw: SegmentWriter, rx: Receiver<WriterCommand>, failure: WriteFailure) {
+    for cmd in rx.iter() {
+        match cmd {
+            WriterCommand::Write(txid, record) => {
+                if write_failure(&failure).is_ok() {
+                    if let Err(err) = sw.write(txid, &record) {
+                        error!("background log writer failed to write {}: {}", sw.path, err);
+                        *failure.lock().unwrap() = Some((err.kind(), err.to_string()));
+                    }
+                }
+            },
+            WriterCommand::Refuse(kind, why) => {
+                if write_failure(&failure).is_ok() {
+                    error!("background log writer failed to write {}: {}", sw.path, why);
+                    *failure.lock().unwrap() = Some((kind, why));
+                }
+            },
+            WriterCommand::Rewrite(records, ack) => {
+                sw.rewrite(&records);
+                let _ = ack.send(());
+            },
+            WriterCommand::Flush(ack) => {
+                let _ = ack.send(write_failure(&failure));
+            },
+            WriterCommand::Sync(ack) => {
+                let _ = ack.send(write_failure(&failure).and_then(|()| sw.sync()));
+            },
+        }
+    }
 }
 
 impl OpLog {
     pub fn new(fpath: String) -> OpLog {
-        let l = HashMap::new();
+        OpLog::with_options(fpath, 0, false, false, OpLogFormat::Json)
+    }
+    pub fn with_max_bytes(fpath: String, max_bytes: u64) -> OpLog {
+        OpLog::with_options(fpath, max_bytes, false, false, OpLogFormat::Json)
+    }
+    ///
+    /// new_in_memory()
+    /// an empty log with no file behind it (--in-memory-log): appends are
+    /// kept in memory, where arc() and read() see them, and lost at exit
+    ///
+    pub fn new_in_memory() -> OpLog {
+        OpLog::with_records(LogWriter::Memory, OpLogFormat::Json, vec![])
+    }
+    ///
+    /// with_options()
+    /// create a new log at fpath, rotating segments at max_bytes (0 = never).
+    /// with background set, records are written by a dedicated thread: 
+    /// append() only queues the record, so a record is not durable until
+    /// flush() returns (or the OpLog is dropped, which flushes). 
+    /// with index set, a sidecar txid index is kept next to the log 
+    /// (see load_index()). records are written in format.
+    ///
+    pub fn with_options(fpath: String, max_bytes: u64, background: bool, index: bool, format: OpLogFormat) -> OpLog {
+        // segments and an index left over from an earlier run would 
+        // otherwise be read back as part of this one
+        let mut n = 1;
+        while fs::remove_file(segment_path(&fpath, n)).is_ok() {
+            n += 1;
+        }
+        let _ = fs::remove_file(index_path(&fpath));
+        let mut lf = File::create(fpath.clone()).unwrap();
+        lf.write_all(format.header()).unwrap();
+        let sw = SegmentWriter {
+            path: fpath.to_string(),
+            index: if index { Some(File::create(index_path(&fpath)).unwrap()) } else { None },
+            lf,
+            max_bytes,
+            bytes: format.header().len() as u64,
+            segment: 0,
+            format,
+        };
+        OpLog::with_writer(sw, background, vec![])
+    }
+    ///
+    /// reopen()
+    /// with_options() for carrying on the log at fpath from an earlier 
+    /// run: its records are loaded, and new ones are appended after them
+    /// (to its last segment) instead of starting the log over. with 
+    /// index set, the index is rebuilt to cover the earlier records. a 
+    /// log that doesn't exist yet is created. the earlier run must have
+    /// written the log in format too: a log can't mix formats.
+    ///
+    pub fn reopen(fpath: String, max_bytes: u64, background: bool, index: bool, format: OpLogFormat) -> OpLog {
+        if fs::metadata(&fpath).is_err() {
+            return OpLog::with_options(fpath, max_bytes, background, index, format);
+        }
+        if let Some(earlier) = SegmentReader::open(fpath.clone()).unwrap().format {
+            if earlier != format {
+                panic!("{} holds {:?} records, so {:?} ones can't be appended to it: a log can't mix formats (carry on with the --log-format it was written with)",
+                       fpath, earlier, format);
+            }
+        }
+        let mut records: Vec<message::ProtocolMessage> = OpLog::from_file(fpath.clone())
+            .arc().lock().unwrap()
+            .values().cloned().collect();
+        records.sort_by_key(|pm| pm.uid);
+        let mut segment = 0;
+        while fs::metadata(segment_path(&fpath, segment + 1)).is_ok() {
+            segment += 1;
+        }
+        // a record torn by a crash mid-write wasn't loaded; cut it off so
+        // the next record starts where it did
+        let mut last = SegmentReader::open(segment_path(&fpath, segment)).unwrap();
+        while last.next_record().unwrap_or_else(|err| panic!("{}", err)).is_some() {}
+        let mut lf = OpenOptions::new().append(true).open(segment_path(&fpath, segment)).unwrap();
+        lf.set_len(last.offset).unwrap();
+        if last.format.is_none() {
+            lf.write_all(format.header()).unwrap();
+        }
+        let idx = if index {
+            OpLog::build_index(&fpath).unwrap_or_else(|err| panic!("{}", err));
+            Some(OpenOptions::new().append(true).open(index_path(&fpath)).unwrap())
+        } else {
+            // it would go stale as soon as we append
+            let _ = fs::remove_file(index_path(&fpath));
+            None
+        };
+        let sw = SegmentWriter {
+            path: fpath.to_string(),
+            index: idx,
+            bytes: lf.metadata().unwrap().len(),
+            lf,
+            max_bytes,
+            segment,
+            format,
+        };
+        OpLog::with_writer(sw, background, records)
+    }
+    fn with_writer(sw: SegmentWriter, background: bool, records: Vec<message::ProtocolMessage>) -> OpLog {
+        let format = sw.format;
+        let writer = if background {
+            let (tx, rx) = channel();
+            let failure: WriteFailure = Arc::new(Mutex::new(None));
+            let writer_failure = failure.clone();
+            let handle = thread::spawn(move || run_writer(sw, rx, writer_failure));
+            LogWriter::Background(tx, Some(handle), failure)
+        } else {
+            LogWriter::Direct(sw)
+        };
+        OpLog::with_records(writer, format, records)
+    }
+    fn with_records(writer: LogWriter, format: OpLogFormat, records: Vec<message::ProtocolMessage>) -> OpLog {
+        let seqno = records.len() as i32;
+        let l: HashMap<i32, message::ProtocolMessage> = records.into_iter()
+            .enumerate()
+            .map(|(n, pm)| (n as i32 + 1, pm))
+            .collect();
         let lck = Mutex::new(l);
         let arc = Arc::new(lck);
         OpLog {
-            seqno: 0,            
+            seqno,            
+            format,
             log_arc: arc,
+            writer,
+            lock_acquisitions: 0,
+            lock_wait: Duration::from_secs(0),
+            appends: 0,
+            disk_full_after: None,
+            write_failures: None,
+            checksums: false,
+            appended: None,
+        }
+    }
+    ///
+    /// fail_randomly()
+    /// simulate a flaky disk: from now on each append fails, without 
+    /// writing anything, with probability prob (drawn from an RNG seeded
+    /// with seed). only meant for fault injection.
+    ///
+    pub fn fail_randomly(&mut self, prob: f64, seed: u64) {
+        self.write_failures = Some((prob, StdRng::seed_from_u64(seed)));
+    }
+    ///
+    /// checksum_records()
+    /// write every record from now on with its checksum, so reading it 
+    /// back catches it having been corrupted (--log-checksums)
+    ///
+    pub fn checksum_records(&mut self) {
+        self.checksums = true;
+    }
+    ///
+    /// fail_after()
+    /// simulate the disk filling up: once n records have been appended, 
+    /// every further append fails without writing anything. with a 
+    /// background writer it's the writer thread that fails, so the 
+    /// caller only hears of it at its next append, flush or sync, as it
+    /// would of a real full disk. only meant for fault injection.
+    ///
+    pub fn fail_after(&mut self, n: u64) {
+        self.disk_full_after = Some(n);
+    }
+    ///
+    /// count_appended()
+    /// from now on, add the bytes of every record appended to total: what
+    /// the logs sharing it have appended between them (rather than how
+    /// big their files are: records loaded from an earlier run don't
+    /// count), e.g. for --max-total-log-bytes
+    ///
+    pub fn count_appended(&mut self, total: Arc<AtomicU64>) {
+        self.appended = Some(total);
+    }
+
+    ///
+    /// records()
+    /// stream the records of the log at fpath, segment by segment, without
+    /// loading it. a missing log has no records.
+    ///
+    pub fn records(fpath: &str) -> Records {
+        Records {
             path: fpath.to_string(),
-            lf: File::create(fpath).unwrap(),
+            segment: 0,
+            reader: None,
+            format: None,
+            failed: false,
         }
     }
+    ///
+    /// read_all()
+    /// every record of the log at fpath (all its segments), in the order
+    /// they were appended, e.g. for offline analysis. unlike records(), 
+    /// a missing log is an error. a torn last record is left out; any 
+    /// other record that can't be read is an error (an InvalidData one
+    /// saying which, see ReadError). (it isn't called read(): that's the
+    /// lookup of one loaded record by key.)
+    ///
+    pub fn read_all(fpath: &str) -> io::Result<Vec<message::ProtocolMessage>> {
+        File::open(fpath)?;
+        Ok(OpLog::records(fpath).collect::<Result<_, _>>()?)
+    }
+    ///
+    /// from_file()
+    /// try_from_file(), for a log that's known to read back: a record 
+    /// that doesn't is a panic
+    ///
     pub fn from_file(fpath: String) -> OpLog {
+        OpLog::try_from_file(fpath).unwrap_or_else(|err| panic!("{}", err))
+    }
+    ///
+    /// try_from_file()
+    /// load the log at fpath (all its segments), to append to the last 
+    /// one. the ReadError if a record in it can't be read.
+    ///
+    pub fn try_from_file(fpath: String) -> Result<OpLog, ReadError> {
         let seqno = 0;        
         let mut l = HashMap::new();
         let scopy = fpath.clone();
-        let tlf = File::open(fpath).unwrap();
-        let mut reader = BufReader::new(&tlf);
-        let mut line = String::new();
-        let mut len = reader.read_line(&mut line).unwrap();
-        while len > 0 {
-            let pm = message::ProtocolMessage::from_string(&line);
+        let tlf = File::open(&fpath).unwrap();
+        let mut records = OpLog::records(&fpath);
+        for pm in &mut records {
+            let pm = pm?;
             l.insert(pm.uid, pm);
-            line.clear();
-            len = reader.read_line(&mut line).unwrap();
         }
+        let segment = records.segment;
+        let format = records.format.unwrap_or(OpLogFormat::Json);
         let lck = Mutex::new(l);
         let arc = Arc::new(lck);
-        OpLog {
+        Ok(OpLog {
             seqno: seqno,            
+            format,
             log_arc: arc,
-            path: scopy,
-            lf: tlf,
-        }
+            writer: LogWriter::Direct(SegmentWriter {
+                path: scopy,
+                lf: tlf,
+                max_bytes: 0,
+                bytes: 0,
+                segment: segment.saturating_sub(1),
+                index: None,
+                format,
+            }),
+            lock_acquisitions: 0,
+            lock_wait: Duration::from_secs(0),
+            appends: 0,
+            disk_full_after: None,
+            write_failures: None,
+            checksums: false,
+            appended: None,
+        })
     }    
-    pub fn append(&mut self, t: message::MessageType, tid: i32, sender: String, op: i32) {
+    ///
+    /// append()
+    /// log a record. an error means the record was not written and must 
+    /// not be acted on. with a background writer, Ok only means the 
+    /// record was queued (see flush()), and an error may be a write of
+    /// an earlier record having failed.
+    ///
+    pub fn append(&mut self, t: message::MessageType, tid: i32, sender: String, op: i32) -> io::Result<()> {
+        self.append_record(message::ProtocolMessage::generate(t, tid, sender, op))
+    }
+    ///
+    /// append_record()
+    /// append() for a record the caller has already built, e.g. one 
+    /// carrying a savepoint segment or a vector clock
+    ///
+    pub fn append_record(&mut self, mut pm: message::ProtocolMessage) -> io::Result<()> {
+        if let LogWriter::Background(_, _, ref failure) = self.writer {
+            write_failure(failure)?;
+        }
+        if self.disk_full_after.is_some_and(|n| self.appends >= n) {
+            let why = "no space left on device (injected)";
+            if let LogWriter::Background(ref tx, _, _) = self.writer {
+                tx.send(WriterCommand::Refuse(io::ErrorKind::StorageFull, why.to_string())).unwrap();
+                return Ok(());
+            }
+            return Err(io::Error::new(io::ErrorKind::StorageFull, why));
+        }
+        if let Some((prob, ref mut rng)) = self.write_failures {
+            if rng.gen::<f64>() < prob {
+                return Err(io::Error::other("write failed (injected)"));
+            }
+        }
+        if !self.checksums {
+            pm.checksum = None;
+        }
         let lck = Arc::clone(&self.log_arc);
+        let waited = Instant::now();
         let mut log = lck.lock().unwrap();
+        self.note_lock_wait(waited.elapsed());
         self.seqno += 1;
         let id = self.seqno;
-        let pm = message::ProtocolMessage::generate(t, tid, sender, op);        
-        serde_json::to_writer(&mut self.lf, &pm).unwrap();    
-        writeln!(&mut self.lf).unwrap();
-        self.lf.flush().unwrap();
+        let record = self.format.encode(&pm);
+        let bytes = match self.writer {
+            LogWriter::Direct(ref mut sw) => {
+                sw.write(pm.txid, &record)?;
+                record.len() as u64
+            },
+            LogWriter::Background(ref tx, _, _) => {
+                let bytes = record.len() as u64;
+                tx.send(WriterCommand::Write(pm.txid, record)).unwrap();
+                bytes
+            },
+            LogWriter::Memory => 0,
+        };
+        if let Some(ref total) = self.appended {
+            total.fetch_add(bytes, Ordering::SeqCst);
+        }
+        self.appends += 1;
         log.insert(id, pm);
+        Ok(())
+    }
+    ///
+    /// append_sync()
+    /// append_record(), then sync(): Ok means the record (and everything
+    /// before it) is on stable storage
+    ///
+    pub fn append_sync(&mut self, pm: message::ProtocolMessage) -> io::Result<()> {
+        self.append_record(pm)?;
+        self.sync()
     }
-    pub fn read(&mut self, offset: &i32) -> message::ProtocolMessage {
+    ///
+    /// sync()
+    /// block until every record appended so far is on stable storage
+    /// (fsync), not just handed to the OS as append() leaves it
+    ///
+    pub fn sync(&mut self) -> io::Result<()> {
+        match self.writer {
+            LogWriter::Direct(ref mut sw) => sw.sync(),
+            LogWriter::Background(ref tx, _, _) => {
+                let (ack_tx, ack_rx) = channel();
+                tx.send(WriterCommand::Sync(ack_tx)).unwrap();
+                ack_rx.recv().unwrap()
+            },
+            LogWriter::Memory => Ok(()),
+        }
+    }
+    ///
+    /// flush()
+    /// block until every record appended so far has been written out,
+    /// and say whether they all were. a no-op (Ok) unless the log has a
+    /// background writer.
+    ///
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.writer {
+            LogWriter::Background(ref tx, _, _) => {
+                let (ack_tx, ack_rx) = channel();
+                tx.send(WriterCommand::Flush(ack_tx)).unwrap();
+                ack_rx.recv().unwrap()
+            },
+            _ => Ok(()),
+        }
+    }
+    ///
+    /// read()
+    /// the record at offset, unless it doesn't match its checksum
+    ///
+    pub fn read(&mut self, offset: &i32) -> Result<message::ProtocolMessage, CorruptRecord> {
         let lck = Arc::clone(&self.log_arc);
+        let waited = Instant::now();
         let log = lck.lock().unwrap();
+        self.note_lock_wait(waited.elapsed());
         let pm = log[&offset].clone();
-        pm
+        if !pm.intact() {
+            return Err(CorruptRecord { offset: *offset as u64, txid: pm.txid });
+        }
+        Ok(pm)
+    }
+    ///
+    /// discard_txid()
+    /// remove every record for txid and rewrite the backing file(s) 
+    /// without them, as if the disk had lost them. returns the number
+    /// of records discarded. only meant for fault injection.
+    ///
+    pub fn discard_txid(&mut self, txid: i32) -> usize {
+        let lck = Arc::clone(&self.log_arc);
+        let mut log = lck.lock().unwrap();
+        let before = log.len();
+        log.retain(|_k, pm| pm.txid != txid);
+        let mut keys: Vec<i32> = log.keys().cloned().collect();
+        keys.sort();
+        let records: Vec<(i32, Vec<u8>)> = keys.iter()
+            .map(|k| (log[k].txid, self.format.encode(&log[k])))
+            .collect();
+        match self.writer {
+            LogWriter::Direct(ref mut sw) => sw.rewrite(&records),
+            LogWriter::Background(ref tx, _, _) => {
+                let (ack_tx, ack_rx) = channel();
+                tx.send(WriterCommand::Rewrite(records, ack_tx)).unwrap();
+                ack_rx.recv().unwrap();
+            },
+            LogWriter::Memory => {},
+        }
+        before - log.len()
+    }
+    fn note_lock_wait(&mut self, waited: Duration) {
+        self.lock_acquisitions += 1;
+        self.lock_wait += waited;
+    }
+    /// number of times log_arc was locked by this handle, and the
+    /// total time spent waiting for it
+    pub fn contention(&self) -> (u64, Duration) {
+        (self.lock_acquisitions, self.lock_wait)
     }
     pub fn arc(&self) -> Arc<Mutex<HashMap<i32, message::ProtocolMessage>>> {
         Arc::clone(&self.log_arc)
     }
-}
\ No newline at end of file
+    ///
+    /// build_index()
+    /// scan every segment of the log at fpath, (re)write its sidecar 
+    /// txid index, and return it. the ReadError if a record in it can't
+    /// be read.
+    ///
+    pub fn build_index(fpath: &str) -> Result<TxIndex, ReadError> {
+        let mut index = TxIndex::new();
+        let mut idx = File::create(index_path(fpath)).unwrap();
+        let mut segment = 0;
+        while let Ok(mut sr) = SegmentReader::open(segment_path(fpath, segment)) {
+            // a torn last record isn't indexed
+            while let Some((offset, pm)) = sr.next_record()? {
+                writeln!(idx, "{} {} {}", pm.txid, segment, offset).unwrap();
+                index.entry(pm.txid).or_default().push((segment, offset));
+            }
+            segment += 1;
+        }
+        Ok(index)
+    }
+    ///
+    /// verify()
+    /// every record in the log at fpath (all its segments) that fails its
+    /// checksum, with the segment file it's in. a log written without
+    /// --log-checksums has none to fail. the ReadError if a record can't
+    /// be read at all.
+    ///
+    pub fn verify(fpath: &str) -> Result<Vec<(String, CorruptRecord)>, ReadError> {
+        let mut corrupt = vec![];
+        let mut segment = 0;
+        while let Ok(mut sr) = SegmentReader::open(segment_path(fpath, segment)) {
+            while sr.next_record()?.is_some() {}
+            let path = sr.path;
+            corrupt.extend(sr.corrupt.into_iter().map(|record| (path.clone(), record)));
+            segment += 1;
+        }
+        Ok(corrupt)
+    }
+    ///
+    /// has_index()
+    /// true if the log at fpath has a sidecar txid index
+    ///
+    pub fn has_index(fpath: &str) -> bool {
+        fs::metadata(index_path(fpath)).is_ok()
+    }
+    ///
+    /// load_index()
+    /// read the sidecar txid index for the log at fpath. an index that
+    /// is missing, unparseable, or points outside the log is rebuilt 
+    /// from the log itself (see build_index()).
+    ///
+    pub fn load_index(fpath: &str) -> Result<TxIndex, ReadError> {
+        let parsed = fs::read_to_string(index_path(fpath)).ok().and_then(|contents| {
+            let mut index = TxIndex::new();
+            let mut seglens: HashMap<u32, u64> = HashMap::new();
+            for line in contents.lines() {
+                let fields: Vec<&str> = line.split(' ').collect();
+                if fields.len() != 3 {
+                    return None;
+                }
+                let txid = fields[0].parse::<i32>().ok()?;
+                let segment = fields[1].parse::<u32>().ok()?;
+                let offset = fields[2].parse::<u64>().ok()?;
+                let seglen = match seglens.get(&segment) {
+                    Some(len) => *len,
+                    None => {
+                        let len = fs::metadata(segment_path(fpath, segment)).ok()?.len();
+                        seglens.insert(segment, len);
+                        len
+                    },
+                };
+                if offset >= seglen {
+                    return None;
+                }
+                index.entry(txid).or_default().push((segment, offset));
+            }
+            Some(index)
+        });
+        match parsed {
+            Some(index) => Ok(index),
+            None => OpLog::build_index(fpath),
+        }
+    }
+    ///
+    /// read_txid()
+    /// the records for txid in the log at fpath, read by seeking to the
+    /// offsets in index rather than scanning the log. each segment they
+    /// are in is opened once (and a txid's records seldom span two). the
+    /// ReadError if one of them can't be read.
+    ///
+    pub fn read_txid(fpath: &str, index: &TxIndex, txid: i32) -> Result<Vec<message::ProtocolMessage>, ReadError> {
+        let mut records = vec![];
+        let mut open: Option<(u32, SegmentReader)> = None;
+        for (segment, offset) in index.get(&txid).into_iter().flatten() {
+            let sr = match open {
+                Some((n, ref mut sr)) if n == *segment => sr,
+                _ => &mut open.insert((*segment, SegmentReader::open(segment_path(fpath, *segment)).unwrap())).1,
+            };
+            sr.seek(*offset);
+            // a torn last record the index still points at is skipped
+            if let Some((_offset, pm)) = sr.next_record()? {
+                records.push(pm);
+            }
+        }
+        Ok(records)
+    }
+}
+
+///
+/// an OpLog held only in memory, over records (in append order), e.g. 
+/// to run checks on logs built by hand. appends to it aren't written 
+/// anywhere.
+///
+impl From<Vec<message::ProtocolMessage>> for OpLog {
+    fn from(records: Vec<message::ProtocolMessage>) -> OpLog {
+        OpLog::with_records(LogWriter::Memory, OpLogFormat::Json, records)
+    }
+}
+
+impl Drop for OpLog {
+    /// hang up on a background writer and wait for it to drain its 
+    /// queue, so no appended record is lost at shut down
+    fn drop(&mut self) {
+        if let LogWriter::Background(ref mut tx, ref mut handle, _) = self.writer {
+            let (hung_up, _) = channel();
+            drop(mem::replace(tx, hung_up));
+            if let Some(handle) = handle.take() {
+                handle.join().unwrap();
+            }
+        }
+    }
+}
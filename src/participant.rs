@@ -5,29 +5,139 @@
 extern crate log;
 extern crate stderrlog;
 extern crate rand;
+extern crate serde;
+extern crate crossbeam_channel;
 use participant::rand::prelude::*;
-use std::sync::mpsc;
-use std::sync::mpsc::{Sender, Receiver};
-use std::time::Duration;
+use participant::crossbeam_channel::{Sender, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
 use std::sync::atomic::{AtomicI32};
-use std::sync::{Arc};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use message;
-use message::MessageType;
+use message::{AbortReason, MessageType};
 use message::ProtocolMessage;
 use message::RequestStatus;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::thread;
+use std::io;
 use oplog;
+use tpcoptions;
+use faults::{FaultAction, FaultSchedule};
+use vclock::Clock;
+use kvstore;
+use kvstore::KvStore;
+use coordinator::derive_seed;
+use failure::{self, FailureModel};
+use dist::Distribution;
+use pool::Step;
+use shutdown::{self, ExitSignal};
+use runstate::RunState;
 
 /// 
 /// ParticipantState
-/// enum for participant 2PC state machine
+/// enum for participant 2PC state machine. for each proposal:
+///
+///     Quiescent -> ReceivedP1 -> VotedCommit/VotedAbort -> AwaitingDecision
+///         -> Decided -> Quiescent
+///
+/// or straight from AwaitingDecision back to Quiescent if no decision
+/// came (we abandoned the txid, or got something else). under 
+/// --protocol 3pc a yes vote can go through PreCommitted on its way from
+/// AwaitingDecision to Decided. a read-only proposal (--read-only-ratio)
+/// goes ReceivedP1 -> VotedReadOnly -> Quiescent: there's nothing to
+/// commit, so no phase 2 to wait on. every move is logged as a 
+/// ParticipantTransition.
 /// 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ParticipantState {    
     Quiescent,          
-    // TODO ...
+    ReceivedP1,         // got a proposal, haven't voted yet
+    VotedCommit,        // logged a yes vote
+    VotedAbort,         // logged a no vote
+    VotedReadOnly,      // voted on a read-only proposal, leaving phase 2 out
+    AwaitingDecision,   // vote sent, waiting on phase 2
+    PreCommitted,       // 3pc: logged and acked the coordinator's pre-commit, waiting on the commit
+    Decided,            // logged the decision (or presumed one)
+}
+
+impl ParticipantState {
+
+    ///
+    /// allows()
+    /// whether the state machine may move from self to to
+    ///
+    pub fn allows(self, to: ParticipantState) -> bool {
+        use self::ParticipantState::*;
+        matches!((self, to),
+                 (Quiescent, ReceivedP1) |
+                 (ReceivedP1, VotedCommit) | (ReceivedP1, VotedAbort) |
+                 (ReceivedP1, VotedReadOnly) | (VotedReadOnly, Quiescent) |
+                 (VotedCommit, AwaitingDecision) | (VotedAbort, AwaitingDecision) |
+                 (AwaitingDecision, Decided) | (AwaitingDecision, Quiescent) |
+                 (AwaitingDecision, PreCommitted) | (PreCommitted, Decided) |
+                 (Decided, Quiescent))
+    }
+}
+
+/// 
+/// ExitPolicy
+/// what a participant does when the coordinator announces shut down
+/// while one of its votes is still waiting on a phase 2 decision
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitPolicy {    
+    Flush,              // keep waiting for the pending decision before exiting
+    Abandon,            // log the in-flight txid as abandoned and exit
+}
+
+impl ExitPolicy {
+    pub fn from_string(policy: &str) -> ExitPolicy {
+        match policy {
+            "flush" => ExitPolicy::Flush,
+            "abandon" => ExitPolicy::Abandon,
+            _ => panic!("unknown exit policy {}", policy),
+        }
+    }
+}
+
+/// 
+/// ExitAck
+/// when a participant acks the coordinator's CoordinatorExit
+/// (--participant-exit-delay makes stragglers for testing)
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitAck {
+    Prompt,             // as soon as we leave the protocol
+    Delayed(Duration),  // that long after
+    Never,
+}
+
+///
+/// Awaiting
+/// --worker-threads: a proposal we voted on and are waiting for the 
+/// decision on, between steps (see step())
+///
+#[derive(Debug)]
+struct Awaiting {
+    proposal: ProtocolMessage,
+    voted_yes: bool,
+    presumed: ProtocolMessage,  // the PresumedAbort to log if it comes to that
+    until: Instant,             // when to stop waiting on the next message (see wait_for_decision())
+}
+
+///
+/// Phase
+/// --worker-threads: how far through protocol() step() has got
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Starting,
+    Running,
+    Exiting,            // left the protocol, waiting for the exit signal
+    Done,
 }
 
 ///
@@ -38,13 +148,53 @@ pub enum ParticipantState {
 #[derive(Debug)]
 pub struct Participant {    
     pub id: i32,
-    id_str: String,
+    pub id_str: String,
     state: ParticipantState,
     log: oplog::OpLog,
     op_success_prob: f64,
-    msg_success_prob: f64,
+    own_op_prob: bool,          // op_success_prob came from --participant-op-probs
+    warmup: i32,                // op_success_prob ramps up over this many proposals (0 = no warmup)
+    proposals: i32,             // proposals handled so far
+    warmup_outcomes: [(i32, i32); 2], // (commits, proposals) during and after the warmup
+    in_doubt: HashSet<i32>,     // txids an earlier run voted on but never learned the outcome of
+    decided: HashSet<i32>,      // txids we've logged the coordinator's decision for, to ack again if it's resent
+    deferred: VecDeque<ProtocolMessage>, // proposals that arrived while we waited on a decision (--max-inflight)
+    early_decisions: HashMap<i32, ProtocolMessage>, // decisions for deferred proposals, e.g. ones that ran out their deadline
+    presumed_abort: Option<Duration>, // --presumed-abort: how long to wait for a decision before asking for it
+    decision_timeout: Duration, // how long to wait for a decision before asking for it again
+    pub decision_rerequests: i32, // decisions we asked for again, having waited decision_timeout for them
+    precommit_timeout: Duration, // 3pc: how long to wait for the commit after a pre-commit before committing on our own
+    pub autonomous_commits: i32, // 3pc: txids we committed on our own, the commit not arriving after the pre-commit
+    heartbeat: Option<(Sender<ProtocolMessage>, Duration)>, // --heartbeat-ms: side channel to the coordinator, and how often to beat on it
+    alive: Arc<AtomicBool>,     // cleared once we crash or finish, stopping the heartbeats
     pub ports: (Sender<message::ProtocolMessage>, Receiver<message::ProtocolMessage>),
     running: Arc<AtomicBool>,
+    exit: ExitSignal,           // raised once running is cleared (see wait_for_exit_signal())
+    run: RunState,              // shared with the run's coordinator and clients: correlated faults come from it
+    exit_policy: ExitPolicy,
+    exit_ack: ExitAck,
+    measure_contention: bool,
+    lose_last_commit: bool,     // fault injection: lose the last committed txid on "restart"
+    log_fail_prob: f64,         // fault injection: chance any one log write fails
+    durable: bool,              // --durable: force votes to stable storage before sending them (see force_log())
+    pub vote_log_failures: i32, // yes votes we couldn't log, so voted abort instead
+    pub read_only: i32,         // read-only proposals we voted on and left out of phase 2
+    in_blast_radius: bool,      // whether a correlated fault makes us vote abort
+    pub correlated_aborts: i32, // abort votes caused by a correlated fault
+    pub independent_aborts: i32, // abort votes from our own operation failing
+    seed: u64,
+    failures: Box<dyn FailureModel>, // which of our sends are lost and which operations fail (--failure-model)
+    faults: FaultSchedule,
+    crashed: bool,              // a scheduled crash fired; handle nothing more
+    clock: Clock,
+    segments: i32,              // EXPERIMENTAL: savepoint-separated segments per transaction (1 = off)
+    pub partial_commits: i32,   // commits that only covered some of the segments
+    abort_reasons: BTreeMap<AbortReason, i32>, // why the coordinator aborted the txids we aborted
+    store: Option<KvStore>,     // the data our transactions write (--kv-store)
+    service: Option<Distribution>, // time spent working on each proposal before voting
+    service_rng: StdRng,        // draws service times, apart from rng so they don't shift its stream
+    phase: Phase,               // --worker-threads: where step() is up to
+    awaiting: Option<Awaiting>, // --worker-threads: the decision step() is waiting on
     pub successful: i32,
     pub failed: i32, 
     pub unknown: i32,
@@ -79,22 +229,189 @@ impl Participant {
         rx: Receiver<ProtocolMessage>, 
         logpath: String,
         r: Arc<AtomicBool>,
-        f_success_prob_ops: f64,
-        f_success_prob_msg: f64) -> Participant {
+        exit: ExitSignal,
+        run: RunState,
+        opts: &tpcoptions::TPCOptions,
+        seed: u64) -> Participant {
 
-        Participant {
+        let mut participant = Participant {
             id: i,
             id_str: is,
-            log: oplog::OpLog::new(logpath),
-            op_success_prob: f_success_prob_ops,
-            msg_success_prob: f_success_prob_msg,
+            log: if opts.in_memory_log {
+                oplog::OpLog::new_in_memory()
+            } else if opts.resume {
+                oplog::OpLog::reopen(logpath, 0, opts.async_log, opts.log_index, oplog::OpLogFormat::from_string(&opts.log_format))
+            } else {
+                oplog::OpLog::with_options(logpath, 0, opts.async_log, opts.log_index, oplog::OpLogFormat::from_string(&opts.log_format))
+            },
+            op_success_prob: opts.op_success_prob(i),
+            own_op_prob: !opts.participant_op_probs.is_empty(),
             state: ParticipantState::Quiescent,
             ports: (tx, rx),
             running: r,
+            exit,
+            run,
+            exit_policy: ExitPolicy::from_string(&opts.exit_policy),
+            exit_ack: match opts.participant_exit_delay.get(&i) {
+                None => ExitAck::Prompt,
+                Some(Some(ms)) => ExitAck::Delayed(Duration::from_millis(*ms)),
+                Some(None) => ExitAck::Never,
+            },
+            measure_contention: opts.measure_contention,
+            lose_last_commit: opts.lose_last_commit.contains(&i),
+            log_fail_prob: opts.participant_log_fail_prob,
+            durable: opts.durable,
+            vote_log_failures: 0,
+            read_only: 0,
+            warmup: opts.warmup_success_ramp,
+            proposals: 0,
+            warmup_outcomes: [(0, 0); 2],
+            in_doubt: HashSet::new(),
+            decided: HashSet::new(),
+            deferred: VecDeque::new(),
+            early_decisions: HashMap::new(),
+            presumed_abort: if opts.presumed_abort { Some(Duration::from_millis(opts.presumed_abort_timeout_ms)) } else { None },
+            decision_timeout: Duration::from_millis(opts.decision_timeout_ms),
+            decision_rerequests: 0,
+            precommit_timeout: Duration::from_millis(opts.precommit_timeout_ms),
+            autonomous_commits: 0,
+            heartbeat: None,
+            alive: Arc::new(AtomicBool::new(true)),
+            in_blast_radius: opts.correlated_failure_set.as_ref().is_none_or(|set| set.contains(&i)),
+            correlated_aborts: 0,
+            independent_aborts: 0,
+            seed,
+            failures: failure::from_string(&opts.failure_model, opts.op_success_prob(i), opts.success_probability_msg, seed),
+            faults: FaultSchedule::for_component(opts.fault_schedule.as_deref(), &format!("participant_{}", i)),
+            crashed: false,
+            clock: Clock::new(&format!("participant_{}", i), opts.vector_clocks),
+            segments: opts.segments,
+            partial_commits: 0,
+            abort_reasons: BTreeMap::new(),
+            store: if opts.kv_store {
+                Some(KvStore::new(kvstore::state_path(&opts.logpath, &format!("participant_{}", i)), opts.kv_keys, opts.segments, opts.resume))
+            } else {
+                None
+            },
+            service: opts.service_dist,
+            service_rng: StdRng::seed_from_u64(derive_seed(seed, "service")),
+            phase: Phase::Starting,
+            awaiting: None,
             successful: 0,
             failed: 0,
             unknown: 0,
-        }   
+        };
+        if opts.participant_log_fail_prob > 0.0 {
+            participant.log.fail_randomly(opts.participant_log_fail_prob, derive_seed(seed, "log"));
+        }
+        if opts.log_checksums {
+            participant.log.checksum_records();
+        }
+        participant.log.count_appended(participant.run.log_bytes.clone());
+        participant.failures.ramp(participant.warmup_fraction());
+        if opts.resume {
+            participant.resume();
+        }
+        participant
+    }
+
+    ///
+    /// resume()
+    /// --resume: count the commits and aborts earlier runs left in the
+    /// log, and carry record uids on past the highest one logged. a txid
+    /// they voted on without logging a decision is still in doubt: a yes
+    /// vote's writes are staged again, and recover() asks the coordinator
+    /// how it ended (see settle()).
+    ///
+    fn resume(&mut self) {
+        let records: Vec<ProtocolMessage> = self.log.arc().lock().unwrap().values().cloned().collect();
+        let mut decided = HashSet::new();
+        for pm in records.iter() {
+            match pm.mtype {
+                MessageType::CoordinatorCommit => self.successful += 1,
+                MessageType::CoordinatorAbort | MessageType::PresumedAbort => {
+                    self.failed += 1;
+                    if let Some(reason) = pm.abort_reason {
+                        *self.abort_reasons.entry(reason).or_insert(0) += 1;
+                    }
+                },
+                // a read-only vote waits on no decision, so it can't be in doubt
+                MessageType::ParticipantVoteCommit if pm.read_only => self.read_only += 1,
+                MessageType::ParticipantVoteCommit | MessageType::ParticipantVoteAbort => {
+                    self.in_doubt.insert(pm.txid);
+                },
+                _ => {},
+            }
+            if pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort || pm.mtype == MessageType::PresumedAbort {
+                decided.insert(pm.txid);
+            }
+        }
+        self.in_doubt.retain(|txid| !decided.contains(txid));
+        self.decided = decided;
+        if let Some(ref mut store) = self.store {
+            let voted_yes: HashSet<i32> = records.iter()
+                .filter(|pm| pm.mtype == MessageType::ParticipantVoteCommit)
+                .map(|pm| pm.txid)
+                .collect();
+            for txid in self.in_doubt.intersection(&voted_yes) {
+                store.stage(*txid);
+            }
+        }
+        if let Some(max_uid) = records.iter().map(|pm| pm.uid).max() {
+            message::resume_uids(max_uid);
+        }
+        info!("participant_{} resumed {} records: C:{} A:{} in doubt:{}", self.id, records.len(), self.successful, self.failed, self.in_doubt.len());
+    }
+
+    ///
+    /// recover()
+    /// after a restart (--resume), ask the coordinator how every txid 
+    /// we're in doubt about ended. it may also re-drive some of them on
+    /// its own; whichever decision gets here first settles the txid.
+    ///
+    fn recover(&mut self) {
+        let mut txids: Vec<i32> = self.in_doubt.iter().copied().collect();
+        txids.sort();
+        for txid in txids {
+            info!("participant_{} asking the coordinator how in-doubt txid {} ended", self.id, txid);
+            let request = ProtocolMessage::generate(MessageType::ParticipantDecisionRequest, txid, format!("participant_{}", self.id), -1);
+            self.send(request);
+        }
+    }
+
+    ///
+    /// settle()
+    /// the coordinator told us how a txid an earlier run left us in doubt
+    /// about ended (see recover()): log it and apply it
+    ///
+    fn settle(&mut self, decision: &ProtocolMessage) {
+        info!("participant_{} settling in-doubt txid {}: {:?}", self.id, decision.txid, decision.mtype);
+        self.log_record(decision);
+        self.ack_decision(decision);
+        if decision.mtype == MessageType::CoordinatorCommit {
+            if let Some(ref mut store) = self.store {
+                store.commit(decision.txid, decision.segment);
+            }
+            self.successful += 1;
+        } else {
+            if let Some(ref mut store) = self.store {
+                store.abort(decision.txid);
+            }
+            self.note_abort(decision);
+        }
+    }
+
+    ///
+    /// note_abort()
+    /// count an abort we logged, and why the coordinator decided it (a
+    /// presumed abort carries no reason)
+    ///
+    fn note_abort(&mut self, decision: &ProtocolMessage) {
+        self.failed += 1;
+        if let Some(reason) = decision.abort_reason {
+            info!("participant_{} aborted txid {}: {}", self.id, decision.txid, reason);
+            *self.abort_reasons.entry(reason).or_insert(0) += 1;
+        }
     }
 
     ///
@@ -110,6 +427,11 @@ impl Participant {
     pub fn send(&mut self, pm: ProtocolMessage) -> bool {
         let result;
 
+        if !self.inject_fault(&pm) {
+            return false;
+        }
+        let pm = self.clock.stamp(pm);
+        self.run.stepper.step(&format!("participant_{}", self.id), "sends", &pm);
         let res = self.ports.0.send(pm);
         match res {
             Ok(_val) => result = true,
@@ -123,7 +445,8 @@ impl Participant {
     /// send()
     /// Send a protocol message to the coordinator, 
     /// with some probability of success thresholded by the 
-    /// command line option success_probability [0.0..1.0].
+    /// command line option success_probability [0.0..1.0]
+    /// (or whatever else --failure-model says).
     /// This variant can be assumed to always succeed
     /// 
     /// HINT: you will need to implement something that does the 
@@ -131,9 +454,8 @@ impl Participant {
     ///       logic in this implementation below. 
     /// 
     pub fn send_unreliable(&mut self, pm: ProtocolMessage) -> bool {
-        let x: f64 = random();
         let result: bool;
-        if x < self.msg_success_prob {
+        if self.failures.should_deliver(&pm) {
             result = self.send(pm);
         } else {
             result = false;
@@ -141,93 +463,579 @@ impl Participant {
         result
     }    
 
+    ///
+    /// log_record()
+    /// log a copy of pm, stamped with our clock. an injected write 
+    /// failure (--participant-log-fail-prob) is taken to be transient,
+    /// and the write is retried until it sticks.
     /// 
-    /// perform_operation
-    /// perform the operation specified in the 2PC proposal,
-    /// with some probability of success/failure determined by the 
-    /// command-line option success_probability. 
+    fn log_record(&mut self, pm: &ProtocolMessage) {
+        while let Err(err) = self.try_log_record(pm) {
+            if self.log_fail_prob == 0.0 {
+                panic!("participant_{} failed to log {:?} for txid {}: {}", self.id, pm.mtype, pm.txid, err);
+            }
+            info!("participant_{} retrying log of {:?} for txid {}: {}", self.id, pm.mtype, pm.txid, err);
+        }
+    }
+
+    ///
+    /// try_log_record()
+    /// log a copy of pm once, and say whether it made it
     /// 
-    /// HINT: The code provided here is not complete--it provides some
-    ///       tracing infrastructure and the probability logic. 
-    ///       Your implementation need not preserve the method signature
-    ///       (it's ok to add parameters or return something other than 
-    ///       bool if it's more convenient for your design).
+    fn try_log_record(&mut self, pm: &ProtocolMessage) -> io::Result<()> {
+        let record = self.clock.stamp(pm.copy());
+        self.log.append_record(record)
+    }
+
+    ///
+    /// force_log()
+    /// --durable: put everything logged so far on stable storage before
+    /// we send a message that promises it's there (a vote, a pre-commit
+    /// ack). otherwise just make sure it's written out, which only has
+    /// to be waited for with --async-log.
+    ///
+    fn force_log(&mut self) -> io::Result<()> {
+        if self.durable {
+            self.log.sync()
+        } else {
+            self.log.flush()
+        }
+    }
+
+    ///
+    /// ack_decision()
+    /// tell the coordinator we've logged decision, so it can stop 
+    /// resending it. a decision the coordinator resends (our ack was 
+    /// lost, or slow) is acked again. with --durable the decision is 
+    /// forced first: the ack promises it's on stable storage.
+    ///
+    fn ack_decision(&mut self, decision: &ProtocolMessage) {
+        self.decided.insert(decision.txid);
+        if let Err(err) = self.force_log() {
+            // the coordinator resends the decision, and we try again
+            info!("participant_{} couldn't force its decision for txid {} ({}), not acking it", self.id, decision.txid, err);
+            return;
+        }
+        let ack = ProtocolMessage::generate(MessageType::ParticipantDecisionAck, decision.txid, format!("participant_{}", self.id), decision.opid)
+            .with_segment(decision.segment)
+            .with_ops(decision.ops.clone());
+        self.send_unreliable(ack);
+    }
+
+    ///
+    /// transition()
+    /// move the state machine to `to` for txid, logging the move. panics
+    /// on a move ParticipantState::allows() doesn't. a crashed 
+    /// participant logs nothing more, so it doesn't move either.
+    ///
+    fn transition(&mut self, txid: i32, to: ParticipantState) {
+        if self.crashed {
+            return;
+        }
+        if !self.state.allows(to) {
+            panic!("participant_{} illegal state transition {:?} -> {:?} for txid {}", self.id, self.state, to, txid);
+        }
+        let record = ProtocolMessage::generate(MessageType::ParticipantTransition, txid, format!("participant_{}", self.id), -1)
+            .with_transition(self.state, to);
+        self.log_record(&record);
+        self.state = to;
+    }
+
+    ///
+    /// finish()
+    /// done with the proposal for txid: through Decided if we logged a
+    /// decision for it, then back to Quiescent
+    ///
+    fn finish(&mut self, txid: i32, decided: bool) {
+        if decided {
+            self.transition(txid, ParticipantState::Decided);
+        }
+        self.transition(txid, ParticipantState::Quiescent);
+    }
+
+    ///
+    /// inject_fault()
+    /// apply any fault scheduled for pm, which we are about to send or
+    /// have just received. returns false if pm is lost (dropped, or we 
+    /// crashed) and must not be acted on.
     /// 
-    pub fn perform_operation(&mut self, request: &Option<ProtocolMessage>) -> bool {
+    fn inject_fault(&mut self, pm: &ProtocolMessage) -> bool {
+        if self.crashed {
+            return false;
+        }
+        match self.faults.take(pm.txid, pm.mtype) {
+            Some(FaultAction::Drop) => false,
+            Some(FaultAction::Delay(delay)) => {
+                thread::sleep(delay);
+                true
+            },
+            Some(FaultAction::Crash) => {
+                info!("participant_{} crashing at {:?} for txid {}", self.id, pm.mtype, pm.txid);
+                self.crashed = true;
+                self.alive.store(false, Ordering::SeqCst);
+                false
+            },
+            None => true,
+        }
+    }
 
-        trace!("participant::perform_operation");
+    ///
+    /// warmup_fraction()
+    /// how far through the warmup (--warmup-success-ramp) we are: the
+    /// chance an operation succeeds ramps linearly up from 0 at the
+    /// first proposal, scaled by this. 1 once warmed up.
+    /// 
+    fn warmup_fraction(&self) -> f64 {
+        if self.proposals >= self.warmup {
+            1.0
+        } else {
+            self.proposals as f64 / self.warmup as f64
+        }
+    }
 
-        let mut result: RequestStatus = RequestStatus::Unknown;
-        let pm: &ProtocolMessage = request.as_ref().unwrap();
+    ///
+    /// tally_proposal()
+    /// count a proposal's outcome towards the warmup or post-warmup 
+    /// commit rate, and move the ramp along
+    /// 
+    fn tally_proposal(&mut self, committed: bool) {
+        let phase = &mut self.warmup_outcomes[if self.proposals < self.warmup { 0 } else { 1 }];
+        phase.0 += committed as i32;
+        phase.1 += 1;
+        self.proposals += 1;
+        self.failures.ramp(self.warmup_fraction());
+    }
 
-        let x: f64 = random();
-        if x > self.op_success_prob {
-            match pm.mtype {
-                MessageType::CoordinatorPropose => {
-                    self.log.append(pm.mtype, pm.txid, pm.senderid.clone(), pm.opid);
-                    let vabort = ProtocolMessage::generate(MessageType::ParticipantVoteAbort, pm.txid, format!("participant_{}", self.id), pm.opid);
-                    self.log.append(vabort.mtype.clone(), vabort.txid.clone(), vabort.senderid.clone(), vabort.opid.clone());
-                    let res;
-                    if self.msg_success_prob == 1.0 {
-                        res = self.send(vabort);
-                    } else {
-                        res = self.send_unreliable(vabort);
-                    }
-                    result = RequestStatus::Aborted;
-                    let reply = self.ports.1.recv().unwrap();
-                    match reply.mtype {
-                        MessageType::CoordinatorAbort => {
-                            self.log.append(reply.mtype, reply.txid, reply.senderid, reply.opid);
-                            self.failed = self.failed + 1;
-                            result = RequestStatus::Aborted;
-                        }
-                        _ => {self.unknown = self.unknown + 1},
-                    }
-                },
-                _ => {},
+    ///
+    /// last_savepoint()
+    /// EXPERIMENTAL (--segments): the first segment of a proposal has 
+    /// already succeeded by the time we get here. roll for the rest, in 
+    /// order, and return how many leading segments succeeded: the last 
+    /// savepoint we can vote to commit through. 0 with savepoints off.
+    /// 
+    fn last_savepoint(&mut self) -> i32 {
+        if self.segments <= 1 {
+            return 0;
+        }
+        let mut done = 1;
+        while done < self.segments && self.failures.should_op_succeed() {
+            done += 1;
+        }
+        done
+    }
+
+    ///
+    /// wait_for_decision()
+    /// block until the coordinator's phase 2 decision for the proposal
+    /// we just voted on arrives. A CoordinatorExit that shows up while the
+    /// vote is in flight is handled according to self.exit_policy: Flush 
+    /// keeps waiting for the decision, Abandon stops waiting. Either way, 
+    /// a txid we give up on is logged as ParticipantAbandon so the checker
+    /// can account for it rather than it being silently dropped. with 
+    /// --max-inflight the coordinator may propose the next txid before 
+    /// this one is decided: that proposal waits its turn in self.deferred,
+    /// and if its decision gets here before we've voted (it can run out
+    /// its deadline while queued), the decision waits with it. under
+    /// --presumed-abort the coordinator doesn't send aborts, so the next
+    /// proposal or the exit instead of a decision means abort: that's a
+    /// PresumedAbort. hearing nothing doesn't: a commit can be held up
+    /// past any timeout (a partition, a slow network or a slow voter), so
+    /// every decision_wait() without a decision we ask the coordinator 
+    /// for it again, until the run is shutting down: then we give up on
+    /// it, as unknown.
+    /// 
+    fn wait_for_decision(&mut self, pm: &ProtocolMessage) -> Option<ProtocolMessage> {
+
+        if let Some(decision) = self.early_decisions.remove(&pm.txid) {
+            return Some(decision);
+        }
+        let presumed = ProtocolMessage::generate(MessageType::PresumedAbort, pm.txid, format!("participant_{}", self.id), pm.opid);
+        loop {
+            if self.crashed {
+                // a crashed participant logs nothing more, not even the abandon
+                return None;
             }
+            let received = self.ports.1.recv_timeout(self.decision_wait());
+            if let Some(decision) = self.on_waiting(pm, &presumed, received) {
+                return decision;
+            }
+        }
+    }
 
-        } else {
-            match pm.mtype {
-                MessageType::CoordinatorPropose => {
-                    self.log.append(pm.mtype, pm.txid, pm.senderid.clone(), pm.opid);
-                    let vcommit = ProtocolMessage::generate(MessageType::ParticipantVoteCommit, pm.txid, format!("participant_{}", self.id), pm.opid);
-                    self.log.append(vcommit.mtype.clone(), vcommit.txid.clone(), vcommit.senderid.clone(), vcommit.opid.clone());
-                    let res;
-                    if self.msg_success_prob == 1.0 {
-                        res = self.send(vcommit);
-                    } else {
-                        res = self.send_unreliable(vcommit);
+    ///
+    /// decision_wait()
+    /// how long wait_for_decision() waits for the next message before 
+    /// asking for the decision again (--presumed-abort-timeout-ms under
+    /// --presumed-abort, else --decision-timeout-ms)
+    ///
+    fn decision_wait(&self) -> Duration {
+        self.presumed_abort.unwrap_or(self.decision_timeout)
+    }
+
+    ///
+    /// on_waiting()
+    /// one turn of wait_for_decision(): act on what was received (or the
+    /// wait timing out) while waiting on the decision for proposal pm. 
+    /// None to keep waiting, otherwise what wait_for_decision() returns.
+    ///
+    fn on_waiting(&mut self, pm: &ProtocolMessage, presumed: &ProtocolMessage, 
+                  received: Result<ProtocolMessage, RecvTimeoutError>) -> Option<Option<ProtocolMessage>> {
+        match received {
+            Err(RecvTimeoutError::Timeout) => {
+                if self.running.load(Ordering::SeqCst) {
+                    info!("participant_{} asking again for the decision on txid {} after {:?} without one", self.id, pm.txid, self.decision_wait());
+                    let request = ProtocolMessage::generate(MessageType::ParticipantDecisionRequest, pm.txid, format!("participant_{}", self.id), pm.opid);
+                    self.decision_rerequests += 1;
+                    self.send_unreliable(request);
+                    return None;
+                }
+                // shutting down: whatever decision there was isn't coming
+            },
+            Ok(reply) => {
+                self.run.stepper.step(&format!("participant_{}", self.id), "receives", &reply);
+                if !self.inject_fault(&reply) {
+                    return None;
+                }
+                self.clock.observe(&reply);
+                let decision = reply.mtype == MessageType::CoordinatorCommit || reply.mtype == MessageType::CoordinatorAbort;
+                if decision && reply.txid != pm.txid {
+                    // an answer to recover() (or a duplicate of one), or
+                    // the decision for a proposal we haven't got to
+                    if self.in_doubt.remove(&reply.txid) {
+                        self.settle(&reply);
+                    } else if self.decided.contains(&reply.txid) {
+                        self.ack_decision(&reply);
+                    } else if self.deferred.iter().any(|p| p.txid == reply.txid) {
+                        self.early_decisions.insert(reply.txid, reply);
                     }
-                    // wait for phase 2
-                    let reply = self.ports.1.recv().unwrap();
-                    match reply.mtype {
-                        MessageType::CoordinatorCommit => {
-                            self.log.append(reply.mtype, reply.txid, reply.senderid, reply.opid);
-                            self.successful = self.successful + 1;
-                            result = RequestStatus::Committed;
-                        }
-                        MessageType::CoordinatorAbort => {
-                            self.log.append(reply.mtype, reply.txid, reply.senderid, reply.opid);
-                            self.failed = self.failed + 1;
-                            result = RequestStatus::Aborted;
-                        }
-                        _ => {self.unknown = self.unknown + 1},
+                    return None;
+                }
+                if reply.mtype == MessageType::CoordinatorPropose {
+                    let moved_on = self.presumed_abort.is_some() && reply.txid != pm.txid;
+                    self.deferred.push_back(reply);
+                    if moved_on {
+                        info!("participant_{} presuming txid {} aborted: the coordinator has moved on", self.id, pm.txid);
+                        return Some(Some(presumed.clone()));
                     }
+                    return None;
+                }
+                if reply.mtype != MessageType::CoordinatorExit {
+                    return Some(Some(reply));
+                }
+                shutdown::stop(&self.running, &self.exit);
+                if self.presumed_abort.is_some() {
+                    // every decision the coordinator sends came before its exit
+                    info!("participant_{} presuming txid {} aborted: the coordinator is exiting", self.id, pm.txid);
+                    return Some(Some(presumed.clone()));
+                }
+                if self.exit_policy == ExitPolicy::Flush {
+                    trace!("participant_{} flushing txid {} before exit", self.id, pm.txid);
+                    return None;
+                }
+            },
+            // coordinator is gone, no decision is coming
+            Err(RecvTimeoutError::Disconnected) => {},
+        }
+        info!("participant_{} abandoning in-flight txid {}", self.id, pm.txid);
+        let abandon = ProtocolMessage::generate(MessageType::ParticipantAbandon, pm.txid, format!("participant_{}", self.id), pm.opid);
+        self.log_record(&abandon);
+        Some(None)
+    }
 
+    ///
+    /// pre_commit()
+    /// --protocol 3pc: the coordinator pre-committed proposal pm, which we
+    /// voted yes on. log the pre-commit, ack it and wait for the commit.
+    /// there's only a pre-commit once everyone has voted yes, so if the 
+    /// commit doesn't come within precommit_timeout (or the coordinator
+    /// is gone), commit on our own: that's what keeps 3pc from blocking.
+    /// the next proposal (and even its decision) can get here before the
+    /// commit when the network reorders (--reorder-prob): they wait their
+    /// turn as in wait_for_decision(). returns the commit to log, None if
+    /// we crashed.
+    ///
+    fn pre_commit(&mut self, pm: &ProtocolMessage, precommit: &ProtocolMessage) -> Option<ProtocolMessage> {
+        self.log_record(precommit);
+        self.transition(pm.txid, ParticipantState::PreCommitted);
+        let ack = ProtocolMessage::generate(MessageType::ParticipantAck, pm.txid, format!("participant_{}", self.id), pm.opid)
+            .with_segment(precommit.segment)
+            .with_ops(pm.ops.clone());
+        self.log_record(&ack);
+        if let Err(err) = self.force_log() {
+            // the coordinator commits without our ack
+            info!("participant_{} couldn't force its pre-commit for txid {} ({}), not acking it", self.id, pm.txid, err);
+        } else {
+            self.send_unreliable(ack);
+        }
+        loop {
+            if self.crashed {
+                return None;
+            }
+            let received = self.ports.1.recv_timeout(self.precommit_timeout);
+            let reply = match received {
+                Ok(reply) => reply,
+                Err(err) => {
+                    info!("participant_{} committing pre-committed txid {} on its own: {}", self.id, pm.txid,
+                          if err == RecvTimeoutError::Timeout { "no commit came" } else { "the coordinator is gone" });
+                    break;
                 },
+            };
+            self.run.stepper.step(&format!("participant_{}", self.id), "receives", &reply);
+            if !self.inject_fault(&reply) {
+                continue;
+            }
+            self.clock.observe(&reply);
+            match reply.mtype {
+                MessageType::CoordinatorCommit if reply.txid == pm.txid => return Some(reply),
+                MessageType::CoordinatorCommit | MessageType::CoordinatorAbort if self.in_doubt.remove(&reply.txid) => self.settle(&reply),
+                MessageType::CoordinatorCommit | MessageType::CoordinatorAbort if self.decided.contains(&reply.txid) => self.ack_decision(&reply),
+                MessageType::CoordinatorCommit | MessageType::CoordinatorAbort if self.deferred.iter().any(|p| p.txid == reply.txid) => {
+                    self.early_decisions.insert(reply.txid, reply);
+                },
+                MessageType::CoordinatorPropose => self.deferred.push_back(reply),
                 MessageType::CoordinatorExit => {
-                    self.running.store(false, Ordering::SeqCst);
+                    shutdown::stop(&self.running, &self.exit);
+                    info!("participant_{} committing pre-committed txid {} on its own: the coordinator is exiting", self.id, pm.txid);
+                    break;
                 },
-                _ => {},
+                _ => trace!("participant_{} skipping {:?} for txid {} while pre-committed", self.id, reply.mtype, reply.txid),
             }
+        }
+        self.autonomous_commits += 1;
+        Some(ProtocolMessage::generate(MessageType::CoordinatorCommit, pm.txid, format!("participant_{}", self.id), pm.opid)
+            .with_segment(precommit.segment)
+            .with_ops(pm.ops.clone()))
+    }
 
+    ///
+    /// vote_abort()
+    /// vote abort on proposal pm. see it through to the decision with 
+    /// conclude().
+    /// 
+    fn vote_abort(&mut self, pm: &ProtocolMessage) {
+        let vabort = ProtocolMessage::generate(MessageType::ParticipantVoteAbort, pm.txid, format!("participant_{}", self.id), pm.opid)
+            .with_ops(pm.ops.clone());
+        self.log_record(&vabort);
+        if let Err(err) = self.force_log() {
+            // an abort vote holds nobody to anything: if it's lost, 
+            // recovery finds no vote and aborts anyway
+            info!("participant_{} couldn't force its abort vote for txid {}: {}", self.id, pm.txid, err);
         }
+        self.transition(pm.txid, ParticipantState::VotedAbort);
+        self.send_unreliable(vabort);
+        self.transition(pm.txid, ParticipantState::AwaitingDecision);
+    }
 
-        trace!("exit participant::perform_operation");
+    ///
+    /// redelivered()
+    /// a decision for a txid we aren't working on: an in-doubt one from
+    /// an earlier run (see recover()), or one the coordinator resent
+    /// having not heard our ack. deal with it and return whether it 
+    /// counts as a commit; None if pm is anything else.
+    ///
+    fn redelivered(&mut self, pm: &ProtocolMessage) -> Option<bool> {
+        if (pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort) && self.in_doubt.remove(&pm.txid) {
+            self.settle(pm);
+            return Some(pm.mtype == MessageType::CoordinatorCommit);
+        }
+        if (pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort) && self.decided.contains(&pm.txid) {
+            // resent: the coordinator hasn't heard our ack
+            self.ack_decision(pm);
+            return Some(false);
+        }
+        None
+    }
+
+    ///
+    /// vote()
+    /// phase 1: perform the operation proposal pm asks for, and vote on
+    /// how it went. returns how we voted (true for yes), or None if pm
+    /// isn't a proposal (and so there's nothing to vote on).
+    ///
+    fn vote(&mut self, pm: &ProtocolMessage) -> Option<bool> {
+        if pm.mtype == MessageType::CoordinatorPropose {
+            self.transition(pm.txid, ParticipantState::ReceivedP1);
+        }
+        if let Some(service) = self.service {
+            if pm.mtype == MessageType::CoordinatorPropose {
+                thread::sleep(service.sample(&mut self.service_rng));
+            }
+        }
+        // with --ops-per-txn every operation in the batch has to succeed
+        // for the transaction to: one failure and we vote abort on it all
+        let failed = (0..pm.ops.len().max(1)).any(|_| !self.failures.should_op_succeed());
+        let correlated = pm.mtype == MessageType::CoordinatorPropose && self.in_blast_radius && 
+                         self.run.fault_oracle.lock().unwrap().contains(&pm.txid);
+        if correlated || failed {
+            if pm.mtype != MessageType::CoordinatorPropose {
+                return None;
+            }
+            if correlated {
+                self.correlated_aborts += 1;
+            } else {
+                self.independent_aborts += 1;
+            }
+            self.log_record(pm);
+            self.vote_abort(pm);
+            return Some(false);
+        }
+        match pm.mtype {
+            MessageType::CoordinatorPropose => {
+                self.log_record(pm);
+                let savepoint = self.last_savepoint();
+                let vcommit = ProtocolMessage::generate(MessageType::ParticipantVoteCommit, pm.txid, format!("participant_{}", self.id), pm.opid)
+                    .with_segment(savepoint)
+                    .with_ops(pm.ops.clone());
+                // no yes vote without a durable record of it: if we 
+                // can't log the promise, we can't make it
+                if let Err(err) = self.try_log_record(&vcommit).and_then(|()| self.force_log()) {
+                    info!("participant_{} couldn't log its commit vote for txid {} ({}), voting abort", self.id, pm.txid, err);
+                    self.vote_log_failures += 1;
+                    self.vote_abort(pm);
+                    return Some(false);
+                }
+                self.transition(pm.txid, ParticipantState::VotedCommit);
+                if let Some(ref mut store) = self.store {
+                    store.stage(pm.txid);
+                }
+                self.send_unreliable(vcommit);
+                // wait for phase 2
+                self.transition(pm.txid, ParticipantState::AwaitingDecision);
+                Some(true)
+            },
+            MessageType::CoordinatorExit => {
+                shutdown::stop(&self.running, &self.exit);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    ///
+    /// vote_read_only()
+    /// vote on a read-only proposal (--read-only-ratio) and be done with
+    /// it: having changed nothing, we've nothing to commit or undo, so
+    /// the coordinator sends us no decision and we don't wait on one. the
+    /// vote is logged but not forced, there being no promise in it to
+    /// keep. returns false, doing nothing, for anything else.
+    ///
+    fn vote_read_only(&mut self, pm: &ProtocolMessage) -> bool {
+        if pm.mtype != MessageType::CoordinatorPropose || !pm.read_only {
+            return false;
+        }
+        self.transition(pm.txid, ParticipantState::ReceivedP1);
+        if let Some(service) = self.service {
+            thread::sleep(service.sample(&mut self.service_rng));
+        }
+        self.log_record(pm);
+        let vote = ProtocolMessage::generate(MessageType::ParticipantVoteCommit, pm.txid, format!("participant_{}", self.id), pm.opid)
+            .with_ops(pm.ops.clone())
+            .with_read_only(true);
+        self.log_record(&vote);
+        self.transition(pm.txid, ParticipantState::VotedReadOnly);
+        self.send_unreliable(vote);
+        self.transition(pm.txid, ParticipantState::Quiescent);
+        self.read_only += 1;
+        true
+    }
+
+    ///
+    /// conclude()
+    /// phase 2: act on the decision (see wait_for_decision()) for 
+    /// proposal pm, which we voted on as voted_yes says. returns whether
+    /// we committed it.
+    ///
+    fn conclude(&mut self, pm: &ProtocolMessage, voted_yes: bool, decision: Option<ProtocolMessage>) -> bool {
+        let mut result: RequestStatus = RequestStatus::Unknown;
+        if !voted_yes {
+            match decision {
+                Some(reply) => match reply.mtype {
+                    MessageType::CoordinatorAbort | MessageType::PresumedAbort => {
+                        self.log_record(&reply);
+                        if reply.mtype == MessageType::CoordinatorAbort {
+                            self.ack_decision(&reply);
+                        }
+                        self.note_abort(&reply);
+                        result = RequestStatus::Aborted;
+                    }
+                    _ => {self.unknown = self.unknown + 1},
+                },
+                None => self.unknown += 1,
+            }
+            self.finish(pm.txid, result != RequestStatus::Unknown);
+            return false;
+        }
+        let decision = match decision {
+            Some(ref reply) if reply.mtype == MessageType::CoordinatorPreCommit => self.pre_commit(pm, reply),
+            decision => decision,
+        };
+        match decision {
+            Some(reply) => match reply.mtype {
+                MessageType::CoordinatorCommit => {
+                    if reply.segment != 0 && reply.segment < self.segments {
+                        self.partial_commits += 1;
+                    }
+                    self.log_record(&reply);
+                    self.ack_decision(&reply);
+                    if let Some(ref mut store) = self.store {
+                        store.commit(pm.txid, reply.segment);
+                    }
+                    self.successful = self.successful + 1;
+                    result = RequestStatus::Committed;
+                }
+                MessageType::CoordinatorAbort | MessageType::PresumedAbort => {
+                    self.log_record(&reply);
+                    if reply.mtype == MessageType::CoordinatorAbort {
+                        self.ack_decision(&reply);
+                    }
+                    if let Some(ref mut store) = self.store {
+                        store.abort(pm.txid);
+                    }
+                    self.note_abort(&reply);
+                    result = RequestStatus::Aborted;
+                }
+                _ => {self.unknown = self.unknown + 1},
+            },
+            None => self.unknown += 1,
+        }
+        self.finish(pm.txid, result != RequestStatus::Unknown);
         result == RequestStatus::Committed
     }
 
+    /// 
+    /// perform_operation
+    /// perform the operation specified in the 2PC proposal,
+    /// with some probability of success/failure determined by the 
+    /// command-line option success_probability, and see it through to
+    /// the coordinator's decision (see vote() and conclude()). 
+    /// 
+    /// HINT: The code provided here is not complete--it provides some
+    ///       tracing infrastructure and the probability logic. 
+    ///       Your implementation need not preserve the method signature
+    ///       (it's ok to add parameters or return something other than 
+    ///       bool if it's more convenient for your design).
+    /// 
+    pub fn perform_operation(&mut self, request: &Option<ProtocolMessage>) -> bool {
+
+        trace!("participant::perform_operation");
+
+        let pm: &ProtocolMessage = request.as_ref().unwrap();
+        let committed = match self.redelivered(pm) {
+            Some(committed) => committed,
+            None if self.vote_read_only(pm) => true,
+            None => match self.vote(pm) {
+                Some(voted_yes) => {
+                    let decision = self.wait_for_decision(pm);
+                    self.conclude(pm, voted_yes, decision)
+                },
+                None => false,
+            },
+        };
+
+        trace!("exit participant::perform_operation");
+        committed
+    }
+
     ///
     /// report_status()
     /// report the abort/commit/unknown status (aggregate) of all 
@@ -239,25 +1047,119 @@ impl Participant {
         let global_successful_ops: i32 = self.successful;
         let global_failed_ops: i32 = self.failed;
         let global_unknown_ops: i32 = self.unknown;
-        println!("participant_{}:\tC:{}\tA:{}\tU:{}", self.id, global_successful_ops, global_failed_ops, global_unknown_ops);
+        println!("participant_{}:\tC:{}\tA:{}\tU:{}\tseed:{}", self.id, global_successful_ops, global_failed_ops, global_unknown_ops, self.seed);
+        if self.own_op_prob {
+            println!("participant_{}:\top success prob:{}\tabort votes:{}", self.id, self.op_success_prob, self.independent_aborts);
+        }
+        if self.correlated_aborts > 0 {
+            println!("participant_{}:\tcorrelated abort votes:{}\tindependent abort votes:{}", self.id, self.correlated_aborts, self.independent_aborts);
+        }
+        if self.segments > 1 {
+            println!("participant_{}:\tpartial commits:{}", self.id, self.partial_commits);
+        }
+        if !self.abort_reasons.is_empty() {
+            let counts: Vec<String> = self.abort_reasons.iter().map(|(reason, n)| format!("{}:{}", reason, n)).collect();
+            println!("participant_{}:\taborts by reason: {}", self.id, counts.join(" "));
+        }
+        if self.warmup > 0 {
+            let rate = |(c, n): (i32, i32)| if n > 0 { 100.0 * c as f64 / n as f64 } else { 0.0 };
+            let (during, after) = (self.warmup_outcomes[0], self.warmup_outcomes[1]);
+            println!("participant_{}:\tcommit rate during warmup:{:.0}% ({}/{})\tafter:{:.0}% ({}/{})", 
+                     self.id, rate(during), during.0, during.1, rate(after), after.0, after.1);
+        }
+        if self.read_only > 0 {
+            println!("participant_{}:\tread-only votes (no phase 2):{}", self.id, self.read_only);
+        }
+        if self.vote_log_failures > 0 {
+            println!("participant_{}:\tcommit votes not logged (voted abort):{}", self.id, self.vote_log_failures);
+        }
+        if self.decision_rerequests > 0 {
+            println!("participant_{}:\tdecisions asked for again:{}", self.id, self.decision_rerequests);
+        }
+        if self.autonomous_commits > 0 {
+            println!("participant_{}:\tcommitted on its own after a pre-commit:{}", self.id, self.autonomous_commits);
+        }
+        if self.measure_contention {
+            let (acquisitions, waited) = self.log.contention();
+            println!("participant_{}:\toplog lock acquisitions:{}\twaited:{:?}", self.id, acquisitions, waited);
+        }
+    }
+
+    ///
+    /// ack_exit()
+    /// tell the coordinator we've left the protocol, so it can stop 
+    /// waiting on us at shutdown. a delayed ack is sent from its own 
+    /// thread: it holds up the coordinator, not the rest of the run.
+    /// 
+    fn ack_exit(&mut self) {
+        let ack = ProtocolMessage::generate(MessageType::ParticipantExitAck, -1, format!("participant_{}", self.id), -1);
+        match self.exit_ack {
+            ExitAck::Prompt => {
+                self.send(ack);
+            },
+            ExitAck::Delayed(delay) => {
+                info!("participant_{} holding back its exit ack for {:?}", self.id, delay);
+                let ack = self.clock.stamp(ack);
+                let tx = self.ports.0.clone();
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    let _ = tx.send(ack);
+                });
+            },
+            ExitAck::Never => info!("participant_{} never acking exit", self.id),
+        }
     }
 
     ///
     /// wait_for_exit_signal(&mut self)
-    /// wait until the running flag is set by the CTRL-C handler
+    /// wait until the running flag is cleared by the CTRL-C handler (or
+    /// the coordinator shutting down), blocking on the exit signal 
+    /// rather than spinning on the flag
     /// 
     pub fn wait_for_exit_signal(&mut self) {
 
         trace!("participant_{} waiting for exit signal", self.id);
 
-        let mut val = self.running.load(Ordering::SeqCst);
-        while val {
-            val = self.running.load(Ordering::SeqCst);
-        }
+        shutdown::wait(&self.running, &self.exit);
 
         trace!("participant_{} exiting", self.id);
     }    
 
+    ///
+    /// heartbeat_to()
+    /// --heartbeat-ms: once running, send a heartbeat on tx every 
+    /// interval, for as long as we're alive
+    ///
+    pub fn heartbeat_to(&mut self, tx: Sender<ProtocolMessage>, interval: Duration) {
+        self.heartbeat = Some((tx, interval));
+    }
+
+    ///
+    /// start_heartbeats()
+    /// beat on the heartbeat channel, if we have one, from a thread of
+    /// its own, so a participant busy with a proposal (or waiting on a
+    /// decision) still looks alive. it stops when we crash or finish, 
+    /// or the coordinator goes away.
+    ///
+    fn start_heartbeats(&self) {
+        let (tx, interval) = match self.heartbeat {
+            Some((ref tx, interval)) => (tx.clone(), interval),
+            None => return,
+        };
+        let alive = self.alive.clone();
+        let running = self.running.clone();
+        let sender = format!("participant_{}", self.id);
+        thread::spawn(move || {
+            while alive.load(Ordering::SeqCst) && running.load(Ordering::SeqCst) {
+                let beat = ProtocolMessage::generate(MessageType::Heartbeat, -1, sender.clone(), -1);
+                if tx.send(beat).is_err() {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+        });
+    }
+
     ///
     /// protocol()
     /// Implements the participant side of the 2PC protocol
@@ -268,15 +1170,33 @@ impl Participant {
         
         trace!("Participant_{}::protocol", self.id);
 
+        self.start();
         let mut running;
         loop {
             running = self.running.load(Ordering::SeqCst);
             if running {
-                let res = self.ports.1.recv();
+                // a deferred proposal was already received (see wait_for_decision())
+                let (res, deferred) = match self.deferred.pop_front() {
+                    Some(pm) => (Ok(pm), true),
+                    None => (self.ports.1.recv(), false),
+                };
                 match res {
                     Ok(pm) => {
+                        if !deferred && !self.admit(&pm) {
+                            if self.crashed {
+                                break;
+                            }
+                            continue;
+                        }
+                        let proposal = pm.mtype == MessageType::CoordinatorPropose;
                         let rf: Option<ProtocolMessage> = Some(pm);
-                        let _res = self.perform_operation(&rf);
+                        let committed = self.perform_operation(&rf);
+                        if proposal {
+                            self.tally_proposal(committed);
+                        }
+                        if self.crashed {
+                            break;
+                        }
                     },
                     Err(_err) => break,
                 }
@@ -286,7 +1206,199 @@ impl Participant {
             }
         }
 
+        self.leave();
         self.wait_for_exit_signal();
+        self.wrap_up();
+    }
+
+    ///
+    /// start()
+    /// get going: start beating, and ask after anything we're in doubt on
+    ///
+    fn start(&mut self) {
+        self.start_heartbeats();
+        if !self.in_doubt.is_empty() {
+            self.recover();
+        }
+    }
+
+    ///
+    /// admit()
+    /// take delivery of pm, fresh off the channel from the coordinator. 
+    /// false if a fault lost it (or crashed us) and it must be ignored.
+    ///
+    fn admit(&mut self, pm: &ProtocolMessage) -> bool {
+        self.run.stepper.step(&format!("participant_{}", self.id), "receives", pm);
+        if !self.inject_fault(pm) {
+            return false;
+        }
+        self.clock.observe(pm);
+        true
+    }
+
+    ///
+    /// leave()
+    /// done handling the coordinator's messages: stop beating and tell 
+    /// the coordinator we're gone
+    ///
+    fn leave(&mut self) {
+        self.alive.store(false, Ordering::SeqCst);
+        if !self.crashed {
+            self.ack_exit();
+        }
+    }
+
+    ///
+    /// wrap_up()
+    /// once the exit signal is in: put the log and store to bed, and 
+    /// report
+    ///
+    fn wrap_up(&mut self) {
+        if let Err(err) = self.log.flush() {
+            error!("participant_{} failed to write out its log: {}", self.id, err);
+        }
+        if self.lose_last_commit {
+            self.simulate_data_loss();
+        }
+        if let Some(ref store) = self.store {
+            if let Err(err) = store.save() {
+                error!("participant_{} failed to save its key-value store: {}", self.id, err);
+            }
+        }
         self.report_status();
     }
+
+    ///
+    /// step()
+    /// --worker-threads: protocol() a step at a time, for a worker that 
+    /// drives many participants. a step handles one message (or a wait
+    /// on a decision timing out) and never blocks waiting for the next:
+    /// it's Idle if there isn't one yet, and Done once we've reported.
+    ///
+    pub fn step(&mut self) -> Step {
+        match self.phase {
+            Phase::Starting => {
+                self.start();
+                self.phase = Phase::Running;
+                Step::Busy
+            },
+            Phase::Running => {
+                let step = self.step_running();
+                if self.crashed && self.awaiting.is_none() {
+                    self.leave();
+                    self.phase = Phase::Exiting;
+                }
+                step
+            },
+            Phase::Exiting if self.running.load(Ordering::SeqCst) => Step::Idle,
+            Phase::Exiting => {
+                self.wrap_up();
+                self.phase = Phase::Done;
+                Step::Done
+            },
+            Phase::Done => Step::Done,
+        }
+    }
+
+    ///
+    /// step_running()
+    /// one step of protocol()'s loop: one turn of wait_for_decision() if
+    /// we're waiting on a decision, else the next message
+    ///
+    fn step_running(&mut self) -> Step {
+        if let Some(mut waiting) = self.awaiting.take() {
+            if self.crashed {
+                // a crashed participant logs nothing more, not even the abandon
+                self.decided(waiting.proposal, waiting.voted_yes, None);
+                return Step::Busy;
+            }
+            let received = match self.ports.1.try_recv() {
+                Ok(reply) => Ok(reply),
+                // an operator paused at a step shouldn't make us presume
+                Err(TryRecvError::Empty) if self.run.stepper.enabled() || Instant::now() < waiting.until => {
+                    self.awaiting = Some(waiting);
+                    return Step::Idle;
+                },
+                Err(TryRecvError::Empty) => Err(RecvTimeoutError::Timeout),
+                Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+            };
+            match self.on_waiting(&waiting.proposal, &waiting.presumed, received) {
+                None => {
+                    waiting.until = Instant::now() + self.decision_wait();
+                    self.awaiting = Some(waiting);
+                },
+                Some(decision) => self.decided(waiting.proposal, waiting.voted_yes, decision),
+            }
+            return Step::Busy;
+        }
+        if !self.running.load(Ordering::SeqCst) {
+            self.leave();
+            self.phase = Phase::Exiting;
+            return Step::Busy;
+        }
+        let pm = match self.deferred.pop_front() {
+            Some(pm) => pm,
+            None => match self.ports.1.try_recv() {
+                Ok(pm) if self.admit(&pm) => pm,
+                Ok(_pm) => return Step::Busy,
+                Err(TryRecvError::Empty) => return Step::Idle,
+                Err(TryRecvError::Disconnected) => {
+                    self.leave();
+                    self.phase = Phase::Exiting;
+                    return Step::Busy;
+                },
+            },
+        };
+        if self.redelivered(&pm).is_some() {
+            return Step::Busy;
+        }
+        if self.vote_read_only(&pm) {
+            self.tally_proposal(true);
+            return Step::Busy;
+        }
+        if let Some(voted_yes) = self.vote(&pm) {
+            match self.early_decisions.remove(&pm.txid) {
+                Some(decision) => self.decided(pm, voted_yes, Some(decision)),
+                None => {
+                    let presumed = ProtocolMessage::generate(MessageType::PresumedAbort, pm.txid, format!("participant_{}", self.id), pm.opid);
+                    self.awaiting = Some(Awaiting {
+                        proposal: pm,
+                        voted_yes,
+                        presumed,
+                        until: Instant::now() + self.decision_wait(),
+                    });
+                },
+            }
+        }
+        Step::Busy
+    }
+
+    ///
+    /// decided()
+    /// step(): the wait for the decision on proposal is over
+    ///
+    fn decided(&mut self, proposal: ProtocolMessage, voted_yes: bool, decision: Option<ProtocolMessage>) {
+        let committed = self.conclude(&proposal, voted_yes, decision);
+        self.tally_proposal(committed);
+    }
+
+    ///
+    /// simulate_data_loss()
+    /// model a crash and restart on a bad disk: every durable record of 
+    /// the most recently committed txid disappears from our log. the 
+    /// coordinator still has it as committed, so the checker must report
+    /// this as a durability violation. 
+    /// 
+    fn simulate_data_loss(&mut self) {
+
+        let last_commit = self.log.arc().lock().unwrap().values()
+            .filter(|pm| pm.mtype == MessageType::CoordinatorCommit)
+            .max_by_key(|pm| pm.uid)
+            .map(|pm| pm.txid);
+        if let Some(txid) = last_commit {
+            let lost = self.log.discard_txid(txid);
+            self.successful -= 1;
+            println!("participant_{}:\tsimulated data loss of committed txid {} ({} records)", self.id, txid, lost);
+        }
+    }
 }
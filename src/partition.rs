@@ -0,0 +1,113 @@
+//!
+//! partition.rs
+//! A scripted network partition (--partition): for a window of the run,
+//! every message between the coordinator and a set of participants is
+//! lost, then the network heals. The spec is a list of key=value pairs:
+//!
+//!     participants=2,3 at=2s duration=5s
+//!
+//! cuts participants 2 and 3 off from 2s into the run until 7s into it.
+//! Times take an s or ms suffix. The same partition can be given as
+//! --partition-participants 2,3 --partition-after-ms 2000
+//! --partition-duration-ms 5000.
+//!
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tpcoptions::TPCOptions;
+
+///
+/// Partition
+/// which participants are cut off, and when
+///
+#[derive(Clone, Debug)]
+pub struct Partition {
+    participants: HashSet<i32>,
+    at: Duration,           // when the partition starts, from the start of the run
+    duration: Duration,     // how long until it heals
+    start: Instant,         // start of the run
+}
+
+fn parse_duration(s: &str) -> Duration {
+    if let Some(ms) = s.strip_suffix("ms") {
+        Duration::from_millis(ms.parse::<u64>().unwrap())
+    } else if let Some(secs) = s.strip_suffix('s') {
+        Duration::from_secs_f64(secs.parse::<f64>().unwrap())
+    } else {
+        panic!("partition time {} needs an s or ms suffix", s)
+    }
+}
+
+impl Partition {
+
+    ///
+    /// new()
+    /// participants cut off from at into the run for duration. the run
+    /// is taken to start now.
+    ///
+    pub fn new(participants: HashSet<i32>, at: Duration, duration: Duration) -> Partition {
+        Partition {
+            participants,
+            at,
+            duration,
+            start: Instant::now(),
+        }
+    }
+
+    ///
+    /// from_options()
+    /// the partition opts scripts, if any: a --partition spec, or
+    /// --partition-participants and its window
+    ///
+    pub fn from_options(opts: &TPCOptions) -> Option<Partition> {
+        if let Some(ref spec) = opts.partition {
+            return Some(Partition::from_string(spec));
+        }
+        if opts.partition_participants.is_empty() {
+            return None;
+        }
+        Some(Partition::new(opts.partition_participants.iter().cloned().collect(),
+                            Duration::from_millis(opts.partition_after_ms),
+                            Duration::from_millis(opts.partition_duration_ms)))
+    }
+
+    ///
+    /// from_string()
+    /// parse a --partition spec. the run is taken to start now.
+    ///
+    pub fn from_string(spec: &str) -> Partition {
+        let mut participants = None;
+        let mut at = Duration::from_secs(0);
+        let mut duration = None;
+        for field in spec.split_whitespace() {
+            match field.split_once('=') {
+                Some(("participants", ids)) => participants = Some(ids.split(',')
+                    .map(|id| id.parse::<i32>().unwrap())
+                    .collect()),
+                Some(("at", t)) => at = parse_duration(t),
+                Some(("duration", t)) => duration = Some(parse_duration(t)),
+                _ => panic!("unknown partition setting {}", field),
+            }
+        }
+        Partition::new(participants.expect("a partition needs participants=<id>,..."),
+                       at,
+                       duration.expect("a partition needs duration=<time>"))
+    }
+
+    ///
+    /// active()
+    /// true while the partition is in effect
+    ///
+    pub fn active(&self) -> bool {
+        let elapsed = self.start.elapsed();
+        elapsed >= self.at && elapsed < self.at + self.duration
+    }
+
+    ///
+    /// cuts()
+    /// true if messages to and from participant (its id, e.g. "2") are
+    /// being lost right now
+    ///
+    pub fn cuts(&self, participant: &str) -> bool {
+        participant.parse::<i32>().is_ok_and(|id| self.participants.contains(&id)) && self.active()
+    }
+}
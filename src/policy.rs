@@ -0,0 +1,124 @@
+//!
+//! policy.rs
+//! How the coordinator turns phase 1 votes into a decision. The
+//! coordinator holds a DecisionPolicy (chosen with --decision-policy)
+//! and asks it to decide every transaction once the votes are in.
+//! Implement the trait and add it to from_string() to plug in another.
+//!
+use std::fmt::Debug;
+
+///
+/// Vote
+/// one participant's phase 1 answer, as the coordinator saw it
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vote {
+    Commit(i32),    // yes, through this savepoint (0 = whole transaction)
+    Abort,
+    Missing,        // no vote arrived before the timeout
+}
+
+///
+/// Decision
+/// what the coordinator logs and broadcasts in phase 2
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Commit(i32),    // commit through this savepoint (0 = whole transaction)
+    Abort,
+}
+
+pub trait DecisionPolicy: Debug + Send {
+    /// the name the policy is selected and reported by
+    fn name(&self) -> String;
+    /// decide a transaction given every participant's vote
+    fn decide(&self, votes: &[Vote]) -> Decision;
+}
+
+///
+/// Unanimous
+/// standard 2PC: commit iff every participant voted yes, through the
+/// last savepoint all of them reached
+///
+#[derive(Debug)]
+pub struct Unanimous;
+
+impl DecisionPolicy for Unanimous {
+    fn name(&self) -> String {
+        String::from("unanimous")
+    }
+    fn decide(&self, votes: &[Vote]) -> Decision {
+        let mut savepoint = i32::MAX;
+        for vote in votes.iter() {
+            match vote {
+                Vote::Commit(s) => savepoint = savepoint.min(*s),
+                Vote::Abort | Vote::Missing => return Decision::Abort,
+            }
+        }
+        Decision::Commit(if savepoint == i32::MAX { 0 } else { savepoint })
+    }
+}
+
+///
+/// Quorum
+/// commit iff at least min_yes participants voted yes. participants that
+/// voted no (or didn't vote) never logged a yes for the transaction, so
+/// the checker's durability check will report them when this commits
+/// without them.
+///
+#[derive(Debug)]
+pub struct Quorum {
+    pub min_yes: usize,
+}
+
+impl DecisionPolicy for Quorum {
+    fn name(&self) -> String {
+        format!("quorum:{}", self.min_yes)
+    }
+    fn decide(&self, votes: &[Vote]) -> Decision {
+        let yes: Vec<i32> = votes.iter()
+            .filter_map(|v| match v { Vote::Commit(s) => Some(*s), _ => None })
+            .collect();
+        if yes.len() >= self.min_yes {
+            Decision::Commit(yes.into_iter().min().unwrap_or(0))
+        } else {
+            Decision::Abort
+        }
+    }
+}
+
+///
+/// from_string()
+/// the policy named by --decision-policy: "unanimous" or "quorum:<n>"
+///
+pub fn from_string(s: &str) -> Box<dyn DecisionPolicy> {
+    match s {
+        "unanimous" => Box::new(Unanimous),
+        _ if s.starts_with("quorum:") => Box::new(Quorum {
+            min_yes: s["quorum:".len()..].parse::<usize>().unwrap(),
+        }),
+        _ => panic!("unknown decision policy {}", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// quorum_commits_without_every_vote()
+    /// quorum:2 of 3 commits over a no, or a missing vote, through the
+    /// lowest savepoint the yes voters reached, and aborts with only 
+    /// one yes
+    ///
+    #[test]
+    fn quorum_commits_without_every_vote() {
+        let policy = from_string("quorum:2");
+        assert_eq!(policy.name(), "quorum:2");
+        assert_eq!(policy.decide(&[Vote::Commit(0), Vote::Abort, Vote::Commit(0)]), Decision::Commit(0));
+        assert_eq!(policy.decide(&[Vote::Missing, Vote::Commit(3), Vote::Commit(2)]), Decision::Commit(2));
+        assert_eq!(policy.decide(&[Vote::Commit(0), Vote::Abort, Vote::Missing]), Decision::Abort);
+        // the same votes unanimous aborts
+        assert_eq!(from_string("unanimous").decide(&[Vote::Commit(0), Vote::Abort, Vote::Commit(0)]), Decision::Abort);
+    }
+}
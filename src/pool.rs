@@ -0,0 +1,78 @@
+//!
+//! pool.rs
+//! A bounded pool of worker threads (--worker-threads) that drives many
+//! clients and participants cooperatively, instead of one OS thread
+//! each. Each entity is stepped (see Entity::step()) rather than left to
+//! block: a step does whatever is ready and hands the worker back. The
+//! entities circulate between the workers on a crossbeam channel until
+//! every one of them is done.
+//!
+extern crate crossbeam_channel;
+use pool::crossbeam_channel::{unbounded, RecvTimeoutError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// how long a worker naps once a round of steps found nothing to do
+const IDLE_NAP: Duration = Duration::from_millis(1);
+
+///
+/// Step
+/// what came of stepping an entity
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Busy,               // it did something: step it again soon
+    Idle,               // nothing was ready (no message, a timeout not yet due)
+    Done,               // it has finished and reported
+}
+
+pub trait Entity: Send {
+    /// do whatever is ready without blocking on anything that isn't
+    fn step(&mut self) -> Step;
+}
+
+///
+/// launch()
+/// start workers worker threads (at least one), stepping entities
+/// until all of them are done, and push their handles onto handles
+///
+pub fn launch(entities: Vec<Box<dyn Entity>>, workers: usize, handles: &mut Vec<JoinHandle<()>>) {
+    let (tx, rx) = unbounded::<Box<dyn Entity>>();
+    let remaining = Arc::new(AtomicUsize::new(entities.len()));
+    let n_entities = entities.len().max(1);
+    for entity in entities {
+        tx.send(entity).unwrap();
+    }
+    for _ in 0..workers.max(1) {
+        let (tx, rx, remaining) = (tx.clone(), rx.clone(), remaining.clone());
+        handles.push(thread::spawn(move || {
+            // steps in a row that found nothing to do: once that's a
+            // whole round of the entities, nap rather than spin
+            let mut idle = 0;
+            while remaining.load(Ordering::SeqCst) > 0 {
+                let mut entity = match rx.recv_timeout(IDLE_NAP) {
+                    Ok(entity) => entity,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                match entity.step() {
+                    Step::Done => {
+                        remaining.fetch_sub(1, Ordering::SeqCst);
+                        idle = 0;
+                        continue;
+                    },
+                    Step::Busy => idle = 0,
+                    Step::Idle => idle += 1,
+                }
+                tx.send(entity).unwrap();
+                if idle >= n_entities {
+                    thread::sleep(IDLE_NAP);
+                    idle = 0;
+                }
+            }
+        }));
+    }
+}
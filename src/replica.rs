@@ -0,0 +1,174 @@
+//!
+//! replica.rs
+//! EXPERIMENTAL (--replicate): a primary/backup coordinator pair.
+//!
+//! The primary ships every record it logs to the backup and waits for
+//! the backup to log it too before acting on it, so the backup's log
+//! (coordinator_backup.log) always holds every decision the primary
+//! could have sent anywhere. A heartbeat thread tells the backup the
+//! primary is alive. When the heartbeats stop without a shutdown (the
+//! primary crashed, e.g. via a "crash" in --fault-schedule), the backup
+//! takes the primary's network endpoints, rebuilds a coordinator from
+//! its replica of the log, settles the transaction the primary was in
+//! the middle of, and carries on with the rest of the run.
+//!
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use coordinator::{Coordinator, Endpoints};
+use message::ProtocolMessage;
+use oplog::{OpLog, OpLogFormat};
+use tpcoptions::TPCOptions;
+use shutdown::ExitSignal;
+use runstate::RunState;
+
+// how often the primary's heartbeat thread checks in
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(20);
+
+///
+/// Replication
+/// what flows from the primary to the backup
+///
+#[derive(Debug)]
+pub enum Replication {
+    Record(ProtocolMessage),    // log this, then ack
+    Heartbeat,                  // still alive
+    Shutdown,                   // the primary finished the run; don't take over
+}
+
+///
+/// ReplicaLink
+/// the primary's end of the replication channel
+///
+#[derive(Debug)]
+pub struct ReplicaLink {
+    tx: Sender<Replication>,
+    acks: Receiver<()>,
+    beating: Arc<AtomicBool>,   // the heartbeat thread runs while this is set
+}
+
+///
+/// Backup
+/// the backup coordinator while it tails the primary
+///
+#[derive(Debug)]
+pub struct Backup {
+    logpath: String,
+    log: OpLog,
+    rx: Receiver<Replication>,
+    acks: Sender<()>,
+    failover_timeout: Duration,
+}
+
+///
+/// link()
+/// set up replication to a backup logging to logpath, and start the
+/// primary's heartbeat
+///
+pub fn link(opts: &TPCOptions, run: &RunState, logpath: String) -> (ReplicaLink, Backup) {
+    let (tx, rx) = channel();
+    let (ack_tx, ack_rx) = channel();
+    let beating = Arc::new(AtomicBool::new(true));
+    let heartbeat_tx = tx.clone();
+    let heartbeat_beating = beating.clone();
+    thread::spawn(move || {
+        while heartbeat_beating.load(Ordering::SeqCst) && heartbeat_tx.send(Replication::Heartbeat).is_ok() {
+            thread::sleep(HEARTBEAT_INTERVAL);
+        }
+    });
+    let link = ReplicaLink {
+        tx,
+        acks: ack_rx,
+        beating,
+    };
+    let mut log = OpLog::with_options(logpath.clone(), opts.log_max_bytes, false, opts.log_index, OpLogFormat::from_string(&opts.log_format));
+    if opts.log_checksums {
+        log.checksum_records();
+    }
+    log.count_appended(run.log_bytes.clone());
+    let backup = Backup {
+        log,
+        logpath,
+        rx,
+        acks: ack_tx,
+        failover_timeout: Duration::from_millis(opts.failover_timeout_ms),
+    };
+    (link, backup)
+}
+
+impl ReplicaLink {
+
+    ///
+    /// ship()
+    /// replicate a record the primary just logged. returns once the
+    /// backup has logged it too (or is gone).
+    ///
+    pub fn ship(&self, pm: &ProtocolMessage) {
+        if self.tx.send(Replication::Record(pm.clone())).is_ok() {
+            let _ = self.acks.recv();
+        }
+    }
+
+    ///
+    /// fail()
+    /// the primary crashed: its heartbeat stops
+    ///
+    pub fn fail(&self) {
+        self.beating.store(false, Ordering::SeqCst);
+    }
+
+    ///
+    /// shutdown()
+    /// the primary is done; the backup can stand down
+    ///
+    pub fn shutdown(&self) {
+        self.beating.store(false, Ordering::SeqCst);
+        let _ = self.tx.send(Replication::Shutdown);
+    }
+}
+
+impl Backup {
+
+    ///
+    /// follow()
+    /// log everything the primary ships until it shuts down (false) or
+    /// misses its heartbeats for failover_timeout (true: take over)
+    ///
+    pub fn follow(&mut self) -> bool {
+        loop {
+            match self.rx.recv_timeout(self.failover_timeout) {
+                Ok(Replication::Record(pm)) => {
+                    self.log.append_record(pm).unwrap();
+                    let _ = self.acks.send(());
+                },
+                Ok(Replication::Heartbeat) => {},
+                Ok(Replication::Shutdown) => return false,
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    warn!("backup coordinator: primary missed its heartbeats for {:?}, taking over", self.failover_timeout);
+                    return true;
+                },
+            }
+        }
+    }
+
+    ///
+    /// take_over()
+    /// become the coordinator: rebuild one from the replicated log (as
+    /// --resume would), give it the primary's endpoints, and settle the
+    /// transaction the primary was in the middle of
+    ///
+    pub fn take_over(self, opts: &TPCOptions, running: Arc<AtomicBool>, exit: ExitSignal, run: RunState, seed: u64, endpoints: Endpoints) -> Coordinator {
+        let logpath = self.logpath.clone();
+        drop(self);
+        let mut bopts = opts.clone();
+        bopts.resume = true;
+        // the schedule's faults were the primary's
+        bopts.fault_schedule = None;
+        let mut coordinator = Coordinator::new(logpath, running, exit, run, &bopts, seed);
+        coordinator.adopt(endpoints);
+        coordinator.recover();
+        coordinator
+    }
+}
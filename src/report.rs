@@ -0,0 +1,93 @@
+//!
+//! report.rs
+//! Export a run's transactions as one CSV table (--csv), for loading
+//! into a spreadsheet instead of reading the logs: a row per txid the
+//! coordinator received a request for, with its client, the decision,
+//! the request->result latency the coordinator saw, how long the client
+//! waited to get the request into a full coordinator queue, and each
+//! participant's vote from its own log.
+//!
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::time::Duration;
+use message::MessageType;
+use oplog::OpLog;
+use summary;
+
+///
+/// votes()
+/// txid -> the vote the participant logged at fpath cast on it ("yes",
+/// "no" or "read-only"), its last if it voted more than once (e.g. per
+/// segment)
+///
+fn votes(fpath: &str) -> io::Result<HashMap<i32, &'static str>> {
+    let mut votes = HashMap::new();
+    for pm in OpLog::records(fpath) {
+        let pm = pm?;
+        match pm.mtype {
+            MessageType::ParticipantVoteCommit if pm.read_only => { votes.insert(pm.txid, "read-only"); },
+            MessageType::ParticipantVoteCommit => { votes.insert(pm.txid, "yes"); },
+            MessageType::ParticipantVoteAbort => { votes.insert(pm.txid, "no"); },
+            _ => {},
+        }
+    }
+    Ok(votes)
+}
+
+///
+/// write_csv()
+///
+/// merge the coordinator's log (clogpath) and the n_participants
+/// participant logs under logpath into a table at csv_path, one row per
+/// txid in txid order:
+///
+///     txid,client,decision,latency_ms,backpressure_ms,participant_0,...,participant_<n-1>
+///
+/// decision is commit or abort, and blank if the coordinator's outcome
+/// is unknown. latency_ms is blank if the client was never answered.
+/// backpressure_ms is from backpressured (see Client::backpressured), 
+/// and 0 for a txid that never found the queue full, which is every 
+/// txid without --channel-capacity. a vote is blank if that participant
+/// logged none. returns how many rows were written.
+///
+pub fn write_csv(logpath: &str, clogpath: &str, n_participants: i32, backpressured: &HashMap<i32, Duration>, csv_path: &str) -> io::Result<usize> {
+    let outcomes = summary::transactions(clogpath);
+    let mut requested: HashMap<i32, u128> = HashMap::new();
+    let mut answered: HashMap<i32, u128> = HashMap::new();
+    for pm in OpLog::records(clogpath) {
+        let pm = pm?;
+        match pm.mtype {
+            MessageType::ClientRequest => { requested.entry(pm.txid).or_insert(pm.timestamp); },
+            MessageType::ClientResultCommit | MessageType::ClientResultAbort => { answered.entry(pm.txid).or_insert(pm.timestamp); },
+            _ => {},
+        }
+    }
+    let votes: Vec<HashMap<i32, &str>> = (0..n_participants)
+        .map(|i| votes(&format!("{}participant_{}.log", logpath, i)))
+        .collect::<io::Result<_>>()?;
+
+    let mut out = BufWriter::new(fs::File::create(csv_path)?);
+    write!(out, "txid,client,decision,latency_ms,backpressure_ms")?;
+    for i in 0..n_participants {
+        write!(out, ",participant_{}", i)?;
+    }
+    writeln!(out)?;
+    for t in outcomes.iter() {
+        let decision = if t.outcome == "unknown" { "" } else { t.outcome.as_str() };
+        let latency = match (requested.get(&t.txid), answered.get(&t.txid)) {
+            (Some(request), Some(result)) => format!("{:.3}", result.saturating_sub(*request) as f64 / 1_000_000.0),
+            _ => String::new(),
+        };
+        let backpressure = backpressured.get(&t.txid).map_or(0.0, |waited| waited.as_secs_f64() * 1000.0);
+        write!(out, "{},{},{},{},{:.3}", t.txid, t.client, decision, latency, backpressure)?;
+        for v in votes.iter() {
+            write!(out, ",{}", v.get(&t.txid).unwrap_or(&""))?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(outcomes.len())
+}
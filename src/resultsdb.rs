@@ -0,0 +1,98 @@
+//! 
+//! resultsdb.rs
+//! Archive run summaries in a SQLite database (--results-db), so many 
+//! runs (e.g. a parameter sweep) can be compared with SQL. Each run adds
+//! a row to `runs`; with --store-transactions, each of its transactions
+//! also gets a row in `transactions`, keyed by the run's id.
+//! 
+extern crate rusqlite;
+extern crate serde_json;
+use self::rusqlite::{params, Connection};
+use summary::{RunSummary, TransactionOutcome};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id                      INTEGER PRIMARY KEY,
+        recorded_at             TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        seed                    TEXT NOT NULL,
+        num_clients             INTEGER NOT NULL,
+        num_participants        INTEGER NOT NULL,
+        num_requests            INTEGER NOT NULL,
+        success_probability_ops REAL NOT NULL,
+        success_probability_msg REAL NOT NULL,
+        decision_policy         TEXT NOT NULL,
+        options                 TEXT NOT NULL,
+        committed               INTEGER NOT NULL,
+        aborted                 INTEGER NOT NULL,
+        unknown                 INTEGER NOT NULL,
+        elapsed_ms              REAL NOT NULL,
+        latency_samples         INTEGER NOT NULL,
+        latency_p50_ms          REAL,
+        latency_p90_ms          REAL,
+        latency_p99_ms          REAL,
+        latency_max_ms          REAL,
+        served_per_client       TEXT NOT NULL,  -- JSON object, client -> transactions decided
+        fairness                REAL            -- Jain's index over served_per_client
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        run_id                  INTEGER NOT NULL REFERENCES runs(id),
+        txid                    INTEGER NOT NULL,
+        client                  TEXT NOT NULL,
+        opid                    INTEGER NOT NULL,
+        outcome                 TEXT NOT NULL,
+        segment                 INTEGER NOT NULL,
+        PRIMARY KEY (run_id, txid)
+    );";
+
+///
+/// store()
+/// append summary (and, if given, its transactions) to the database at
+/// path, creating the file and schema if needed. returns the new run's id.
+///
+pub fn store(
+    path: &str,
+    summary: &RunSummary,
+    transactions: Option<&[TransactionOutcome]>) -> rusqlite::Result<i64> {
+
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+    let ms = |p| summary.latency(p).map(|d| d.as_secs_f64() * 1000.0);
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (seed, num_clients, num_participants, num_requests, 
+                           success_probability_ops, success_probability_msg, decision_policy, options,
+                           committed, aborted, unknown, elapsed_ms, latency_samples,
+                           latency_p50_ms, latency_p90_ms, latency_p99_ms, latency_max_ms,
+                           served_per_client, fairness)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        params![
+            summary.seed.to_string(),
+            summary.options.num_clients,
+            summary.options.num_participants,
+            summary.options.num_requests,
+            summary.options.success_probability_ops,
+            summary.options.success_probability_msg,
+            summary.options.decision_policy,
+            format!("{:?}", summary.options),
+            summary.committed,
+            summary.aborted,
+            summary.unknown,
+            summary.elapsed.as_secs_f64() * 1000.0,
+            summary.latencies.len() as i64,
+            ms(50.0),
+            ms(90.0),
+            ms(99.0),
+            ms(100.0),
+            serde_json::to_string(&summary.served).unwrap(),
+            summary.fairness(),
+        ])?;
+    let run_id = tx.last_insert_rowid();
+    for t in transactions.into_iter().flatten() {
+        tx.execute(
+            "INSERT INTO transactions (run_id, txid, client, opid, outcome, segment) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![run_id, t.txid, t.client, t.opid, t.outcome, t.segment])?;
+    }
+    tx.commit()?;
+    Ok(run_id)
+}
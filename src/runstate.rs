@@ -0,0 +1,69 @@
+//!
+//! runstate.rs
+//! What one run's coordinator, clients and participants share besides
+//! the running flag and exit signal (see shutdown.rs): the counter txids
+//! are handed out from, whether the run is being stepped through 
+//! (--interactive), the txids hit by correlated faults, every client's
+//! latencies, and the bytes its logs have appended. simulation::run_with() makes it fresh for each run 
+//! and hands it down, rather than keeping it in statics, so runs in the
+//! same process (e.g. tests running side by side) don't see each 
+//! other's.
+//!
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::time::Duration;
+use stepper::Stepper;
+use tpcoptions::TPCOptions;
+
+#[derive(Clone, Debug)]
+pub struct RunState {
+    txids: Arc<AtomicI32>,      // the next txid a client sends
+    pub stepper: Stepper,       // --interactive
+    pub fault_oracle: Arc<Mutex<HashSet<i32>>>, // txids the coordinator hit with a correlated fault
+    pub latencies: Arc<Mutex<Vec<Duration>>>, // every client's request->result latencies, handed in as it leaves
+    pub log_bytes: Arc<AtomicU64>, // bytes this run's logs have appended between them (see OpLog::count_appended())
+}
+
+impl RunState {
+
+    ///
+    /// new()
+    /// the state of a run of opts, before anything has happened in it
+    ///
+    pub fn new(opts: &TPCOptions) -> RunState {
+        RunState {
+            txids: Arc::new(AtomicI32::new(1)),
+            stepper: Stepper::new(opts.interactive),
+            fault_oracle: Arc::new(Mutex::new(HashSet::new())),
+            latencies: Arc::new(Mutex::new(vec![])),
+            log_bytes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    ///
+    /// next_txid()
+    /// a txid no client of this run has sent yet
+    ///
+    pub fn next_txid(&self) -> i32 {
+        self.txids.fetch_add(1, Ordering::SeqCst)
+    }
+
+    ///
+    /// resume_txids()
+    /// carry txid numbering on past max_txid, the highest txid an earlier
+    /// run logged (see --resume)
+    ///
+    pub fn resume_txids(&self, max_txid: i32) {
+        self.txids.fetch_max(max_txid + 1, Ordering::SeqCst);
+    }
+
+    ///
+    /// bytes_appended()
+    /// how many bytes this run's logs have appended so far, for
+    /// --max-total-log-bytes
+    ///
+    pub fn bytes_appended(&self) -> u64 {
+        self.log_bytes.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,141 @@
+//!
+//! seal.rs
+//! Tamper/corruption detection for a run's logs. With --seal, the end of
+//! a run writes manifest.sha256 next to the logs: the SHA-256 of every
+//! coordinator and participant log file (segments and index sidecars
+//! included), in sha256sum's format, followed by an aggregate hash over
+//! those lines. Check mode with --verify-seal recomputes them and refuses
+//! to check logs that no longer match.
+//!
+extern crate sha2;
+use self::sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+const MANIFEST: &str = "manifest.sha256";
+const AGGREGATE: &str = "# aggregate ";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+///
+/// log_files()
+/// names of the log files under logpath, sorted: coordinator.log and
+/// participant_<n>.log plus their rotated segments and sidecar indexes
+///
+fn log_files(logpath: &str) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in fs::read_dir(logpath)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_log = name.starts_with("coordinator.log") ||
+                     (name.starts_with("participant_") && name.contains(".log"));
+        if is_log && entry.file_type()?.is_file() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+///
+/// file_hashes()
+/// file name -> hex SHA-256 of its contents, for every log file under logpath
+///
+fn file_hashes(logpath: &str) -> io::Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    for name in log_files(logpath)? {
+        let contents = fs::read(format!("{}{}", logpath, name))?;
+        hashes.insert(name, hex(&Sha256::digest(&contents)));
+    }
+    Ok(hashes)
+}
+
+///
+/// aggregate()
+/// one hash covering every file: SHA-256 over the manifest's per-file lines
+///
+fn aggregate(lines: &str) -> String {
+    hex(&Sha256::digest(lines.as_bytes()))
+}
+
+fn per_file_lines(hashes: &BTreeMap<String, String>) -> String {
+    hashes.iter().map(|(name, hash)| format!("{}  {}\n", hash, name)).collect()
+}
+
+///
+/// seal()
+/// write manifest.sha256 for the logs under logpath. returns the
+/// aggregate hash.
+///
+pub fn seal(logpath: &str) -> io::Result<String> {
+    let lines = per_file_lines(&file_hashes(logpath)?);
+    let total = aggregate(&lines);
+    fs::write(format!("{}{}", logpath, MANIFEST), format!("{}{}{}\n", lines, AGGREGATE, total))?;
+    Ok(total)
+}
+
+///
+/// verify()
+/// recompute the hashes of the logs under logpath and compare them with
+/// its manifest.sha256. prints every file that changed, went missing, or
+/// appeared since the run was sealed, then a summary line. returns true
+/// if everything still matches.
+///
+pub fn verify(logpath: &str) -> bool {
+    let manifest = match fs::read_to_string(format!("{}{}", logpath, MANIFEST)) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            println!("seal: can't read {}{}: {}", logpath, MANIFEST, err);
+            return false;
+        },
+    };
+    let mut sealed = BTreeMap::new();
+    let mut sealed_total = None;
+    for line in manifest.lines() {
+        if let Some(total) = line.strip_prefix(AGGREGATE) {
+            sealed_total = Some(total.to_string());
+        } else if let Some((hash, name)) = line.split_once("  ") {
+            sealed.insert(name.to_string(), hash.to_string());
+        }
+    }
+    let current = match file_hashes(logpath) {
+        Ok(current) => current,
+        Err(err) => {
+            println!("seal: can't hash the logs in {}: {}", logpath, err);
+            return false;
+        },
+    };
+
+    let mut ok = true;
+    for (name, hash) in sealed.iter() {
+        match current.get(name) {
+            Some(now) if now == hash => {},
+            Some(_now) => {
+                println!("seal: {} CHANGED", name);
+                ok = false;
+            },
+            None => {
+                println!("seal: {} MISSING", name);
+                ok = false;
+            },
+        }
+    }
+    for name in current.keys().filter(|name| !sealed.contains_key(*name)) {
+        println!("seal: {} NOT IN MANIFEST", name);
+        ok = false;
+    }
+    // catches an edited manifest too
+    if sealed_total != Some(aggregate(&per_file_lines(&sealed))) {
+        println!("seal: aggregate hash doesn't match the manifest's file hashes");
+        ok = false;
+    }
+    if ok {
+        println!("seal: {} files verified, aggregate {}", sealed.len(), sealed_total.unwrap());
+    } else {
+        println!("seal: logs in {} don't match {}, not checking them", logpath, MANIFEST);
+    }
+    ok
+}
@@ -0,0 +1,47 @@
+//!
+//! shutdown.rs
+//! The signal that tells clients and participants the run is over. The
+//! running flag (an AtomicBool) is what the protocol loops check as they
+//! go; this is the condvar next to it, for a thread with nothing left to
+//! do but wait for the end, so it can block instead of spinning on the
+//! flag. Whoever clears the flag (the CTRL-C handler, the coordinator at
+//! shut down, a client or participant told to exit) raises the signal
+//! with it, via stop().
+//!
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// set (and notified) once the run is over
+pub type ExitSignal = Arc<(Mutex<bool>, Condvar)>;
+
+///
+/// signal()
+/// a fresh, unraised exit signal
+///
+pub fn signal() -> ExitSignal {
+    Arc::new((Mutex::new(false), Condvar::new()))
+}
+
+///
+/// stop()
+/// clear the running flag and wake everyone waiting on exit
+///
+pub fn stop(running: &AtomicBool, exit: &ExitSignal) {
+    running.store(false, Ordering::SeqCst);
+    let (raised, cvar) = &**exit;
+    *raised.lock().unwrap() = true;
+    cvar.notify_all();
+}
+
+///
+/// wait()
+/// block until the run is over: exit is raised, or the running flag
+/// found already clear
+///
+pub fn wait(running: &AtomicBool, exit: &ExitSignal) {
+    let (raised, cvar) = &**exit;
+    let mut done = raised.lock().unwrap();
+    while !*done && running.load(Ordering::SeqCst) {
+        done = cvar.wait(done).unwrap();
+    }
+}
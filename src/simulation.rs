@@ -0,0 +1,535 @@
+//!
+//! simulation.rs
+//! A whole run (-m run): set up the coordinator, clients and
+//! participants, run them in their own threads until they're done, and
+//! hand back what came of it as SimulationStats. main.rs prints those;
+//! a test can assert on them instead.
+//!
+extern crate ctrlc;
+extern crate rand;
+use std::thread;
+use std::thread::JoinHandle;
+use checker;
+use coordinator;
+use coordinator::Coordinator;
+use participant::Participant;
+use client::Client;
+use pool;
+use pool::{Entity, Step};
+use replica;
+use report;
+use resultsdb;
+use seal;
+use shutdown;
+use runstate::RunState;
+use summary;
+use tpcoptions;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::channel;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+///
+/// SimulationStats
+/// what came of a run
+///
+#[derive(Clone, Debug)]
+pub struct SimulationStats {
+    pub committed: i32,         // coordinator's totals (its backup's, if it took over)
+    pub aborted: i32,
+    pub unknown: i32,
+    pub participant_committed: i32, // summed over every participant
+    pub participant_aborted: i32,
+    pub participant_unknown: i32,
+    pub read_only_votes: i32,   // read-only votes on transactions the coordinator committed (--read-only-ratio)
+    pub client_committed: i32,  // summed over every client
+    pub client_aborted: i32,
+    pub client_unknown: i32,
+    pub elapsed: Duration,
+    pub seed: u64,
+    pub latencies: Vec<Duration>, // every client's request->result latencies, ascending
+    pub served: BTreeMap<String, i32>, // transactions the coordinator decided, per client
+    pub backpressured: HashMap<i32, Duration>, // txid -> how long its request waited on a full coordinator queue (--channel-capacity)
+}
+
+///
+/// Tally
+/// counters the participant (or client) threads add their totals to as
+/// they exit
+///
+#[derive(Default)]
+struct Tally {
+    committed: AtomicI32,
+    aborted: AtomicI32,
+    unknown: AtomicI32,
+    backpressured: Mutex<HashMap<i32, Duration>>, // clients only: see Client::backpressured
+}
+///
+/// register_clients()
+/// 
+/// The coordinator needs to know about all clients. 
+/// This function should create clients and use some communication 
+/// primitive to ensure the coordinator and clients are aware of 
+/// each other and able to exchange messages. Starting threads to run the
+/// client protocol should be deferred until after all the communication 
+/// structures are created. 
+/// 
+/// HINT: you probably want to look at rust's mpsc::channel or crossbeam 
+///       channels to set up communication. Communication in 2PC 
+///       is duplex!
+/// 
+/// HINT: read the logpathbase documentation carefully.
+/// 
+/// <params>
+///     coordinator: the coordinator!
+///     n_clients: number of clients to create and register
+///     logpathbase: each participant, client, and the coordinator 
+///         needs to maintain its own operation and commit log. 
+///         The project checker assumes a specific directory structure 
+///         for files backing these logs. Concretely, participant log files 
+///         will be expected to be produced in:
+///            logpathbase/client_<num>.log
+///     running: atomic bool indicating whether the simulation is still running
+///
+fn register_clients(
+    coordinator: &mut Coordinator,
+    n_clients: i32) -> Vec<Client> {
+
+    let mut clients = vec![];
+    for i in 0..n_clients {
+        let client = coordinator.client_join(format!("{}", i));
+        clients.push(client);
+    }
+    // register clients with coordinator (set up communication channels and sync objects)
+    // add client to the vector and return the vector.
+    clients
+}
+
+/// 
+/// register_participants()
+/// 
+/// The coordinator needs to know about all participants. 
+/// This function should create participants and use some communication 
+/// primitive to ensure the coordinator and participants are aware of 
+/// each other and able to exchange messages. Starting threads to run the
+/// participant protocol should be deferred until after all the communication 
+/// structures are created. 
+/// 
+/// HINT: you probably want to look at rust's mpsc::channel or crossbeam 
+///       channels to set up communication. Note that communication in 2PC 
+///       is duplex!
+/// 
+/// HINT: read the logpathbase documentation carefully.
+/// 
+/// <params>
+///     coordinator: the coordinator!
+///     n_participants: number of participants to create an register
+///     logpathbase: each participant, client, and the coordinator 
+///         needs to maintain its own operation and commit log. 
+///         The project checker assumes a specific directory structure 
+///         for files backing these logs. Concretely, participant log files 
+///         will be expected to be produced in:
+///            logpathbase/participant_<num>.log
+///     running: atomic bool indicating whether the simulation is still running
+///     success_prob_op: [0.0..1.0] probability that operations succeed.
+///     success_prob_msg: [0.0..1.0] probability that sends succeed.
+///
+fn register_participants(
+    coordinator: &mut Coordinator,
+    n_participants: i32,
+    logpathbase: &String,
+    success_prob_op: f64,
+    success_prob_msg: f64) -> Vec<Participant> {
+
+    let mut participants = vec![];
+    // register participants with coordinator (set up communication channels and sync objects)
+    // add client to the vector and return the vector.
+    for i in 0..n_participants {
+        let part = coordinator.participant_join(format!("{}", i), logpathbase);
+        participants.push(part);
+    }
+
+    participants
+}
+
+///
+/// launch_clients()
+/// 
+/// create a thread per client to run the client
+/// part of the 2PC protocol. Somewhere in each of the threads created
+/// here, there should be a call to Client::protocol(...). Telling the client
+/// how many requests to send is probably a good idea. :-)
+/// 
+/// <params>
+/// participants: a vector of Participant structs
+/// tally: where each client adds its committed/aborted/unknown totals
+///    when it's done
+/// handles: (optional depending on design) -- a mutable vector 
+///    to return wait handles to the caller
+///
+fn launch_clients(
+    clients: Vec<Client>,
+    n_requests: i32,
+    tally: &Arc<Tally>,
+    handles: &mut Vec<JoinHandle<()>>) {
+
+    for mut client in clients {
+        let tally = tally.clone();
+        let handle = std::thread::spawn(move || {
+            client.protocol(n_requests);
+            tally.committed.fetch_add(client.successful, Ordering::SeqCst);
+            tally.aborted.fetch_add(client.failed, Ordering::SeqCst);
+            tally.unknown.fetch_add(client.unknown, Ordering::SeqCst);
+            tally.backpressured.lock().unwrap().extend(client.backpressured.drain());
+        });
+        handles.push(handle);
+    }
+
+    // do something to create threads for client 'processes'
+    // the mutable handles parameter allows you to return 
+    // more than one wait handle to the caller to join on. 
+}
+
+///
+/// launch_participants()
+/// 
+/// create a thread per participant to run the participant 
+/// part of the 2PC protocol. Somewhere in each of the threads created
+/// here, there should be a call to Participant::participate(...).
+/// 
+/// <params>
+/// participants: a vector of Participant structs
+/// start_delays: per-participant delay (ms, keyed by id) before it starts
+///    running the protocol, to model staggered joins
+/// tally: where each participant adds its committed/aborted/unknown
+///    totals when it's done
+/// handles: (optional depending on design) -- a mutable vector 
+///    to return wait handles to the caller
+///
+fn launch_participants(
+    participants: Vec<Participant>,
+    start_delays: &HashMap<i32, u64>,
+    tally: &Arc<Tally>,
+    handles: &mut Vec<JoinHandle<()>>) {
+
+    // do something to create threads for participant 'processes'
+    // the mutable handles parameter allows you to return 
+    // more than one wait handle to the caller to join on.
+    for mut participant in participants {
+        let delay = start_delays.get(&participant.id).cloned().unwrap_or(0);
+        let tally = tally.clone();
+        let handle = std::thread::spawn(move || {
+            if delay > 0 {
+                info!("participant_{} starting in {}ms", participant.id, delay);
+                thread::sleep(Duration::from_millis(delay));
+            }
+            participant.protocol();
+            tally.committed.fetch_add(participant.successful, Ordering::SeqCst);
+            tally.aborted.fetch_add(participant.failed, Ordering::SeqCst);
+            tally.unknown.fetch_add(participant.unknown, Ordering::SeqCst);
+        });
+        handles.push(handle);
+    } 
+}
+
+///
+/// PooledClient
+/// --worker-threads: a client as a pool entity, adding to the tally once
+/// it's done
+///
+struct PooledClient {
+    client: Client,
+    n_requests: i32,
+    tally: Arc<Tally>,
+}
+
+impl Entity for PooledClient {
+    fn step(&mut self) -> Step {
+        let step = self.client.step(self.n_requests);
+        if step == Step::Done {
+            self.tally.committed.fetch_add(self.client.successful, Ordering::SeqCst);
+            self.tally.aborted.fetch_add(self.client.failed, Ordering::SeqCst);
+            self.tally.unknown.fetch_add(self.client.unknown, Ordering::SeqCst);
+            self.tally.backpressured.lock().unwrap().extend(self.client.backpressured.drain());
+        }
+        step
+    }
+}
+
+///
+/// PooledParticipant
+/// --worker-threads: a participant as a pool entity, idling until its 
+/// start delay is up and adding to the tally once it's done
+///
+struct PooledParticipant {
+    participant: Participant,
+    start_at: Instant,
+    tally: Arc<Tally>,
+}
+
+impl Entity for PooledParticipant {
+    fn step(&mut self) -> Step {
+        if Instant::now() < self.start_at {
+            return Step::Idle;
+        }
+        let step = self.participant.step();
+        if step == Step::Done {
+            self.tally.committed.fetch_add(self.participant.successful, Ordering::SeqCst);
+            self.tally.aborted.fetch_add(self.participant.failed, Ordering::SeqCst);
+            self.tally.unknown.fetch_add(self.participant.unknown, Ordering::SeqCst);
+        }
+        step
+    }
+}
+
+///
+/// launch_pool()
+/// 
+/// like launch_clients() and launch_participants() together, but on 
+/// opts.worker_threads threads that step the clients and participants 
+/// in turn (see pool.rs) rather than a thread each
+///
+fn launch_pool(
+    clients: Vec<Client>,
+    client_tally: &Arc<Tally>,
+    participants: Vec<Participant>,
+    tally: &Arc<Tally>,
+    opts: &tpcoptions::TPCOptions,
+    handles: &mut Vec<JoinHandle<()>>) {
+
+    let mut entities: Vec<Box<dyn Entity>> = vec![];
+    for client in clients {
+        entities.push(Box::new(PooledClient { client, n_requests: opts.num_requests, tally: client_tally.clone() }));
+    }
+    let now = Instant::now();
+    for participant in participants {
+        let delay = opts.participant_start_delay.get(&participant.id).cloned().unwrap_or(0);
+        if delay > 0 {
+            info!("participant_{} starting in {}ms", participant.id, delay);
+        }
+        entities.push(Box::new(PooledParticipant {
+            participant,
+            start_at: now + Duration::from_millis(delay),
+            tally: tally.clone(),
+        }));
+    }
+    pool::launch(entities, opts.worker_threads, handles);
+}
+
+impl SimulationStats {
+
+    ///
+    /// disagreement()
+    /// in a lossless run (see lossless()), every participant should have
+    /// committed exactly what the coordinator did, less the read-only 
+    /// votes (a participant that voted read-only has nothing to commit):
+    /// if their totals don't add up to that, say how far off they are.
+    /// that's a protocol bug.
+    ///
+    pub fn disagreement(&self, n_participants: i32) -> Option<String> {
+        let expected = self.committed * n_participants - self.read_only_votes;
+        if self.participant_committed == expected {
+            return None;
+        }
+        Some(format!("coordinator committed {} transactions, so its {} participants should have committed {} between them, but they committed {}", 
+                     self.committed, n_participants, expected, self.participant_committed))
+    }
+}
+
+///
+/// lossless()
+/// whether a run under opts should end with every participant agreeing
+/// with the coordinator on every commit: nothing lost on the way, no 
+/// faults or disk failures, no log carried over from an earlier run, 
+/// and a decision policy that only commits what everyone voted for
+///
+pub fn lossless(opts: &tpcoptions::TPCOptions) -> bool {
+    opts.success_probability_msg == 1.0 && opts.failure_model == "bernoulli" &&
+        opts.decision_policy == "unanimous" &&
+        opts.fault_schedule.is_none() && opts.partition.is_none() && opts.partition_participants.is_empty() &&
+        opts.lose_last_commit.is_empty() && opts.participant_log_fail_prob == 0.0 &&
+        opts.disk_full_after.is_none() && opts.max_total_log_bytes.is_none() &&
+        !opts.resume && !opts.replicate
+}
+
+/// 
+/// run()
+/// opts: an options structure describing mode and parameters
+/// 
+/// 0. install a signal handler that manages a global atomic boolean flag
+/// 1. creates a new coordinator
+/// 2. creates new clients and registers them with the coordinator
+/// 3. creates new participants and registers them with coordinator
+/// 4. launches participants in their own threads
+/// 5. launches clients in their own threads (or both on a pool of 
+///    --worker-threads threads)
+/// 6. creates a thread to run the coordinator protocol
+/// 7. waits for them all and returns what came of the run, having
+///    sealed the logs and stored the results if opts asks for that
+/// 
+pub fn run(opts: & tpcoptions::TPCOptions) -> SimulationStats {
+
+    // create an atomic bool object and a signal handler
+    // that sets it. this allows us to inform clients and 
+    // participants that we are exiting the simulation 
+    // by pressing "control-C", which will set the running 
+    // flag to false and raise the exit signal the ones with
+    // nothing left to do wait on. a process only gets one handler, so
+    // in a process that runs more than one simulation only
+    // the first can be stopped this way.
+    let running = Arc::new(AtomicBool::new(true));
+    let exit = shutdown::signal();
+    let (r, e) = (running.clone(), exit.clone());
+    if let Err(err) = ctrlc::set_handler(move || {
+        println!("CTRL-C!");
+        shutdown::stop(&r, &e);
+    }) {
+        warn!("CTRL-C won't stop this run: {}", err);
+    }
+    run_with(opts, running, exit)
+}
+
+///
+/// run_with()
+/// run() steps 1-7, for a caller that holds the running flag and exit
+/// signal itself: shutdown::stop() on them ends the run early, just as
+/// CTRL-C does a run()
+///
+pub fn run_with(opts: & tpcoptions::TPCOptions, running: Arc<AtomicBool>, exit: shutdown::ExitSignal) -> SimulationStats {
+
+    // vector for wait handles, allowing us to 
+    // wait for client, participant, and coordinator 
+    // threads to join.
+    let start = Instant::now();
+    let mut handles: Vec<JoinHandle<()>> = vec![];  
+    let num_clients = opts.num_clients;
+    let num_participants = opts.num_participants;
+    let num_requests = opts.num_requests; 
+    let logpathbase = &opts.logpath;
+    let message_succ = opts.success_probability_msg;
+    let ops_succ = opts.success_probability_ops;  
+    let seed = opts.seed.unwrap_or_else(rand::random);
+    // everyone in this run shares it, and nobody in any other
+    let run = RunState::new(opts);
+
+    // create a coordinator, create and register clients and participants
+    // launch threads for all, and wait on handles. 
+    let cpath = format!("{}{}", opts.logpath, "coordinator.log");
+    let mut coordinator: Coordinator = coordinator::Coordinator::new(cpath, running.clone(), exit.clone(), run.clone(), opts, seed);
+    let clients: Vec<Client>; 
+    let participants: Vec<Participant>;
+    clients = register_clients(&mut coordinator, num_clients);
+    participants = register_participants(&mut coordinator, num_participants, logpathbase, ops_succ, message_succ);
+    if opts.resume {
+        coordinator.redrive();
+    }
+    let backup = if opts.replicate {
+        let (link, backup) = replica::link(opts, &run, format!("{}{}", opts.logpath, "coordinator_backup.log"));
+        coordinator.replicate_to(link);
+        Some(backup)
+    } else {
+        None
+    };
+    let (handoff_tx, handoff_rx) = channel();
+    let coord_handle = std::thread::spawn(move || {
+        coordinator.protocol();
+        if coordinator.crashed() {
+            let _ = handoff_tx.send(coordinator.hand_off());
+        }
+        (coordinator.successful, coordinator.failed, coordinator.unknown, coordinator.served.clone(), coordinator.read_only_votes)
+    });
+    // EXPERIMENTAL (--replicate): the backup tails the primary, and if
+    // the primary crashes, finishes the run in its place
+    let backup_handle = backup.map(|mut backup| {
+        let (opts, running, exit, run) = (opts.clone(), running.clone(), exit.clone(), run.clone());
+        std::thread::spawn(move || {
+            if !backup.follow() {
+                return None;
+            }
+            let endpoints = handoff_rx.recv().expect("primary crashed without handing off its endpoints");
+            let mut successor = backup.take_over(&opts, running, exit, run, seed, endpoints);
+            successor.protocol();
+            Some((successor.successful, successor.failed, successor.unknown, successor.served.clone(), successor.read_only_votes))
+        })
+    });
+    let tally = Arc::new(Tally::default());
+    let client_tally = Arc::new(Tally::default());
+    if opts.worker_threads > 0 {
+        launch_pool(clients, &client_tally, participants, &tally, opts, &mut handles);
+    } else {
+        launch_clients(clients, num_requests, &client_tally, &mut handles);
+        launch_participants(participants, &opts.participant_start_delay, &tally, &mut handles);
+    }
+
+    for join_handle in handles {
+        join_handle.join().expect("can't join on associated thread");
+    }
+    let (mut committed, mut aborted, mut unknown, mut served, mut read_only_votes) = coord_handle.join().expect("can't join on coordinator thread");
+    if let Some(handle) = backup_handle {
+        if let Some(counts) = handle.join().expect("can't join on backup coordinator thread") {
+            (committed, aborted, unknown, served, read_only_votes) = counts;
+        }
+    }
+    let duration = start.elapsed();
+    let mut latencies = run.latencies.lock().unwrap().clone();
+    latencies.sort();
+
+    if opts.seal {
+        match seal::seal(&opts.logpath) {
+            Ok(aggregate) => println!("Logs sealed in {}manifest.sha256, aggregate {}", opts.logpath, aggregate),
+            Err(err) => error!("could not seal the logs in {}: {}", opts.logpath, err),
+        }
+    }
+
+    if let Some(ref db) = opts.results_db {
+        let summary = summary::RunSummary {
+            seed,
+            options: opts.clone(),
+            committed,
+            aborted,
+            unknown,
+            elapsed: duration,
+            latencies: latencies.clone(),
+            served: served.clone(),
+        };
+        let transactions = if opts.store_transactions {
+            Some(summary::transactions(&format!("{}{}", opts.logpath, checker::coordinator_log(opts.replicate))))
+        } else {
+            None
+        };
+        match resultsdb::store(db, &summary, transactions.as_deref()) {
+            Ok(run_id) => println!("Results stored in {} as run {}", db, run_id),
+            Err(err) => error!("could not store results in {}: {}", db, err),
+        }
+    }
+
+    let backpressured = client_tally.backpressured.lock().unwrap().clone();
+    if let Some(ref csv) = opts.csv {
+        let clogpath = format!("{}{}", opts.logpath, checker::coordinator_log(opts.replicate));
+        match report::write_csv(&opts.logpath, &clogpath, num_participants, &backpressured, csv) {
+            Ok(rows) => println!("Outcomes of {} transactions written to {}", rows, csv),
+            Err(err) => error!("could not write outcomes to {}: {}", csv, err),
+        }
+    }
+
+    SimulationStats {
+        committed,
+        aborted,
+        unknown,
+        participant_committed: tally.committed.load(Ordering::SeqCst),
+        participant_aborted: tally.aborted.load(Ordering::SeqCst),
+        participant_unknown: tally.unknown.load(Ordering::SeqCst),
+        read_only_votes,
+        client_committed: client_tally.committed.load(Ordering::SeqCst),
+        client_aborted: client_tally.aborted.load(Ordering::SeqCst),
+        client_unknown: client_tally.unknown.load(Ordering::SeqCst),
+        elapsed: duration,
+        seed,
+        latencies,
+        served,
+        backpressured,
+    }
+}
+
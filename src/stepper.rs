@@ -0,0 +1,83 @@
+//! 
+//! stepper.rs
+//! Interactive single-stepping of protocol messages. When enabled,
+//! the clients and participants are stepped on a pool of one worker
+//! (see pool.rs; --interactive implies --worker-threads 1), which takes
+//! them in a fixed round: clients in id order, then participants. Every
+//! send and receive they make passes through step(), which prints the
+//! message and waits for the operator to press enter. Nothing else runs
+//! on that worker, so messages are shown (and proceed) one by one. The
+//! coordinator keeps its own thread: its side of each message is only
+//! shown (see show()), since it can't move until a client or 
+//! participant does.
+//! 
+use std::io;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use message::ProtocolMessage;
+
+///
+/// Stepper
+/// whether a run is being stepped through. everyone in the run holds a
+/// clone (see runstate.rs), so when one of them turns stepping off, it's
+/// off for all of them.
+///
+#[derive(Clone, Debug)]
+pub struct Stepper {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Stepper {
+
+    ///
+    /// new()
+    /// stepping, on or off for the run
+    ///
+    pub fn new(enabled: bool) -> Stepper {
+        Stepper { enabled: Arc::new(AtomicBool::new(enabled)) }
+    }
+
+    ///
+    /// enabled()
+    /// true if interactive stepping is on
+    /// 
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// step()
+    /// if stepping is on, print what actor is doing with pm and block 
+    /// until the operator presses enter. end of input (e.g. ctrl-D) turns
+    /// stepping off and lets the simulation free-run to completion.
+    /// 
+    /// <params>
+    ///     actor: who is acting, e.g. "coordinator" or "participant_2"
+    ///     action: what it is doing with the message, e.g. "sends" or "receives"
+    ///     pm: the message
+    ///
+    pub fn step(&self, actor: &str, action: &str, pm: &ProtocolMessage) {
+        if !self.enabled() {
+            return;
+        }
+        self.show(actor, action, pm);
+        print!("[step] press enter to continue...");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            self.enabled.store(false, Ordering::SeqCst);
+        }
+    }
+
+    ///
+    /// show()
+    /// step() without the pause, for the coordinator: print what actor is
+    /// doing with pm if stepping is on
+    ///
+    pub fn show(&self, actor: &str, action: &str, pm: &ProtocolMessage) {
+        if self.enabled() {
+            println!("[step] {} {} {:?} txid:{} opid:{} from:{}", actor, action, pm.mtype, pm.txid, pm.opid, pm.senderid);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! 
+//! summary.rs
+//! RunSummary: the configuration and outcome of a single run, for 
+//! reporting and archiving (see resultsdb.rs).
+//! 
+use std::collections::BTreeMap;
+use std::time::Duration;
+use oplog::OpLog;
+use message::{AbortReason, MessageType};
+use tpcoptions::TPCOptions;
+
+///
+/// RunSummary
+/// what a run was configured with and what came of it
+///
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub seed: u64,
+    pub options: TPCOptions,
+    pub committed: i32,         // coordinator's totals
+    pub aborted: i32,
+    pub unknown: i32,
+    pub elapsed: Duration,
+    pub latencies: Vec<Duration>, // every client's request->result latencies, ascending
+    pub served: BTreeMap<String, i32>, // transactions the coordinator decided, per client
+}
+
+///
+/// TransactionOutcome
+/// how one transaction ended, as recorded in the coordinator's log
+///
+#[derive(Clone, Debug)]
+pub struct TransactionOutcome {
+    pub txid: i32,
+    pub client: String,
+    pub opid: i32,
+    pub outcome: String,        // "commit", "abort" or "unknown"
+    pub segment: i32,           // savepoint a commit went through (0 = whole transaction)
+    pub reason: Option<AbortReason>, // why an abort was decided, if the log says
+}
+
+///
+/// percentile()
+/// 
+/// the p-th percentile (0.0..100.0) of an ascending list of samples,
+/// nearest-rank. None if there are no samples. 
+///
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+///
+/// latency_report()
+/// 
+/// p50/p95/p99/max of an ascending list of latencies, in microseconds,
+/// or a note that there are none
+///
+pub fn latency_report(sorted: &[Duration]) -> String {
+    let us = |p| percentile(sorted, p).map_or(0, |d| d.as_micros());
+    match sorted.last() {
+        Some(max) => format!("n={}\tp50:{}us\tp95:{}us\tp99:{}us\tmax:{}us",
+                             sorted.len(), us(50.0), us(95.0), us(99.0), max.as_micros()),
+        None => String::from("no completed requests"),
+    }
+}
+
+///
+/// histogram()
+/// 
+/// an ascending list of latencies counted into power-of-two microsecond
+/// buckets: (inclusive upper bound in us, count), from the smallest sample's 
+/// bucket to the largest's. empty if there are no samples.
+///
+pub fn histogram(sorted: &[Duration]) -> Vec<(u128, usize)> {
+    let bucket = |d: &Duration| d.as_micros().max(1).next_power_of_two();
+    let (first, last) = match (sorted.first(), sorted.last()) {
+        (Some(first), Some(last)) => (bucket(first), bucket(last)),
+        _ => return vec![],
+    };
+    let mut buckets = vec![];
+    let mut bound = first;
+    while bound <= last {
+        buckets.push((bound, sorted.iter().filter(|d| bucket(d) == bound).count()));
+        bound *= 2;
+    }
+    buckets
+}
+
+///
+/// fairness()
+/// 
+/// Jain's fairness index of how many transactions each client got 
+/// served: 1.0 when every client got the same share, down to 1/n when
+/// one client got everything. None if nobody was served.
+///
+pub fn fairness(served: &BTreeMap<String, i32>) -> Option<f64> {
+    let sum: f64 = served.values().map(|n| *n as f64).sum();
+    let sum_squares: f64 = served.values().map(|n| (*n as f64) * (*n as f64)).sum();
+    if sum_squares == 0.0 {
+        return None;
+    }
+    Some(sum * sum / (served.len() as f64 * sum_squares))
+}
+
+impl RunSummary {
+    pub fn latency(&self, p: f64) -> Option<Duration> {
+        percentile(&self.latencies, p)
+    }
+
+    pub fn fairness(&self) -> Option<f64> {
+        fairness(&self.served)
+    }
+}
+
+///
+/// transactions()
+/// 
+/// every transaction the coordinator at logpath received a request
+/// for, and how it ended, in txid order
+///
+pub fn transactions(logpath: &str) -> Vec<TransactionOutcome> {
+    let log = OpLog::from_file(logpath.to_string());
+    let lck = log.arc();
+    let records = lck.lock().unwrap();
+    let mut outcomes: Vec<TransactionOutcome> = records.values()
+        .filter(|pm| pm.mtype == MessageType::ClientRequest)
+        .map(|request| {
+            let decision = records.values().find(|pm| pm.txid == request.txid && 
+                (pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort));
+            // under --presumed-abort an abort is only logged as the client's result
+            let presumed = records.values().find(|pm| pm.txid == request.txid && pm.mtype == MessageType::ClientResultAbort);
+            TransactionOutcome {
+                txid: request.txid,
+                client: request.senderid.clone(),
+                opid: request.opid,
+                outcome: match decision.map(|pm| pm.mtype) {
+                    Some(MessageType::CoordinatorCommit) => "commit",
+                    Some(_) => "abort",
+                    None if presumed.is_some() => "abort",
+                    None => "unknown",
+                }.to_string(),
+                segment: decision.map_or(0, |pm| pm.segment),
+                reason: decision.or(presumed).and_then(|pm| pm.abort_reason),
+            }
+        })
+        .collect();
+    outcomes.sort_by_key(|t| t.txid);
+    outcomes
+}
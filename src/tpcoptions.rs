@@ -21,10 +21,14 @@ extern crate clap;
 extern crate ctrlc;
 extern crate shellexpand;
 use clap::{Arg, App};
+use std::collections::HashMap;
+use dist::Distribution;
+use failure;
 
 #[derive(Clone, Debug)]
 pub struct TPCOptions {    
     pub success_probability_ops: f64,   // probability that an operation succeeds 
+    pub participant_op_probs: Vec<f64>, // per-participant overrides of success_probability_ops, by id (see op_success_prob())
     pub success_probability_msg: f64,   // probability that message send succeeds 
     pub num_clients: i32,               // number of concurrent clients issuing requests
     pub num_requests: i32,              // number of requests issued per client
@@ -32,6 +36,85 @@ pub struct TPCOptions {
     pub verbosity: usize,               // integer verbosity level. experiment with 0 (default) to 5 (fire-hose of output)
     pub mode: String,                   // "run" or "check"
     pub logpath: String,                // directory for client, participant, and coordinator logs
+    pub exit_policy: String,            // "flush" or "abandon": participant handling of in-flight txids at exit
+    pub seed: Option<u64>,              // global RNG seed for failure injection (random if not given)
+    pub log_max_bytes: u64,             // rotate the coordinator log once a segment reaches this size (0 = never)
+    pub measure_contention: bool,       // time waits on shared locks and report them on exit
+    pub arrival_rate: f64,              // open-loop Poisson arrivals per client per second (0 = closed loop)
+    pub quiet: bool,                    // check mode: one line pass/fail summary and exit status only
+    pub check_window_size: i32,         // check mode (--quiet): check this many txids at a time to bound memory (0 = all at once)
+    pub participant_start_delay: HashMap<i32, u64>, // participant id -> ms to wait before joining the protocol
+    pub participant_exit_delay: HashMap<i32, Option<u64>>, // participant id -> ms it holds back its exit ack (None = never acks)
+    pub exit_ack_timeout_ms: u64,       // how long the coordinator waits at shutdown for every participant's exit ack
+    pub vote_timeout_ms: u64,           // how long the coordinator waits for each participant's vote before counting it missing
+    pub net_latency_ms_min: u64,        // least delay on a message the coordinator or a participant sends (see netdelay.rs)
+    pub net_latency_ms_max: u64,        // most such delay (0 = messages are delivered at once)
+    pub reorder_prob: f64,              // probability such a message is held back until after the next one on its link
+    pub request_poll_ms: u64,           // how long the coordinator waits for any client's request before giving up for now
+    pub channel_capacity: usize,        // requests a client can queue for the coordinator before its sends block (0 = unbounded)
+    pub send_attempts: u32,             // how many times the coordinator tries a dropped send before giving up on it
+    pub send_backoff_ms: u64,           // how long the coordinator waits between those tries
+    pub max_inflight: i32,              // how many txids the coordinator may have proposed and not yet decided at once
+    pub parallel_prepare: bool,         // coordinator sends each proposal to every participant at once, and takes votes as they come
+    pub presumed_abort: bool,           // aborts are neither logged nor broadcast: a participant with no decision presumes abort
+    pub presumed_abort_timeout_ms: u64, // how long a participant waits for a decision before asking the coordinator for it
+    pub decision_timeout_ms: u64,       // how long a participant waits for a decision before asking for it again
+    pub heartbeat_ms: u64,              // how often participants send the coordinator a heartbeat (0 = never)
+    pub max_missed: u32,                // heartbeats a participant may miss in a row before the coordinator counts it failed
+    pub protocol: String,               // "2pc" or "3pc": whether a pre-commit round comes between voting and commit
+    pub precommit_timeout_ms: u64,      // 3pc: how long a pre-committed participant waits for the commit before committing on its own
+    pub max_txn_lifetime_ms: Option<u64>, // the coordinator aborts any txid still undecided this long after its request arrived
+    pub min_live_participants: i32,     // the coordinator only commits while at least this many participants answer the proposal
+    pub warmup_success_ramp: i32,       // participants' op success probability ramps up from 0 over this many proposals
+    pub max_total_log_bytes: Option<u64>, // stop the run gracefully before every log's appends together pass this many bytes
+    pub lose_last_commit: Vec<i32>,     // participants that lose their last committed txid's records at exit
+    pub async_log: bool,                // write oplogs from a background thread (durable only once flushed)
+    pub durable: bool,                  // fsync votes and decisions to the oplog before sending them
+    pub early_client_reply: bool,       // coordinator answers the client before broadcasting the decision
+    pub correlated_failure_prob: f64,   // probability a txid hits a fault shared by the participants below
+    pub correlated_failure_set: Option<Vec<i32>>, // participants a correlated fault affects (None = all)
+    pub log_index: bool,                // keep a sidecar txid index next to each oplog
+    pub log_format: String,             // "json" or "bincode": how oplog records are written
+    pub log_checksums: bool,            // write each oplog record with a checksum, so the checker catches corrupted ones
+    pub in_memory_log: bool,            // coordinator and participants keep their oplogs in memory only, writing no files
+    pub disk_full_after: Option<u64>,   // coordinator oplog appends fail after this many (simulated full disk)
+    pub participant_log_fail_prob: f64, // probability any one participant log write fails (simulated flaky disk)
+    pub client_result_retries: Option<u32>, // times a client resends a request before declaring its txid unknown (None = keep resending)
+    pub client_result_timeout_ms: u64,  // how long a client waits for a result before resending its request
+    pub think_dist: Option<Distribution>, // closed-loop client think time between requests (None = no think time)
+    pub client_think_time_ms: u64,      // shorthand for --think-dist: a fixed think time (0 = none, or --think-dist's)
+    pub client_think_jitter_ms: u64,    // with client_think_time_ms: vary each think time uniformly by up to this much either way
+    pub service_dist: Option<Distribution>, // participant work time per proposal (None = instant)
+    pub outcome_cache: usize,           // decided outcomes the coordinator keeps in memory (0 = all)
+    pub kv_store: bool,                 // participants keep a key-value store that commits modify (see kvstore.rs)
+    pub kv_keys: i32,                   // keys in that store
+    pub pregenerate: bool,              // clients build all their requests before sending any
+    pub deadlines: Vec<(u64, f64)>,     // (deadline ms, fraction of requests) clients attach to their requests
+    pub read_only_ratio: f64,           // fraction of client requests that only read (participants vote read-only and skip phase 2)
+    pub check_liveness: bool,           // checker also flags proposed transactions that never terminated, and unacked commits
+    pub fault_schedule: Option<String>, // file of deterministic faults to inject (see faults.rs)
+    pub segments: i32,                  // EXPERIMENTAL: savepoint-separated segments per transaction (1 = off)
+    pub ops_per_txn: i32,               // operations a client bundles into each transaction, voted on and decided as one
+    pub golden: bool,                   // check mode: compare decisions with the golden model (needs --seed)
+    pub vector_clocks: bool,            // stamp messages and log records with vector clocks
+    pub results_db: Option<String>,     // SQLite file to append this run's summary to
+    pub store_transactions: bool,       // with results_db, also store every transaction's outcome
+    pub csv: Option<String>,            // file to export every transaction's outcome to as CSV after the run
+    pub decision_policy: String,        // how the coordinator decides from the votes (see policy.rs)
+    pub failure_model: String,          // which messages are lost and which operations fail (see failure.rs)
+    pub estimate: bool,                 // run mode: print projected resource usage instead of running
+    pub resume: bool,                   // carry on from the committed state in the existing logs
+    pub partition: Option<String>,      // scripted network partition spec (see partition.rs)
+    pub partition_participants: Vec<i32>, // or: participants to cut off from the coordinator (empty = no partition)
+    pub partition_after_ms: u64,        // how far into the run they're cut off
+    pub partition_duration_ms: u64,     // and for how long
+    pub drain_timeout_ms: u64,          // after CTRL-C, the coordinator gives up on the in-flight txid after this long
+    pub seal: bool,                     // write a manifest of log hashes at the end of the run
+    pub verify_seal: bool,              // check mode: refuse to check logs that don't match their manifest
+    pub replicate: bool,                // EXPERIMENTAL: run a backup coordinator that takes over if the primary crashes
+    pub failover_timeout_ms: u64,       // how long the backup waits on a silent primary before taking over
+    pub interactive: bool,              // pause at every message send/receive until enter is pressed
+    pub worker_threads: usize,          // threads that step the clients and participants between them (0 = a thread each)
 }
 
 impl TPCOptions {
@@ -43,6 +126,16 @@ impl TPCOptions {
     /// trace/log tools as well. 
     ///
     pub fn new() -> TPCOptions {
+        TPCOptions::from_args(std::env::args_os())
+    }
+
+    ///
+    /// from_args()
+    /// new(), but parsing args (starting with the program name) instead
+    /// of the process's command line, e.g. to set up a run from a test
+    ///
+    pub fn from_args<I, T>(args: I) -> TPCOptions
+        where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone {
     
         let default_n_participants = "3";
         let default_n_clients = "3";
@@ -51,6 +144,36 @@ impl TPCOptions {
         let default_mode = "run";
         let default_success_prob_ops = "1.0";
         let default_success_prob_msg = "1.0";
+        let default_exit_policy = "flush";
+        let default_log_max_bytes = "0";
+        let default_arrival_rate = "0";
+        let default_correlated_failure_prob = "0.0";
+        let default_client_result_timeout_ms = "1000";
+        let default_segments = "1";
+        let default_ops_per_txn = "1";
+        let default_decision_policy = "unanimous";
+        let default_failure_model = "bernoulli";
+        let default_drain_timeout_ms = "5000";
+        let default_failover_timeout_ms = "200";
+        let default_kv_keys = "8";
+        let default_participant_log_fail_prob = "0.0";
+        let default_outcome_cache = "0";
+        let default_exit_ack_timeout_ms = "1000";
+        let default_vote_timeout_ms = "500";
+        let default_net_latency_ms = "0";
+        let default_reorder_prob = "0.0";
+        let default_partition_after_ms = "0";
+        let default_request_poll_ms = "10";
+        let default_max_inflight = "1";
+        let default_log_format = "json";
+        let default_presumed_abort_timeout_ms = "10000";
+        let default_decision_timeout_ms = "1000";
+        let default_heartbeat_ms = "0";
+        let default_max_missed = "3";
+        let default_protocol = "2pc";
+        let default_precommit_timeout_ms = "1000";
+        let default_send_attempts = "100";
+        let default_send_backoff_ms = "1";
 
         let default_logpath = shellexpand::tilde("~/tmp/").clone();
 
@@ -63,6 +186,12 @@ impl TPCOptions {
                     .required(false)
                     .takes_value(true)
                     .help("probability participants successfully execute requests"))
+            .arg(Arg::with_name("participant_op_probs")
+                    .long("participant-op-probs")
+                    .required(false)
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .help("comma separated per-participant probabilities of executing requests, by id (participants past the end of the list use -s)"))
             .arg(Arg::with_name("success_probability_msg")
                     .short("S")
                     .required(false)
@@ -97,33 +226,804 @@ impl TPCOptions {
                     .short("m")
                     .required(false)
                     .takes_value(true)
-                    .help("mode--\"run\" runs 2pc, \"check\" checks logs produced by previous run"))       
-            .get_matches();
+                    .help("mode--\"run\" runs 2pc, \"check\" checks logs produced by previous run, \"verify\" replays them from the log directory alone (pass the run's --decision-policy), \"merge-logs\" merges every component's log into global.log in append order"))       
+            .arg(Arg::with_name("exit_policy")
+                    .long("exit-policy")
+                    .required(false)
+                    .takes_value(true)
+                    .help("participant action on shut down mid-transaction--\"flush\" waits for the decision, \"abandon\" logs the txid as abandoned"))
+            .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .required(false)
+                    .takes_value(true)
+                    .help("global seed for failure injection; per-component seeds are derived from it"))
+            .arg(Arg::with_name("log_max_bytes")
+                    .long("log-max-bytes")
+                    .required(false)
+                    .takes_value(true)
+                    .help("roll coordinator.log to coordinator.log.1, .2, ... once a segment reaches this many bytes (0 = never)"))
+            .arg(Arg::with_name("measure_contention")
+                    .long("measure-contention")
+                    .required(false)
+                    .takes_value(false)
+                    .help("report time spent waiting on shared oplog locks per component"))
+            .arg(Arg::with_name("arrival_rate")
+                    .long("arrival-rate")
+                    .required(false)
+                    .takes_value(true)
+                    .help("open-loop mode: each client issues requests as a Poisson process at this many per second (0 = closed loop)"))
+            .arg(Arg::with_name("quiet")
+                    .long("quiet")
+                    .required(false)
+                    .takes_value(false)
+                    .help("with -m check: print a one line pass/fail summary and exit nonzero on any violation"))
+            .arg(Arg::with_name("participant_start_delay")
+                    .long("participant-start-delay")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("<id>:<ms> delay before a participant starts running the protocol (repeatable or comma separated)"))
+            .arg(Arg::with_name("participant_exit_delay")
+                    .long("participant-exit-delay")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("<id>:<ms> or <id>:never, how long a participant holds back its exit ack at shutdown (repeatable or comma separated)"))
+            .arg(Arg::with_name("exit_ack_timeout_ms")
+                    .long("exit-ack-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how long the coordinator waits at shutdown for participants to ack its exit before logging the stragglers and exiting anyway"))
+            .arg(Arg::with_name("vote_timeout_ms")
+                    .long("vote-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how long the coordinator waits for each participant's vote before counting it as missing (raise it when messages are lossy)"))
+            .arg(Arg::with_name("net_latency_ms_min")
+                    .long("net-latency-ms-min")
+                    .required(false)
+                    .takes_value(true)
+                    .help("least simulated network latency on each message the coordinator or a participant sends"))
+            .arg(Arg::with_name("net_latency_ms_max")
+                    .long("net-latency-ms-max")
+                    .required(false)
+                    .takes_value(true)
+                    .help("most simulated network latency; each message's is drawn uniformly between the two (0 = none)"))
+            .arg(Arg::with_name("reorder_prob")
+                    .long("reorder-prob")
+                    .required(false)
+                    .takes_value(true)
+                    .help("probability a message the coordinator or a participant sends is delivered after the next one on its channel [0.0..1.0]"))
+            .arg(Arg::with_name("request_poll_ms")
+                    .long("request-poll-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how long the coordinator waits on every client's channel at once before giving up on finding a request (and checking whether to stop)"))
+            .arg(Arg::with_name("channel_capacity")
+                    .long("channel-capacity")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how many messages each client's channel to the coordinator holds before the client's sends block until the coordinator catches up (0 = unbounded)"))
+            .arg(Arg::with_name("send_attempts")
+                    .long("send-attempts")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how many times the coordinator tries a dropped send before giving up on the receiver as unreachable (a client it gives up on only moves on with --client-result-retries)"))
+            .arg(Arg::with_name("send_backoff_ms")
+                    .long("send-backoff-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how long the coordinator waits between tries at a dropped send"))
+            .arg(Arg::with_name("presumed_abort")
+                    .long("presumed-abort")
+                    .required(false)
+                    .takes_value(false)
+                    .help("presumed abort: the coordinator neither logs nor broadcasts abort decisions, and a participant that doesn't hear a decision before the coordinator moves on to the next txid or exits aborts (one that times out asks the coordinator)"))
+            .arg(Arg::with_name("presumed_abort_timeout_ms")
+                    .long("presumed-abort-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --presumed-abort, how long a participant waits for a decision before asking the coordinator for it (and between asking again)"))
+            .arg(Arg::with_name("decision_timeout_ms")
+                    .long("decision-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how long a participant waits for a decision before asking the coordinator for it again (or, once the run is shutting down, giving up on it as unknown)"))
+            .arg(Arg::with_name("heartbeat_ms")
+                    .long("heartbeat-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("participants send the coordinator a heartbeat this often on a side channel, so it can stop waiting on the votes of dead ones (0 = no heartbeats)"))
+            .arg(Arg::with_name("max_missed")
+                    .long("max-missed")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --heartbeat-ms, how many heartbeats in a row a participant may miss before the coordinator counts it failed and stops waiting for its votes"))
+            .arg(Arg::with_name("max_inflight")
+                    .long("max-inflight")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how many client requests the coordinator works on at once, votes for them sorted out by txid (1 = one at a time)"))
+            .arg(Arg::with_name("parallel_prepare")
+                    .long("parallel-prepare")
+                    .required(false)
+                    .takes_value(false)
+                    .help("coordinator sends each proposal to all participants at once rather than one after another, and takes their votes in whatever order they arrive"))
+            .arg(Arg::with_name("protocol")
+                    .long("protocol")
+                    .required(false)
+                    .takes_value(true)
+                    .help("atomic commit protocol--\"2pc\" or \"3pc\" (a pre-commit round after a unanimous yes, so a participant that stops hearing from the coordinator can commit on its own)"))
+            .arg(Arg::with_name("precommit_timeout_ms")
+                    .long("precommit-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --protocol 3pc, how long a pre-committed participant waits for the commit before committing on its own"))
+            .arg(Arg::with_name("max_txn_lifetime_ms")
+                    .long("max-txn-lifetime-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("abort (and log as scavenged) any transaction still undecided this many ms after the coordinator received its request, however many participants it is still waiting on"))
+            .arg(Arg::with_name("min_live_participants")
+                    .long("min-live-participants")
+                    .required(false)
+                    .takes_value(true)
+                    .help("only commit a transaction if at least this many participants answered its proposal (yes or no), whatever the decision policy says; otherwise abort it for insufficient replicas"))
+            .arg(Arg::with_name("warmup_success_ramp")
+                    .long("warmup-success-ramp")
+                    .required(false)
+                    .takes_value(true)
+                    .help("model a warmup: each participant's operation success probability ramps linearly from 0 up to -s over its first N proposals (0 = off)"))
+            .arg(Arg::with_name("check_window_size")
+                    .long("check-window-size")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with -m check --quiet, stream the logs and check this many txids at a time, so only one window's records are in memory (0 = load everything)"))
+            .arg(Arg::with_name("max_total_log_bytes")
+                    .long("max-total-log-bytes")
+                    .required(false)
+                    .takes_value(true)
+                    .help("budget for the bytes all components' logs append this run: the coordinator stops taking transactions and shuts the run down gracefully before another one could go over it"))
+            .arg(Arg::with_name("lose_last_commit")
+                    .long("lose-last-commit")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("participant id(s) that simulate a bad disk by losing their last committed txid's log records at exit"))
+            .arg(Arg::with_name("async_log")
+                    .long("async-log")
+                    .required(false)
+                    .takes_value(false)
+                    .help("write oplogs from a background thread per component; appends are durable only once flushed"))
+            .arg(Arg::with_name("durable")
+                    .long("durable")
+                    .required(false)
+                    .takes_value(false)
+                    .help("force (fsync) a participant's vote and the coordinator's decision to stable storage before sending them, as real 2PC must"))
+            .arg(Arg::with_name("log_index")
+                    .long("log-index")
+                    .required(false)
+                    .takes_value(false)
+                    .help("keep a sidecar txid -> offset index next to each oplog so the checker can seek to a txid's records"))
+            .arg(Arg::with_name("log_format")
+                    .long("log-format")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how oplog records are written--\"json\" (a line per record) or \"bincode\" (compact binary); readers detect either, but --resume must use the earlier run's"))
+            .arg(Arg::with_name("log_checksums")
+                    .long("log-checksums")
+                    .required(false)
+                    .takes_value(false)
+                    .help("write every oplog record with a CRC32 of its type, txid, sender and opid, so a corrupted record is reported rather than misread (logs without them still load)"))
+            .arg(Arg::with_name("in_memory_log")
+                    .long("in-memory-log")
+                    .required(false)
+                    .takes_value(false)
+                    .help("coordinator and participants keep their oplogs in memory instead of writing them to disk, to time the protocol without file I/O (leaves nothing to check)"))
+            .arg(Arg::with_name("participant_log_fail_prob")
+                    .long("participant-log-fail-prob")
+                    .required(false)
+                    .takes_value(true)
+                    .help("probability each participant log write fails; a participant that can't log its commit vote votes abort, other writes are retried (must be below 1)"))
+            .arg(Arg::with_name("disk_full_after")
+                    .long("disk-full-after")
+                    .required(false)
+                    .takes_value(true)
+                    .help("simulate the coordinator's log disk filling up: appends after the first N fail"))
+            .arg(Arg::with_name("client_result_retries")
+                    .long("client-result-retries")
+                    .required(false)
+                    .takes_value(true)
+                    .help("times a client resends a request with no result before declaring its txid unknown (default: keep resending)"))
+            .arg(Arg::with_name("client_result_timeout_ms")
+                    .long("client-result-timeout")
+                    .required(false)
+                    .takes_value(true)
+                    .help("ms a client waits for a result before resending its request (same txid)"))
+            .arg(Arg::with_name("check_liveness")
+                    .long("check-liveness")
+                    .required(false)
+                    .takes_value(false)
+                    .help("in check mode, also verify every proposed transaction reached a decision or was abandoned, and every commit was acked by the participants"))
+            .arg(Arg::with_name("fault_schedule")
+                    .long("fault-schedule")
+                    .required(false)
+                    .takes_value(true)
+                    .help("file of '<txid> <message type> <component> drop|delay:<ms>|crash' lines to inject deterministically"))
+            .arg(Arg::with_name("ops_per_txn")
+                    .long("ops-per-txn")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how many operations a client bundles into each transaction; a participant votes commit only if every one of them succeeds, and the decision covers them all"))
+            .arg(Arg::with_name("segments")
+                    .long("segments")
+                    .required(false)
+                    .takes_value(true)
+                    .help("EXPERIMENTAL: split each transaction into this many savepoint-separated segments; a failed segment rolls back to the last savepoint every participant reached instead of aborting everything"))
+            .arg(Arg::with_name("golden")
+                    .long("golden")
+                    .required(false)
+                    .takes_value(false)
+                    .help("in check mode, compare the coordinator's decisions with an in-memory reference model; pass the run's --seed and options"))
+            .arg(Arg::with_name("vector_clocks")
+                    .long("vector-clocks")
+                    .required(false)
+                    .takes_value(false)
+                    .help("keep a vector clock per component, carried on every message and logged with every record, so the checker can verify happens-before"))
+            .arg(Arg::with_name("results_db")
+                    .long("results-db")
+                    .required(false)
+                    .takes_value(true)
+                    .help("append the run's configuration and outcome to this SQLite database (created if needed)"))
+            .arg(Arg::with_name("store_transactions")
+                    .long("store-transactions")
+                    .required(false)
+                    .takes_value(false)
+                    .help("with --results-db, also store a row per transaction"))
+            .arg(Arg::with_name("csv")
+                    .long("csv")
+                    .required(false)
+                    .takes_value(true)
+                    .help("after the run, write a CSV table with a row per transaction (txid, client, decision, latency, every participant's vote) to this file"))
+            .arg(Arg::with_name("decision_policy")
+                    .long("decision-policy")
+                    .required(false)
+                    .takes_value(true)
+                    .help("how the coordinator decides from the votes: unanimous (commit iff every participant votes yes) or quorum:<n> (commit iff at least n vote yes)"))
+            .arg(Arg::with_name("failure_model")
+                    .long("failure-model")
+                    .required(false)
+                    .takes_value(true)
+                    .help("which messages are lost and which operations fail: bernoulli (each independently, with -S and -s) or bursty:<p>:<len> (each send or operation starts a burst of len failures with probability p; -s and -S are ignored)"))
+            .arg(Arg::with_name("estimate")
+                    .long("estimate")
+                    .required(false)
+                    .takes_value(false)
+                    .help("dry run: print the projected log size, disk space, threads and runtime for these options, then exit"))
+            .arg(Arg::with_name("resume")
+                    .long("resume")
+                    .required(false)
+                    .takes_value(false)
+                    .help("append to the logs already in the log directory and carry on from the committed state, counts and txid numbering they record, instead of starting over"))
+            .arg(Arg::with_name("partition")
+                    .long("partition")
+                    .required(false)
+                    .takes_value(true)
+                    .help("cut participants off from the coordinator for a window of the run, e.g. \"participants=2,3 at=2s duration=5s\""))
+            .arg(Arg::with_name("partition_participants")
+                    .long("partition-participants")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("participant id(s) to cut off from the coordinator, from --partition-after-ms into the run for --partition-duration-ms (instead of a --partition spec)"))
+            .arg(Arg::with_name("partition_after_ms")
+                    .long("partition-after-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --partition-participants, how far into the run the partition starts"))
+            .arg(Arg::with_name("partition_duration_ms")
+                    .long("partition-duration-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --partition-participants, how long until the partition heals"))
+            .arg(Arg::with_name("drain_timeout_ms")
+                    .long("drain-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("after CTRL-C, how long the coordinator keeps trying to deliver the in-flight transaction's decision before abandoning it and exiting"))
+            .arg(Arg::with_name("seal")
+                    .long("seal")
+                    .required(false)
+                    .takes_value(false)
+                    .help("at the end of the run, write the SHA-256 of every log file (and an aggregate) to manifest.sha256 in the log directory"))
+            .arg(Arg::with_name("verify_seal")
+                    .long("verify-seal")
+                    .required(false)
+                    .takes_value(false)
+                    .help("in check mode, first verify the logs against manifest.sha256 and refuse to check them if anything changed"))
+            .arg(Arg::with_name("replicate")
+                    .long("replicate")
+                    .required(false)
+                    .takes_value(false)
+                    .help("EXPERIMENTAL: replicate the coordinator's log to a backup that takes over if the primary crashes (schedule one with a coordinator crash in --fault-schedule); in check mode, check against the backup's log"))
+            .arg(Arg::with_name("failover_timeout_ms")
+                    .long("failover-timeout-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --replicate, how long the backup waits without a heartbeat from the primary before taking over"))
+            .arg(Arg::with_name("outcome_cache")
+                    .long("outcome-cache")
+                    .required(false)
+                    .takes_value(true)
+                    .help("most decided outcomes the coordinator keeps in memory to answer inquiries, evicting the oldest first (0 = keep all)"))
+            .arg(Arg::with_name("think_dist")
+                    .long("think-dist")
+                    .required(false)
+                    .takes_value(true)
+                    .help("closed-loop client think time between a result and the next request: const:<ms>, exp:<mean ms>, uniform:<low>,<high> or lognormal:<median ms>,<sigma>"))
+            .arg(Arg::with_name("client_think_time_ms")
+                    .long("client-think-time-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("closed-loop client think time in ms between a result and the next request, to offer a set load rather than saturate (shorthand for --think-dist const:<ms>)"))
+            .arg(Arg::with_name("client_think_jitter_ms")
+                    .long("client-think-jitter-ms")
+                    .required(false)
+                    .takes_value(true)
+                    .help("with --client-think-time-ms: vary each think time uniformly by up to this many ms either way"))
+            .arg(Arg::with_name("service_dist")
+                    .long("service-dist")
+                    .required(false)
+                    .takes_value(true)
+                    .help("time a participant spends working on each proposal before voting, as a distribution like --think-dist"))
+            .arg(Arg::with_name("kv_store")
+                    .long("kv-store")
+                    .required(false)
+                    .takes_value(false)
+                    .help("participants keep a replicated key-value store that committed transactions write to, saved as participant_<n>.state; in check mode, also check every participant's store against the coordinator's commits"))
+            .arg(Arg::with_name("kv_keys")
+                    .long("kv-keys")
+                    .required(false)
+                    .takes_value(true)
+                    .help("number of keys in the --kv-store store"))
+            .arg(Arg::with_name("pregenerate")
+                    .long("pregenerate")
+                    .required(false)
+                    .takes_value(false)
+                    .help("clients build all their requests (and draw their deadlines) before sending any, rather than as they go, so the workload doesn't depend on the arrival process"))
+            .arg(Arg::with_name("deadlines")
+                    .long("deadlines")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("<ms>:<fraction> give that fraction of each client's requests a deadline of <ms> for the coordinator to decide them by, or abort (repeatable or comma separated; the rest have none)"))
+            .arg(Arg::with_name("read_only_ratio")
+                    .long("read-only-ratio")
+                    .required(false)
+                    .takes_value(true)
+                    .help("fraction of client requests that only read: every participant votes read-only on them and the coordinator sends them no phase 2 decision"))
+            .arg(Arg::with_name("early_client_reply")
+                    .long("early-client-reply")
+                    .required(false)
+                    .takes_value(false)
+                    .help("coordinator replies to the client as soon as the decision is logged, before (not in parallel with) broadcasting it to participants; shortens the client's wait when the broadcast is held up resending a dropped decision or waiting out a partition"))
+            .arg(Arg::with_name("correlated_failure_prob")
+                    .long("correlated-failure-prob")
+                    .required(false)
+                    .takes_value(true)
+                    .help("probability a transaction hits one fault that makes every participant in --correlated-failure-set vote abort"))
+            .arg(Arg::with_name("correlated_failure_set")
+                    .long("correlated-failure-set")
+                    .required(false)
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .help("participant ids affected by a correlated fault (default: all participants)"))
+            .arg(Arg::with_name("interactive")
+                    .long("interactive")
+                    .required(false)
+                    .takes_value(false)
+                    .help("step through the protocol: pause at every message a client or participant sends or receives until enter is pressed, taking them in turn on one worker thread"))
+            .arg(Arg::with_name("worker_threads")
+                    .long("worker-threads")
+                    .required(false)
+                    .takes_value(true)
+                    .help("run the clients and participants on a pool of this many worker threads that step them in turn, rather than a thread each, to simulate thousands of participants (default 0: a thread each)"))
+            .get_matches_from(args);
         
         let _mode = matches.value_of("mode").unwrap_or(default_mode);    
         let f_success_prob_ops = matches.value_of("success_probability_ops").unwrap_or(default_success_prob_ops).parse::<f64>().unwrap();
+        let _participant_op_probs: Vec<f64> = matches.values_of("participant_op_probs").into_iter().flatten()
+            .map(|p| p.parse::<f64>().unwrap())
+            .collect();
+        if _participant_op_probs.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            panic!("participant op probabilities must be between 0 and 1");
+        }
         let f_success_prob_msg = matches.value_of("success_probability_msg").unwrap_or(default_success_prob_msg).parse::<f64>().unwrap();
         let n_participants = matches.value_of("num_participants").unwrap_or(default_n_participants).parse::<i32>().unwrap();
         let n_clients = matches.value_of("num_clients").unwrap_or(default_n_clients).parse::<i32>().unwrap();
         let n_requests = matches.value_of("num_requests").unwrap_or(default_n_requests).parse::<i32>().unwrap();
         let _verbosity = matches.value_of("verbose").unwrap_or(default_verbosity).parse::<usize>().unwrap();
-        let _logpath = matches.value_of("logpath").unwrap_or(&default_logpath);
+        let mut _logpath = matches.value_of("logpath").unwrap_or(&default_logpath).to_string();
+        // every log path is built as logpath + file name
+        if !_logpath.is_empty() && !_logpath.ends_with(std::path::MAIN_SEPARATOR) {
+            _logpath.push(std::path::MAIN_SEPARATOR);
+        }
+        let _exit_policy = matches.value_of("exit_policy").unwrap_or(default_exit_policy);
+        let _seed = matches.value_of("seed").map(|s| s.parse::<u64>().unwrap());
+        let _log_max_bytes = matches.value_of("log_max_bytes").unwrap_or(default_log_max_bytes).parse::<u64>().unwrap();
+        let _measure_contention = matches.is_present("measure_contention");
+        let _arrival_rate = matches.value_of("arrival_rate").unwrap_or(default_arrival_rate).parse::<f64>().unwrap();
+        let _quiet = matches.is_present("quiet");
+        let _async_log = matches.is_present("async_log");
+        let _durable = matches.is_present("durable");
+        let _early_client_reply = matches.is_present("early_client_reply");
+        let _correlated_failure_prob = matches.value_of("correlated_failure_prob").unwrap_or(default_correlated_failure_prob).parse::<f64>().unwrap();
+        let _interactive = matches.is_present("interactive");
+        let _results_db = matches.value_of("results_db").map(|path| path.to_string());
+        let _store_transactions = matches.is_present("store_transactions");
+        let _csv = matches.value_of("csv").map(|path| path.to_string());
+        let _estimate = matches.is_present("estimate");
+        let _resume = matches.is_present("resume");
+        let _drain_timeout_ms = matches.value_of("drain_timeout_ms").unwrap_or(default_drain_timeout_ms).parse::<u64>().unwrap();
+        let _seal = matches.is_present("seal");
+        let _replicate = matches.is_present("replicate");
+        let _failover_timeout_ms = matches.value_of("failover_timeout_ms").unwrap_or(default_failover_timeout_ms).parse::<u64>().unwrap();
+        let _verify_seal = matches.is_present("verify_seal");
+        let _partition = matches.value_of("partition").map(|spec| spec.to_string());
+        let _partition_participants: Vec<i32> = matches.values_of("partition_participants").into_iter().flatten()
+            .map(|id| id.parse::<i32>().unwrap())
+            .collect();
+        let _partition_after_ms = matches.value_of("partition_after_ms").unwrap_or(default_partition_after_ms).parse::<u64>().unwrap();
+        let _partition_duration_ms = matches.value_of("partition_duration_ms").map(|ms| ms.parse::<u64>().unwrap());
+        if !_partition_participants.is_empty() && _partition.is_some() {
+            panic!("--partition-participants can't be combined with --partition");
+        }
+        if !_partition_participants.is_empty() && _partition_duration_ms.is_none() {
+            panic!("--partition-participants needs --partition-duration-ms");
+        }
+        let _decision_policy = matches.value_of("decision_policy").unwrap_or(default_decision_policy).to_string();
+        let _failure_model = matches.value_of("failure_model").unwrap_or(default_failure_model).to_string();
+        // panics on a malformed model here, rather than in every thread
+        failure::from_string(&_failure_model, 1.0, 1.0, 0);
+        let _vector_clocks = matches.is_present("vector_clocks");
+        let _golden = matches.is_present("golden");
+        if _golden && _failure_model != default_failure_model {
+            // the golden model replays bernoulli draws
+            panic!("--golden needs the bernoulli failure model");
+        }
+        let _segments = matches.value_of("segments").unwrap_or(default_segments).parse::<i32>().unwrap();
+        let _ops_per_txn = matches.value_of("ops_per_txn").unwrap_or(default_ops_per_txn).parse::<i32>().unwrap();
+        if _ops_per_txn < 1 {
+            panic!("--ops-per-txn must be at least 1");
+        }
+        let _fault_schedule = matches.value_of("fault_schedule").map(|path| path.to_string());
+        let _check_liveness = matches.is_present("check_liveness");
+        let _client_result_retries = matches.value_of("client_result_retries").map(|n| n.parse::<u32>().unwrap());
+        let _client_result_timeout_ms = matches.value_of("client_result_timeout_ms").unwrap_or(default_client_result_timeout_ms).parse::<u64>().unwrap();
+        let _participant_log_fail_prob = matches.value_of("participant_log_fail_prob").unwrap_or(default_participant_log_fail_prob).parse::<f64>().unwrap();
+        if !(0.0..1.0).contains(&_participant_log_fail_prob) {
+            panic!("--participant-log-fail-prob must be at least 0 and below 1");
+        }
+        if _participant_log_fail_prob > 0.0 && _golden {
+            // a commit vote that fails to log is cast as abort, off an
+            // RNG the golden model doesn't replay
+            panic!("--participant-log-fail-prob can't be combined with --golden");
+        }
+        let _min_live_participants = matches.value_of("min_live_participants").unwrap_or("0").parse::<i32>().unwrap();
+        if _min_live_participants > n_participants {
+            panic!("--min-live-participants {} can never be met with {} participants", _min_live_participants, n_participants);
+        }
+        let _check_window_size = matches.value_of("check_window_size").unwrap_or("0").parse::<i32>().unwrap();
+        if _check_window_size > 0 && _mode == "check" && !_quiet {
+            panic!("--check-window-size only applies to -m check --quiet");
+        }
+        let _max_total_log_bytes = matches.value_of("max_total_log_bytes").map(|n| n.parse::<u64>().unwrap());
+        let _warmup_success_ramp = matches.value_of("warmup_success_ramp").unwrap_or("0").parse::<i32>().unwrap();
+        if _warmup_success_ramp > 0 && _golden {
+            // the golden model draws every operation at the full -s
+            panic!("--warmup-success-ramp can't be combined with --golden");
+        }
+        let _max_txn_lifetime_ms = matches.value_of("max_txn_lifetime_ms").map(|ms| ms.parse::<u64>().unwrap());
+        let _disk_full_after = matches.value_of("disk_full_after").map(|n| n.parse::<u64>().unwrap());
+        let _log_index = matches.is_present("log_index");
+        let _log_format = matches.value_of("log_format").unwrap_or(default_log_format);
+        let _log_checksums = matches.is_present("log_checksums");
+        let _in_memory_log = matches.is_present("in_memory_log");
+        if _in_memory_log && _mode != "run" {
+            panic!("--in-memory-log runs leave no logs for -m {} to read", _mode);
+        }
+        if _in_memory_log && (_resume || _seal || _store_transactions || _csv.is_some() || _replicate || _max_total_log_bytes.is_some()) {
+            // each of these reads the logs back from disk, or budgets their bytes
+            panic!("--in-memory-log can't be combined with --resume, --seal, --store-transactions, --csv, --replicate or --max-total-log-bytes");
+        }
+        let _correlated_failure_set: Option<Vec<i32>> = matches.values_of("correlated_failure_set")
+            .map(|ids| ids.map(|id| id.parse::<i32>().unwrap()).collect());
+        let _lose_last_commit: Vec<i32> = matches.values_of("lose_last_commit").into_iter().flatten()
+            .map(|id| id.parse::<i32>().unwrap())
+            .collect();
+        let mut _think_dist = matches.value_of("think_dist").map(Distribution::from_string);
+        let _client_think_time_ms = matches.value_of("client_think_time_ms").unwrap_or("0").parse::<u64>().unwrap();
+        let _client_think_jitter_ms = matches.value_of("client_think_jitter_ms").unwrap_or("0").parse::<u64>().unwrap();
+        if _client_think_jitter_ms > 0 && _client_think_time_ms == 0 {
+            panic!("--client-think-jitter-ms needs --client-think-time-ms");
+        }
+        if _client_think_time_ms > 0 {
+            if _think_dist.is_some() {
+                panic!("--client-think-time-ms can't be combined with --think-dist");
+            }
+            if _client_think_jitter_ms > _client_think_time_ms {
+                panic!("--client-think-jitter-ms can't be more than --client-think-time-ms");
+            }
+            let (think, jitter) = (_client_think_time_ms as f64, _client_think_jitter_ms as f64);
+            _think_dist = Some(if jitter > 0.0 {
+                Distribution::Uniform { low: think - jitter, high: think + jitter }
+            } else {
+                Distribution::Constant(think)
+            });
+        }
+        let _service_dist = matches.value_of("service_dist").map(Distribution::from_string);
+        let _outcome_cache = matches.value_of("outcome_cache").unwrap_or(default_outcome_cache).parse::<usize>().unwrap();
+        let _kv_store = matches.is_present("kv_store");
+        let _kv_keys = matches.value_of("kv_keys").unwrap_or(default_kv_keys).parse::<i32>().unwrap();
+        let _pregenerate = matches.is_present("pregenerate");
+        let mut _deadlines = vec![];
+        for deadline in matches.values_of("deadlines").into_iter().flatten() {
+            let (ms, fraction) = deadline.split_at(deadline.find(':').expect("deadline must be <ms>:<fraction>"));
+            _deadlines.push((ms.parse::<u64>().unwrap(), fraction[1..].parse::<f64>().unwrap()));
+        }
+        if _deadlines.iter().map(|(_ms, fraction)| fraction).sum::<f64>() > 1.0 {
+            panic!("deadline fractions add up to more than 1");
+        }
+        let _exit_ack_timeout_ms = matches.value_of("exit_ack_timeout_ms").unwrap_or(default_exit_ack_timeout_ms).parse::<u64>().unwrap();
+        let _vote_timeout_ms = matches.value_of("vote_timeout_ms").unwrap_or(default_vote_timeout_ms).parse::<u64>().unwrap();
+        let _net_latency_ms_min = matches.value_of("net_latency_ms_min").unwrap_or(default_net_latency_ms).parse::<u64>().unwrap();
+        let _net_latency_ms_max = matches.value_of("net_latency_ms_max").unwrap_or(default_net_latency_ms).parse::<u64>().unwrap();
+        let _reorder_prob = matches.value_of("reorder_prob").unwrap_or(default_reorder_prob).parse::<f64>().unwrap();
+        let _request_poll_ms = matches.value_of("request_poll_ms").unwrap_or(default_request_poll_ms).parse::<u64>().unwrap();
+        let _channel_capacity = matches.value_of("channel_capacity").unwrap_or("0").parse::<usize>().unwrap();
+        let _send_attempts = matches.value_of("send_attempts").unwrap_or(default_send_attempts).parse::<u32>().unwrap();
+        let _send_backoff_ms = matches.value_of("send_backoff_ms").unwrap_or(default_send_backoff_ms).parse::<u64>().unwrap();
+        let _max_inflight = matches.value_of("max_inflight").unwrap_or(default_max_inflight).parse::<i32>().unwrap();
+        let _parallel_prepare = matches.is_present("parallel_prepare");
+        let _presumed_abort = matches.is_present("presumed_abort");
+        let _presumed_abort_timeout_ms = matches.value_of("presumed_abort_timeout_ms").unwrap_or(default_presumed_abort_timeout_ms).parse::<u64>().unwrap();
+        let _decision_timeout_ms = matches.value_of("decision_timeout_ms").unwrap_or(default_decision_timeout_ms).parse::<u64>().unwrap();
+        let _heartbeat_ms = matches.value_of("heartbeat_ms").unwrap_or(default_heartbeat_ms).parse::<u64>().unwrap();
+        let _max_missed = matches.value_of("max_missed").unwrap_or(default_max_missed).parse::<u32>().unwrap();
+        let _protocol = matches.value_of("protocol").unwrap_or(default_protocol);
+        let _precommit_timeout_ms = matches.value_of("precommit_timeout_ms").unwrap_or(default_precommit_timeout_ms).parse::<u64>().unwrap();
+        let _read_only_ratio = matches.value_of("read_only_ratio").unwrap_or("0.0").parse::<f64>().unwrap();
+        let mut _participant_exit_delay = HashMap::new();
+        for delay in matches.values_of("participant_exit_delay").into_iter().flatten() {
+            let (id, ms) = delay.split_at(delay.find(':').expect("participant exit delay must be <id>:<ms> or <id>:never"));
+            let ms = match &ms[1..] {
+                "never" => None,
+                ms => Some(ms.parse::<u64>().unwrap()),
+            };
+            _participant_exit_delay.insert(id.parse::<i32>().unwrap(), ms);
+        }
+        let mut _participant_start_delay = HashMap::new();
+        for delay in matches.values_of("participant_start_delay").into_iter().flatten() {
+            let (id, ms) = delay.split_at(delay.find(':').expect("participant start delay must be <id>:<ms>"));
+            _participant_start_delay.insert(id.parse::<i32>().unwrap(), ms[1..].parse::<u64>().unwrap());
+        }
+        if _interactive && matches.is_present("worker_threads") {
+            panic!("--interactive steps everything on one worker thread; drop --worker-threads");
+        }
+        // --interactive: one worker, so only one entity is ever paused at a step
+        let _worker_threads = if _interactive { 1 } else { matches.value_of("worker_threads").unwrap_or("0").parse::<usize>().unwrap() };
+        if _worker_threads > 0 && (_heartbeat_ms > 0 || _protocol == "3pc" || _arrival_rate > 0.0 || _service_dist.is_some()) {
+            // each of these blocks (or sleeps) a participant or client mid-protocol,
+            // which would stall every other entity on its worker
+            panic!("--worker-threads (or --interactive) can't be combined with --heartbeat-ms, --protocol 3pc, --arrival-rate or --service-dist");
+        }
 
         match _mode.as_ref() {
             "run" => {},
             "check" => {},
+            "verify" => {},
+            "merge-logs" => {},
             _ => panic!("unknown execution mode requested!"),
         } 
 
+        match _exit_policy {
+            "flush" => {},
+            "abandon" => {},
+            _ => panic!("unknown exit policy requested!"),
+        } 
+
+        match _log_format {
+            "json" => {},
+            "bincode" => {},
+            _ => panic!("unknown log format requested!"),
+        } 
+
+        match _protocol {
+            "2pc" => {},
+            "3pc" => {},
+            _ => panic!("unknown protocol requested!"),
+        } 
+
         TPCOptions {
             success_probability_ops: f_success_prob_ops,
+            participant_op_probs: _participant_op_probs,
             success_probability_msg: f_success_prob_msg,
             num_clients: n_clients,
             num_requests: n_requests,
             num_participants: n_participants,
             verbosity: _verbosity,
             mode: _mode.to_string(),
-            logpath: _logpath.to_string(),
+            logpath: _logpath,
+            exit_policy: _exit_policy.to_string(),
+            seed: _seed,
+            log_max_bytes: _log_max_bytes,
+            measure_contention: _measure_contention,
+            arrival_rate: _arrival_rate,
+            quiet: _quiet,
+            participant_start_delay: _participant_start_delay,
+            participant_exit_delay: _participant_exit_delay,
+            exit_ack_timeout_ms: _exit_ack_timeout_ms,
+            vote_timeout_ms: _vote_timeout_ms,
+            net_latency_ms_min: _net_latency_ms_min,
+            net_latency_ms_max: _net_latency_ms_max,
+            reorder_prob: _reorder_prob,
+            request_poll_ms: _request_poll_ms,
+            channel_capacity: _channel_capacity,
+            send_attempts: _send_attempts,
+            send_backoff_ms: _send_backoff_ms,
+            max_inflight: _max_inflight,
+            parallel_prepare: _parallel_prepare,
+            presumed_abort: _presumed_abort,
+            presumed_abort_timeout_ms: _presumed_abort_timeout_ms,
+            decision_timeout_ms: _decision_timeout_ms,
+            heartbeat_ms: _heartbeat_ms,
+            max_missed: _max_missed,
+            protocol: _protocol.to_string(),
+            precommit_timeout_ms: _precommit_timeout_ms,
+            max_txn_lifetime_ms: _max_txn_lifetime_ms,
+            min_live_participants: _min_live_participants,
+            warmup_success_ramp: _warmup_success_ramp,
+            check_window_size: _check_window_size,
+            max_total_log_bytes: _max_total_log_bytes,
+            lose_last_commit: _lose_last_commit,
+            async_log: _async_log,
+            durable: _durable,
+            early_client_reply: _early_client_reply,
+            correlated_failure_prob: _correlated_failure_prob,
+            correlated_failure_set: _correlated_failure_set,
+            interactive: _interactive,
+            worker_threads: _worker_threads,
+            results_db: _results_db,
+            store_transactions: _store_transactions,
+            csv: _csv,
+            decision_policy: _decision_policy,
+            failure_model: _failure_model,
+            estimate: _estimate,
+            resume: _resume,
+            partition: _partition,
+            partition_participants: _partition_participants,
+            partition_after_ms: _partition_after_ms,
+            partition_duration_ms: _partition_duration_ms.unwrap_or(0),
+            drain_timeout_ms: _drain_timeout_ms,
+            seal: _seal,
+            verify_seal: _verify_seal,
+            replicate: _replicate,
+            failover_timeout_ms: _failover_timeout_ms,
+            vector_clocks: _vector_clocks,
+            golden: _golden,
+            segments: _segments,
+            ops_per_txn: _ops_per_txn,
+            fault_schedule: _fault_schedule,
+            check_liveness: _check_liveness,
+            client_result_retries: _client_result_retries,
+            client_result_timeout_ms: _client_result_timeout_ms,
+            think_dist: _think_dist,
+            client_think_time_ms: _client_think_time_ms,
+            client_think_jitter_ms: _client_think_jitter_ms,
+            service_dist: _service_dist,
+            outcome_cache: _outcome_cache,
+            kv_store: _kv_store,
+            kv_keys: _kv_keys,
+            pregenerate: _pregenerate,
+            deadlines: _deadlines,
+            read_only_ratio: _read_only_ratio,
+            disk_full_after: _disk_full_after,
+            participant_log_fail_prob: _participant_log_fail_prob,
+            log_index: _log_index,
+            log_format: _log_format.to_string(),
+            log_checksums: _log_checksums,
+            in_memory_log: _in_memory_log,
         }
     }
+
+    ///
+    /// validate()
+    /// check the options make sense before anything runs on them: the 
+    /// success probabilities are probabilities, the counts aren't 
+    /// negative (zero clients or requests is a run that does nothing), 
+    /// there's at least one participant and a log path, and options that
+    /// don't work together aren't given together. Err says what's wrong
+    /// with the first bad field.
+    ///
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.success_probability_ops) {
+            return Err(format!("-s (success_probability_ops) must be between 0.0 and 1.0, not {}", self.success_probability_ops));
+        }
+        if !(0.0..=1.0).contains(&self.success_probability_msg) {
+            return Err(format!("-S (success_probability_msg) must be between 0.0 and 1.0, not {}", self.success_probability_msg));
+        }
+        if self.num_clients < 0 {
+            return Err(format!("-c (num_clients) can't be negative, not {}", self.num_clients));
+        }
+        if self.num_participants < 1 {
+            return Err(format!("-p (num_participants) must be at least 1, not {}", self.num_participants));
+        }
+        if self.num_requests < 0 {
+            return Err(format!("-r (num_requests) can't be negative, not {}", self.num_requests));
+        }
+        if self.logpath.is_empty() {
+            return Err(String::from("-l (logpath) can't be empty"));
+        }
+        if self.net_latency_ms_min > self.net_latency_ms_max {
+            return Err(format!("--net-latency-ms-min ({}) can't be more than --net-latency-ms-max ({})", self.net_latency_ms_min, self.net_latency_ms_max));
+        }
+        if !(0.0..=1.0).contains(&self.reorder_prob) {
+            return Err(format!("--reorder-prob must be between 0.0 and 1.0, not {}", self.reorder_prob));
+        }
+        if self.reorder_prob > 0.0 && self.presumed_abort {
+            // a participant takes the next proposal or the exit arriving
+            // before a decision to mean there isn't one, which only holds
+            // if they can't overtake it
+            return Err(String::from("--presumed-abort can't be combined with --reorder-prob"));
+        }
+        if self.max_inflight > 1 && self.replicate {
+            // a backup taking over only knows how to finish one txid
+            return Err(String::from("--max-inflight can't be combined with --replicate"));
+        }
+        if self.decision_timeout_ms == 0 {
+            return Err(String::from("--decision-timeout-ms must be at least 1, not 0"));
+        }
+        if self.presumed_abort && self.max_inflight > 1 {
+            // a participant presumes abort when the next txid is proposed
+            return Err(String::from("--presumed-abort can't be combined with --max-inflight"));
+        }
+        if self.heartbeat_ms > 0 && self.max_missed == 0 {
+            return Err(String::from("--max-missed must be at least 1 with --heartbeat-ms, not 0"));
+        }
+        if self.protocol == "3pc" {
+            // a pre-commit is only safe once every participant voted yes,
+            // for the one txid each of them is working on, and a backup
+            // coordinator only knows how to finish 2pc
+            if self.decision_policy != "unanimous" {
+                return Err(format!("--protocol 3pc needs the unanimous decision policy, not {}", self.decision_policy));
+            }
+            if self.max_inflight > 1 || self.presumed_abort || self.replicate {
+                return Err(String::from("--protocol 3pc can't be combined with --max-inflight, --presumed-abort or --replicate"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.read_only_ratio) {
+            return Err(format!("--read-only-ratio must be between 0.0 and 1.0, not {}", self.read_only_ratio));
+        }
+        if self.read_only_ratio > 0.0 && (self.protocol == "3pc" || self.golden) {
+            // a read-only voter is done with the txid, so it can't be
+            // pre-committed, and the golden model has every vote drawn
+            return Err(String::from("--read-only-ratio can't be combined with --protocol 3pc or --golden"));
+        }
+        Ok(())
+    }
+
+    ///
+    /// op_success_prob()
+    /// the probability participant id executes requests successfully: 
+    /// its entry in --participant-op-probs, or -s if the list is shorter
+    ///
+    pub fn op_success_prob(&self, id: i32) -> f64 {
+        self.participant_op_probs.get(id as usize).copied().unwrap_or(self.success_probability_ops)
+    }
 }
\ No newline at end of file
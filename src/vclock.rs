@@ -0,0 +1,70 @@
+//!
+//! vclock.rs
+//! Vector clocks for tracking happens-before across components
+//! (--vector-clocks). Every component keeps a Clock, ticks it for each
+//! record it logs and message it sends, stamps the result onto the
+//! record/message, and merges in the clock of every message it receives.
+//! The checker can then order records from different logs without
+//! relying on uids or wall-clock time.
+//!
+use std::collections::BTreeMap;
+use message::ProtocolMessage;
+
+/// component name -> number of events it has had
+pub type VectorClock = BTreeMap<String, u64>;
+
+///
+/// happens_before()
+/// true if the event stamped a happened before the event stamped b:
+/// every entry of a is <= the same entry of b, and they aren't equal
+///
+pub fn happens_before(a: &VectorClock, b: &VectorClock) -> bool {
+    a != b && a.iter().all(|(k, n)| b.get(k).is_some_and(|m| n <= m))
+}
+
+///
+/// Clock
+/// one component's vector clock. a disabled clock stamps nothing, so
+/// messages and records carry an empty clock.
+///
+#[derive(Clone, Debug)]
+pub struct Clock {
+    me: String,
+    clock: Option<VectorClock>,
+}
+
+impl Clock {
+    pub fn new(me: &str, enabled: bool) -> Clock {
+        Clock {
+            me: me.to_string(),
+            clock: if enabled { Some(VectorClock::new()) } else { None },
+        }
+    }
+
+    ///
+    /// stamp()
+    /// a local event (sending pm, or logging it): tick, and attach the
+    /// resulting clock to pm
+    ///
+    pub fn stamp(&mut self, mut pm: ProtocolMessage) -> ProtocolMessage {
+        if let Some(ref mut clock) = self.clock {
+            *clock.entry(self.me.clone()).or_insert(0) += 1;
+            pm.clock = clock.clone();
+        }
+        pm
+    }
+
+    ///
+    /// observe()
+    /// pm was received: take the entrywise max with its clock, then tick
+    ///
+    pub fn observe(&mut self, pm: &ProtocolMessage) {
+        if let Some(ref mut clock) = self.clock {
+            for (k, n) in pm.clock.iter() {
+                let mine = clock.entry(k.clone()).or_insert(0);
+                *mine = (*mine).max(*n);
+            }
+            *clock.entry(self.me.clone()).or_insert(0) += 1;
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//!
+//! verify.rs
+//! Offline replay of a run (-m verify). Unlike the checker, which is
+//! handed the run's shape (participant count etc.) on the command line,
+//! this rebuilds the run from nothing but the files in the log
+//! directory, in a process that shares no memory with the run, and
+//! checks that the logs on their own justify every outcome:
+//!
+//!   - membership: the participants are whoever left a log
+//!   - every request the coordinator logged was proposed, and its
+//!     decision, commit point and client result are consistent
+//!   - every commit is justified by the votes the participants logged,
+//!     under the decision policy the run used (--decision-policy,
+//!     which isn't in the logs)
+//!   - every participant that logged a decision logged the coordinator's
+//!   - with --kv-store, the stores hold what replaying the commits gives
+//!
+//! Aborts can't always be justified from the logs (a yes vote lost in
+//! transit leaves no trace), so those are counted rather than failed.
+//! Under --presumed-abort an abort leaves only the client's result in
+//! the coordinator's log, and a participant that heard no decision logs
+//! a PresumedAbort; both count as aborts.
+//!
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use checker;
+use kvstore;
+use message::{MessageType, ProtocolMessage};
+use oplog::OpLog;
+use policy::{self, Decision, Vote};
+
+///
+/// members()
+/// the participants that left a log under logpath, by id
+///
+fn members(logpath: &str) -> Vec<i32> {
+    let mut ids: Vec<i32> = fs::read_dir(logpath).into_iter().flatten().flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_prefix("participant_")?.strip_suffix(".log")?.parse::<i32>().ok()
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+///
+/// by_txid()
+/// a log's records grouped by txid, each group in the order logged
+///
+fn by_txid(log: &HashMap<i32, ProtocolMessage>) -> BTreeMap<i32, Vec<ProtocolMessage>> {
+    let mut txids: BTreeMap<i32, Vec<ProtocolMessage>> = BTreeMap::new();
+    for pm in log.values() {
+        txids.entry(pm.txid).or_default().push(pm.clone());
+    }
+    for records in txids.values_mut() {
+        records.sort_by_key(|pm| pm.uid);
+    }
+    txids
+}
+
+fn find(records: &[ProtocolMessage], mtypes: &[MessageType]) -> Option<ProtocolMessage> {
+    records.iter().find(|pm| mtypes.contains(&pm.mtype)).cloned()
+}
+
+///
+/// logged_vote()
+/// what a participant's log says it voted on a txid
+///
+fn logged_vote(records: Option<&Vec<ProtocolMessage>>) -> Vote {
+    let records = match records {
+        Some(records) => records,
+        None => return Vote::Missing,
+    };
+    match find(records, &[MessageType::ParticipantVoteCommit, MessageType::ParticipantVoteAbort]) {
+        Some(vote) if vote.mtype == MessageType::ParticipantVoteCommit => Vote::Commit(vote.segment),
+        Some(_vote) => Vote::Abort,
+        None => Vote::Missing,
+    }
+}
+
+///
+/// verify()
+/// replay the run whose logs are under logpath, print what it adds up
+/// to and anything the logs don't justify. returns true if they
+/// justify everything.
+///
+pub fn verify(logpath: &str, decision_policy: &str) -> bool {
+    let policy = policy::from_string(decision_policy);
+    let replicated = Path::new(&format!("{}{}", logpath, checker::coordinator_log(true))).exists();
+    let clogpath = format!("{}{}", logpath, checker::coordinator_log(replicated));
+    if !Path::new(&clogpath).exists() {
+        println!("verify: no coordinator log at {}", clogpath);
+        return false;
+    }
+    let cmap = OpLog::from_file(clogpath.clone()).arc().lock().unwrap().clone();
+    let coordinator = by_txid(&cmap);
+    let ids = members(logpath);
+    let participants: Vec<(String, BTreeMap<i32, Vec<ProtocolMessage>>)> = ids.iter()
+        .map(|id| {
+            let name = format!("participant_{}", id);
+            let plog = OpLog::from_file(format!("{}{}.log", logpath, name)).arc().lock().unwrap().clone();
+            (name, by_txid(&plog))
+        })
+        .collect();
+
+    let mut bad: Vec<String> = vec![];
+    let (mut committed, mut aborted, mut undecided, mut unexplained, mut presumed) = (0, 0, 0, 0, 0);
+    for (txid, records) in coordinator.iter() {
+        let request = match find(records, &[MessageType::ClientRequest]) {
+            Some(request) => request,
+            None => continue,
+        };
+        let decision = match find(records, &[MessageType::CoordinatorCommit, MessageType::CoordinatorAbort]) {
+            Some(decision) => decision,
+            None if find(records, &[MessageType::ClientResultAbort]).is_some() => {
+                presumed += 1;
+                ProtocolMessage { mtype: MessageType::CoordinatorAbort, ..find(records, &[MessageType::ClientResultAbort]).unwrap() }
+            },
+            None => {
+                undecided += 1;
+                continue;
+            },
+        };
+        if find(records, &[MessageType::CoordinatorPropose]).is_none() {
+            bad.push(format!("txid {}: decided {:?} without ever being proposed", txid, decision.mtype));
+        }
+        let votes: Vec<Vote> = participants.iter().map(|(_name, plog)| logged_vote(plog.get(txid))).collect();
+
+        if decision.mtype == MessageType::CoordinatorCommit {
+            committed += 1;
+            // votes that reached at least the committed savepoint are the
+            // ones that could have justified it
+            let enough: Vec<Vote> = votes.iter()
+                .map(|v| match v {
+                    Vote::Commit(s) if *s >= decision.segment => *v,
+                    _ => Vote::Missing,
+                })
+                .collect();
+            if policy.decide(&enough) == Decision::Abort {
+                bad.push(format!("txid {}: committed through savepoint {} but the logged votes {:?} don't justify it under {}",
+                                 txid, decision.segment, votes, policy.name()));
+            }
+            match find(records, &[MessageType::CommitPoint]) {
+                Some(point) if point.uid > decision.uid => {},
+                _ => bad.push(format!("txid {}: committed without a commit point logged after the decision", txid)),
+            }
+        } else {
+            aborted += 1;
+            let deadline = find(records, &[MessageType::ClientResultAbort]).is_some_and(|r| r.deadline_ms > 0);
+            // the coordinator logs why it overrode the policy
+            let overridden = find(records, &[MessageType::CoordinatorScavenge, MessageType::InsufficientReplicas]).is_some();
+            if !deadline && !overridden && policy.decide(&votes) != Decision::Abort {
+                unexplained += 1;
+            }
+        }
+
+        if let Some(result) = find(records, &[MessageType::ClientResultCommit, MessageType::ClientResultAbort]) {
+            let agrees = (result.mtype == MessageType::ClientResultCommit) == (decision.mtype == MessageType::CoordinatorCommit);
+            if !agrees {
+                bad.push(format!("txid {}: {} was told {:?} but the decision was {:?}", txid, request.senderid, result.mtype, decision.mtype));
+            }
+        }
+        for (name, plog) in participants.iter() {
+            let theirs = plog.get(txid).and_then(|r| find(r, &[MessageType::CoordinatorCommit, MessageType::CoordinatorAbort, MessageType::PresumedAbort]));
+            if let Some(theirs) = theirs {
+                let agrees = match theirs.mtype {
+                    MessageType::PresumedAbort => decision.mtype == MessageType::CoordinatorAbort,
+                    _ => (theirs.mtype, theirs.segment) == (decision.mtype, decision.segment),
+                };
+                if !agrees {
+                    bad.push(format!("txid {}: {} logged {:?} (savepoint {}) but the coordinator decided {:?} (savepoint {})",
+                                     txid, name, theirs.mtype, theirs.segment, decision.mtype, decision.segment));
+                }
+            }
+        }
+    }
+    // nobody may decide what the coordinator didn't (presuming abort
+    // is what no decision means)
+    for (name, plog) in participants.iter() {
+        for (txid, records) in plog.iter() {
+            let decided = find(records, &[MessageType::CoordinatorCommit, MessageType::CoordinatorAbort]);
+            // under --protocol 3pc a pre-commit already decides commit
+            let coordinator_decided = coordinator.get(txid)
+                .and_then(|r| find(r, &[MessageType::CoordinatorCommit, MessageType::CoordinatorAbort, MessageType::ClientResultAbort,
+                                        MessageType::CoordinatorPreCommit]));
+            if let (Some(decided), None) = (decided, coordinator_decided) {
+                bad.push(format!("txid {}: {} logged {:?} but the coordinator never decided it", txid, name, decided.mtype));
+            }
+        }
+    }
+
+    let mut stores = 0;
+    for id in ids.iter() {
+        let p = format!("participant_{}", id);
+        if let Ok(state) = kvstore::load(&kvstore::state_path(logpath, &p)) {
+            stores += 1;
+            if state.data != kvstore::replay(&cmap, state.keys, state.segments) {
+                bad.push(format!("{}'s store doesn't match replaying the coordinator's commits", p));
+            }
+        }
+    }
+
+    if presumed > 0 {
+        println!("verify: {} abort(s) presumed, with only the client's result logged", presumed);
+    }
+    println!("verify: replayed {} with {} participant log(s){}: C:{} A:{} U:{}",
+             clogpath, participants.len(),
+             if stores > 0 { format!(" and {} store(s)", stores) } else { String::new() },
+             committed, aborted, undecided);
+    if unexplained > 0 {
+        println!("verify: {} abort(s) despite logged votes that would commit (votes lost in transit leave no trace)", unexplained);
+    }
+    for problem in bad.iter() {
+        println!("verify: {}", problem);
+    }
+    println!("verify: {}", if bad.is_empty() { "PASS" } else { "FAIL" });
+    bad.is_empty()
+}
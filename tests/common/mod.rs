@@ -0,0 +1,38 @@
+//!
+//! common
+//! Setup shared by the integration tests: options for a run logging to a
+//! fresh directory of its own, and the checker's verdict on it.
+//!
+use concurrency_2pc::checker;
+use concurrency_2pc::checker::CheckReport;
+use concurrency_2pc::tpcoptions::TPCOptions;
+use std::env;
+use std::fs;
+
+///
+/// options()
+/// the options args give (as on the command line), logging to an empty
+/// directory named after the test, so tests running side by side don't
+/// share logs
+///
+pub fn options(name: &str, args: &[&str]) -> TPCOptions {
+    let logpath = env::temp_dir().join(format!("2pc-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&logpath);
+    fs::create_dir_all(&logpath).unwrap();
+    let mut argv = vec!["concurrency-2pc", "-l", logpath.to_str().unwrap()];
+    argv.extend_from_slice(args);
+    TPCOptions::from_args(argv)
+}
+
+///
+/// check()
+/// what check_last_run() finds in the logs of the run opts describes,
+/// failing the test with its violations if the logs break 2PC
+///
+pub fn check(opts: &TPCOptions) -> CheckReport {
+    match checker::check_last_run(opts.num_clients, opts.num_requests, opts.num_participants, &opts.logpath,
+                                  opts.check_liveness, opts.replicate, opts.kv_store) {
+        Ok(report) => report,
+        Err(err) => panic!("{}", err),
+    }
+}
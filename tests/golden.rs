@@ -0,0 +1,60 @@
+//!
+//! golden.rs
+//! Model-based tests: runs through the library, with the coordinator's
+//! decisions compared against what the golden model (src/golden.rs)
+//! decides for the same seed and options.
+//!
+extern crate concurrency_2pc;
+mod common;
+use concurrency_2pc::checker;
+use concurrency_2pc::checker::CheckError;
+use concurrency_2pc::simulation;
+
+///
+/// coordinator_decides_as_the_model_does()
+/// with operations failing and votes lost, every txid the coordinator
+/// decided (and the model saw no failure injected in) goes the way the
+/// model says, savepoint included with --segments
+///
+#[test]
+fn coordinator_decides_as_the_model_does() {
+    let runs: [&[&str]; 3] = [
+        &["-s", "0.8"],
+        &["-s", "0.8", "-S", "0.95"],
+        &["-s", "0.9", "--segments", "3"],
+    ];
+    for (n, args) in runs.iter().enumerate() {
+        let mut argv = vec!["-c", "3", "-p", "3", "-r", "10", "--seed", "7", "--golden"];
+        argv.extend_from_slice(args);
+        let opts = common::options(&format!("golden-{}", n), &argv);
+        simulation::run(&opts);
+        common::check(&opts);
+        match checker::compare_golden(&opts) {
+            Ok(matched) => assert!(matched, "the coordinator and the model disagree with {:?}", args),
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+///
+/// unmodelled_runs_are_refused()
+/// the model replays a run from its seed, and can't replay correlated
+/// faults: comparing without a seed, or with them, is an error rather
+/// than a panic (or a comparison that means nothing)
+///
+#[test]
+fn unmodelled_runs_are_refused() {
+    let runs: [&[&str]; 2] = [
+        &["--golden"],
+        &["--golden", "--seed", "7", "--correlated-failure-prob", "0.1"],
+    ];
+    for (n, args) in runs.iter().enumerate() {
+        let mut argv = vec!["-m", "check", "-c", "1", "-p", "2", "-r", "1"];
+        argv.extend_from_slice(args);
+        let opts = common::options(&format!("golden-unmodelled-{}", n), &argv);
+        match checker::compare_golden(&opts) {
+            Err(CheckError::Unsupported(_)) => {},
+            other => panic!("compare_golden() with {:?} gave {:?}, not Unsupported", args, other),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//!
+//! options.rs
+//! What TPCOptions::validate() lets through, and what it refuses (with
+//! an Err naming the bad option, rather than a panic while parsing).
+//!
+extern crate concurrency_2pc;
+use concurrency_2pc::tpcoptions::TPCOptions;
+
+fn validate(args: &[&str]) -> Result<(), String> {
+    let mut argv = vec!["concurrency-2pc"];
+    argv.extend_from_slice(args);
+    TPCOptions::from_args(argv).validate()
+}
+
+///
+/// zero_participants_are_refused()
+/// a run needs a participant, but not clients or requests
+///
+#[test]
+fn zero_participants_are_refused() {
+    assert_eq!(validate(&["-c", "0", "-r", "0"]), Ok(()));
+    let err = validate(&["-p", "0"]).unwrap_err();
+    assert!(err.starts_with("-p (num_participants)"), "{}", err);
+}
+
+///
+/// conflicting_options_are_refused()
+/// options that don't work together are an Err from validate(), each
+/// saying which option is wrong
+///
+#[test]
+fn conflicting_options_are_refused() {
+    let refused: &[(&[&str], &str)] = &[
+        (&["--net-latency-ms-min", "10", "--net-latency-ms-max", "5"], "--net-latency-ms-min"),
+        (&["--reorder-prob", "1.5"], "--reorder-prob"),
+        (&["--reorder-prob", "0.5", "--presumed-abort"], "--presumed-abort"),
+        (&["--max-inflight", "4", "--replicate"], "--max-inflight"),
+        (&["--decision-timeout-ms", "0"], "--decision-timeout-ms"),
+        (&["--presumed-abort", "--max-inflight", "4"], "--presumed-abort"),
+        (&["--heartbeat-ms", "10", "--max-missed", "0"], "--max-missed"),
+        (&["--protocol", "3pc", "--decision-policy", "quorum:2"], "--protocol 3pc"),
+        (&["--protocol", "3pc", "--presumed-abort"], "--protocol 3pc"),
+        (&["--read-only-ratio", "1.1"], "--read-only-ratio"),
+        (&["--read-only-ratio", "0.5", "--protocol", "3pc"], "--read-only-ratio"),
+    ];
+    for (args, option) in refused.iter() {
+        match validate(args) {
+            Err(err) => assert!(err.starts_with(option), "{:?}: {}", args, err),
+            Ok(()) => panic!("{:?} was let through", args),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//!
+//! shutdown.rs
+//! Runs that have to end on their own, or promptly once stopped: the
+//! library's run_with() stands in for main(), and shutdown::stop() for
+//! CTRL-C.
+//!
+extern crate concurrency_2pc;
+mod common;
+use concurrency_2pc::message::MessageType;
+use concurrency_2pc::oplog::OpLog;
+use concurrency_2pc::shutdown;
+use concurrency_2pc::simulation;
+use concurrency_2pc::simulation::SimulationStats;
+use concurrency_2pc::tpcoptions::TPCOptions;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+///
+/// timed_run()
+/// run opts, stopping it (as CTRL-C would) after stop_after if given:
+/// what came of it, and how long it took to finish from then (or from
+/// the start). fails the test if it hasn't finished within limit of 
+/// that.
+///
+fn timed_run(opts: &TPCOptions, stop_after: Option<Duration>, limit: Duration) -> (SimulationStats, Duration) {
+    let running = Arc::new(AtomicBool::new(true));
+    let exit = shutdown::signal();
+    let (done_tx, done_rx) = channel();
+    let (run_opts, r, e) = (opts.clone(), running.clone(), exit.clone());
+    thread::spawn(move || {
+        let _ = done_tx.send(simulation::run_with(&run_opts, r, e));
+    });
+    if let Some(after) = stop_after {
+        thread::sleep(after);
+    }
+    let since = Instant::now();
+    if stop_after.is_some() {
+        shutdown::stop(&running, &exit);
+    }
+    match done_rx.recv_timeout(limit) {
+        Ok(stats) => (stats, since.elapsed()),
+        Err(_) => panic!("run still going after {:?}", limit),
+    }
+}
+
+///
+/// partitioned_run_stops_within_its_drain()
+/// every participant is cut off for a minute while the first txid's
+/// votes are on their way back (100ms each way), so the coordinator is
+/// stuck trying to deliver its decision when it's stopped: it gives up
+/// after --drain-timeout-ms rather than wait out the partition, and
+/// the logs still pass the liveness check
+///
+#[test]
+fn partitioned_run_stops_within_its_drain() {
+    let opts = common::options("partition-stop",
+                               &["-c", "1", "-p", "3", "-r", "1000", "--net-latency-ms-min", "100", "--net-latency-ms-max", "100",
+                                 "--partition-participants", "0,1,2", "--partition-after-ms", "150",
+                                 "--partition-duration-ms", "60000", "--drain-timeout-ms", "500",
+                                 "--check-liveness"]);
+    let (_, took) = timed_run(&opts, Some(Duration::from_millis(1500)), Duration::from_secs(20));
+    assert!(took < Duration::from_secs(5), "stopping took {:?}", took);
+    let coordinator = OpLog::read_all(&format!("{}coordinator.log", opts.logpath)).unwrap();
+    assert!(coordinator.iter().any(|pm| pm.mtype == MessageType::CoordinatorAbandon),
+            "the coordinator wasn't delivering a decision when stopped");
+    common::check(&opts);
+}
+
+///
+/// unacked_exit_is_waited_out()
+/// a participant that never acks the coordinator's exit holds up 
+/// shutdown by --exit-ack-timeout-ms at most: the coordinator logs it
+/// as timed out and the run ends
+///
+#[test]
+fn unacked_exit_is_waited_out() {
+    let opts = common::options("exit-ack-timeout",
+                               &["-c", "1", "-p", "3", "-r", "5", "--participant-exit-delay", "1:never",
+                                 "--exit-ack-timeout-ms", "300"]);
+    let (_, took) = timed_run(&opts, None, Duration::from_secs(20));
+    assert!(took < Duration::from_secs(3), "the run took {:?}", took);
+    let coordinator = OpLog::read_all(&format!("{}coordinator.log", opts.logpath)).unwrap();
+    let timed_out: Vec<_> = coordinator.iter()
+        .filter(|pm| pm.mtype == MessageType::ExitAckTimeout)
+        .map(|pm| pm.senderid.clone())
+        .collect();
+    assert_eq!(timed_out, vec!["participant_1"]);
+    common::check(&opts);
+}
+
+///
+/// zero_request_run_ends_at_once()
+/// with nothing to request (-r 0, or no clients at all), the run goes
+/// straight to shutting down: everyone exits, and acks the exit, 
+/// without a single transaction
+///
+#[test]
+fn zero_request_run_ends_at_once() {
+    for (n, args) in [["-c", "3", "-r", "0"], ["-c", "0", "-r", "5"]].iter().enumerate() {
+        let mut argv = vec!["-p", "3"];
+        argv.extend_from_slice(args);
+        let opts = common::options(&format!("zero-requests-{}", n), &argv);
+        let (stats, took) = timed_run(&opts, None, Duration::from_secs(20));
+        assert!(took < Duration::from_secs(1), "{:?}: the run took {:?}", args, took);
+        assert_eq!((stats.committed, stats.aborted, stats.unknown), (0, 0, 0));
+        assert_eq!(stats.participant_committed, 0);
+        let coordinator = OpLog::read_all(&format!("{}coordinator.log", opts.logpath)).unwrap();
+        assert!(!coordinator.iter().any(|pm| pm.mtype == MessageType::ExitAckTimeout),
+                "{:?}: a participant didn't ack the exit", args);
+        common::check(&opts);
+    }
+}
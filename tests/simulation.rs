@@ -0,0 +1,387 @@
+//!
+//! simulation.rs
+//! Whole runs through the library (simulation::run()), checking what
+//! they add up to and that their logs pass the checker.
+//!
+extern crate concurrency_2pc;
+mod common;
+use concurrency_2pc::checker;
+use concurrency_2pc::checker::CheckError;
+use concurrency_2pc::coordinator::Coordinator;
+use concurrency_2pc::message::{MessageType, ProtocolMessage};
+use concurrency_2pc::oplog::OpLog;
+use concurrency_2pc::runstate::RunState;
+use concurrency_2pc::shutdown;
+use concurrency_2pc::simulation;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+
+///
+/// lossless_run_commits_every_request()
+/// with every probability 1.0 nothing can abort: each client request
+/// commits, at every participant, and each client hears so
+///
+#[test]
+fn lossless_run_commits_every_request() {
+    let opts = common::options("lossless", &["-c", "4", "-p", "3", "-r", "5", "-s", "1.0", "-S", "1.0"]);
+    let stats = simulation::run(&opts);
+    assert_eq!(stats.committed, opts.num_clients * opts.num_requests);
+    assert_eq!((stats.aborted, stats.unknown), (0, 0));
+    assert_eq!(stats.participant_committed, stats.committed * opts.num_participants);
+    assert_eq!(stats.client_committed, stats.committed);
+    assert_eq!(stats.disagreement(opts.num_participants), None);
+    // unbounded channels never push back
+    assert!(stats.backpressured.is_empty(), "backpressure without --channel-capacity: {:?}", stats.backpressured);
+    let report = common::check(&opts);
+    assert_eq!(report.committed, stats.committed as usize);
+}
+
+///
+/// failing_operations_still_decide_every_request()
+/// with operations failing, some requests abort, but every one is
+/// still decided one way or the other, and the logs agree
+///
+#[test]
+fn failing_operations_still_decide_every_request() {
+    let opts = common::options("failing-ops", &["-c", "3", "-p", "3", "-r", "10", "-s", "0.7", "--seed", "11"]);
+    let stats = simulation::run(&opts);
+    assert_eq!(stats.committed + stats.aborted, opts.num_clients * opts.num_requests);
+    assert!(stats.aborted > 0, "no request aborted with -s 0.7");
+    assert_eq!(stats.unknown, 0);
+    assert_eq!((stats.client_committed, stats.client_aborted), (stats.committed, stats.aborted));
+    let report = common::check(&opts);
+    assert_eq!((report.committed, report.aborted), (stats.committed as usize, stats.aborted as usize));
+}
+
+///
+/// reordered_messages_stay_atomic()
+/// --reorder-prob lets messages overtake one another, e.g. a decision
+/// arriving ahead of its proposal. every participant must still commit
+/// exactly what the coordinator did (the checker's atomicity check),
+/// under both protocols
+///
+#[test]
+fn reordered_messages_stay_atomic() {
+    for protocol in ["2pc", "3pc"].iter() {
+        let opts = common::options(&format!("reorder-{}", protocol), 
+                                   &["-c", "3", "-p", "3", "-r", "10", "-s", "0.9", "--seed", "5", 
+                                     "--reorder-prob", "0.5", "--protocol", protocol, "--check-liveness"]);
+        let stats = simulation::run(&opts);
+        assert_eq!(stats.committed + stats.aborted + stats.unknown, opts.num_clients * opts.num_requests);
+        let report = common::check(&opts);
+        assert_eq!(report.committed, stats.committed as usize);
+    }
+}
+
+///
+/// repeated_txid_is_proposed_once()
+/// a client that sends the same txid twice (say, a resend after its
+/// result was lost) gets the stored outcome for the second: the 
+/// coordinator doesn't ask the participants to vote on it again. the
+/// client's side is played by hand here, to control exactly what it
+/// sends.
+///
+#[test]
+fn repeated_txid_is_proposed_once() {
+    let opts = common::options("repeated-txid", &["-c", "1", "-p", "2", "-r", "2"]);
+    let running = Arc::new(AtomicBool::new(true));
+    let mut coordinator = Coordinator::new(format!("{}coordinator.log", opts.logpath), running, shutdown::signal(), RunState::new(&opts), &opts, 1);
+    let client = coordinator.client_join(String::from("0"));
+    let participants: Vec<_> = (0..opts.num_participants)
+        .map(|i| coordinator.participant_join(format!("{}", i), &opts.logpath))
+        .collect();
+    let handles: Vec<_> = participants.into_iter()
+        .map(|mut participant| thread::spawn(move || participant.protocol()))
+        .collect();
+    let coordinator = thread::spawn(move || {
+        coordinator.protocol();
+        coordinator.resent_requests
+    });
+
+    // txid 1 twice, then txid 2 to make up the 2 requests
+    for (txid, opid) in [(1, 0), (1, 0), (2, 1)].iter() {
+        let request = ProtocolMessage::generate(MessageType::ClientRequest, *txid, String::from("Client_0"), *opid);
+        client.ports.0.send(request).unwrap();
+    }
+    let mut results = vec![];
+    for pm in client.ports.1.iter() {
+        if pm.mtype == MessageType::CoordinatorExit {
+            break;
+        }
+        results.push(pm.txid);
+    }
+    let resent = coordinator.join().unwrap();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(resent, 1);
+    assert_eq!(results.iter().filter(|txid| **txid == 1).count(), 2, "txid 1 wasn't answered twice: {:?}", results);
+    for i in 0..opts.num_participants {
+        let records = OpLog::read_all(&format!("{}participant_{}.log", opts.logpath, i)).unwrap();
+        let proposals = records.iter().filter(|pm| pm.txid == 1 && pm.mtype == MessageType::CoordinatorPropose).count();
+        let votes = records.iter().filter(|pm| pm.txid == 1 && pm.mtype == MessageType::ParticipantVoteCommit).count();
+        assert_eq!((proposals, votes), (1, 1), "participant_{} was asked to vote on txid 1 more than once", i);
+    }
+    let report = common::check(&opts);
+    assert_eq!(report.committed, 2);
+}
+
+///
+/// clients_take_turns_under_load()
+/// four clients that always have a request waiting, cut off part way 
+/// through by the log budget: the coordinator serves them in turn, so 
+/// none of them gets more than a request or two ahead of the others
+///
+#[test]
+fn clients_take_turns_under_load() {
+    let opts = common::options("turns", &["-c", "4", "-p", "3", "-r", "400", "-s", "1.0", "-S", "1.0", "--max-total-log-bytes", "200000"]);
+    let stats = simulation::run(&opts);
+    assert_eq!(stats.served.len(), 4, "{:?}", stats.served);
+    let (least, most) = (*stats.served.values().min().unwrap(), *stats.served.values().max().unwrap());
+    assert!(most < opts.num_requests, "the log budget should stop the run part way: {:?}", stats.served);
+    assert!(most - least <= 2, "clients weren't served in turn: {:?}", stats.served);
+    common::check(&opts);
+}
+
+///
+/// bounded_channels_drop_nothing()
+/// with --channel-capacity 1 and many clients sending as fast as they
+/// can, clients block on the coordinator rather than lose requests:
+/// every request is served, and the coordinator, participants and
+/// clients all add up to the same totals. the clients do get pushed
+/// back on, waiting for room in the coordinator's queue.
+///
+#[test]
+fn bounded_channels_drop_nothing() {
+    let opts = common::options("capacity-1", &["-c", "16", "-p", "3", "-r", "10", "--arrival-rate", "2000", "--channel-capacity", "1"]);
+    let stats = simulation::run(&opts);
+    assert_eq!(stats.committed, opts.num_clients * opts.num_requests);
+    assert!(stats.served.values().all(|served| *served == opts.num_requests), "a client went short: {:?}", stats.served);
+    assert_eq!((stats.client_committed, stats.client_aborted, stats.client_unknown), (stats.committed, 0, 0));
+    assert_eq!(stats.disagreement(opts.num_participants), None);
+    assert!(!stats.backpressured.is_empty(), "16 clients never found a queue of 1 full");
+    common::check(&opts);
+}
+
+///
+/// failed_decision_write_is_never_sent()
+/// with the disk full by the time the coordinator logs its decision on
+/// the first request (its 3rd record, after the request and the 
+/// proposal), or the commit point that's forced out with it (its 4th),
+/// that decision must never reach a participant: the coordinator halts
+/// instead. with --async-log the write fails in the writer thread, and
+/// has to get back to the coordinator before it sends anything.
+///
+#[test]
+fn failed_decision_write_is_never_sent() {
+    let logged = [MessageType::ClientRequest, MessageType::CoordinatorPropose, MessageType::CoordinatorCommit];
+    for writer in ["sync", "async"].iter() {
+        for full_after in [2, 3].iter() {
+            let full_after_arg = full_after.to_string();
+            let mut args = vec!["-c", "1", "-p", "2", "-r", "3", "-s", "1.0", "-S", "1.0", "--disk-full-after", &full_after_arg];
+            if *writer == "async" {
+                args.push("--async-log");
+            }
+            let opts = common::options(&format!("disk-full-{}-{}", writer, full_after), &args);
+            let stats = simulation::run(&opts);
+            assert_eq!((stats.committed, stats.client_committed), (0, 0));
+            assert!(stats.client_unknown > 0, "{} writer: the client heard back about its first request", writer);
+            assert!(stats.served.values().all(|served| *served == 0), "{} writer: an undecided request counted as served: {:?}", writer, stats.served);
+
+            let coordinator = OpLog::read_all(&format!("{}coordinator.log", opts.logpath)).unwrap();
+            let mtypes: Vec<_> = coordinator.iter().map(|pm| pm.mtype).collect();
+            assert_eq!(mtypes, logged[..*full_after].to_vec(), 
+                       "{} writer: the disk filled up somewhere else", writer);
+            let txid = coordinator[1].txid;
+            let decided = |pm: &ProtocolMessage| pm.txid == txid && 
+                (pm.mtype == MessageType::CoordinatorCommit || pm.mtype == MessageType::CoordinatorAbort);
+            for i in 0..opts.num_participants {
+                let records = OpLog::read_all(&format!("{}participant_{}.log", opts.logpath, i)).unwrap();
+                assert!(!records.iter().any(decided), 
+                        "{} writer: participant_{} got a decision on txid {} the coordinator never forced out", writer, i, txid);
+            }
+        }
+    }
+}
+
+///
+/// runs_side_by_side_number_their_own_txids()
+/// two runs at once in the same process each hand out txids 1, 2, ...
+/// of their own: neither skips the numbers the other one took
+///
+#[test]
+fn runs_side_by_side_number_their_own_txids() {
+    let runs: Vec<_> = (0..2).map(|n| {
+        let opts = common::options(&format!("own-txids-{}", n), &["-c", "2", "-p", "2", "-r", "5", "-s", "1.0", "-S", "1.0"]);
+        let run_opts = opts.clone();
+        (opts, thread::spawn(move || simulation::run(&run_opts)))
+    }).collect();
+    for (opts, handle) in runs {
+        handle.join().unwrap();
+        let coordinator = OpLog::read_all(&format!("{}coordinator.log", opts.logpath)).unwrap();
+        let mut txids: Vec<i32> = coordinator.iter()
+            .filter(|pm| pm.mtype == MessageType::ClientRequest)
+            .map(|pm| pm.txid)
+            .collect();
+        txids.sort();
+        assert_eq!(txids, (1..=opts.num_clients * opts.num_requests).collect::<Vec<_>>(), "{}", opts.logpath);
+        common::check(&opts);
+    }
+}
+
+///
+/// runs_side_by_side_keep_their_own_log_budget()
+/// --max-total-log-bytes counts what one run's logs append: two runs at
+/// once must each get about as far on their budget as a run on its own
+///
+#[test]
+fn runs_side_by_side_keep_their_own_log_budget() {
+    let args = ["-c", "1", "-p", "2", "-r", "40", "-s", "1.0", "-S", "1.0", "--max-total-log-bytes", "20000"];
+    let alone = simulation::run(&common::options("own-budget-alone", &args));
+    assert!(alone.committed > 2 && alone.committed < 40, "the budget should stop a run part way: {} committed", alone.committed);
+    let runs: Vec<_> = (0..2).map(|n| {
+        let opts = common::options(&format!("own-budget-{}", n), &args);
+        let run_opts = opts.clone();
+        (opts, thread::spawn(move || simulation::run(&run_opts)))
+    }).collect();
+    for (opts, handle) in runs {
+        let stats = handle.join().unwrap();
+        assert!((stats.committed - alone.committed).abs() <= 1, "{} committed {}, a run on its own {}", opts.logpath, stats.committed, alone.committed);
+        common::check(&opts);
+    }
+}
+
+///
+/// presumed_abort_waits_out_a_partition()
+/// under --presumed-abort, a participant that voted yes and is then cut
+/// off hears nothing for longer than --presumed-abort-timeout-ms while
+/// the coordinator waits to send it the commit: it must ask for the
+/// decision rather than presume abort, or the logs break atomicity
+///
+#[test]
+fn presumed_abort_waits_out_a_partition() {
+    // participant_2's vote for txid 3 is slow, so participant_1 has 
+    // voted yes and is waiting on the decision when it's cut off
+    let schedule = env::temp_dir().join(format!("2pc-test-presumed-partition-{}.faults", std::process::id()));
+    fs::write(&schedule, "3 ParticipantVoteCommit participant_2 delay:300\n").unwrap();
+    let opts = common::options("presumed-partition", &["-c", "1", "-p", "3", "-r", "10", "-s", "1.0", "-S", "1.0",
+                                                       "--presumed-abort", "--presumed-abort-timeout-ms", "50",
+                                                       "--vote-timeout-ms", "1000", "--parallel-prepare", "--fault-schedule", schedule.to_str().unwrap(),
+                                                       "--partition-participants", "1", "--partition-after-ms", "100",
+                                                       "--partition-duration-ms", "500"]);
+    simulation::run(&opts);
+    let records = OpLog::read_all(&format!("{}participant_1.log", opts.logpath)).unwrap();
+    assert!(records.iter().any(|pm| pm.txid == 3 && pm.mtype == MessageType::CoordinatorCommit), "participant_1 didn't commit txid 3");
+    common::check(&opts);
+}
+
+///
+/// unreadable_logs_fail_the_check()
+/// a record in the middle of a log that doesn't decode, or a segment in
+/// a different format from the rest of its log, fails the check with a
+/// CheckError saying where, windowed or not, rather than panicking
+///
+#[test]
+fn unreadable_logs_fail_the_check() {
+    let opts = common::options("unreadable", &["-c", "1", "-p", "2", "-r", "5", "-s", "1.0", "-S", "1.0", "--log-max-bytes", "2000"]);
+    simulation::run(&opts);
+    let check = |window| (
+        checker::check_last_run(opts.num_clients, opts.num_requests, opts.num_participants, &opts.logpath, false, false, false).map(|_report| true),
+        checker::validate_last_run(opts.num_participants, &opts.logpath, false, false, false, window),
+    );
+
+    let plog = format!("{}participant_0.log", opts.logpath);
+    let written = fs::read_to_string(&plog).unwrap();
+    let second = written.find('\n').unwrap() + 1;
+    fs::write(&plog, format!("{}{{garbage\n{}", &written[..second], &written[second..])).unwrap();
+    for window in [0, 2].iter() {
+        let (checked, validated) = check(*window);
+        for result in [checked, validated].iter() {
+            match result {
+                Err(CheckError::BadRecord { path, offset, .. }) => assert_eq!((path, *offset), (&plog, second as u64)),
+                other => panic!("expected a bad record in {} at offset {}, got {:?}", plog, second, other),
+            }
+        }
+    }
+    fs::write(&plog, written).unwrap();
+
+    let segment = format!("{}coordinator.log.1", opts.logpath);
+    assert!(Path::new(&segment).exists(), "the coordinator log wasn't split into segments");
+    fs::write(&segment, b"2PCLOG\x00\x01").unwrap();
+    for window in [0, 2].iter() {
+        let (checked, validated) = check(*window);
+        for result in [checked, validated].iter() {
+            match result {
+                Err(CheckError::MixedFormats { path, .. }) => assert_eq!(path, &segment),
+                other => panic!("expected {} to be in the wrong format, got {:?}", segment, other),
+            }
+        }
+    }
+}
+
+///
+/// read_all_ends_at_a_torn_record_only()
+/// OpLog::read_all() leaves out a last record a crash cut short, in 
+/// either format, but a record that doesn't decode before the end of 
+/// the log is an error rather than a panic
+///
+#[test]
+fn read_all_ends_at_a_torn_record_only() {
+    for format in ["json", "bincode"].iter() {
+        let opts = common::options(&format!("read-all-{}", format), &["-c", "1", "-p", "1", "-r", "3", "-s", "1.0", "-S", "1.0", "--log-format", format]);
+        simulation::run(&opts);
+        let path = format!("{}coordinator.log", opts.logpath);
+        let written = fs::read(&path).unwrap();
+        let records = OpLog::read_all(&path).unwrap();
+
+        // another copy of the first record, cut off part way through
+        let first = if *format == "json" {
+            &written[..written.iter().position(|b| *b == b'\n').unwrap() + 1]
+        } else {
+            let len = u32::from_le_bytes([written[8], written[9], written[10], written[11]]) as usize;
+            &written[8..12 + len]
+        };
+        fs::write(&path, [&written[..], &first[..first.len() / 2]].concat()).unwrap();
+        let uids = |records: Vec<ProtocolMessage>| records.iter().map(|pm| pm.uid).collect::<Vec<_>>();
+        assert_eq!(uids(OpLog::read_all(&path).unwrap()), uids(records), "{} log with a torn last record", format);
+
+        // a record that can't be one, with complete records after it
+        let bad: &[u8] = if *format == "json" { b"{garbage\n" } else { &[0xff; 4] };
+        fs::write(&path, [&written[..], bad, first].concat()).unwrap();
+        let err = OpLog::read_all(&path).expect_err("a bad record mid-log");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}: {}", format, err);
+    }
+}
+
+///
+/// indexed_logs_check_by_window()
+/// with --log-index the checker reads a window's records by seeking 
+/// through the index rather than scanning the logs, segments and all:
+/// it must find what a scan finds, txid by txid, and the logs must 
+/// pass window by window as they do whole
+///
+#[test]
+fn indexed_logs_check_by_window() {
+    let opts = common::options("indexed-window", &["-c", "3", "-p", "3", "-r", "10", "-s", "0.8", "--seed", "3",
+                                                   "--log-index", "--log-max-bytes", "2000"]);
+    simulation::run(&opts);
+    let path = format!("{}coordinator.log", opts.logpath);
+    assert!(OpLog::has_index(&path) && Path::new(&format!("{}.1", path)).exists(), "the coordinator log wasn't split into indexed segments");
+    let index = OpLog::load_index(&path).unwrap();
+    let records = OpLog::read_all(&path).unwrap();
+    for txid in index.keys() {
+        let scanned: Vec<_> = records.iter().filter(|pm| pm.txid == *txid).map(|pm| pm.uid).collect();
+        let seeked: Vec<_> = OpLog::read_txid(&path, &index, *txid).unwrap().iter().map(|pm| pm.uid).collect();
+        assert_eq!(seeked, scanned, "txid {} read through the index", txid);
+    }
+    for window in [0, 4].iter() {
+        let passed = checker::validate_last_run(opts.num_participants, &opts.logpath, false, false, false, *window);
+        assert_eq!(passed.ok(), Some(true), "window of {} txids", window);
+    }
+}